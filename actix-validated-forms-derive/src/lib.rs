@@ -1,10 +1,52 @@
+#![recursion_limit = "256"]
+
 extern crate proc_macro;
 
 use crate::proc_macro::TokenStream;
 use quote::quote;
 use syn::{PathArguments, Type};
 
-#[proc_macro_derive(FromMultipart)]
+/// The items inside any `#[multipart(...)]` attributes on a field
+fn multipart_attributes(field: &syn::Field) -> Vec<syn::Meta> {
+    let mut metas = Vec::new();
+    for attr in field.attrs.iter() {
+        if let Some(syn::Meta::List(list)) = attr.interpret_meta() {
+            if list.ident == "multipart" {
+                for nested in list.nested.into_iter() {
+                    match nested {
+                        syn::NestedMeta::Meta(m) => metas.push(m),
+                        _ => panic!("Invalid multipart attribute"),
+                    }
+                }
+            }
+        }
+    }
+    metas
+}
+
+/// The validators of the `#[validate(...)]` attributes on a field that can be checked on their
+/// own, leaving out `custom` and `must_match` (which may refer to `Self` or to other fields)
+fn early_validators(field: &syn::Field) -> Vec<syn::NestedMeta> {
+    let mut validators = Vec::new();
+    for attr in field.attrs.iter() {
+        if let Some(syn::Meta::List(list)) = attr.interpret_meta() {
+            if list.ident == "validate" {
+                validators.extend(list.nested.into_iter().filter(|nested| match nested {
+                    syn::NestedMeta::Meta(m) => m.name() != "custom" && m.name() != "must_match",
+                    _ => true,
+                }));
+            }
+        }
+    }
+    validators
+}
+
+/// The name of a field in the form, without the `r#` of a raw identifier
+fn form_name(ident: &syn::Ident) -> String {
+    ident.to_string().trim_start_matches("r#").to_string()
+}
+
+#[proc_macro_derive(FromMultipart, attributes(multipart))]
 pub fn impl_from_multipart(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
 
@@ -19,8 +61,16 @@ pub fn impl_from_multipart(input: TokenStream) -> TokenStream {
     };
 
     let mut fields_vec_innards = quote!();
+    let mut early_validations = quote!();
+    let mut bindings = quote!();
+    // Calls adjusting the `MultipartLoadConfig`, made by `EarlyValidate::load_config()`
+    let mut config_calls = quote!();
+    let mut field_names = Vec::new();
+    let mut form_names = Vec::new();
+    let mut binding_names = Vec::new();
     for field in fields.named.iter() {
         let name = field.ident.as_ref().unwrap();
+        let form_name = form_name(name);
         let ty = &field.ty;
         let p = match ty {
             Type::Path(ref p) => p,
@@ -29,25 +79,329 @@ pub fn impl_from_multipart(input: TokenStream) -> TokenStream {
         let mut x = p.clone();
         let last = &mut x.path.segments.last_mut().unwrap();
         last.value_mut().arguments = PathArguments::None;
+
+        let mut messages = Vec::new();
+        for meta in multipart_attributes(field) {
+            if let syn::Meta::NameValue(nv) = &meta {
+                let value = match &nv.lit {
+                    syn::Lit::Str(s) => s.value(),
+                    _ => panic!("Multipart attribute {} must be a string", nv.ident),
+                };
+                if nv.ident == "charset" {
+                    // Check the label now, rather than when the form is loaded
+                    if encoding_rs::Encoding::for_label(value.as_bytes()).is_none() {
+                        panic!("Unknown charset {}", value);
+                    }
+                    config_calls.extend(quote!(
+                        .field_charset(
+                            #form_name,
+                            actix_validated_forms::encoding_rs::Encoding::for_label(
+                                #value.as_bytes()
+                            ).unwrap(),
+                        )
+                    ));
+                } else if nv.ident == "allowed_mimes" {
+                    let mimes = value.split(',').map(str::trim).filter(|m| !m.is_empty());
+                    config_calls.extend(quote!(
+                        .field_mimes(#form_name, &[#(#mimes,)*])
+                    ));
+                } else {
+                    messages.push((nv.ident.to_string(), value));
+                }
+            } else if meta.name() == "validate_early" {
+                // Validate the field on its own using a struct containing just this field, the
+                // validators left out are only checked once the whole form has been loaded
+                let validators = early_validators(field);
+                if validators.is_empty() {
+                    continue;
+                }
+                early_validations.extend(quote!(
+                    {
+                        #[derive(Validate)]
+                        struct EarlyField {
+                            #[validate(#(#validators),*)]
+                            #name: #ty,
+                        }
+                        if let Ok(v) = #x::get(form, #form_name) {
+                            actix_validated_forms::validator::Validate::validate(
+                                &EarlyField { #name: v }
+                            )?;
+                        }
+                    }
+                ));
+            } else {
+                panic!("Unknown multipart attribute");
+            }
+        }
+
+        let getter = if messages.is_empty() {
+            quote!(#x::get(parts.field(#form_name), #form_name))
+        } else {
+            let message = |kind: &str| match messages.iter().find(|(k, _)| k == kind) {
+                Some((_, m)) => quote!(Some(#m)),
+                None => quote!(None),
+            };
+            if let Some((k, _)) = messages
+                .iter()
+                .find(|(k, _)| !["missing_msg", "duplicate_msg", "type_msg"].contains(&k.as_str()))
+            {
+                panic!("Unknown multipart attribute {}", k);
+            }
+            let (missing, duplicate, type_error) = (
+                message("missing_msg"),
+                message("duplicate_msg"),
+                message("type_msg"),
+            );
+            quote!(
+                #x::get(parts.field(#form_name), #form_name)
+                    .map_err(|e| e.with_messages(#missing, #duplicate, #type_error))
+            )
+        };
         fields_vec_innards.extend(quote!(
-            #name: #x::get(&mut value, stringify!(#name))?,
+            #name: #getter?,
         ));
+
+        // Used to bind every field, collecting all the errors
+        let binding = syn::Ident::new(&format!("field_{}", form_name), name.span());
+        bindings.extend(quote!(
+            let #binding = match #getter {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    errors.add(#form_name, e.into());
+                    None
+                }
+            };
+        ));
+        field_names.push(name);
+        form_names.push(form_name);
+        binding_names.push(binding);
     }
+    let field_names = &field_names;
+    let binding_names = &binding_names;
+    let load_config = quote!(
+        fn load_config(
+            config: actix_validated_forms::multipart::MultipartLoadConfig
+        ) -> actix_validated_forms::multipart::MultipartLoadConfig {
+            config #config_calls .expected_fields(&[#(#form_names,)*])
+        }
+    );
 
     let gen = quote! {
         impl std::convert::TryFrom<actix_validated_forms::multipart::Multiparts> for #name {
 
             type Error = actix_validated_forms::multipart::GetError;
 
-            fn try_from(mut value: actix_validated_forms::multipart::Multiparts) -> Result<Self, Self::Error> {
+            fn try_from(value: actix_validated_forms::multipart::Multiparts) -> Result<Self, Self::Error> {
                 use actix_validated_forms::multipart::MultipartType;
                 use actix_validated_forms::multipart::MultipartTypeSpecial;
+                // Grouped once, rather than each field searching all of the parts
+                let mut parts = actix_validated_forms::multipart::IndexedParts::new(value);
                 let x = Self {
                     #fields_vec_innards
                 };
                 Ok(x)
             }
         }
+
+        impl actix_validated_forms::multipart::EarlyValidate for #name {
+            #[allow(unused_imports, unused_variables)]
+            fn validate_early(
+                form: &mut actix_validated_forms::multipart::Multiparts
+            ) -> Result<(), actix_validated_forms::validator::ValidationErrors> {
+                use actix_validated_forms::multipart::MultipartType;
+                use actix_validated_forms::multipart::MultipartTypeSpecial;
+                #early_validations
+                Ok(())
+            }
+
+            #load_config
+
+            #[allow(unreachable_patterns, unused_mut)]
+            fn bind_combined(
+                form: &mut actix_validated_forms::multipart::Multiparts
+            ) -> Option<Result<Self, actix_validated_forms::validator::ValidationErrors>> {
+                use actix_validated_forms::multipart::MultipartType;
+                use actix_validated_forms::multipart::MultipartTypeSpecial;
+                let mut parts =
+                    actix_validated_forms::multipart::IndexedParts::new(std::mem::take(form));
+                let mut errors = actix_validated_forms::validator::ValidationErrors::new();
+                #bindings
+                *form = parts.into_remaining();
+                Some(match (#(#binding_names,)*) {
+                    (#(Some(#binding_names),)*) => Ok(Self {
+                        #(#field_names: #binding_names,)*
+                    }),
+                    _ => Err(errors),
+                })
+            }
+        }
+    };
+    gen.into()
+}
+
+#[proc_macro_derive(DescribeForm)]
+pub fn impl_describe_form(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+
+    let name = &ast.ident;
+    let fields = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(n),
+            ..
+        }) => n,
+        _ => panic!("This trait can only be derived for a struct"),
+    };
+
+    let mut descriptions = quote!();
+    for field in fields.named.iter() {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let form_name = form_name(name);
+        descriptions.extend(quote!(
+            <#ty>::describe(#form_name),
+        ));
+    }
+
+    let gen = quote! {
+        impl actix_validated_forms::multipart::DescribeForm for #name {
+            fn describe_fields() -> Vec<actix_validated_forms::multipart::FieldDescription> {
+                use actix_validated_forms::multipart::DescribeField;
+                use actix_validated_forms::multipart::DescribeFieldSpecial;
+                vec![#descriptions]
+            }
+        }
+    };
+    gen.into()
+}
+
+/// Converts a variant name such as `DarkBlue` into `dark_blue`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[proc_macro_derive(FormEnum, attributes(form_enum))]
+pub fn impl_form_enum(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+
+    let name = &ast.ident;
+    let data = match &ast.data {
+        syn::Data::Enum(e) => e,
+        _ => panic!("FormEnum can only be derived for an enum"),
+    };
+
+    let mut canonical = Vec::new();
+    let mut arms = quote!();
+    let mut matches = quote!();
+    for variant in data.variants.iter() {
+        match variant.fields {
+            syn::Fields::Unit => {}
+            _ => panic!("FormEnum variants can't have fields"),
+        }
+        let ident = &variant.ident;
+        let mut rename = None;
+        let mut aliases = Vec::new();
+        for attr in variant.attrs.iter() {
+            if let Some(syn::Meta::List(list)) = attr.interpret_meta() {
+                if list.ident != "form_enum" {
+                    continue;
+                }
+                for nested in list.nested.into_iter() {
+                    let nv = match nested {
+                        syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => nv,
+                        _ => panic!("Invalid form_enum attribute"),
+                    };
+                    let value = match &nv.lit {
+                        syn::Lit::Str(s) => s.value(),
+                        _ => panic!("form_enum attribute {} must be a string", nv.ident),
+                    };
+                    if nv.ident == "rename" {
+                        rename = Some(value);
+                    } else if nv.ident == "alias" {
+                        aliases.push(value);
+                    } else {
+                        panic!("Unknown form_enum attribute {}", nv.ident);
+                    }
+                }
+            }
+        }
+        let name_str = rename.unwrap_or_else(|| snake_case(&ident.to_string()));
+        let mut accepted = vec![name_str.clone()];
+        accepted.extend(aliases);
+        matches.extend(quote!(
+            if [#(#accepted),*].iter().any(|a| value.eq_ignore_ascii_case(a)) {
+                return Ok(#name::#ident);
+            }
+        ));
+        arms.extend(quote!(
+            #name::#ident => #name_str,
+        ));
+        canonical.push(name_str);
+    }
+    let canonical = &canonical;
+
+    let gen = quote! {
+        impl actix_validated_forms::enums::FormEnum for #name {
+            const VARIANTS: &'static [&'static str] = &[#(#canonical),*];
+
+            fn as_str(&self) -> &'static str {
+                match self {
+                    #arms
+                }
+            }
+        }
+
+        impl std::str::FromStr for #name {
+            type Err = actix_validated_forms::enums::EnumError;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                let value = value.trim();
+                #matches
+                Err(actix_validated_forms::enums::EnumError::new(
+                    value,
+                    <Self as actix_validated_forms::enums::FormEnum>::VARIANTS,
+                ))
+            }
+        }
+
+        impl std::fmt::Display for #name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(actix_validated_forms::enums::FormEnum::as_str(self))
+            }
+        }
+
+        impl<'de> actix_validated_forms::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: actix_validated_forms::serde::Deserializer<'de>,
+            {
+                let value = <String as actix_validated_forms::serde::Deserialize>::deserialize(
+                    deserializer,
+                )?;
+                value
+                    .parse()
+                    .map_err(actix_validated_forms::serde::de::Error::custom)
+            }
+        }
+
+        impl actix_validated_forms::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: actix_validated_forms::serde::Serializer,
+            {
+                serializer.serialize_str(actix_validated_forms::enums::FormEnum::as_str(self))
+            }
+        }
     };
     gen.into()
 }
@@ -0,0 +1,399 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for a HTTP request body in any of the accepted `BodyFormat`s, chosen by
+/// the Content-Type of the request
+///
+/// # Example
+/// First define a structure to represent the body that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Comment {
+///     #[validate(length(min = 1, max = 500))]
+///     text: String,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::body::ValidatedBody;
+///
+/// async fn route(
+///     comment: ValidatedBody<Comment>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// The same route accepts both a submitted HTML form and a JSON API request. When the body of
+/// route is executed `comment` can be dereferenced to a `Comment`, with the guarantee to have
+/// been successfully validated.
+pub struct ValidatedBody<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedBody<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedBody<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedBody<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedBody<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedBodyConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let format = BodyFormat::from_content_type(req.content_type())
+            .filter(|f| config.formats.contains(f));
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            let format = format.ok_or(BodyErrorWrapper::ContentType)?;
+            let body = read_body(payload, length, limit).await?;
+            format.deserialize::<T>(&body)
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedBody(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedBody<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedBody<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A format that `ValidatedBody` can parse
+///
+/// The CBOR, XML and TOML formats are available when the crate feature of the same name is
+/// enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyFormat {
+    /// `application/x-www-form-urlencoded`
+    Form,
+    /// `application/json`, or any `+json` suffix type
+    Json,
+    /// `application/cbor`
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// `application/xml` or `text/xml`
+    #[cfg(feature = "xml")]
+    Xml,
+    /// `application/toml`
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+impl BodyFormat {
+    pub(crate) fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "application/x-www-form-urlencoded" => Some(BodyFormat::Form),
+            "application/json" => Some(BodyFormat::Json),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(BodyFormat::Cbor),
+            #[cfg(feature = "xml")]
+            "application/xml" | "text/xml" => Some(BodyFormat::Xml),
+            #[cfg(feature = "toml")]
+            "application/toml" => Some(BodyFormat::Toml),
+            t if t.starts_with("application/") && t.ends_with("+json") => Some(BodyFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        self,
+        body: &[u8],
+    ) -> Result<T, BodyErrorWrapper> {
+        match self {
+            BodyFormat::Form => {
+                serde_urlencoded::from_bytes(body).map_err(BodyErrorWrapper::Urlencoded)
+            }
+            BodyFormat::Json => serde_json::from_slice(body).map_err(BodyErrorWrapper::Json),
+            #[cfg(feature = "cbor")]
+            BodyFormat::Cbor => serde_cbor::from_slice(body).map_err(BodyErrorWrapper::Cbor),
+            #[cfg(feature = "xml")]
+            BodyFormat::Xml => {
+                let mut deserializer = quick_xml::de::Deserializer::from_reader(body);
+                serde_path_to_error::deserialize(&mut deserializer).map_err(BodyErrorWrapper::Xml)
+            }
+            #[cfg(feature = "toml")]
+            BodyFormat::Toml => {
+                let body = std::str::from_utf8(body).map_err(BodyErrorWrapper::Utf8)?;
+                ::toml::from_str(body).map_err(BodyErrorWrapper::Toml)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BodyErrorWrapper {
+    /// The Content-Type of the request isn't one of the accepted `BodyFormat`s
+    ContentType,
+    /// The body is larger than the `ValidatedBodyConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    Urlencoded(serde_urlencoded::de::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "cbor")]
+    Cbor(serde_cbor::Error),
+    #[cfg(feature = "xml")]
+    Xml(serde_path_to_error::Error<quick_xml::DeError>),
+    #[cfg(feature = "toml")]
+    Utf8(std::str::Utf8Error),
+    #[cfg(feature = "toml")]
+    Toml(::toml::de::Error),
+}
+
+impl std::error::Error for BodyErrorWrapper {}
+
+impl From<BodyError> for BodyErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => BodyErrorWrapper::Overflow,
+            BodyError::Payload(e) => BodyErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for BodyErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BodyErrorWrapper::ContentType => f.write_str("Content type error"),
+            BodyErrorWrapper::Overflow => f.write_str("Payload size is bigger than allowed"),
+            BodyErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            BodyErrorWrapper::Urlencoded(e) => Display::fmt(&e, f),
+            BodyErrorWrapper::Json(e) => Display::fmt(&e, f),
+            #[cfg(feature = "cbor")]
+            BodyErrorWrapper::Cbor(e) => Display::fmt(&e, f),
+            #[cfg(feature = "xml")]
+            BodyErrorWrapper::Xml(e) => Display::fmt(&e, f),
+            #[cfg(feature = "toml")]
+            BodyErrorWrapper::Utf8(e) => Display::fmt(&e, f),
+            #[cfg(feature = "toml")]
+            BodyErrorWrapper::Toml(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedBody extractor
+///
+/// # Usage
+/// Add a `ValidatedBodyConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::body::{BodyFormat, ValidatedBodyConfig};
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedBodyConfig::default()
+///         .formats(&[BodyFormat::Json])
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedBodyConfig {
+    limit: usize,
+    formats: Vec<BodyFormat>,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<BodyErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedBodyConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the formats that are accepted, by default `BodyFormat::Form` and `BodyFormat::Json`
+    ///
+    /// # Panics
+    /// If `formats` is empty, as no body could ever be accepted
+    pub fn formats(mut self, formats: &[BodyFormat]) -> Self {
+        assert!(
+            !formats.is_empty(),
+            "ValidatedBodyConfig must accept at least one format"
+        );
+        self.formats = formats.to_vec();
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a body that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<BodyErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedBodyConfig {
+    fn default() -> Self {
+        ValidatedBodyConfig {
+            limit: 262_144,
+            formats: vec![BodyFormat::Form, BodyFormat::Json],
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct Comment {
+        #[validate(length(min = 1, max = 10))]
+        text: String,
+        rating: u8,
+    }
+
+    async fn route(comment: ValidatedBody<Comment>) -> impl Responder {
+        HttpResponse::Ok().json(&*comment)
+    }
+
+    fn request(content_type: &str, body: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", content_type)
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let requests = vec![
+            request("application/x-www-form-urlencoded", "text=hello&rating=4"),
+            request("application/json", r#"{"text": "hello", "rating": 4}"#),
+            request(
+                "application/vnd.api+json",
+                r#"{"text": "hello", "rating": 4}"#,
+            ),
+        ];
+        for req in requests {
+            let resp: Comment = test::read_response_json(&mut app, req.to_request()).await;
+            assert_eq!(resp.text, "hello");
+            assert_eq!(resp.rating, 4);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app =
+            test::init_service(
+                App::new()
+                    .app_data(ValidatedBodyConfig::default().limit(64).error_handler(
+                        |e, _| match e {
+                            ValidatedFormError::Validation(_) => {
+                                actix_web::error::ErrorUnprocessableEntity("invalid")
+                            }
+                            e => actix_web::error::ErrorBadRequest(e.to_string()),
+                        },
+                    ))
+                    .route("/", web::post().to(route)),
+            )
+            .await;
+        let requests = vec![
+            request("application/x-www-form-urlencoded", "text=&rating=4"),
+            request("application/json", r#"{"text": "", "rating": 4}"#),
+        ];
+        for req in requests {
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let req = request("application/json", r#"{"text": "hello", "rating": 400}"#);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = request("text/plain", "hello");
+        let resp = test::read_response(&mut app, req.to_request()).await;
+        assert_eq!(resp, "Content type error");
+
+        let body =
+            r#"{"text": "hello", "rating": 4, "padding": "................................"}"#;
+        let resp =
+            test::read_response(&mut app, request("application/json", body).to_request()).await;
+        assert_eq!(resp, "Payload size is bigger than allowed");
+    }
+
+    #[actix_rt::test]
+    async fn test_formats() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedBodyConfig::default()
+                        .formats(&[BodyFormat::Json])
+                        .error_handler(|e, _| match e {
+                            ValidatedFormError::Deserialization(BodyErrorWrapper::ContentType) => {
+                                actix_web::error::ErrorBadRequest(e.to_string())
+                            }
+                            e => actix_web::error::ErrorInternalServerError(e.to_string()),
+                        }),
+                )
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let req = request("application/x-www-form-urlencoded", "text=hello&rating=4");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
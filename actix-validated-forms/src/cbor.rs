@@ -0,0 +1,275 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for an application/cbor HTTP request body
+///
+/// # Example
+/// First define a structure to represent the body that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Reading {
+///     #[validate(range(min = -50.0, max = 150.0))]
+///     temperature: f64,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::cbor::ValidatedCbor;
+///
+/// async fn route(
+///     reading: ValidatedCbor<Reading>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `reading` can be dereferenced to a `Reading`, with the
+/// guarantee to have been successfully validated.
+pub struct ValidatedCbor<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedCbor<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedCbor<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedCbor<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedCbor<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedCborConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let is_cbor = req.content_type() == "application/cbor";
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            if !is_cbor {
+                return Err(CborErrorWrapper::ContentType);
+            }
+            let body = read_body(payload, length, limit).await?;
+            serde_cbor::from_slice::<T>(&body).map_err(CborErrorWrapper::Deserialize)
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedCbor(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedCbor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedCbor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum CborErrorWrapper {
+    /// The Content-Type of the request isn't `application/cbor`
+    ContentType,
+    /// The body is larger than the `ValidatedCborConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    Deserialize(serde_cbor::Error),
+}
+
+impl std::error::Error for CborErrorWrapper {}
+
+impl From<BodyError> for CborErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => CborErrorWrapper::Overflow,
+            BodyError::Payload(e) => CborErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for CborErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CborErrorWrapper::ContentType => f.write_str("Content type error"),
+            CborErrorWrapper::Overflow => f.write_str("CBOR payload size is bigger than allowed"),
+            CborErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            CborErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedCbor extractor
+///
+/// # Usage
+/// Add a `ValidatedCborConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::cbor::ValidatedCborConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedCborConfig::default()
+///         .limit(4096)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedCborConfig {
+    limit: usize,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<CborErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedCborConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a body that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<CborErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedCborConfig {
+    fn default() -> Self {
+        ValidatedCborConfig {
+            limit: 262_144,
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct Reading {
+        #[validate(length(min = 1, max = 5))]
+        sensor: String,
+        value: i32,
+    }
+
+    async fn route(reading: ValidatedCbor<Reading>) -> impl Responder {
+        HttpResponse::Ok().json(&*reading)
+    }
+
+    fn request(reading: &Reading) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/cbor")
+            .set_payload(serde_cbor::to_vec(reading).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let reading = Reading {
+            sensor: "t1".to_string(),
+            value: 21,
+        };
+        let resp: Reading =
+            test::read_response_json(&mut app, request(&reading).to_request()).await;
+        assert_eq!(resp.sensor, "t1");
+        assert_eq!(resp.value, 21);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app =
+            test::init_service(
+                App::new()
+                    .app_data(ValidatedCborConfig::default().limit(32).error_handler(
+                        |e, _| match e {
+                            ValidatedFormError::Validation(_) => {
+                                actix_web::error::ErrorUnprocessableEntity("invalid")
+                            }
+                            e => actix_web::error::ErrorBadRequest(e.to_string()),
+                        },
+                    ))
+                    .route("/", web::post().to(route)),
+            )
+            .await;
+        let reading = Reading {
+            sensor: "too long".to_string(),
+            value: 21,
+        };
+        let resp = test::call_service(&mut app, request(&reading).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let reading = Reading {
+            sensor: "a".repeat(40),
+            value: 21,
+        };
+        let resp = test::read_response(&mut app, request(&reading).to_request()).await;
+        assert_eq!(resp, "CBOR payload size is bigger than allowed");
+
+        let req = test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/json")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Content type error");
+    }
+}
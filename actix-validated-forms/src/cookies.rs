@@ -0,0 +1,274 @@
+use crate::error::ValidatedFormError;
+use actix_web::dev::Payload;
+use actix_web::error::CookieParseError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{err, ok, Ready};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for the cookies of a request
+///
+/// # Example
+/// First define a structure to represent the cookies that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Preferences {
+///     #[validate(length(min = 2, max = 5))]
+///     locale: Option<String>,
+///     #[serde(default)]
+///     experiment: bool,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::cookies::ValidatedCookies;
+///
+/// async fn route(
+///     preferences: ValidatedCookies<Preferences>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// Cookies that aren't fields of the structure are ignored, optional cookies can be represented
+/// by an `Option` or a `#[serde(default)]` field. When the body of route is executed
+/// `preferences` can be dereferenced to a `Preferences`, with the guarantee to have been
+/// successfully validated.
+pub struct ValidatedCookies<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedCookies<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedCookies<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedCookies<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedCookies<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedCookiesConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(ValidatedCookiesConfig::default());
+
+        let validated = deserialize_cookies::<T>(req, &config)
+            .map_err(ValidatedFormError::Deserialization)
+            .and_then(|c: T| {
+                c.validate()
+                    .map(|_| c)
+                    .map_err(|e| ValidatedFormError::Validation(e))
+            });
+        match validated {
+            Ok(val) => ok(ValidatedCookies(val)),
+            Err(e) => err(if let Some(error_handler) = config.error_handler {
+                (error_handler)(e, req)
+            } else {
+                e.into()
+            }),
+        }
+    }
+}
+
+fn deserialize_cookies<T: DeserializeOwned>(
+    req: &HttpRequest,
+    config: &ValidatedCookiesConfig,
+) -> Result<T, CookieErrorWrapper> {
+    let cookies = req.cookies().map_err(CookieErrorWrapper::Parse)?;
+    if let Some(missing) = config
+        .required
+        .iter()
+        .find(|name| !cookies.iter().any(|c| c.name() == name.as_str()))
+    {
+        return Err(CookieErrorWrapper::Missing(missing.clone()));
+    }
+    // Cookies are deserialized in the same way as a query string, so that the values can be
+    // parsed into numbers etc.
+    let pairs: Vec<(&str, &str)> = cookies.iter().map(|c| (c.name(), c.value())).collect();
+    let encoded = serde_urlencoded::to_string(pairs)
+        .map_err(|e| CookieErrorWrapper::Deserialize(serde::de::Error::custom(e)))?;
+    serde_urlencoded::from_str(&encoded).map_err(CookieErrorWrapper::Deserialize)
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedCookies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedCookies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum CookieErrorWrapper {
+    /// The Cookie header couldn't be parsed
+    Parse(CookieParseError),
+    /// A cookie listed in `ValidatedCookiesConfig::required()` wasn't sent
+    Missing(String),
+    Deserialize(serde_urlencoded::de::Error),
+}
+
+impl std::error::Error for CookieErrorWrapper {}
+
+impl Display for CookieErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CookieErrorWrapper::Parse(e) => Display::fmt(&e, f),
+            CookieErrorWrapper::Missing(name) => write!(f, "Cookie '{}' is required", name),
+            CookieErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedCookies extractor
+///
+/// # Usage
+/// Add a `ValidatedCookiesConfig` to your actix app data
+/// ```
+/// # use actix_web::web::scope;
+/// # use actix_validated_forms::cookies::ValidatedCookiesConfig;
+/// scope("/").app_data(
+///     ValidatedCookiesConfig::default()
+///         .required(&["session"])
+///         .error_handler(|e, _| actix_web::error::ErrorUnauthorized(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedCookiesConfig {
+    required: Vec<String>,
+    error_handler: Option<
+        Arc<
+            dyn Fn(ValidatedFormError<CookieErrorWrapper>, &HttpRequest) -> actix_web::Error
+                + Send
+                + Sync,
+        >,
+    >,
+}
+
+impl ValidatedCookiesConfig {
+    /// Cookies that must be sent, whether or not they are fields of the structure. A request
+    /// without one of them fails with `CookieErrorWrapper::Missing`, so it can be told apart
+    /// from a cookie with an invalid value
+    pub fn required(mut self, names: &[&str]) -> Self {
+        self.required = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from cookies that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<CookieErrorWrapper>, &HttpRequest) -> actix_web::Error
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedCookiesConfig {
+    fn default() -> Self {
+        ValidatedCookiesConfig {
+            required: Vec::new(),
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct Preferences {
+        #[validate(length(min = 2, max = 5))]
+        locale: Option<String>,
+        #[serde(default)]
+        variant: u8,
+    }
+
+    async fn route(preferences: ValidatedCookies<Preferences>) -> impl Responder {
+        HttpResponse::Ok().json(&*preferences)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::get().to(route))).await;
+        let req = test::TestRequest::with_uri("/")
+            .header("cookie", "locale=en-GB; variant=2; session=abc")
+            .to_request();
+        let resp: Preferences = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.locale.as_deref(), Some("en-GB"));
+        assert_eq!(resp.variant, 2);
+
+        let req = test::TestRequest::with_uri("/").to_request();
+        let resp: Preferences = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.locale, None);
+        assert_eq!(resp.variant, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedCookiesConfig::default()
+                        .required(&["session"])
+                        .error_handler(|e, _| match e {
+                            ValidatedFormError::Deserialization(CookieErrorWrapper::Missing(_)) => {
+                                actix_web::error::ErrorUnauthorized("login")
+                            }
+                            e => actix_web::error::ErrorBadRequest(e.to_string()),
+                        }),
+                )
+                .route("/", web::get().to(route)),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/")
+            .header("cookie", "locale=en")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        for cookie in &["session=abc; locale=english", "session=abc; variant=b"] {
+            let req = test::TestRequest::with_uri("/")
+                .header("cookie", *cookie)
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,667 @@
+//! Deserialization of url encoded data with detailed error messages
+//!
+//! The deserializer is wrapped in order to keep track of the key currently being deserialized
+//! and the type that was requested for its value, so that a failure can be reported with the
+//! key, the raw value and the expected type (rather than just e.g. "invalid digit found in
+//! string").
+//!
+//! It is also used to accept numbers written in a locale format (see `NumberFormat`).
+use crate::error::ValidatedFormError;
+use crate::numbers::NumberFormat;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use std::cell::RefCell;
+use std::fmt;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+#[derive(Default)]
+struct State {
+    key: Option<String>,
+    expected: Option<&'static str>,
+    /// The fields of the top level struct (if it is one)
+    fields: &'static [&'static str],
+    format: Option<NumberFormat>,
+}
+
+impl State {
+    fn set_key(&mut self, key: Option<String>) {
+        self.key = key;
+        self.expected = None;
+    }
+}
+
+/// Deserializes `input`, returning the state at the point of failure along with the error
+fn deserialize<T: DeserializeOwned>(
+    input: &str,
+    format: Option<NumberFormat>,
+) -> Result<T, (serde_urlencoded::de::Error, State)> {
+    let state = RefCell::new(State {
+        format,
+        ..State::default()
+    });
+    let de = serde_urlencoded::Deserializer::new(url::form_urlencoded::parse(input.as_bytes()));
+    let result = T::deserialize(Tracked { de, state: &state });
+    result.map_err(|e| (e, state.into_inner()))
+}
+
+/// Adds the key, value and expected type to the error (if they are known)
+fn describe(
+    input: &str,
+    e: serde_urlencoded::de::Error,
+    state: &State,
+) -> serde_urlencoded::de::Error {
+    let message = match (&state.key, state.expected) {
+        (Some(key), Some(expected)) => {
+            let value = url::form_urlencoded::parse(input.as_bytes())
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+            format!(
+                "Invalid value {:?} for field '{}', expected {}: {}",
+                value, key, expected, e
+            )
+        }
+        (Some(key), None) => format!("Field '{}': {}", key, e),
+        _ => return e,
+    };
+    de::Error::custom(message)
+}
+
+/// Like `serde_urlencoded::from_str()`, but with a detailed error message on failure, and
+/// numbers may be written in the given `format`
+pub(crate) fn from_str<T: DeserializeOwned>(
+    input: &str,
+    format: Option<NumberFormat>,
+) -> Result<T, serde_urlencoded::de::Error> {
+    deserialize(input, format).map_err(|(e, state)| describe(input, e, &state))
+}
+
+/// Like `from_str()`, but for key value pairs that have already been decoded
+#[cfg(feature = "form")]
+pub(crate) fn from_pairs<T: DeserializeOwned>(
+    pairs: &[(String, String)],
+    format: Option<NumberFormat>,
+) -> Result<T, serde_urlencoded::de::Error> {
+    let encoded = serde_urlencoded::to_string(pairs).map_err(de::Error::custom)?;
+    from_str(&encoded, format)
+}
+
+fn binding_error(code: &'static str, e: &serde_urlencoded::de::Error) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(e.to_string().into());
+    error
+}
+
+/// Deserializes as much of `pairs` as possible, returning an error for every field that
+/// couldn't be bound
+///
+/// Fields that fail to deserialize are removed and deserialization is retried, so that a `T` can
+/// still be returned if those fields are optional. A missing (required) field can't be
+/// recovered from. Errors that can't be attributed to a field of `T` are returned as is.
+fn bind_partial<T: DeserializeOwned>(
+    mut pairs: Vec<(String, String)>,
+    format: Option<NumberFormat>,
+) -> Result<(Option<T>, ValidationErrors), serde_urlencoded::de::Error> {
+    let mut errors = ValidationErrors::new();
+    loop {
+        let input = serde_urlencoded::to_string(&pairs).map_err(de::Error::custom)?;
+        let (e, state) = match deserialize::<T>(&input, format) {
+            Ok(value) => return Ok((Some(value), errors)),
+            Err(failure) => failure,
+        };
+        let e = describe(&input, e, &state);
+        let field = |name: &str| state.fields.iter().copied().find(|f| *f == name);
+        if let Some(key) = &state.key {
+            if let Some(field) = field(key) {
+                errors.add(field, binding_error("invalid_type", &e));
+                pairs.retain(|(k, _)| k != key);
+                continue;
+            }
+        }
+        let message = e.to_string();
+        let missing = message
+            .strip_prefix("missing field `")
+            .and_then(|m| m.strip_suffix('`'))
+            .and_then(field);
+        return match missing {
+            Some(field) => {
+                errors.add(field, binding_error("required", &e));
+                Ok((None, errors))
+            }
+            None => Err(e),
+        };
+    }
+}
+
+/// Binds what it can from `pairs`, validates it, and reports every binding and validation error
+/// together (as `ValidationErrors`)
+pub(crate) fn bind_combined<T: DeserializeOwned + Validate>(
+    pairs: Vec<(String, String)>,
+    format: Option<NumberFormat>,
+) -> Result<T, ValidatedFormError<serde_urlencoded::de::Error>> {
+    let (value, mut errors) =
+        bind_partial::<T>(pairs, format).map_err(ValidatedFormError::Deserialization)?;
+    if let Some(Err(validation)) = value.as_ref().map(Validate::validate) {
+        let failed: Vec<&str> = errors.field_errors().keys().copied().collect();
+        for (field, field_errors) in validation.field_errors() {
+            // The field failed to bind (so has been left out), any error for it is misleading
+            if failed.contains(&field) {
+                continue;
+            }
+            for error in field_errors {
+                errors.add(field, error.clone());
+            }
+        }
+    }
+    match value {
+        Some(value) if errors.is_empty() => Ok(value),
+        _ => Err(ValidatedFormError::Validation(errors)),
+    }
+}
+
+/// The top level deserializer (of the whole map)
+struct Tracked<'s, D> {
+    de: D,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, D: Deserializer<'de>> Deserializer<'de> for Tracked<'s, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_any(MapVisitor {
+            visitor,
+            state: self.state,
+        })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_map(MapVisitor {
+            visitor,
+            state: self.state,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.state.borrow_mut().fields = fields;
+        self.de.deserialize_struct(
+            name,
+            fields,
+            MapVisitor {
+                visitor,
+                state: self.state,
+            },
+        )
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_seq(MapVisitor {
+            visitor,
+            state: self.state,
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_newtype_struct(
+            name,
+            MapVisitor {
+                visitor,
+                state: self.state,
+            },
+        )
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct tuple tuple_struct enum identifier ignored_any
+    }
+}
+
+struct MapVisitor<'s, V> {
+    visitor: V,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, V: Visitor<'de>> Visitor<'de> for MapVisitor<'s, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(TrackedMap {
+            map,
+            state: self.state,
+        })
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(seq)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_newtype_struct(Tracked {
+            de: deserializer,
+            state: self.state,
+        })
+    }
+}
+
+struct TrackedMap<'s, A> {
+    map: A,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, A: MapAccess<'de>> MapAccess<'de> for TrackedMap<'s, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.map.next_key_seed(KeySeed {
+            seed,
+            state: self.state,
+        })
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let value = self.map.next_value_seed(ValueSeed {
+            seed,
+            state: self.state,
+        })?;
+        // Successfully deserialized, so any later error isn't about this key
+        self.state.borrow_mut().set_key(None);
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+struct KeySeed<'s, K> {
+    seed: K,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, K: DeserializeSeed<'de>> DeserializeSeed<'de> for KeySeed<'s, K> {
+    type Value = K::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.seed.deserialize(KeyDeserializer {
+            de: deserializer,
+            state: self.state,
+        })
+    }
+}
+
+struct KeyDeserializer<'s, D> {
+    de: D,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, D: Deserializer<'de>> Deserializer<'de> for KeyDeserializer<'s, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_any(KeyVisitor {
+            visitor,
+            state: self.state,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+/// Records the name of each key
+struct KeyVisitor<'s, V> {
+    visitor: V,
+    state: &'s RefCell<State>,
+}
+
+impl<'s, V> KeyVisitor<'s, V> {
+    fn record(&self, key: &str) {
+        self.state.borrow_mut().set_key(Some(key.to_owned()));
+    }
+}
+
+impl<'de, 's, V: Visitor<'de>> Visitor<'de> for KeyVisitor<'s, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.record(v);
+        self.visitor.visit_str(v)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        self.record(v);
+        self.visitor.visit_borrowed_str(v)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        self.record(&v);
+        self.visitor.visit_string(v)
+    }
+}
+
+struct ValueSeed<'s, S> {
+    seed: S,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, S: DeserializeSeed<'de>> DeserializeSeed<'de> for ValueSeed<'s, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.seed.deserialize(ValueDeserializer {
+            de: deserializer,
+            state: self.state,
+        })
+    }
+}
+
+/// Records the type requested for each value
+struct ValueDeserializer<'s, D> {
+    de: D,
+    state: &'s RefCell<State>,
+}
+
+impl<'s, D> ValueDeserializer<'s, D> {
+    fn expect(&self, expected: &'static str) {
+        self.state.borrow_mut().expected = Some(expected);
+    }
+}
+
+macro_rules! expect {
+    ($($method:ident => $expected:expr,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.expect($expected);
+                self.de.$method(visitor)
+            }
+        )*
+    };
+}
+
+/// Parses the number from a string, so that it can first be normalized from the `NumberFormat`
+macro_rules! number {
+    ($($method:ident => $ty:ident, $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.expect(stringify!($ty));
+                let format = self.state.borrow().format;
+                let format = match format {
+                    Some(format) => format,
+                    None => return self.de.$method(visitor),
+                };
+                let value = self.de.deserialize_string(StringVisitor)?;
+                let value = format.normalize(&value).unwrap_or(value);
+                match value.parse::<$ty>() {
+                    Ok(number) => visitor.$visit(number),
+                    Err(e) => Err(de::Error::custom(e)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 's, D: Deserializer<'de>> Deserializer<'de> for ValueDeserializer<'s, D> {
+    type Error = D::Error;
+
+    expect! {
+        deserialize_any => "any value",
+        deserialize_bool => "bool",
+        deserialize_char => "char",
+        deserialize_str => "String",
+        deserialize_string => "String",
+        deserialize_bytes => "bytes",
+        deserialize_byte_buf => "bytes",
+        deserialize_unit => "unit",
+        deserialize_seq => "sequence",
+        deserialize_map => "map",
+        deserialize_identifier => "identifier",
+        deserialize_ignored_any => "any value",
+    }
+
+    number! {
+        deserialize_i8 => i8, visit_i8,
+        deserialize_i16 => i16, visit_i16,
+        deserialize_i32 => i32, visit_i32,
+        deserialize_i64 => i64, visit_i64,
+        deserialize_i128 => i128, visit_i128,
+        deserialize_u8 => u8, visit_u8,
+        deserialize_u16 => u16, visit_u16,
+        deserialize_u32 => u32, visit_u32,
+        deserialize_u64 => u64, visit_u64,
+        deserialize_u128 => u128, visit_u128,
+        deserialize_f32 => f32, visit_f32,
+        deserialize_f64 => f64, visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_option(OptionVisitor {
+            visitor,
+            state: self.state,
+        })
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect(name);
+        self.de.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect(name);
+        self.de.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect("tuple");
+        self.de.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect(name);
+        self.de.deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect(name);
+        self.de.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect(name);
+        self.de.deserialize_enum(name, variants, visitor)
+    }
+}
+
+struct StringVisitor;
+
+impl<'de> Visitor<'de> for StringVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.to_owned())
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+}
+
+/// Tracks the type inside of an `Option`
+struct OptionVisitor<'s, V> {
+    visitor: V,
+    state: &'s RefCell<State>,
+}
+
+impl<'de, 's, V: Visitor<'de>> Visitor<'de> for OptionVisitor<'s, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.visitor.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(ValueDeserializer {
+            de: deserializer,
+            state: self.state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Query {
+        limit: i64,
+        search: Option<String>,
+        page: Option<u32>,
+    }
+
+    #[test]
+    fn test_messages() {
+        let q: Query = from_str("limit=20&page=2", None).unwrap();
+        assert_eq!(q.limit, 20);
+        assert_eq!(q.search, None);
+        assert_eq!(q.page, Some(2));
+
+        let e = from_str::<Query>("limit=abc", None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid value \"abc\" for field 'limit', expected i64: invalid digit found in string"
+        );
+        let e = from_str::<Query>("limit=1&page=-1", None).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("Invalid value \"-1\" for field 'page', expected u32"));
+        let e = from_str::<Query>("page=1", None).unwrap_err();
+        assert_eq!(e.to_string(), "missing field `limit`");
+    }
+
+    #[test]
+    fn test_number_format() {
+        let format = Some(NumberFormat::comma_decimal());
+        let q: Query = from_str("limit=1.000&page=12", format).unwrap();
+        assert_eq!(q.limit, 1000);
+        assert_eq!(q.page, Some(12));
+
+        #[derive(Deserialize)]
+        struct Price {
+            amount: f64,
+        }
+        let p: Price = from_str("amount=1.234%2C56", format).unwrap();
+        assert_eq!(p.amount, 1234.56);
+        // The usual format is still accepted
+        let p: Price = from_str("amount=0.5", format).unwrap();
+        assert_eq!(p.amount, 0.5);
+        assert!(from_str::<Price>("amount=1.234%2C56", None).is_err());
+
+        let e = from_str::<Query>("limit=1%2C5", format).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("Invalid value \"1,5\" for field 'limit', expected i64"));
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Form {
+        #[validate(length(max = 3))]
+        name: String,
+        #[validate(range(min = 1))]
+        age: Option<u32>,
+        #[validate(range(min = 1))]
+        count: Option<u32>,
+    }
+
+    fn pairs(input: &str) -> Vec<(String, String)> {
+        serde_urlencoded::from_str(input).unwrap()
+    }
+
+    fn combined_errors(input: &str) -> ValidationErrors {
+        match bind_combined::<Form>(pairs(input), None) {
+            Err(ValidatedFormError::Validation(e)) => e,
+            _ => panic!("Expected validation errors"),
+        }
+    }
+
+    #[test]
+    fn test_combined() {
+        let form: Form = bind_combined(pairs("name=abc&age=20"), None).unwrap();
+        assert_eq!(form.age, Some(20));
+
+        // Both the binding error and the validation errors are reported
+        let errors = combined_errors("name=abcdef&age=old&count=0");
+        let errors = errors.field_errors();
+        assert_eq!(errors["name"][0].code, "length");
+        assert_eq!(errors["age"][0].code, "invalid_type");
+        assert_eq!(errors["count"][0].code, "range");
+
+        let errors = combined_errors("age=old");
+        let errors = errors.field_errors();
+        assert_eq!(errors["age"][0].code, "invalid_type");
+        assert_eq!(errors["name"][0].code, "required");
+    }
+}
@@ -0,0 +1,179 @@
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::ErrorPayloadTooLarge;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::stream::{self, StreamExt};
+
+/// Combines two extractors, trying to extract `L` and falling back to `R` if that fails
+///
+/// As both extractors may need to read the request body it is buffered in memory first (up to
+/// the `ValidatedEitherConfig::limit()`), and replayed to each of them. Any limits that `L` and
+/// `R` have of their own still apply. If neither can be extracted then the error from `R` is
+/// returned.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Comment {
+///     #[validate(length(min = 1, max = 500))]
+///     text: String,
+/// }
+/// # use actix_web::{web, HttpResponse};
+/// # use actix_validated_forms::either::ValidatedEither;
+/// # use actix_validated_forms::form::ValidatedForm;
+///
+/// async fn route(
+///     comment: ValidatedEither<ValidatedForm<Comment>, web::Json<Comment>>,
+/// ) -> HttpResponse {
+///     match comment {
+///         ValidatedEither::Left(form) => { /* An HTML form submission */ }
+///         ValidatedEither::Right(json) => { /* An API request */ }
+///     }
+///     # unimplemented!(); }
+/// # }
+/// ```
+#[derive(Debug)]
+pub enum ValidatedEither<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L, R> FromRequest for ValidatedEither<L, R>
+where
+    L: FromRequest + 'static,
+    R: FromRequest + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedEitherConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let limit = req
+            .app_data::<Self::Config>()
+            .map(|c| c.limit)
+            .unwrap_or(ValidatedEitherConfig::default().limit);
+        let length = content_length(&req);
+        let payload = payload.take();
+
+        async move {
+            let body = read_body(payload, length, limit)
+                .await
+                .map_err(|e| match e {
+                    BodyError::Overflow => {
+                        ErrorPayloadTooLarge("Payload size is bigger than allowed")
+                    }
+                    BodyError::Payload(e) => e.into(),
+                })?
+                .freeze();
+            let either = match L::from_request(&req, &mut replay(body.clone())).await {
+                Ok(left) => ValidatedEither::Left(left),
+                Err(_) => {
+                    let right = R::from_request(&req, &mut replay(body))
+                        .await
+                        .map_err(Into::<actix_web::Error>::into)?;
+                    ValidatedEither::Right(right)
+                }
+            };
+            Ok::<_, actix_web::Error>(either)
+        }
+        .boxed_local()
+    }
+}
+
+fn replay(body: Bytes) -> Payload {
+    Payload::Stream(stream::once(future::ok(body)).boxed_local())
+}
+
+/// Configure the behaviour of the ValidatedEither extractor
+///
+/// # Usage
+/// Add a `ValidatedEitherConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::either::ValidatedEitherConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(ValidatedEitherConfig::default().limit(65_536));
+/// ```
+#[derive(Clone)]
+pub struct ValidatedEitherConfig {
+    limit: usize,
+}
+
+impl ValidatedEitherConfig {
+    /// Set the max size of the buffered payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl Default for ValidatedEitherConfig {
+    fn default() -> Self {
+        ValidatedEitherConfig { limit: 262_144 }
+    }
+}
+
+#[cfg(all(test, feature = "form"))]
+mod tests {
+    use super::*;
+    use crate::form::ValidatedForm;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct ExampleForm {
+        #[validate(length(min = 1, max = 5))]
+        field: String,
+    }
+
+    async fn route(
+        form: ValidatedEither<ValidatedForm<ExampleForm>, web::Json<ExampleForm>>,
+    ) -> impl Responder {
+        match form {
+            ValidatedEither::Left(form) => HttpResponse::Ok().body(format!("form {}", form.field)),
+            ValidatedEither::Right(json) => HttpResponse::Ok().body(format!("json {}", json.field)),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_either() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedEitherConfig::default().limit(64))
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let form = ExampleForm {
+            field: "abc".to_string(),
+        };
+        let req = test::TestRequest::post().set_form(&form).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "form abc");
+
+        let req = test::TestRequest::post().set_json(&form).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "json abc");
+
+        let req = test::TestRequest::post()
+            .header("content-type", "text/plain")
+            .set_payload("abc")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let form = ExampleForm {
+            field: "a".repeat(100),
+        };
+        let req = test::TestRequest::post().set_json(&form).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
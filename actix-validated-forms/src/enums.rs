@@ -0,0 +1,67 @@
+use std::fmt;
+
+/// An enum that is bound from one of a fixed set of strings, implemented by
+/// `#[derive(FormEnum)]`
+///
+/// The derive also implements `FromStr`, `Display`, `serde::Deserialize` and `serde::Serialize`,
+/// so the enum can be used as a field of a form, query or multipart form. Values are matched
+/// case-insensitively against the name of each variant (in snake_case by default) and any
+/// aliases, and an `EnumError` listing the accepted values is returned for anything else.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::FormEnum;
+/// # use actix_validated_forms::enums::FormEnum as _;
+/// #[derive(FormEnum, Debug, PartialEq)]
+/// enum Colour {
+///     Red,
+///     #[form_enum(alias = "grey")]
+///     Gray,
+///     #[form_enum(rename = "navy")]
+///     DarkBlue,
+/// }
+///
+/// assert_eq!("GREY".parse::<Colour>().unwrap(), Colour::Gray);
+/// assert_eq!(Colour::DarkBlue.as_str(), "navy");
+/// assert_eq!(
+///     "purple".parse::<Colour>().unwrap_err().to_string(),
+///     "Invalid value \"purple\", expected one of: red, gray, navy"
+/// );
+/// ```
+pub trait FormEnum: Sized {
+    /// The name of each variant
+    const VARIANTS: &'static [&'static str];
+
+    /// The name of this variant
+    fn as_str(&self) -> &'static str;
+}
+
+/// A value that didn't match any variant of a `FormEnum`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumError {
+    pub value: String,
+    /// The name of each variant
+    pub accepted: &'static [&'static str],
+}
+
+impl EnumError {
+    pub fn new(value: &str, accepted: &'static [&'static str]) -> Self {
+        EnumError {
+            value: value.to_owned(),
+            accepted,
+        }
+    }
+}
+
+impl fmt::Display for EnumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid value {:?}, expected one of: {}",
+            self.value,
+            self.accepted.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for EnumError {}
@@ -1,6 +1,7 @@
 use actix_web::ResponseError;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result};
+use std::path::PathBuf;
 use validator::ValidationErrors;
 
 #[derive(Debug)]
@@ -9,6 +10,20 @@ pub enum ValidatedFormError<T: Debug + Display> {
     Validation(ValidationErrors),
 }
 
+impl<T: Debug + Display> ValidatedFormError<T> {
+    /// Converts the `Deserialization` error into a different type
+    pub fn map_deserialization<U, F>(self, f: F) -> ValidatedFormError<U>
+    where
+        U: Debug + Display,
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            ValidatedFormError::Deserialization(e) => ValidatedFormError::Deserialization(f(e)),
+            ValidatedFormError::Validation(e) => ValidatedFormError::Validation(e),
+        }
+    }
+}
+
 impl<T: Debug + Display> Error for ValidatedFormError<T> {}
 impl<T: Debug + Display> ResponseError for ValidatedFormError<T> {}
 
@@ -20,3 +35,34 @@ impl<T: Debug + Display> Display for ValidatedFormError<T> {
         }
     }
 }
+
+/// A problem with the settings of a config, see `MultipartLoadConfig::validate()` and
+/// `ValidatedFormConfig::validate()`
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A setting has a value that could never work, e.g. a limit of zero
+    Invalid(String),
+    /// Two or more settings contradict each other
+    Inconsistent(String),
+    /// A directory that files are to be written to doesn't exist
+    NotADirectory(&'static str, PathBuf),
+}
+
+impl Error for ConfigError {}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ConfigError::Invalid(e) | ConfigError::Inconsistent(e) => {
+                write!(f, "Invalid config: {}", e)
+            }
+            ConfigError::NotADirectory(setting, dir) => {
+                write!(
+                    f,
+                    "Invalid config: {} {:?} is not a directory",
+                    setting, dir
+                )
+            }
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use crate::error::ValidatedFormError;
+use crate::error::{ConfigError, ValidatedFormError};
+use crate::honeypot::Honeypot;
+use crate::method::{is_invalid_override, MethodOverride, METHOD_FIELD};
+use crate::numbers::NumberFormat;
 use actix_web::dev::{Payload, UrlEncoded};
 use actix_web::error::UrlencodedError;
 use actix_web::{FromRequest, HttpRequest};
 use futures::future::{self, FutureExt, LocalBoxFuture};
 use futures::TryFutureExt;
 use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 use std::{fmt, ops};
 use validator::Validate;
@@ -74,26 +78,103 @@ where
             .app_data::<Self::Config>()
             .map(|c| c.clone())
             .unwrap_or(Self::Config::default());
+        let number_format = config.number_format;
+        let combine_errors = config.combine_errors;
+        let detailed = config.diagnostics || number_format.is_some();
+        let method_override = config.method_override;
+        let honeypot = config.honeypot.clone();
 
-        UrlEncoded::new(req, payload)
-            .limit(config.limit)
-            .map_err(move |e| ValidatedFormError::Deserialization(e))
-            .and_then(|c: T| match c.validate() {
-                Ok(_) => future::ok(c),
-                Err(e) => future::err(ValidatedFormError::Validation(e)),
-            })
-            .map_ok(ValidatedForm)
-            .map_err(move |e| {
-                if let Some(err) = config.error_handler {
-                    (*err)(e, &req2)
-                } else {
-                    e.into()
-                }
+        // Resolves to None if the honeypot field was filled in
+        let validated = if combine_errors || detailed || method_override || honeypot.is_some() {
+            let req3 = req.clone();
+            let honeypot = honeypot.clone();
+            UrlEncoded::<Vec<(String, String)>>::new(req, payload)
+                .limit(config.limit)
+                .map_err(|e| ValidatedFormError::Deserialization(FormErrorWrapper::Urlencoded(e)))
+                .and_then(move |mut pairs| {
+                    if let Some(honeypot) = honeypot {
+                        if pairs.iter().any(|(k, v)| honeypot.is_filled(k, v)) {
+                            return future::ok(None);
+                        }
+                        pairs.retain(|(k, _)| k != honeypot.field());
+                    }
+                    if method_override {
+                        if let Err(e) = take_method_override(&req3, &mut pairs) {
+                            return future::err(e);
+                        }
+                    }
+                    future::ready(
+                        bind_pairs::<T>(pairs, combine_errors, detailed, number_format).map(Some),
+                    )
+                })
+                .boxed_local()
+        } else {
+            UrlEncoded::new(req, payload)
+                .limit(config.limit)
+                .map_err(|e| ValidatedFormError::Deserialization(FormErrorWrapper::Urlencoded(e)))
+                .and_then(|c: T| match c.validate() {
+                    Ok(_) => future::ok(Some(c)),
+                    Err(e) => future::err(ValidatedFormError::Validation(e)),
+                })
+                .boxed_local()
+        };
+
+        validated
+            .map(move |res| match res {
+                Ok(Some(form)) => Ok(ValidatedForm(form)),
+                Ok(None) => Err(honeypot.unwrap().error(&req2)),
+                Err(e) => Err(match config.error_handler {
+                    Some(err) => (*err)(e, &req2),
+                    None if is_invalid_override(&e) => actix_web::error::ErrorBadRequest(e),
+                    None => e.into(),
+                }),
             })
             .boxed_local()
     }
 }
 
+/// Removes the `_method` field from the form, adding the method to the request extensions
+fn take_method_override(
+    req: &HttpRequest,
+    pairs: &mut Vec<(String, String)>,
+) -> Result<(), ValidatedFormError<FormErrorWrapper>> {
+    let value = pairs
+        .iter()
+        .rev()
+        .find(|(k, _)| k == METHOD_FIELD)
+        .map(|(_, v)| v.clone());
+    pairs.retain(|(k, _)| k != METHOD_FIELD);
+    if let Some(value) = value {
+        let method = MethodOverride::parse(&value).map_err(ValidatedFormError::Validation)?;
+        req.extensions_mut().insert(method);
+    }
+    Ok(())
+}
+
+/// Deserializes and validates a form that has already been decoded into key value pairs
+fn bind_pairs<T: DeserializeOwned + Validate>(
+    pairs: Vec<(String, String)>,
+    combine_errors: bool,
+    detailed: bool,
+    number_format: Option<NumberFormat>,
+) -> Result<T, ValidatedFormError<FormErrorWrapper>> {
+    if combine_errors {
+        return crate::diagnostics::bind_combined::<T>(pairs, number_format)
+            .map_err(|e| e.map_deserialization(FormErrorWrapper::Deserialize));
+    }
+    let deserialized = if detailed {
+        crate::diagnostics::from_pairs::<T>(&pairs, number_format)
+    } else {
+        serde_urlencoded::to_string(&pairs)
+            .map_err(serde::de::Error::custom)
+            .and_then(|encoded| serde_urlencoded::from_str::<T>(&encoded))
+    };
+    let c = deserialized
+        .map_err(|e| ValidatedFormError::Deserialization(FormErrorWrapper::Deserialize(e)))?;
+    c.validate().map_err(ValidatedFormError::Validation)?;
+    Ok(c)
+}
+
 impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedForm<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -106,6 +187,25 @@ impl<T: Validate + fmt::Display> fmt::Display for ValidatedForm<T> {
     }
 }
 
+#[derive(Debug)]
+pub enum FormErrorWrapper {
+    Urlencoded(UrlencodedError),
+    /// A deserialization error, when the form was first decoded into key value pairs (it is
+    /// detailed when `ValidatedFormConfig::diagnostics` is enabled)
+    Deserialize(serde_urlencoded::de::Error),
+}
+
+impl std::error::Error for FormErrorWrapper {}
+
+impl Display for FormErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FormErrorWrapper::Urlencoded(e) => Display::fmt(&e, f),
+            FormErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
 /// Configure the behaviour of the ValidatedForm extractor
 ///
 /// # Usage
@@ -139,8 +239,13 @@ impl<T: Validate + fmt::Display> fmt::Display for ValidatedForm<T> {
 #[derive(Clone)]
 pub struct ValidatedFormConfig {
     limit: usize,
+    diagnostics: bool,
+    combine_errors: bool,
+    number_format: Option<NumberFormat>,
+    method_override: bool,
+    honeypot: Option<Honeypot>,
     error_handler:
-        Option<Rc<dyn Fn(ValidatedFormError<UrlencodedError>, &HttpRequest) -> actix_web::Error>>,
+        Option<Rc<dyn Fn(ValidatedFormError<FormErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
 }
 
 impl ValidatedFormConfig {
@@ -150,11 +255,90 @@ impl ValidatedFormConfig {
         self
     }
 
+    /// Report which field failed to deserialize, its value and the expected type, e.g.
+    /// `Invalid value "abc" for field 'age', expected u32: invalid digit found in string`
+    ///
+    /// This has some overhead so is disabled by default.
+    pub fn diagnostics(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled;
+        self
+    }
+
+    /// Report deserialization and validation errors together, instead of failing on the first
+    /// field that can't be deserialized (see `ValidatedQueryConfig::combine_errors()`)
+    pub fn combine_errors(mut self, enabled: bool) -> Self {
+        self.combine_errors = enabled;
+        self
+    }
+
+    /// Accept numeric fields written in `format` (e.g. `1.234,56`), as well as the usual format
+    ///
+    /// Enables `diagnostics()`, which is used to parse the numbers.
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = Some(format);
+        self
+    }
+
+    /// Recognise a `_method` field overriding the method of the request (see `MethodOverride`)
+    pub fn method_override(mut self, enabled: bool) -> Self {
+        self.method_override = enabled;
+        self
+    }
+
+    /// Reject forms that fill in the `honeypot` field (see `Honeypot`)
+    pub fn honeypot(mut self, honeypot: Honeypot) -> Self {
+        self.honeypot = Some(honeypot);
+        self
+    }
+
+    /// Checks that the settings could accept a form, call this at startup so that a
+    /// misconfiguration is reported straight away (see `MultipartLoadConfig::validate()`)
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.limit == 0 {
+            return Err(ConfigError::Invalid(
+                "limit must be at least 1 byte".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A description of the effective limit and settings, to log at startup
+    pub fn summarize(&self) -> String {
+        format!(
+            "limit: {} bytes, diagnostics: {}, combine_errors: {}, method_override: {}, \
+             honeypot: {}",
+            self.limit,
+            self.diagnostics || self.number_format.is_some(),
+            self.combine_errors,
+            self.method_override,
+            self.honeypot.as_ref().map_or("none", |h| h.field()),
+        )
+    }
+
     /// Sets a custom error handler to convert the error (arising from a form that failed to
     /// either deserialize or validate) into a different type
+    ///
+    /// A detailed deserialization error (see `diagnostics()`) is passed as
+    /// `UrlencodedError::Parse`, use `detailed_error_handler()` to receive it instead.
     pub fn error_handler<F>(mut self, f: F) -> Self
     where
         F: Fn(ValidatedFormError<UrlencodedError>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(move |e, req| {
+            let e = e.map_deserialization(|e| match e {
+                FormErrorWrapper::Urlencoded(e) => e,
+                FormErrorWrapper::Deserialize(_) => UrlencodedError::Parse,
+            });
+            f(e, req)
+        }));
+        self
+    }
+
+    /// Sets a custom error handler like `error_handler()`, but which also receives the detailed
+    /// deserialization errors, when the form was first decoded into key value pairs
+    pub fn detailed_error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<FormErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
     {
         self.error_handler = Some(Rc::new(f));
         self
@@ -165,6 +349,11 @@ impl Default for ValidatedFormConfig {
     fn default() -> Self {
         ValidatedFormConfig {
             limit: 16384,
+            diagnostics: false,
+            combine_errors: false,
+            number_format: None,
+            method_override: false,
+            honeypot: None,
             error_handler: None,
         }
     }
@@ -233,4 +422,147 @@ mod tests {
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
     }
+
+    #[derive(Debug, Deserialize, Validate)]
+    #[allow(dead_code)]
+    pub struct NumberForm {
+        number: u32,
+    }
+
+    async fn number_route(_: ValidatedForm<NumberForm>) -> impl Responder {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn test_diagnostics() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedFormConfig::default().diagnostics(true))
+                .route("/", web::get().to(number_route)),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/")
+            .set_form(&[("number", "abc")])
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(
+            resp,
+            "Invalid value \"abc\" for field 'number', expected u32: invalid digit found in string"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_diagnostics_error_handlers() {
+        let mut app = test::init_service(
+            App::new()
+                .service(
+                    web::resource("/plain")
+                        .app_data(
+                            ValidatedFormConfig::default()
+                                .diagnostics(true)
+                                .error_handler(|e, _| match e {
+                                    ValidatedFormError::Deserialization(UrlencodedError::Parse) => {
+                                        Teapot {}.into()
+                                    }
+                                    e => actix_web::error::ErrorBadRequest(e.to_string()),
+                                }),
+                        )
+                        .route(web::get().to(number_route)),
+                )
+                .service(
+                    web::resource("/detailed")
+                        .app_data(
+                            ValidatedFormConfig::default()
+                                .diagnostics(true)
+                                .detailed_error_handler(|e, _| {
+                                    actix_web::error::ErrorBadRequest(format!("detailed: {}", e))
+                                }),
+                        )
+                        .route(web::get().to(number_route)),
+                ),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/plain")
+            .set_form(&[("number", "abc")])
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+        let req = test::TestRequest::with_uri("/detailed")
+            .set_form(&[("number", "abc")])
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(
+            resp,
+            "detailed: Invalid value \"abc\" for field 'number', expected u32: invalid digit found \
+             in string"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_method_override() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedFormConfig::default().method_override(true))
+                .route(
+                    "/",
+                    web::post().to(|req: HttpRequest, form: ValidatedForm<ExampleForm>| {
+                        let method = crate::method::effective_method(&req);
+                        async move { HttpResponse::Ok().body(format!("{} {}", method, form.field)) }
+                    }),
+                ),
+        )
+        .await;
+        let send = |body: &'static str| {
+            test::TestRequest::post()
+                .uri("/")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .set_payload(body)
+                .to_request()
+        };
+        let resp = test::read_response(&mut app, send("field=abc&_method=delete")).await;
+        assert_eq!(resp, "DELETE abc");
+        let resp = test::read_response(&mut app, send("field=abc")).await;
+        assert_eq!(resp, "POST abc");
+        let resp = test::call_service(&mut app, send("field=abc&_method=GET")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_honeypot() {
+        let honeypot = Honeypot::new("website").response(|_| HttpResponse::Ok().body("thanks"));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedFormConfig::default().honeypot(honeypot))
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let send = |body: &'static str| {
+            test::TestRequest::post()
+                .uri("/")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .set_payload(body)
+                .to_request()
+        };
+        let resp: ExampleForm =
+            test::read_response_json(&mut app, send("field=abc&website=")).await;
+        assert_eq!(resp.field, "abc");
+        let resp = test::read_response(&mut app, send("field=abc&website=spam")).await;
+        assert_eq!(resp, "thanks");
+        // The honeypot is checked before validation
+        let resp = test::read_response(&mut app, send("field=toolong&website=spam")).await;
+        assert_eq!(resp, "thanks");
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(ValidatedFormConfig::default().validate().is_ok());
+        let err = ValidatedFormConfig::default()
+            .limit(0)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid config: limit must be at least 1 byte"
+        );
+    }
 }
@@ -0,0 +1,197 @@
+use actix_web::http::{HeaderMap, StatusCode};
+use actix_web::ResponseError;
+use err_derive::Error;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use validator::{Validate, ValidationErrors};
+
+/// An error encountered while folding the values of a header
+#[derive(Debug, Error)]
+pub enum HeaderError {
+    /// The header contained characters that aren't visible ASCII
+    #[error(display = "Header '{}' is not a valid string", _0)]
+    NotString(String),
+    #[error(display = "Header '{}' item {} couldn't be parsed: {}", _0, _1, _2)]
+    Parse(String, usize, String),
+    #[error(display = "Header '{}' item {} is invalid: {}", _0, _1, _2)]
+    Validation(String, usize, ValidationErrors),
+}
+
+impl ResponseError for HeaderError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Splits a header value on commas, ignoring commas inside of quoted strings
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    let mut escaped = false;
+    value
+        .split(move |c| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => return true,
+                _ => {}
+            }
+            false
+        })
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// Folds every occurrence of a (possibly repeated) header into a single list of its comma
+/// separated values, in the order they were received
+///
+/// `Accept-Language: en-GB, en;q=0.9` and `Accept-Language: fr` become
+/// `["en-GB", "en;q=0.9", "fr"]`
+pub fn fold_header<'a>(headers: &'a HeaderMap, name: &str) -> Result<Vec<&'a str>, HeaderError> {
+    let mut items = Vec::new();
+    for value in headers.get_all(name) {
+        let value = value
+            .to_str()
+            .map_err(|_| HeaderError::NotString(name.into()))?;
+        items.extend(split_list(value));
+    }
+    Ok(items)
+}
+
+/// Folds a header (see `fold_header()`), parsing and validating every item
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use validator::Validate;
+/// # use actix_web::{HttpRequest, HttpResponse, Error};
+/// # use actix_validated_forms::headers::header_list;
+/// #[derive(Validate)]
+/// struct Tag {
+///     #[validate(length(min = 1, max = 16))]
+///     name: String,
+/// }
+/// impl std::str::FromStr for Tag {
+///     type Err = std::convert::Infallible;
+///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+///         Ok(Tag { name: s.into() })
+///     }
+/// }
+///
+/// async fn route(req: HttpRequest) -> Result<HttpResponse, Error> {
+///     let tags: Vec<Tag> = header_list(req.headers(), "x-tags")?;
+///     # unimplemented!()
+/// }
+/// # }
+/// ```
+pub fn header_list<T>(headers: &HeaderMap, name: &str) -> Result<Vec<T>, HeaderError>
+where
+    T: FromStr + Validate,
+    T::Err: std::fmt::Display,
+{
+    fold_header(headers, name)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| parse_item(name, i, item))
+        .collect()
+}
+
+/// Folds a header of items with optional quality values (such as `Accept-Language`) and returns
+/// the parsed and validated items, most preferred first
+///
+/// Items with the same quality keep the order they were received in, and items with a quality
+/// of 0 (i.e. "not acceptable") are removed. Only the part of each item before the first `;` is
+/// parsed into `T`.
+pub fn quality_list<T>(headers: &HeaderMap, name: &str) -> Result<Vec<T>, HeaderError>
+where
+    T: FromStr + Validate,
+    T::Err: std::fmt::Display,
+{
+    let mut items = Vec::new();
+    for (i, item) in fold_header(headers, name)?.into_iter().enumerate() {
+        let mut params = item.split(';').map(str::trim);
+        let value = params.next().unwrap_or_default();
+        let quality = params
+            .filter_map(|p| p.strip_prefix("q="))
+            .last()
+            .map(|q| {
+                q.parse::<f32>()
+                    .map_err(|e| HeaderError::Parse(name.into(), i, e.to_string()))
+            })
+            .transpose()?
+            .unwrap_or(1.0);
+        if quality > 0.0 {
+            items.push((parse_item(name, i, value)?, quality));
+        }
+    }
+    items.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    Ok(items.into_iter().map(|(item, _)| item).collect())
+}
+
+fn parse_item<T>(name: &str, index: usize, item: &str) -> Result<T, HeaderError>
+where
+    T: FromStr + Validate,
+    T::Err: std::fmt::Display,
+{
+    let item =
+        T::from_str(item).map_err(|e| HeaderError::Parse(name.into(), index, e.to_string()))?;
+    item.validate()
+        .map_err(|e| HeaderError::Validation(name.into(), index, e))?;
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderValue, ACCEPT_LANGUAGE};
+    use std::convert::Infallible;
+
+    #[derive(Debug, PartialEq, Validate)]
+    struct Language {
+        #[validate(length(min = 2, max = 8))]
+        tag: String,
+    }
+
+    impl FromStr for Language {
+        type Err = Infallible;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(Language { tag: s.into() })
+        }
+    }
+
+    fn header_map(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for v in values {
+            headers.append(ACCEPT_LANGUAGE, HeaderValue::from_static(v));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_fold() {
+        let headers = header_map(&[" fr ,,\"a,b\", en-GB"]);
+        let folded = fold_header(&headers, "accept-language").unwrap();
+        assert_eq!(folded, vec!["fr", "\"a,b\"", "en-GB"]);
+
+        // The order of separate header lines isn't preserved by the `HeaderMap`
+        let headers = header_map(&["en-GB, en;q=0.9", " fr ,,\"a,b\""]);
+        let mut folded = fold_header(&headers, "accept-language").unwrap();
+        folded.sort();
+        assert_eq!(folded, vec!["\"a,b\"", "en-GB", "en;q=0.9", "fr"]);
+    }
+
+    #[test]
+    fn test_quality_list() {
+        let headers = header_map(&["fr;q=0.5, en-GB", "de;q=0, en;q=0.9, es;q=0.4"]);
+        let languages: Vec<Language> = quality_list(&headers, "accept-language").unwrap();
+        let tags: Vec<&str> = languages.iter().map(|l| l.tag.as_str()).collect();
+        assert_eq!(tags, vec!["en-GB", "en", "fr", "es"]);
+
+        let headers = header_map(&["en, this-is-too-long"]);
+        match header_list::<Language>(&headers, "accept-language") {
+            Err(HeaderError::Validation(_, 1, _)) => {}
+            _ => panic!("expected validation error"),
+        }
+    }
+}
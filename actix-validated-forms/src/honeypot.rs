@@ -0,0 +1,74 @@
+use actix_web::error::InternalError;
+use actix_web::{HttpRequest, HttpResponse};
+use std::fmt;
+use std::rc::Rc;
+
+/// A hidden field of a form that people leave empty, but spam bots fill in
+///
+/// Set using `ValidatedFormConfig::honeypot()` or `ValidatedMultipartFormConfig::honeypot()`, if
+/// the field is present and non-empty the extractor fails with the honeypot response (`400 Bad
+/// Request` by default) instead of the form, without calling the error handler. The field is
+/// removed from the form before it is deserialized, so it doesn't need to be part of your
+/// struct.
+///
+/// A multipart form stops being read as soon as the honeypot field is found, so as long as the
+/// field comes before any file inputs in the HTML form no files will be written to disk.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::form::ValidatedFormConfig;
+/// # use actix_validated_forms::honeypot::Honeypot;
+/// # use actix_web::HttpResponse;
+/// // Pretend the submission succeeded, so the bot doesn't try again
+/// let honeypot = Honeypot::new("website").response(|_| HttpResponse::Ok().finish());
+/// let config = ValidatedFormConfig::default().honeypot(honeypot);
+/// ```
+#[derive(Clone)]
+pub struct Honeypot {
+    field: String,
+    response: Option<Rc<dyn Fn(&HttpRequest) -> HttpResponse>>,
+}
+
+impl Honeypot {
+    /// Use the field named `field` as the honeypot
+    pub fn new(field: &str) -> Self {
+        Honeypot {
+            field: field.to_owned(),
+            response: None,
+        }
+    }
+
+    /// Sets the response sent when the honeypot field is filled in
+    pub fn response<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + 'static,
+    {
+        self.response = Some(Rc::new(f));
+        self
+    }
+
+    /// The name of the honeypot field
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub(crate) fn is_filled(&self, name: &str, value: &str) -> bool {
+        name == self.field && !value.trim().is_empty()
+    }
+
+    pub(crate) fn error(&self, req: &HttpRequest) -> actix_web::Error {
+        let response = match &self.response {
+            Some(f) => (*f)(req),
+            None => HttpResponse::BadRequest().finish(),
+        };
+        InternalError::from_response("The honeypot field was filled in", response).into()
+    }
+}
+
+impl fmt::Debug for Honeypot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Honeypot")
+            .field("field", &self.field)
+            .finish()
+    }
+}
@@ -5,6 +5,33 @@
 //! that generates temporary files on disk using the [tempfile] crate with similar
 //! behaviour to the php [$_FILES] variable in php
 //!
+//! # Features
+//! Each extractor is behind its own cargo feature, only `form`, `query` and `multipart` are
+//! enabled by default (disable default features if you don't need the upload stack):
+//! - `form`: `ValidatedForm`
+//! - `query`: `ValidatedQuery`
+//! - `path`: `ValidatedPath` and `ValidatedPathQuery`
+//! - `cookies`: `ValidatedCookies`
+//! - `body`: `ValidatedBody`, accepting a form or JSON (or any of the formats below), and
+//!   `ValidatedRequest`, combining it with the query string
+//! - `text`: `ValidatedText`
+//! - `ndjson`: `ValidatedNdjsonStream`
+//! - `cbor`: `ValidatedCbor`
+//! - `xml`: `ValidatedXml`
+//! - `toml`: `ValidatedToml`
+//! - `qs`: `ValidatedQsForm` and `ValidatedQsQuery`, for nested field names
+//! - `pagination`: `ValidatedPagination`
+//! - `headers`: multi-value header folding
+//! - `multipart`: the multipart form extractor and everything to do with file uploads
+//! - `derive`: `#[derive(FromMultipart)]`, `#[derive(DescribeForm)]` and `#[derive(FormEnum)]`
+//!   (enables `multipart`)
+//! - `encryption`: encryption of file uploads before they are written to disk (enables
+//!   `multipart`)
+//! - `relay`: conversion of multipart forms into `reqwest` forms (enables `multipart`)
+//! - `images`: checking the format and dimensions of uploaded images (enables `multipart`)
+//! - `io-uring`: writing file uploads using io-uring on Linux (enables `multipart`)
+//! - `testing`: `TestForm` for testing your multipart forms (enables `multipart`)
+//!
 //! [Actix-Web]: https://github.com/actix/actix-web
 //! [validator]: https://github.com/Keats/validator
 //! [tempfile]: https://github.com/Stebalien/tempfile
@@ -14,14 +41,75 @@
 #[macro_use]
 extern crate validator_derive;
 
+/// Validated extractor for a HTTP request body in a format chosen by its Content-Type
+#[cfg(feature = "body")]
+pub mod body;
+/// Validated extractor for an application/cbor HTTP request body
+#[cfg(feature = "cbor")]
+pub mod cbor;
+/// Validated extractor for the cookies of a request
+#[cfg(feature = "cookies")]
+pub mod cookies;
+#[cfg(any(feature = "form", feature = "query"))]
+mod diagnostics;
+/// Combining two extractors, falling back to the second if the first fails
+pub mod either;
+/// Binding of enums from strings, with errors listing the accepted values
+#[cfg(feature = "derive")]
+pub mod enums;
 pub mod error;
 /// Validated extractor for an application/x-www-form-urlencoded HTTP request body
+#[cfg(feature = "form")]
 pub mod form;
+/// Folding and validation of multi-value HTTP headers
+#[cfg(feature = "headers")]
+pub mod headers;
+/// Rejection of forms that fill in a hidden honeypot field
+#[cfg(any(feature = "form", feature = "multipart"))]
+pub mod honeypot;
+/// Overriding the method of a form submission using a `_method` field
+#[cfg(any(feature = "form", feature = "multipart"))]
+pub mod method;
 /// Validated extractor for a multipart/form-data HTTP request body
+#[cfg(feature = "multipart")]
 pub mod multipart;
+/// Streaming extractor for an application/x-ndjson HTTP request body, validating each line
+#[cfg(feature = "ndjson")]
+pub mod ndjson;
+/// Parsing of numbers written in a locale format
+#[cfg(any(feature = "form", feature = "query"))]
+pub mod numbers;
+/// Validated extractor for the pagination and sorting parameters of a query string
+#[cfg(feature = "pagination")]
+pub mod pagination;
+/// Validated extractor for the parameters of the matched path
+#[cfg(feature = "path")]
+pub mod path;
+mod payload;
+/// Validated extractors for forms with nested field names, using serde_qs
+#[cfg(feature = "qs")]
+pub mod qs;
 /// Validated extractor for a Url Encoded HTTP Query String
+#[cfg(feature = "query")]
 pub mod query;
+/// Validated extractor combining the query string and the body of a request
+#[cfg(feature = "body")]
+pub mod request;
+/// Validated extractor for a text/plain HTTP request body
+#[cfg(feature = "text")]
+pub mod text;
+/// Validated extractor for an application/toml HTTP request body
+#[cfg(feature = "toml")]
+pub mod toml;
+/// Validated extractor for an application/xml HTTP request body
+#[cfg(feature = "xml")]
+pub mod xml;
 
+#[cfg(feature = "multipart")]
+pub use encoding_rs;
+#[doc(hidden)]
+pub use serde;
+#[cfg(feature = "multipart")]
 pub use tempfile;
 pub use validator;
 
@@ -32,4 +120,4 @@ pub use validator;
 extern crate actix_validated_forms_derive;
 #[cfg(feature = "derive")]
 #[doc(hidden)]
-pub use actix_validated_forms_derive::FromMultipart;
+pub use actix_validated_forms_derive::{DescribeForm, FormEnum, FromMultipart};
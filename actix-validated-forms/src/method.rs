@@ -0,0 +1,90 @@
+use crate::error::ValidatedFormError;
+use actix_web::http::Method;
+use actix_web::HttpRequest;
+use std::fmt::{Debug, Display};
+use validator::{ValidationError, ValidationErrors};
+
+/// The name of the field used to override the method of a form
+pub const METHOD_FIELD: &str = "_method";
+
+/// The method requested by the `_method` field of a form, the way Rails and Laravel let HTML forms
+/// (which can only be submitted with GET or POST) target PUT, PATCH and DELETE handlers
+///
+/// When enabled using `ValidatedFormConfig::method_override()` (or the multipart equivalent) the
+/// `_method` field is removed from the form before it is deserialized, and if present is added to
+/// the request extensions. Only `PUT`, `PATCH` and `DELETE` (in any case) are accepted, anything
+/// else is rejected with a 400 Bad Request (or passed to the `error_handler` as a validation
+/// error of the `_method` field). Routing happens before the body is read, so the handler for the POST
+/// has to dispatch on the overridden method itself.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use actix_web::{http::Method, HttpRequest, HttpResponse};
+/// # use actix_validated_forms::form::ValidatedForm;
+/// # use actix_validated_forms::method::effective_method;
+/// # use validator::Validate;
+/// # #[derive(serde::Deserialize, Validate)] struct ArticleForm {}
+/// async fn article(req: HttpRequest, form: ValidatedForm<ArticleForm>) -> HttpResponse {
+///     match effective_method(&req) {
+///         Method::DELETE => { /* delete the article */ }
+///         _ => { /* update the article */ }
+///     }
+///     # unimplemented!()
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodOverride(pub Method);
+
+impl MethodOverride {
+    pub(crate) fn parse(value: &str) -> Result<Self, ValidationErrors> {
+        let method = match value.trim().to_ascii_uppercase().as_str() {
+            "PUT" => Method::PUT,
+            "PATCH" => Method::PATCH,
+            "DELETE" => Method::DELETE,
+            _ => {
+                let mut error = ValidationError::new("method");
+                error.add_param("value".into(), &value);
+                error.add_param("accepted".into(), &["PUT", "PATCH", "DELETE"]);
+                let mut errors = ValidationErrors::new();
+                errors.add(METHOD_FIELD, error);
+                return Err(errors);
+            }
+        };
+        Ok(MethodOverride(method))
+    }
+}
+
+/// Whether a form failed because of its `_method` field, which is a bad request rather than a form
+/// that failed validation (it is still passed to the `error_handler` as a validation error)
+pub(crate) fn is_invalid_override<T: Debug + Display>(error: &ValidatedFormError<T>) -> bool {
+    match error {
+        ValidatedFormError::Validation(e) => e.errors().contains_key(METHOD_FIELD),
+        _ => false,
+    }
+}
+
+/// The method requested by the `_method` field of the form (if there was one), otherwise the
+/// method of the request
+///
+/// The form must already have been extracted, with `method_override` enabled.
+pub fn effective_method(req: &HttpRequest) -> Method {
+    match req.extensions().get::<MethodOverride>() {
+        Some(m) => m.0.clone(),
+        None => req.method().clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(MethodOverride::parse("delete").unwrap().0, Method::DELETE);
+        assert_eq!(MethodOverride::parse(" Put ").unwrap().0, Method::PUT);
+        let errors = MethodOverride::parse("GET").unwrap_err();
+        assert_eq!(errors.field_errors()[METHOD_FIELD][0].code, "method");
+    }
+}
@@ -0,0 +1,323 @@
+use super::MultipartField;
+use actix_web::error::BlockingError;
+use actix_web::web;
+use err_derive::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+/// Thresholds for the archives inspected by `MultipartLoadConfig::inspect_archives()`
+#[derive(Clone, Debug)]
+pub struct ArchiveLimits {
+    max_entries: u64,
+    max_ratio: u64,
+    max_total_size: u64,
+    max_nested: Option<u64>,
+}
+
+impl ArchiveLimits {
+    /// Maximum entries (files and directories) in an archive - default 10,000
+    pub fn max_entries(mut self, max: u64) -> Self {
+        self.max_entries = max;
+        self
+    }
+
+    /// Maximum ratio of the total size of the contents of an archive to the size of the archive
+    /// itself - default 100
+    pub fn max_ratio(mut self, max: u64) -> Self {
+        self.max_ratio = max;
+        self
+    }
+
+    /// Maximum total bytes of the contents of an archive - default 1 GiB
+    pub fn max_total_size(mut self, max: u64) -> Self {
+        self.max_total_size = max;
+        self
+    }
+
+    /// Maximum entries of an archive that are themselves archives (judged by their name, e.g.
+    /// `inner.zip`) - by default unlimited
+    pub fn max_nested(mut self, max: u64) -> Self {
+        self.max_nested = Some(max);
+        self
+    }
+
+    fn check(&self, summary: &ArchiveSummary, size: u64) -> Result<(), ArchiveError> {
+        if summary.entries > self.max_entries {
+            return Err(ArchiveError::TooManyEntries(self.max_entries));
+        }
+        if summary.total_size > self.max_total_size {
+            return Err(ArchiveError::TooLarge(self.max_total_size));
+        }
+        if summary.total_size > size.saturating_mul(self.max_ratio) {
+            return Err(ArchiveError::Ratio(self.max_ratio));
+        }
+        match self.max_nested {
+            Some(max) if summary.nested > max => Err(ArchiveError::Nested(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        ArchiveLimits {
+            max_entries: 10_000,
+            max_ratio: 100,
+            max_total_size: 1_073_741_824,
+            max_nested: None,
+        }
+    }
+}
+
+/// Why an archive was rejected by `inspect_archive()`
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error(display = "Archive has more than {} entries", _0)]
+    TooManyEntries(u64),
+    #[error(display = "Archive expands to more than {} times its size", _0)]
+    Ratio(u64),
+    #[error(display = "Archive expands to more than {} bytes", _0)]
+    TooLarge(u64),
+    #[error(display = "Archive contains more than {} other archives", _0)]
+    Nested(u64),
+    /// The records of the archive couldn't be read
+    #[error(display = "Archive is malformed")]
+    Malformed,
+    #[error(display = "{}", _0)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => ArchiveError::Malformed,
+            _ => ArchiveError::Io(e),
+        }
+    }
+}
+
+/// The contents of an archive, as declared by its records
+#[derive(Debug, Default, PartialEq)]
+pub struct ArchiveSummary {
+    pub entries: u64,
+    pub total_size: u64,
+    /// The entries that are themselves archives
+    pub nested: u64,
+}
+
+impl ArchiveSummary {
+    fn add(&mut self, name: &[u8], size: u64) {
+        const EXTENSIONS: &[&[u8]] = &[
+            b".zip", b".jar", b".gz", b".tgz", b".tar", b".bz2", b".xz", b".7z", b".rar",
+        ];
+        self.entries += 1;
+        self.total_size = self.total_size.saturating_add(size);
+        let name = name.to_ascii_lowercase();
+        if EXTENSIONS.iter().any(|e| name.ends_with(e)) {
+            self.nested += 1;
+        }
+    }
+}
+
+/// Checks a file for the characteristics of a decompression bomb, if it is a zip, tar or gzip
+/// archive, returning `None` if it isn't one
+///
+/// Nothing is decompressed, instead the sizes that the archive's own records declare for its
+/// contents are checked against the `limits`. A gzip file declares only its (last member's)
+/// uncompressed size, so a `.tar.gz` is checked as a single entry.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{inspect_archive, ArchiveLimits};
+/// # use std::io::Cursor;
+/// let not_an_archive = Cursor::new(b"Hello world");
+/// assert!(inspect_archive(not_an_archive, &ArchiveLimits::default()).unwrap().is_none());
+/// ```
+pub fn inspect_archive<R: Read + Seek>(
+    mut reader: R,
+    limits: &ArchiveLimits,
+) -> Result<Option<ArchiveSummary>, ArchiveError> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = Vec::with_capacity(512);
+    (&mut reader).take(512).read_to_end(&mut header)?;
+    let summary = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        zip_summary(&mut reader, size, limits)?
+    } else if header.starts_with(b"\x1f\x8b") {
+        gzip_summary(&mut reader, size)?
+    } else if header.get(257..262) == Some(&b"ustar"[..]) {
+        tar_summary(&mut reader, size, limits)?
+    } else {
+        return Ok(None);
+    };
+    limits.check(&summary, size)?;
+    Ok(Some(summary))
+}
+
+/// Inspects the file of a part once it has been loaded
+pub(crate) async fn inspect_field(
+    field: &MultipartField,
+    limits: &ArchiveLimits,
+) -> Result<(), ArchiveError> {
+    let path = match field {
+        MultipartField::File(f) => f.file.path().to_owned(),
+        MultipartField::Saved(f) => f.path.clone(),
+        MultipartField::Memory(f) => {
+            return inspect_archive(Cursor::new(&f.data[..]), limits).map(drop)
+        }
+        _ => return Ok(()),
+    };
+    let limits = limits.clone();
+    web::block(move || inspect_archive(File::open(path)?, &limits).map(drop))
+        .await
+        .map_err(|e| match e {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => {
+                ArchiveError::Io(io::Error::new(io::ErrorKind::Other, "Thread pool is gone"))
+            }
+        })
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u64 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]]) as u64
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u64 {
+    let mut le = [0; 4];
+    le.copy_from_slice(&bytes[at..at + 4]);
+    u32::from_le_bytes(le) as u64
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    let mut le = [0; 8];
+    le.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(le)
+}
+
+/// Reads the central directory of a zip file
+fn zip_summary<R: Read + Seek>(
+    reader: &mut R,
+    size: u64,
+    limits: &ArchiveLimits,
+) -> Result<ArchiveSummary, ArchiveError> {
+    // The end of central directory record is last, followed only by a comment of up to 64 KiB
+    let tail_length = size.min(22 + 65_535);
+    reader.seek(SeekFrom::Start(size - tail_length))?;
+    let mut tail = vec![0; tail_length as usize];
+    reader.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|i| tail[*i..].starts_with(b"PK\x05\x06"))
+        .ok_or(ArchiveError::Malformed)?;
+    let record = &tail[end..];
+    let (mut entries, mut directory_size, mut directory_offset) =
+        (u16_at(record, 10), u32_at(record, 12), u32_at(record, 16));
+    if entries == 0xFFFF || directory_size == 0xFFFF_FFFF || directory_offset == 0xFFFF_FFFF {
+        // A Zip64 archive, the real values are in the record found by the Zip64 locator
+        let locator = end
+            .checked_sub(20)
+            .map(|start| &tail[start..end])
+            .filter(|l| l.starts_with(b"PK\x06\x07"))
+            .ok_or(ArchiveError::Malformed)?;
+        let mut record = [0; 56];
+        reader.seek(SeekFrom::Start(u64_at(locator, 8)))?;
+        reader.read_exact(&mut record)?;
+        if !record.starts_with(b"PK\x06\x06") {
+            return Err(ArchiveError::Malformed);
+        }
+        entries = u64_at(&record, 32);
+        directory_size = u64_at(&record, 40);
+        directory_offset = u64_at(&record, 48);
+    }
+    if entries > limits.max_entries {
+        return Err(ArchiveError::TooManyEntries(limits.max_entries));
+    }
+    if directory_offset.saturating_add(directory_size) > size {
+        return Err(ArchiveError::Malformed);
+    }
+    let mut directory = vec![0; directory_size as usize];
+    reader.seek(SeekFrom::Start(directory_offset))?;
+    reader.read_exact(&mut directory)?;
+
+    let mut summary = ArchiveSummary::default();
+    let mut rest = &directory[..];
+    for _ in 0..entries {
+        if rest.len() < 46 || !rest.starts_with(b"PK\x01\x02") {
+            return Err(ArchiveError::Malformed);
+        }
+        let name_end = 46 + u16_at(rest, 28) as usize;
+        let extra_end = name_end + u16_at(rest, 30) as usize;
+        let entry_end = extra_end + u16_at(rest, 32) as usize;
+        if rest.len() < entry_end {
+            return Err(ArchiveError::Malformed);
+        }
+        let mut uncompressed = u32_at(rest, 24);
+        if uncompressed == 0xFFFF_FFFF {
+            uncompressed = zip64_size(&rest[name_end..extra_end]).ok_or(ArchiveError::Malformed)?;
+        }
+        summary.add(&rest[46..name_end], uncompressed);
+        rest = &rest[entry_end..];
+    }
+    Ok(summary)
+}
+
+/// The uncompressed size from the Zip64 extended information in the extra field of an entry
+fn zip64_size(mut extra: &[u8]) -> Option<u64> {
+    while extra.len() >= 4 {
+        let (id, length) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+        let data = extra.get(4..4 + length)?;
+        if id == 0x0001 && data.len() >= 8 {
+            return Some(u64_at(data, 0));
+        }
+        extra = &extra[4 + length..];
+    }
+    None
+}
+
+/// Reads the size declared at the end of a gzip file
+fn gzip_summary<R: Read + Seek>(reader: &mut R, size: u64) -> Result<ArchiveSummary, ArchiveError> {
+    if size < 18 {
+        return Err(ArchiveError::Malformed);
+    }
+    let mut trailer = [0; 4];
+    reader.seek(SeekFrom::Start(size - 4))?;
+    reader.read_exact(&mut trailer)?;
+    Ok(ArchiveSummary {
+        entries: 1,
+        total_size: u32::from_le_bytes(trailer) as u64,
+        nested: 0,
+    })
+}
+
+/// Reads the header of each entry of a tar file
+fn tar_summary<R: Read + Seek>(
+    reader: &mut R,
+    size: u64,
+    limits: &ArchiveLimits,
+) -> Result<ArchiveSummary, ArchiveError> {
+    let mut summary = ArchiveSummary::default();
+    let mut header = [0; 512];
+    let mut offset = 0;
+    while offset + 512 <= size {
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        // The archive ends with empty blocks
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let entry_size = std::str::from_utf8(&header[124..136])
+            .ok()
+            .map(|s| s.trim_matches(|c| c == '\0' || c == ' '))
+            .and_then(|s| u64::from_str_radix(s, 8).ok())
+            .ok_or(ArchiveError::Malformed)?;
+        let name_length = header[..100].iter().position(|b| *b == 0).unwrap_or(100);
+        summary.add(&header[..name_length], entry_size);
+        if summary.entries > limits.max_entries {
+            return Err(ArchiveError::TooManyEntries(limits.max_entries));
+        }
+        offset = offset
+            .saturating_add(512)
+            .saturating_add(entry_size.saturating_add(511) / 512 * 512);
+    }
+    Ok(summary)
+}
@@ -0,0 +1,154 @@
+//! Building blocks for validating file upload fields with `validator`
+//!
+//! `validator` custom functions can't take arguments, so combine these in your own function with
+//! the limits you need, then use it as `#[validate(custom = "...")]` on a `MultipartFile` or
+//! `SavedFile` field (or an `Option` / `Vec` of them).
+//!
+//! # Example
+//! ```
+//! # #[macro_use] extern crate validator_derive;
+//! # fn main() {
+//! # use actix_validated_forms_derive::FromMultipart;
+//! use actix_validated_forms::multipart::constraints::{allowed_mime, max_size, FileField};
+//! use actix_validated_forms::multipart::MultipartFile;
+//! use validator::{Validate, ValidationError};
+//!
+//! fn small_image<F: FileField>(file: &F) -> Result<(), ValidationError> {
+//!     max_size(file, 1024 * 1024)?;
+//!     allowed_mime(file, &[mime::IMAGE_PNG, mime::IMAGE_JPEG])
+//! }
+//!
+//! #[derive(FromMultipart, Validate)]
+//! struct Upload {
+//!     #[validate(custom = "small_image")]
+//!     image: MultipartFile,
+//!     #[validate(custom = "small_image")]
+//!     thumbnail: Option<MultipartFile>,
+//! }
+//! # }
+//! ```
+use super::{MultipartFile, SavedFile};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::ffi::OsStr;
+use std::path::Path;
+use validator::ValidationError;
+
+/// The metadata of an uploaded file that can be validated
+pub struct FileInfo<'a> {
+    /// Where the file is on disk, to validate its content
+    pub path: &'a Path,
+    pub size: u64,
+    pub filename: Option<&'a str>,
+    pub mime: &'a mime::Mime,
+}
+
+impl<'a> FileInfo<'a> {
+    fn extension(&self) -> Option<&'a str> {
+        self.filename
+            .and_then(|f| Path::new(f).extension().and_then(OsStr::to_str))
+    }
+}
+
+/// Only the metadata is serialized (the `value` parameter `validator` adds to the errors of custom
+/// functions), the path on the server is left out
+impl Serialize for FileInfo<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FileInfo", 3)?;
+        state.serialize_field("size", &self.size)?;
+        state.serialize_field("filename", &self.filename)?;
+        state.serialize_field("mime", self.mime.as_ref())?;
+        state.end()
+    }
+}
+
+impl Serialize for MultipartFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.files()[0].serialize(serializer)
+    }
+}
+
+impl Serialize for SavedFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.files()[0].serialize(serializer)
+    }
+}
+
+/// A field containing file uploads
+pub trait FileField {
+    fn files(&self) -> Vec<FileInfo<'_>>;
+}
+
+impl FileField for MultipartFile {
+    fn files(&self) -> Vec<FileInfo<'_>> {
+        vec![FileInfo {
+            path: self.file.path(),
+            size: self.size,
+            filename: self.filename.as_deref(),
+            mime: &self.mime,
+        }]
+    }
+}
+
+impl FileField for SavedFile {
+    fn files(&self) -> Vec<FileInfo<'_>> {
+        vec![FileInfo {
+            path: &self.path,
+            size: self.size,
+            filename: self.filename.as_deref(),
+            mime: &self.mime,
+        }]
+    }
+}
+
+impl<T: FileField> FileField for Vec<T> {
+    fn files(&self) -> Vec<FileInfo<'_>> {
+        self.iter().flat_map(|f| f.files()).collect()
+    }
+}
+
+/// Fails with a `max_size` error if any file is larger than `max` bytes
+pub fn max_size<F: FileField>(field: &F, max: u64) -> Result<(), ValidationError> {
+    if field.files().iter().any(|f| f.size > max) {
+        let mut error = ValidationError::new("max_size");
+        error.add_param("max".into(), &max);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Fails with an `allowed_extensions` error unless every file has a `filename` with one of the
+/// `extensions` (compared case insensitively)
+pub fn allowed_extensions<F: FileField>(
+    field: &F,
+    extensions: &[&str],
+) -> Result<(), ValidationError> {
+    let allowed = |f: &FileInfo| match f.extension() {
+        Some(ext) => extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+        None => false,
+    };
+    if !field.files().iter().all(allowed) {
+        let mut error = ValidationError::new("allowed_extensions");
+        error.add_param("extensions".into(), &extensions);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Fails with an `allowed_mime` error unless every file's Content-Type (ignoring parameters) is
+/// one of `mimes`
+///
+/// Remember the Content-Type is reported by the client, DO NOT trust this as being accurate
+pub fn allowed_mime<F: FileField>(field: &F, mimes: &[mime::Mime]) -> Result<(), ValidationError> {
+    let allowed = |f: &FileInfo| {
+        mimes
+            .iter()
+            .any(|m| m.essence_str() == f.mime.essence_str())
+    };
+    if !field.files().iter().all(allowed) {
+        let mut error = ValidationError::new("allowed_mime");
+        let mimes: Vec<&str> = mimes.iter().map(|m| m.essence_str()).collect();
+        error.add_param("mimes".into(), &mimes);
+        return Err(error);
+    }
+    Ok(())
+}
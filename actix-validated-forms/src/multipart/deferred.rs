@@ -0,0 +1,214 @@
+use super::load::{
+    read_prefix, remove_saved_files, LoadError, Loader, PartInfo, PartKind, PartStream,
+};
+use super::{
+    ExtractionDeadline, MultipartLimitError, MultipartLoadConfig, Multiparts, SNIFF_LENGTH,
+};
+use actix_web::dev::Payload;
+use actix_web::http::HeaderMap;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::stream::{self, StreamExt};
+
+/// A multipart form that has been loaded up until (and including a prefix of) its first file
+///
+/// See `load_parts_deferred()`. It can also be used as an extractor, which loads the form using
+/// the `MultipartLoadConfig` from the app data (or the default), reading the first
+/// `SNIFF_LENGTH` bytes of the first file.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{DeferredParts, MultipartType};
+/// # use actix_web::{HttpResponse, Error};
+/// async fn route(mut form: DeferredParts) -> Result<HttpResponse, Error> {
+///     let title: String = MultipartType::get(&mut form.parts, "title")?;
+///     if title.is_empty() {
+///         // The rest of the upload is never written to disk
+///         return Ok(HttpResponse::BadRequest().finish());
+///     }
+///     let rest = form.materialize().await?;
+///     # unimplemented!() }
+/// ```
+pub struct DeferredParts {
+    /// The parts of the form that preceded the first file upload
+    pub parts: Multiparts,
+    pending: Option<PendingFile>,
+    loader: Loader,
+}
+
+/// A file upload that has only been partially read
+pub struct PendingFile {
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The `filename` value in the `Content-Disposition` header, preferring the extended
+    /// `filename*` value (RFC 5987) if there is one
+    pub filename: Option<String>,
+    /// The plain `filename` value in the `Content-Disposition` header, when `filename` was taken
+    /// from the `filename*` value instead
+    pub fallback_filename: Option<String>,
+    /// The Content-Type specified as reported in the uploaded form
+    /// DO NOT trust this as being accurate
+    pub mime: mime::Mime,
+    /// The first bytes of the file (may be the entire file if it is small), as they are after the
+    /// configured `PartStage`s and any Content-Transfer-Encoding have been applied
+    pub prefix: Bytes,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+    kind: PartKind,
+    /// The rest of the body, already passed through `Loader::process()`
+    body: PartStream,
+}
+
+impl DeferredParts {
+    /// The first file upload in the form, if there was one
+    pub fn pending(&self) -> Option<&PendingFile> {
+        self.pending.as_ref()
+    }
+
+    /// Finish loading the form, writing the pending file and any remaining parts to disk
+    ///
+    /// Returns the complete form (minus any parts already taken from `parts`)
+    pub async fn materialize(self) -> Result<Multiparts, LoadError> {
+        let DeferredParts {
+            mut parts,
+            pending,
+            mut loader,
+        } = self;
+        if let Some(p) = pending {
+            let info = PartInfo {
+                name: p.name,
+                filename: p.filename,
+                fallback_filename: p.fallback_filename,
+                content_type: p.mime,
+                headers: p.headers,
+                index: p.index,
+            };
+            let body = Box::pin(stream::once(future::ok(p.prefix)).chain(p.body));
+            let res = match loader.load_body(p.kind, body, info).await {
+                Ok(item) => {
+                    parts.extend(item);
+                    loader.load_all(&mut parts).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                remove_saved_files(&parts);
+                return Err(e);
+            }
+        }
+        Ok(parts)
+    }
+}
+
+/// Use to load a multipart form, deferring writing file uploads to disk
+///
+/// The form is loaded only until the first file upload is found, of which only the first
+/// `prefix_length` bytes are read (for example so its type can be sniffed). The parts already
+/// loaded can then be inspected and validated before calling `DeferredParts::materialize()` to
+/// load the rest of the form. If the request is instead rejected, the remainder of the upload is
+/// never written to disk.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{load_parts_deferred, MultipartLoadConfig, MultipartType};
+/// # use actix_web::{HttpResponse, Error};
+/// async fn route(payload: actix_multipart::Multipart) -> Result<HttpResponse, Error> {
+///     let mut form = load_parts_deferred(payload, MultipartLoadConfig::default(), 512).await?;
+///     let title: String = MultipartType::get(&mut form.parts, "title")?;
+///     if title.is_empty() {
+///         return Ok(HttpResponse::BadRequest().finish());
+///     }
+///     let rest = form.materialize().await?;
+///     # unimplemented!() }
+/// ```
+pub async fn load_parts_deferred(
+    payload: actix_multipart::Multipart,
+    config: MultipartLoadConfig,
+    prefix_length: usize,
+) -> Result<DeferredParts, LoadError> {
+    let mut parts = Multiparts::new();
+    let mut loader = Loader::new(payload, config);
+    match load_until_file(&mut loader, &mut parts, prefix_length).await {
+        Ok(pending) => Ok(DeferredParts {
+            parts,
+            pending,
+            loader,
+        }),
+        Err(e) => {
+            remove_saved_files(&parts);
+            Err(e)
+        }
+    }
+}
+
+async fn load_until_file(
+    loader: &mut Loader,
+    parts: &mut Multiparts,
+    prefix_length: usize,
+) -> Result<Option<PendingFile>, LoadError> {
+    while let Some((field, info)) = loader.next_field().await? {
+        if info.is_text() {
+            parts.extend(loader.load_field(field, info).await?);
+            continue;
+        }
+        // The deadline, throughput limit and stages apply to the prefix as to the rest of the body
+        let kind = loader.classify(&info);
+        let mut body = loader.process(&info, kind, field);
+        let prefix = loader.check_deadline(read_prefix(&mut body, prefix_length).await)?;
+        if prefix.len() as u64 > loader.file_max_size() {
+            let (limit_kind, limit) = loader.limit_of(kind);
+            return Err(LoadError::Limit(MultipartLimitError {
+                field: info.name,
+                limit,
+                kind: limit_kind,
+            }));
+        }
+        return Ok(Some(PendingFile {
+            name: info.name,
+            filename: info.filename,
+            fallback_filename: info.fallback_filename,
+            mime: info.content_type,
+            headers: info.headers,
+            index: info.index,
+            prefix,
+            kind,
+            body,
+        }));
+    }
+    Ok(None)
+}
+
+impl FromRequest for DeferredParts {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = MultipartLoadConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let mut config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        if let Some(deadline) = req.extensions().get::<ExtractionDeadline>() {
+            config = config.deadline(deadline.0);
+        }
+        config = config.content_length(req);
+        let (x, drain) = config.multipart(req.headers(), payload.take());
+        let drain = config.drain(drain);
+        load_parts_deferred(x, config, SNIFF_LENGTH)
+            .then(move |res| async move {
+                match res {
+                    Ok(parts) => Ok(parts),
+                    Err(e) => {
+                        drain.await;
+                        Err(e.into())
+                    }
+                }
+            })
+            .boxed_local()
+    }
+}
@@ -0,0 +1,316 @@
+use super::{
+    MemoryFile, MultipartBytes, MultipartFile, MultipartLoadConfig, MultipartText, Repeated,
+    SavedFile, SpooledFile, StoredFile, TextStream,
+};
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
+use futures::future::{ok, Ready};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// The kind of part(s) a field expects
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Text,
+    File,
+    /// A `Repeated` group of sub fields
+    Group,
+}
+
+/// A machine-readable description of a single field of a multipart form
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDescription {
+    /// The name of the field in the multipart form
+    pub name: String,
+    pub kind: FieldKind,
+    /// The Rust type a text field is parsed into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<String>,
+    /// Whether the form is rejected if this field is missing
+    pub required: bool,
+    /// Whether the field accepts more than one part
+    pub multiple: bool,
+    /// The fields of each item, if this is a `Group`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldDescription>,
+}
+
+impl FieldDescription {
+    fn new(name: &str, kind: FieldKind) -> Self {
+        FieldDescription {
+            name: name.into(),
+            kind,
+            value_type: None,
+            required: true,
+            multiple: false,
+            fields: Vec::new(),
+        }
+    }
+
+    fn text<T>(name: &str) -> Self {
+        FieldDescription {
+            value_type: Some(std::any::type_name::<T>().into()),
+            ..FieldDescription::new(name, FieldKind::Text)
+        }
+    }
+
+    fn optional(self) -> Self {
+        FieldDescription {
+            required: false,
+            ..self
+        }
+    }
+
+    fn multiple(self) -> Self {
+        FieldDescription {
+            required: false,
+            multiple: true,
+            ..self
+        }
+    }
+}
+
+/// Describes the fields a multipart form expects
+///
+/// This is implemented by `#[derive(DescribeForm)]`, which requires every field's type to
+/// implement `DescribeField`
+pub trait DescribeForm {
+    fn describe_fields() -> Vec<FieldDescription>;
+}
+
+/// Describes a single field of a multipart form (see `MultipartType`)
+pub trait DescribeField {
+    fn describe(field_name: &str) -> FieldDescription;
+}
+
+/// A work-around while Rust trait specialization is not yet available (see
+/// `MultipartTypeSpecial`)
+pub trait DescribeFieldSpecial {
+    fn describe(field_name: &str) -> FieldDescription;
+}
+
+impl<T: FromStr> DescribeField for T {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::text::<T>(field_name)
+    }
+}
+
+impl<T: FromStr> DescribeFieldSpecial for Option<T> {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::text::<T>(field_name).optional()
+    }
+}
+
+impl<T: FromStr> DescribeFieldSpecial for Vec<T> {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::text::<T>(field_name).multiple()
+    }
+}
+
+impl DescribeField for MultipartText {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::text::<String>(field_name)
+    }
+}
+
+impl DescribeFieldSpecial for Option<MultipartText> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartText::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<MultipartText> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartText::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for MultipartBytes {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::Text)
+    }
+}
+
+impl DescribeFieldSpecial for Option<MultipartBytes> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartBytes::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<MultipartBytes> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartBytes::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for TextStream {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::text::<String>(field_name)
+    }
+}
+
+impl DescribeFieldSpecial for Option<TextStream> {
+    fn describe(field_name: &str) -> FieldDescription {
+        TextStream::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<TextStream> {
+    fn describe(field_name: &str) -> FieldDescription {
+        TextStream::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for MultipartFile {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::File)
+    }
+}
+
+impl DescribeFieldSpecial for Option<MultipartFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartFile::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<MultipartFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MultipartFile::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for SavedFile {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::File)
+    }
+}
+
+impl DescribeFieldSpecial for Option<SavedFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        SavedFile::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<SavedFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        SavedFile::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for MemoryFile {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::File)
+    }
+}
+
+impl DescribeFieldSpecial for Option<MemoryFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MemoryFile::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<MemoryFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        MemoryFile::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for StoredFile {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::File)
+    }
+}
+
+impl DescribeFieldSpecial for Option<StoredFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        StoredFile::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<StoredFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        StoredFile::describe(field_name).multiple()
+    }
+}
+
+impl DescribeField for SpooledFile {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription::new(field_name, FieldKind::File)
+    }
+}
+
+impl DescribeFieldSpecial for Option<SpooledFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        SpooledFile::describe(field_name).optional()
+    }
+}
+
+impl DescribeFieldSpecial for Vec<SpooledFile> {
+    fn describe(field_name: &str) -> FieldDescription {
+        SpooledFile::describe(field_name).multiple()
+    }
+}
+
+impl<T: DescribeForm> DescribeField for Repeated<T> {
+    fn describe(field_name: &str) -> FieldDescription {
+        FieldDescription {
+            fields: T::describe_fields(),
+            ..FieldDescription::new(field_name, FieldKind::Group).multiple()
+        }
+    }
+}
+
+/// A machine-readable description of a multipart form, including the limits it will be loaded
+/// with
+///
+/// Responds with the description as JSON, so that it can be exposed at an endpoint for clients
+/// to dynamically generate the form.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{FormDescription, MultipartLoadConfig};
+/// # use actix_validated_forms_derive::{DescribeForm, FromMultipart};
+/// #[derive(FromMultipart, DescribeForm)]
+/// struct Upload {
+///     title: String,
+///     image: actix_validated_forms::multipart::MultipartFile,
+/// }
+///
+/// async fn describe_upload() -> FormDescription {
+///     FormDescription::new::<Upload>(&MultipartLoadConfig::default())
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct FormDescription {
+    pub fields: Vec<FieldDescription>,
+    /// Maximum total bytes of text
+    pub text_limit: usize,
+    /// Maximum total bytes of file uploads
+    pub file_limit: u64,
+    /// Maximum parts the form may contain
+    pub max_parts: usize,
+    /// Maximum parts that may share the same field name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_parts_per_name: Option<usize>,
+}
+
+impl FormDescription {
+    pub fn new<T: DescribeForm>(config: &MultipartLoadConfig) -> Self {
+        FormDescription {
+            fields: T::describe_fields(),
+            text_limit: config.text_limit,
+            file_limit: config.file_limit,
+            max_parts: config.max_parts,
+            max_parts_per_name: config.max_parts_per_name,
+        }
+    }
+}
+
+impl Responder for FormDescription {
+    type Error = Error;
+    type Future = Ready<Result<HttpResponse, Error>>;
+
+    fn respond_to(self, _: &HttpRequest) -> Self::Future {
+        ok(HttpResponse::Ok().json(self))
+    }
+}
@@ -0,0 +1,235 @@
+use super::filename::sanitize_filename;
+use super::stream::read_chunks;
+use super::{MultipartFile, SavedFile};
+use actix_web::dev::{Body, SizedStream};
+use actix_web::error::BlockingError;
+use actix_web::http::header::{
+    self, Charset, ContentDisposition, DispositionParam, DispositionType, EntityTag, ExtendedValue,
+    IfNoneMatch, IfRange,
+};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use futures::future::{FutureExt, LocalBoxFuture};
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Serves an uploaded file back to a client
+///
+/// Sets the `Content-Type` (from the uploaded mime type), a `Content-Disposition` containing the
+/// sanitized upload `filename`, and an `ETag` from the SHA-256 digest of the contents (computed
+/// when serving if it isn't already known). Conditional requests (`If-None-Match`) and single
+/// byte ranges (`Range` / `If-Range`) are supported.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{FileResponse, SavedFile};
+/// # use actix_web::Error;
+/// async fn download(file: SavedFile) -> Result<FileResponse, Error> {
+///     Ok(FileResponse::from_saved(&file)?)
+/// }
+/// ```
+pub struct FileResponse {
+    file: File,
+    size: u64,
+    filename: Option<String>,
+    mime: mime::Mime,
+    sha256: Option<String>,
+    disposition: DispositionType,
+}
+
+impl FileResponse {
+    /// Serve the temporary file of a `MultipartFile`
+    ///
+    /// Fails if the contents of the file were discarded by deduplication
+    pub fn from_file(file: &MultipartFile) -> io::Result<Self> {
+        if file.deduplicated {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The contents of a deduplicated file were discarded",
+            ));
+        }
+        Ok(FileResponse {
+            file: file.file.reopen()?,
+            size: file.size,
+            filename: file.filename.clone(),
+            mime: file.mime.clone(),
+            sha256: file.sha256.clone(),
+            disposition: DispositionType::Attachment,
+        })
+    }
+
+    /// Serve a `SavedFile` from its saved location
+    pub fn from_saved(file: &SavedFile) -> io::Result<Self> {
+        let f = File::open(&file.path)?;
+        let size = f.metadata()?.len();
+        Ok(FileResponse {
+            file: f,
+            size,
+            filename: file.filename.clone(),
+            mime: file.mime.clone(),
+            sha256: None,
+            disposition: DispositionType::Attachment,
+        })
+    }
+
+    /// Use an `inline` Content-Disposition rather than `attachment` (the default)
+    pub fn inline(mut self, inline: bool) -> Self {
+        self.disposition = if inline {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        };
+        self
+    }
+
+    fn content_disposition(&self) -> ContentDisposition {
+        let mut parameters = Vec::new();
+        if let Some(filename) = self.filename.as_deref().and_then(sanitize_filename) {
+            let ascii: String = filename
+                .chars()
+                .map(|c| if c.is_ascii() { c } else { '_' })
+                .collect();
+            if ascii != filename {
+                parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+                    charset: Charset::Ext("UTF-8".into()),
+                    language_tag: None,
+                    value: filename.into_bytes(),
+                }));
+            }
+            parameters.insert(0, DispositionParam::Filename(ascii));
+        }
+        ContentDisposition {
+            disposition: self.disposition.clone(),
+            parameters,
+        }
+    }
+}
+
+/// Parses a `Range` header containing a single byte range into an end-inclusive range
+///
+/// Returns `None` if the header can't be used (and the whole file should be served), or
+/// `Some(None)` if the range is not satisfiable
+fn parse_range(value: &str, size: u64) -> Option<Option<(u64, u64)>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let mut split = spec.splitn(2, '-');
+    let (start, end) = (split.next()?.trim(), split.next()?.trim());
+    let range = if start.is_empty() {
+        let last: u64 = end.parse().ok()?;
+        match last {
+            0 => None,
+            _ => Some((size.saturating_sub(last), size.saturating_sub(1))),
+        }
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end: u64 = match end {
+            "" => u64::max_value(),
+            x => x.parse().ok()?,
+        };
+        if end < start {
+            return None;
+        }
+        Some((start, std::cmp::min(end, size.saturating_sub(1))))
+    };
+    Some(range.filter(|(start, _)| *start < size))
+}
+
+fn hash_file(mut file: File) -> io::Result<(File, String)> {
+    let mut hasher = Sha256::new();
+    file.seek(SeekFrom::Start(0))?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok((file, format!("{:x}", hasher.finalize())))
+}
+
+impl Responder for FileResponse {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<HttpResponse, Self::Error>>;
+
+    fn respond_to(self, req: &HttpRequest) -> Self::Future {
+        let if_none_match = req.get_header::<IfNoneMatch>();
+        let if_range = req.get_header::<IfRange>();
+        let range = req
+            .headers()
+            .get(header::RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        async move {
+            let content_disposition = self.content_disposition();
+            let FileResponse {
+                file,
+                size,
+                mime,
+                sha256,
+                ..
+            } = self;
+            let (file, sha256) = match sha256 {
+                Some(sha256) => (file, sha256),
+                None => web::block(move || hash_file(file))
+                    .await
+                    .map_err(|e| match e {
+                        BlockingError::Error(e) => actix_web::Error::from(e),
+                        BlockingError::Canceled => {
+                            actix_web::error::ErrorInternalServerError("Hashing was cancelled")
+                        }
+                    })?,
+            };
+            let etag = EntityTag::strong(sha256);
+
+            let not_modified = match if_none_match {
+                Some(IfNoneMatch::Any) => true,
+                Some(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+                None => false,
+            };
+            if not_modified {
+                return Ok(HttpResponse::NotModified().set(header::ETag(etag)).finish());
+            }
+
+            // A range is only used if the client's copy (if any) is still current
+            let range_valid = match if_range {
+                Some(IfRange::EntityTag(tag)) => tag.strong_eq(&etag),
+                Some(IfRange::Date(_)) => false,
+                None => true,
+            };
+            let range = range
+                .filter(|_| range_valid)
+                .and_then(|r| parse_range(&r, size));
+
+            let mut builder = HttpResponse::Ok();
+            builder
+                .content_type(mime.to_string())
+                .set(content_disposition)
+                .set(header::ETag(etag))
+                .header(header::ACCEPT_RANGES, "bytes");
+            let (start, length) = match range {
+                None => (0, size),
+                Some(None) => {
+                    return Ok(builder
+                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                        .finish());
+                }
+                Some(Some((start, end))) => {
+                    builder.status(StatusCode::PARTIAL_CONTENT).header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", start, end, size),
+                    );
+                    (start, end - start + 1)
+                }
+            };
+            let body = read_chunks(file, start, length).map(|r| r.map_err(actix_web::Error::from));
+            Ok(builder.body(Body::from_message(SizedStream::new(length, body))))
+        }
+        .boxed_local()
+    }
+}
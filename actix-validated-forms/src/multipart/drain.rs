@@ -0,0 +1,63 @@
+use actix_web::error::PayloadError;
+use actix_web::web::Bytes;
+use futures::future::poll_fn;
+use futures::stream::LocalBoxStream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+type Body = Rc<RefCell<LocalBoxStream<'static, Result<Bytes, PayloadError>>>>;
+
+/// The body of a request, read by a `Multipart` while `PayloadDrain` keeps hold of it
+pub(crate) struct SharedPayload(Body);
+
+impl Stream for SharedPayload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.0.borrow_mut().as_mut().poll_next(cx)
+    }
+}
+
+/// Used to discard the rest of a request body once loading the form has failed, so that the
+/// connection can be reused for the next request
+pub(crate) struct PayloadDrain(Body);
+
+/// Shares `payload` between a `Multipart` and a `PayloadDrain`
+pub(crate) fn share_payload<S>(payload: S) -> (SharedPayload, PayloadDrain)
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static,
+{
+    let body: Body = Rc::new(RefCell::new(payload.boxed_local()));
+    (SharedPayload(body.clone()), PayloadDrain(body))
+}
+
+impl PayloadDrain {
+    /// Reads and discards what remains of the body, giving up if there is more than `max` bytes
+    /// of it or the `deadline` passes first
+    pub(crate) async fn drain(self, max: u64, deadline: Option<Instant>) {
+        if max == 0 {
+            return;
+        }
+        let body = self.0;
+        let read = async move {
+            let mut remaining = max;
+            while let Some(Ok(bytes)) = poll_fn(|cx| body.borrow_mut().as_mut().poll_next(cx)).await
+            {
+                match remaining.checked_sub(bytes.len() as u64) {
+                    Some(r) => remaining = r,
+                    None => break,
+                }
+            }
+        };
+        match deadline {
+            Some(deadline) => {
+                let _ = tokio::time::timeout_at(deadline.into(), read).await;
+            }
+            None => read.await,
+        }
+    }
+}
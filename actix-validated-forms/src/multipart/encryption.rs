@@ -0,0 +1,143 @@
+use super::{MultipartFile, MultipartLoadConfig, PartInfo, PartKind, PartStage, PartStream};
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::web::Bytes;
+use chacha20::cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use chacha20::{ChaCha20, Key, Nonce};
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The length of the random nonce written at the start of each encrypted file
+pub const NONCE_LENGTH: u64 = 12;
+
+/// A 256-bit key used to encrypt file uploads with ChaCha20
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        EncryptionKey(key)
+    }
+
+    fn cipher(&self, nonce: &[u8; NONCE_LENGTH as usize]) -> ChaCha20 {
+        ChaCha20::new(&Key::from(self.0), &Nonce::from(*nonce))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// A `PartStage` that encrypts file uploads before they are written to their temporary file
+///
+/// Each file is encrypted with ChaCha20 using a random nonce, which is written in front of the
+/// ciphertext, so the `size` of the `MultipartFile` includes the `NONCE_LENGTH` bytes. Read the
+/// file back using `MultipartFile::decrypt()`. Only `PartKind::File` parts are encrypted, not
+/// `stream_text()` fields or files written using `save_to()`.
+///
+/// The ciphertext isn't authenticated, this protects the contents of the temporary files from
+/// being read, not from being modified. As the nonce is random `deduplicate()` won't find any
+/// matches.
+#[derive(Clone, Debug)]
+pub struct EncryptFiles {
+    key: EncryptionKey,
+}
+
+impl EncryptFiles {
+    pub fn new(key: EncryptionKey) -> Self {
+        EncryptFiles { key }
+    }
+}
+
+impl PartStage for EncryptFiles {
+    fn process(&self, _info: &PartInfo, kind: PartKind, body: PartStream) -> PartStream {
+        if kind != PartKind::File {
+            return body;
+        }
+        let mut nonce = [0u8; NONCE_LENGTH as usize];
+        if let Err(e) = getrandom::getrandom(&mut nonce) {
+            let e = io::Error::new(io::ErrorKind::Other, e.to_string());
+            return stream::once(future::err(MultipartError::Payload(PayloadError::Io(e))))
+                .boxed_local();
+        }
+        let mut cipher = self.key.cipher(&nonce);
+        let ciphertext = body.map_ok(move |chunk| {
+            let mut buf = chunk.to_vec();
+            cipher.apply_keystream(&mut buf);
+            Bytes::from(buf)
+        });
+        stream::once(future::ok(Bytes::copy_from_slice(&nonce)))
+            .chain(ciphertext)
+            .boxed_local()
+    }
+}
+
+impl MultipartLoadConfig {
+    /// Encrypt file uploads using `key` before they are written to disk (see `EncryptFiles`)
+    pub fn encrypt_files(self, key: EncryptionKey) -> Self {
+        self.stage(EncryptFiles::new(key))
+    }
+}
+
+/// Reads (and seeks within) the plaintext of a file encrypted by `EncryptFiles`
+pub struct DecryptingReader<R> {
+    inner: R,
+    cipher: ChaCha20,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Reads the nonce from the start of `inner`
+    pub fn new(mut inner: R, key: &EncryptionKey) -> io::Result<Self> {
+        let mut nonce = [0u8; NONCE_LENGTH as usize];
+        inner.read_exact(&mut nonce)?;
+        Ok(DecryptingReader {
+            cipher: key.cipher(&nonce),
+            inner,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.cipher.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(p) => SeekFrom::Start(p + NONCE_LENGTH),
+            other => other,
+        };
+        let current = self.inner.seek(SeekFrom::Current(0))?;
+        let inner_pos = self.inner.seek(pos)?;
+        if inner_pos < NONCE_LENGTH {
+            self.inner.seek(SeekFrom::Start(current))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to before the start of the plaintext",
+            ));
+        }
+        let plaintext_pos = inner_pos - NONCE_LENGTH;
+        self.cipher.seek(plaintext_pos);
+        Ok(plaintext_pos)
+    }
+}
+
+impl MultipartFile {
+    /// Opens the file for reading, decrypting it with `key` (see `EncryptFiles`)
+    pub fn decrypt(&self, key: &EncryptionKey) -> io::Result<DecryptingReader<File>> {
+        DecryptingReader::new(self.file.reopen()?, key)
+    }
+}
@@ -1,16 +1,23 @@
-use super::{load_parts, MultipartLoadConfig, Multiparts};
+use super::load::{
+    load_parts_with_skipped, remove_saved_files, ConfigError, LoadError, Loader, PartKind,
+    SavedFiles, SkippedParts, CHARSET_FIELD,
+};
+use super::{MultipartField, MultipartLoadConfig, Multiparts};
 use crate::error::ValidatedFormError;
-use crate::multipart::GetError;
+use crate::honeypot::Honeypot;
+use crate::method::{is_invalid_override, MethodOverride, METHOD_FIELD};
+use crate::multipart::{belongs_to, DescribeForm, GetError, MultipartTypeSpecial};
 use actix_multipart::{Multipart, MultipartError};
 use actix_web::dev::Payload;
-use actix_web::{FromRequest, HttpRequest};
-use futures::future::LocalBoxFuture;
-use futures::{FutureExt, TryFutureExt};
+use actix_web::error::InternalError;
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use futures::future::{self, FutureExt, LocalBoxFuture};
 use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
 use std::ops;
 use std::rc::Rc;
-use validator::Validate;
+use std::time::Instant;
+use validator::{Validate, ValidationErrors};
 
 /// Validated extractor for a HTTP Multipart request
 ///
@@ -39,6 +46,41 @@ use validator::Validate;
 ///     # unimplemented!(); }
 /// # }
 /// ```
+///
+/// A form can also implement `TryFrom<Multiparts>` by hand instead of using the derive
+///
+/// The form's `EarlyValidate` implementation (e.g. `#[multipart(validate_early)]` and
+/// `#[multipart(charset = "...")]`) is not used by this extractor, use
+/// `EarlyValidatedMultipartForm` for that.
+///
+/// ```
+/// # use actix_validated_forms::multipart::{
+/// #     GetError, MultipartFile, MultipartType, Multiparts, ValidatedMultipartForm,
+/// # };
+/// # use actix_web::HttpResponse;
+/// # use std::convert::TryFrom;
+/// # use validator::{Validate, ValidationErrors};
+/// struct Upload {
+///     image: MultipartFile,
+/// }
+///
+/// impl TryFrom<Multiparts> for Upload {
+///     type Error = GetError;
+///
+///     fn try_from(mut form: Multiparts) -> Result<Self, Self::Error> {
+///         Ok(Upload {
+///             image: MultipartType::get(&mut form, "image")?,
+///         })
+///     }
+/// }
+///
+/// # impl Validate for Upload {
+/// #     fn validate(&self) -> Result<(), ValidationErrors> { Ok(()) }
+/// # }
+/// async fn route(form: ValidatedMultipartForm<Upload>) -> HttpResponse {
+///     # unimplemented!()
+/// }
+/// ```
 pub struct ValidatedMultipartForm<T: Validate>(pub T);
 
 impl<T: Validate> ValidatedMultipartForm<T> {
@@ -61,55 +103,616 @@ impl<T: Validate> ops::DerefMut for ValidatedMultipartForm<T> {
     }
 }
 
+/// A request extension that bounds how long the `ValidatedMultipartForm` extractor may take
+///
+/// Middleware enforcing an end-to-end timeout can insert this, so that an upload is aborted with
+/// a `LoadError::Timeout` rather than continuing past the handler's budget. It is applied as a
+/// `MultipartLoadConfig::deadline()`, so the earlier of the two deadlines wins.
+///
+/// # Example
+/// ```
+/// # use actix_web::{dev::ServiceRequest, HttpMessage};
+/// # use actix_validated_forms::multipart::ExtractionDeadline;
+/// # use std::time::{Duration, Instant};
+/// fn start_timeout(req: &ServiceRequest) {
+///     let deadline = Instant::now() + Duration::from_secs(30);
+///     req.extensions_mut().insert(ExtractionDeadline(deadline));
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtractionDeadline(pub Instant);
+
+/// The idempotency key of an upload, added to the request extensions by the
+/// `ValidatedMultipartForm` extractor when `ValidatedMultipartFormConfig::idempotency_key()` is
+/// configured and the request has one
+///
+/// Keys may only contain ASCII letters, digits, `-` and `_` (surrounding quotes are removed),
+/// and be at most 255 characters, any other value is rejected with `400 Bad Request`.
+///
+/// # Example
+/// ```
+/// # use actix_web::{HttpRequest, HttpResponse};
+/// # use actix_validated_forms::multipart::IdempotencyKey;
+/// async fn route(req: HttpRequest) -> HttpResponse {
+///     if let Some(key) = req.extensions().get::<IdempotencyKey>() {
+///         eprintln!("Upload {}", key.0);
+///     }
+///     # unimplemented!()
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdempotencyKey(pub String);
+
+impl IdempotencyKey {
+    fn parse(value: &str) -> Option<Self> {
+        let key = value.trim().trim_matches('"');
+        let valid = (1..=255).contains(&key.len())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Some(IdempotencyKey(key.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Validation of a multipart form's text fields while it is still being loaded
+///
+/// This is implemented by `#[derive(FromMultipart)]`: fields marked with
+/// `#[multipart(validate_early)]` are validated (using their own `#[validate]` attributes) as
+/// soon as they have been received, before any subsequent file uploads (or `stream_text`
+/// fields) are read. This means a request can be rejected without first having to receive a
+/// large upload. Likewise a file upload with a content type that isn't in the field's
+/// `#[multipart(allowed_mimes = "...")]` fails the load as soon as its headers are read.
+///
+/// The `custom` and `must_match` validators of a field (which may refer to `Self` or to other
+/// fields) aren't checked early, only by `Validate` once the whole form has been loaded.
+///
+/// It is only used by the `EarlyValidatedMultipartForm` and `ValidatedMultipartWithExtras`
+/// extractors. Every method has a default, so a form that implements `TryFrom<Multiparts>` by
+/// hand (rather than using the derive) only needs `impl EarlyValidate for MyForm {}`.
+pub trait EarlyValidate {
+    /// Validate the fields that have been loaded so far (`form` only contains the text fields)
+    fn validate_early(_form: &mut Multiparts) -> Result<(), ValidationErrors> {
+        Ok(())
+    }
+
+    /// Adjust the config the form is loaded with, e.g. `#[multipart(charset = "...")]` sets the
+    /// `MultipartLoadConfig::field_charset()` of the field, and the derive sets the
+    /// `MultipartLoadConfig::expected_fields()`
+    fn load_config(config: MultipartLoadConfig) -> MultipartLoadConfig {
+        config
+    }
+
+    /// Bind every field of the form, returning an error for each field that couldn't be (used
+    /// by `ValidatedMultipartFormConfig::combine_errors()`)
+    ///
+    /// Returns `None` (without modifying `form`) if this isn't supported, in which case the form
+    /// is converted using `TryFrom` instead.
+    fn bind_combined(_form: &mut Multiparts) -> Option<Result<Self, ValidationErrors>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
 impl<T> FromRequest for ValidatedMultipartForm<T>
 where
-    T: TryFrom<Multiparts, Error = GetError> + Validate + 'static,
+    T: TryFrom<Multiparts> + Validate + 'static,
+    T::Error: Into<MultipartErrorWrapper>,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedMultipartFormConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        extract_form::<WithoutEarlyValidation<T>>(req, payload, None)
+            .map(|res| res.map(|(form, _)| ValidatedMultipartForm(form.0)))
+            .boxed_local()
+    }
+}
+
+/// A form extracted by `ValidatedMultipartForm`, which uses the defaults of `EarlyValidate`
+/// rather than the form's own implementation
+struct WithoutEarlyValidation<T>(T);
+
+impl<T: TryFrom<Multiparts>> TryFrom<Multiparts> for WithoutEarlyValidation<T> {
+    type Error = T::Error;
+
+    fn try_from(form: Multiparts) -> Result<Self, Self::Error> {
+        T::try_from(form).map(WithoutEarlyValidation)
+    }
+}
+
+impl<T: Validate> Validate for WithoutEarlyValidation<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.0.validate()
+    }
+}
+
+impl<T> EarlyValidate for WithoutEarlyValidation<T> {}
+
+/// Validated extractor for a HTTP Multipart request, that uses the form's `EarlyValidate`
+/// implementation while it is being loaded
+///
+/// Otherwise the same as `ValidatedMultipartForm`, and configured by the
+/// `ValidatedMultipartFormConfig`. With `#[derive(FromMultipart)]` this applies the
+/// `#[multipart(...)]` attributes that affect loading, such as `validate_early`, `charset` and
+/// `allowed_mimes`, and supports `ValidatedMultipartFormConfig::combine_errors()`.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive;
+/// # fn main() {
+/// # use actix_validated_forms_derive::FromMultipart;
+/// # use validator::Validate;
+/// #[derive(FromMultipart, Validate)]
+/// struct MultipartUpload {
+///    #[multipart(validate_early)]
+///    #[validate(length(max = 4096))]
+///    description: String,
+///    image: MultipartFile,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::multipart::{EarlyValidatedMultipartForm, MultipartFile};
+///
+/// async fn route(
+///     form: EarlyValidatedMultipartForm<MultipartUpload>,
+/// ) -> HttpResponse {
+///     let img_bytes = std::fs::read(form.image.file.path()).unwrap();
+///     # unimplemented!(); }
+/// # }
+/// ```
+pub struct EarlyValidatedMultipartForm<T: Validate>(pub T);
+
+impl<T: Validate> EarlyValidatedMultipartForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for EarlyValidatedMultipartForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for EarlyValidatedMultipartForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for EarlyValidatedMultipartForm<T>
+where
+    T: TryFrom<Multiparts> + EarlyValidate + Validate + 'static,
+    T::Error: Into<MultipartErrorWrapper>,
 {
     type Error = actix_web::Error;
     type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     type Config = ValidatedMultipartFormConfig;
 
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        extract_form::<T>(req, payload, None)
+            .map(|res| res.map(|(form, _)| EarlyValidatedMultipartForm(form)))
+            .boxed_local()
+    }
+}
+
+/// Loads and validates the form, if `fields` is given the parts that don't belong to any of
+/// them are returned separately rather than being passed to `TryFrom`
+fn extract_form<T>(
+    req: &HttpRequest,
+    payload: &mut Payload,
+    fields: Option<Vec<String>>,
+) -> LocalBoxFuture<'static, Result<(T, Multiparts), actix_web::Error>>
+where
+    T: TryFrom<Multiparts> + EarlyValidate + Validate + 'static,
+    T::Error: Into<MultipartErrorWrapper>,
+{
+    let req2 = req.clone();
+    let req3 = req.clone();
+    let config = req
+        .app_data::<ValidatedMultipartFormConfig>()
+        .map(|c| c.clone())
+        .unwrap_or(ValidatedMultipartFormConfig::default());
+
+    let combine_errors = config.combine_errors;
+    let method_override = config.method_override;
+    let honeypot = config.honeypot.clone();
+    let mut load_config = match &config.request_config {
+        Some(f) => (*f)(req, config.config.clone()),
+        None => config.config.clone(),
+    };
+    if let Some(deadline) = req.extensions().get::<ExtractionDeadline>() {
+        load_config = load_config.deadline(deadline.0);
+    }
+    if let Some(header) = &config.idempotency_key {
+        if let Some(value) = req.headers().get(header) {
+            let key = match value.to_str().ok().and_then(IdempotencyKey::parse) {
+                Some(key) => key,
+                None => {
+                    let e = actix_web::error::ErrorBadRequest("Invalid idempotency key");
+                    return future::err(e).boxed_local();
+                }
+            };
+            // Checked before any of the body has been read
+            if let Some(f) = &config.on_duplicate {
+                if let Some(response) = (*f)(req, &key) {
+                    let e = InternalError::from_response("Duplicate upload", response);
+                    return future::err(e.into()).boxed_local();
+                }
+            }
+            load_config = load_config.idempotency_key(&key.0);
+            req.extensions_mut().insert(key);
+        }
+    }
+
+    let mut load_config = T::load_config(load_config)
+        .content_length(req)
+        .expect_field(CHARSET_FIELD);
+    // These fields are taken from the form before it is passed to `TryFrom`
+    if method_override {
+        load_config = load_config.expect_field(METHOD_FIELD);
+    }
+    if let Some(honeypot) = &honeypot {
+        load_config = load_config.expect_field(honeypot.field());
+    }
+
+    // Create actix_multipart::Multipart from HTTP Request
+    let (x, drain) = load_config.multipart(req.headers(), payload.take());
+    let drain = load_config.drain(drain);
+    // Read into a Multiparts (a vector of fields and temp files on disk), and then into T
+    load_form::<T>(
+        x,
+        load_config,
+        combine_errors,
+        method_override,
+        honeypot.clone(),
+        fields,
+    )
+    .then(move |res| async move {
+        if !matches!(res, Ok(Some(_))) {
+            drain.await;
+        }
+        match res {
+            Ok(Some(loaded)) => {
+                if !loaded.skipped.0.is_empty() {
+                    req3.extensions_mut().insert(loaded.skipped);
+                }
+                if let Some(method) = loaded.method {
+                    req3.extensions_mut().insert(method);
+                }
+                Ok((loaded.form, loaded.extras))
+            }
+            Ok(None) => Err(honeypot.unwrap().error(&req2)),
+            Err(e) => Err(match config.error_handler {
+                Some(err) => (*err)(e, &req2),
+                None if is_invalid_override(&e) => actix_web::error::ErrorBadRequest(e),
+                None => actix_web::Error::from(e),
+            }),
+        }
+    })
+    .boxed_local()
+}
+
+/// Validated extractor for a HTTP Multipart request, that also keeps the parts that don't
+/// belong to any field of the form
+///
+/// The parts are split using the field names from `DescribeForm` (implemented by
+/// `#[derive(DescribeForm)]`): a part belongs to a field if it is named exactly the same, or
+/// is an item of it such as `field[0]`. Only the parts belonging to the form are passed to
+/// `TryFrom`, the others are returned as they were loaded. It is configured in the same way as
+/// `ValidatedMultipartForm` by the `ValidatedMultipartFormConfig`.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive;
+/// # fn main() {
+/// # use actix_validated_forms_derive::{DescribeForm, FromMultipart};
+/// # use validator::Validate;
+/// #[derive(FromMultipart, DescribeForm, Validate)]
+/// struct PluginUpload {
+///    #[validate(length(max = 64))]
+///    plugin: String,
+///    bundle: MultipartFile,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::multipart::{MultipartFile, ValidatedMultipartWithExtras};
+///
+/// async fn route(
+///     form: ValidatedMultipartWithExtras<PluginUpload>,
+/// ) -> HttpResponse {
+///     let (upload, extras) = form.into_inner();
+///     for part in extras {
+///         eprintln!("{} sent extra part {}", upload.plugin, part.name());
+///     }
+///     # unimplemented!(); }
+/// # }
+/// ```
+pub struct ValidatedMultipartWithExtras<T: Validate>(pub T, pub Multiparts);
+
+impl<T: Validate> ValidatedMultipartWithExtras<T> {
+    pub fn into_inner(self) -> (T, Multiparts) {
+        (self.0, self.1)
+    }
+
+    /// The parts that didn't belong to any field of the form
+    pub fn extras(&self) -> &Multiparts {
+        &self.1
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedMultipartWithExtras<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedMultipartWithExtras<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedMultipartWithExtras<T>
+where
+    T: TryFrom<Multiparts> + EarlyValidate + DescribeForm + Validate + 'static,
+    T::Error: Into<MultipartErrorWrapper>,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedMultipartFormConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let fields = T::describe_fields().into_iter().map(|f| f.name).collect();
+        extract_form::<T>(req, payload, Some(fields))
+            .map(|res| res.map(|(form, extras)| ValidatedMultipartWithExtras(form, extras)))
+            .boxed_local()
+    }
+}
+
+/// Extractor for a HTTP Multipart request, as the untyped `Multiparts`
+///
+/// Useful when the fields of a form aren't known in advance. The form is loaded using the
+/// `MultipartLoadConfig` from the app data (or the default), and any parts that were skipped
+/// are added to the request extensions as `SkippedParts`.
+///
+/// # Example
+/// ```
+/// # use actix_web::{HttpResponse, web, App};
+/// # use actix_validated_forms::multipart::{MultipartLoadConfig, RawMultipart};
+/// async fn route(form: RawMultipart) -> HttpResponse {
+///     for part in form.iter() {
+///         eprintln!("Received {}", part.name());
+///     }
+///     # unimplemented!()
+/// }
+///
+/// App::new()
+///     .app_data(MultipartLoadConfig::default().max_parts(100))
+///     .route("/", web::post().to(route));
+/// ```
+#[derive(Debug)]
+pub struct RawMultipart(pub Multiparts);
+
+impl RawMultipart {
+    pub fn into_inner(self) -> Multiparts {
+        self.0
+    }
+}
+
+impl ops::Deref for RawMultipart {
+    type Target = Multiparts;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for RawMultipart {
+    fn deref_mut(&mut self) -> &mut Multiparts {
+        &mut self.0
+    }
+}
+
+impl FromRequest for RawMultipart {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = MultipartLoadConfig;
+
     #[inline]
     fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
         let req2 = req.clone();
-        let config = req
+        let mut config = req
             .app_data::<Self::Config>()
             .map(|c| c.clone())
             .unwrap_or(Self::Config::default());
-
-        // Create actix_multipart::Multipart from HTTP Request
-        let x = Multipart::new(req.headers(), payload.take());
-        // Read into a Multiparts (a vector of fields and temp files on disk)
-        load_parts(x, config.config.clone())
-            .map(move |res| match res {
-                Ok(item) => {
-                    // Try to parse the multiparts into the struct T
-                    let x = T::try_from(item).map_err(|e| {
-                        ValidatedFormError::Deserialization(MultipartErrorWrapper::Deserialization(
-                            e,
-                        ))
-                    })?;
-                    // And then validate the struct T
-                    x.validate()
-                        .map_err(|e| ValidatedFormError::Validation(e))?;
-                    Ok(x)
-                }
-                Err(e) => Err(ValidatedFormError::Deserialization(
-                    MultipartErrorWrapper::Multipart(e),
-                )),
-            })
-            .map_ok(ValidatedMultipartForm)
-            .map_err(move |e| {
-                if let Some(err) = config.error_handler {
-                    (*err)(e, &req2)
-                } else {
-                    Self::Error::from(e)
+        if let Some(deadline) = req.extensions().get::<ExtractionDeadline>() {
+            config = config.deadline(deadline.0);
+        }
+        config = config.content_length(req);
+        let (x, drain) = config.multipart(req.headers(), payload.take());
+        let drain = config.drain(drain);
+        load_parts_with_skipped(x, config)
+            .then(move |res| async move {
+                match res {
+                    Ok((parts, skipped)) => {
+                        if !skipped.is_empty() {
+                            req2.extensions_mut().insert(SkippedParts(skipped));
+                        }
+                        Ok(RawMultipart(parts))
+                    }
+                    Err(e) => {
+                        drain.await;
+                        Err(e.into())
+                    }
                 }
             })
             .boxed_local()
     }
 }
 
+struct LoadedForm<T> {
+    form: T,
+    skipped: SkippedParts,
+    method: Option<MethodOverride>,
+    /// The parts that didn't belong to any of the fields of `T`
+    extras: Multiparts,
+}
+
+async fn load_form<T>(
+    payload: Multipart,
+    config: MultipartLoadConfig,
+    combine_errors: bool,
+    method_override: bool,
+    honeypot: Option<Honeypot>,
+    fields: Option<Vec<String>>,
+) -> Result<Option<LoadedForm<T>>, ValidatedFormError<MultipartErrorWrapper>>
+where
+    T: TryFrom<Multiparts> + EarlyValidate + Validate,
+    T::Error: Into<MultipartErrorWrapper>,
+{
+    let mut parts = Multiparts::new();
+    let mut loader = Loader::new(payload, config);
+    match load_validating_early::<T>(&mut loader, &mut parts, honeypot.as_ref()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            remove_saved_files(&parts);
+            return Ok(None);
+        }
+        Err(e) => {
+            remove_saved_files(&parts);
+            return Err(e);
+        }
+    }
+    let method = if method_override {
+        match take_method_override(&mut parts) {
+            Ok(method) => method,
+            Err(e) => {
+                remove_saved_files(&parts);
+                return Err(ValidatedFormError::Validation(e));
+            }
+        }
+    } else {
+        None
+    };
+    let extras = match fields {
+        Some(fields) => {
+            let (form, extras) = parts
+                .into_iter()
+                .partition(|p| fields.iter().any(|f| belongs_to(p.name(), f)));
+            parts = form;
+            extras
+        }
+        None => Multiparts::new(),
+    };
+    // The parts are consumed by T, so take note of their files in case it fails
+    let saved = SavedFiles::of(&parts);
+    // Try to parse the multiparts into the struct T
+    let combined = if combine_errors {
+        T::bind_combined(&mut parts)
+    } else {
+        None
+    };
+    let x = match combined {
+        Some(result) => result.map_err(|e| ValidatedFormError::Validation(e)),
+        None => T::try_from(parts).map_err(|e| ValidatedFormError::Deserialization(e.into())),
+    };
+    // And then validate the struct T
+    let validated = x.and_then(|x| {
+        x.validate()
+            .map(|_| x)
+            .map_err(|e| ValidatedFormError::Validation(e))
+    });
+    match validated {
+        Ok(form) => Ok(Some(LoadedForm {
+            form,
+            skipped: SkippedParts(loader.skipped),
+            method,
+            extras,
+        })),
+        Err(e) => {
+            remove_saved_files(&extras);
+            saved.remove();
+            Err(e)
+        }
+    }
+}
+
+/// Removes the `_method` field from the form
+fn take_method_override(
+    parts: &mut Multiparts,
+) -> Result<Option<MethodOverride>, ValidationErrors> {
+    let value: Option<String> = MultipartTypeSpecial::get(parts, METHOD_FIELD).map_err(|e| {
+        let mut errors = ValidationErrors::new();
+        errors.add(METHOD_FIELD, e.into());
+        errors
+    })?;
+    value.map(|v| MethodOverride::parse(&v)).transpose()
+}
+
+fn multipart_error(e: LoadError) -> ValidatedFormError<MultipartErrorWrapper> {
+    ValidatedFormError::Deserialization(e.into())
+}
+
+/// Returns false (having stopped reading the form) if the honeypot field was filled in
+async fn load_validating_early<T: EarlyValidate>(
+    loader: &mut Loader,
+    parts: &mut Multiparts,
+    honeypot: Option<&Honeypot>,
+) -> Result<bool, ValidatedFormError<MultipartErrorWrapper>> {
+    // The number of text fields when they were last validated, they only need validating again
+    // once more have arrived
+    let mut text_count = 0;
+    let mut validated_count = None;
+    while let Some((field, info)) = loader.next_field().await.map_err(multipart_error)? {
+        // Every part other than an in memory text field could be a large upload
+        if loader.classify(&info) != PartKind::Text && validated_count != Some(text_count) {
+            validated_count = Some(text_count);
+            let mut text_parts: Multiparts = parts
+                .iter()
+                .filter_map(|p| match p {
+                    MultipartField::Text(t) => Some(MultipartField::Text(t.clone())),
+                    _ => None,
+                })
+                .collect();
+            T::validate_early(&mut text_parts).map_err(|e| ValidatedFormError::Validation(e))?;
+        }
+        let item = match loader
+            .load_field(field, info)
+            .await
+            .map_err(multipart_error)?
+        {
+            Some(item) => item,
+            None => continue,
+        };
+        if let (Some(honeypot), MultipartField::Text(t)) = (honeypot, &item) {
+            if honeypot.is_filled(&t.name, &t.text) {
+                return Ok(false);
+            }
+            if t.name == honeypot.field() {
+                continue;
+            }
+        }
+        if let MultipartField::Text(_) = item {
+            text_count += 1;
+        }
+        parts.push(item);
+    }
+    Ok(true)
+}
+
 /// Configure the behaviour of the ValidatedMultipartForm extractor
 ///
 /// # Usage
@@ -126,16 +729,113 @@ where
 #[derive(Clone)]
 pub struct ValidatedMultipartFormConfig {
     config: MultipartLoadConfig,
+    combine_errors: bool,
+    method_override: bool,
+    honeypot: Option<Honeypot>,
+    idempotency_key: Option<String>,
+    on_duplicate: Option<Rc<dyn Fn(&HttpRequest, &IdempotencyKey) -> Option<HttpResponse>>>,
+    request_config: Option<Rc<dyn Fn(&HttpRequest, MultipartLoadConfig) -> MultipartLoadConfig>>,
     error_handler: Option<
         Rc<dyn Fn(ValidatedFormError<MultipartErrorWrapper>, &HttpRequest) -> actix_web::Error>,
     >,
 }
 
 impl ValidatedMultipartFormConfig {
+    /// Checks the `MultipartLoadConfig` (see `MultipartLoadConfig::validate()`)
+    ///
+    /// The config computed for each request by `request_config()` can't be checked in advance.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+    /// A description of the effective limits and settings, to log at startup (see
+    /// `MultipartLoadConfig::summarize()`)
+    pub fn summarize(&self) -> String {
+        format!(
+            "{}, combine_errors: {}, method_override: {}, honeypot: {}, per-request config: {}",
+            self.config.summarize(),
+            self.combine_errors,
+            self.method_override,
+            self.honeypot.as_ref().map_or("none", |h| h.field()),
+            self.request_config.is_some(),
+        )
+    }
     pub fn config(mut self, config: MultipartLoadConfig) -> Self {
         self.config = config;
         self
     }
+    /// Compute the effective config for each request (before loading begins), from the static
+    /// `config`
+    ///
+    /// For example to apply limits depending on the authenticated user, looked up from the
+    /// request `extensions()`
+    pub fn request_config<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HttpRequest, MultipartLoadConfig) -> MultipartLoadConfig + 'static,
+    {
+        self.request_config = Some(Rc::new(f));
+        self
+    }
+    /// Report every field that couldn't be bound, and not just the first
+    ///
+    /// Fields that are missing or couldn't be converted are reported as a
+    /// `ValidatedFormError::Validation`, with the code `required`, `invalid_type` or `duplicate`
+    /// (or `invalid` for a custom message). Requires a form that implements
+    /// `EarlyValidate::bind_combined()`, as `#[derive(FromMultipart)]` does, extracted with
+    /// `EarlyValidatedMultipartForm` or `ValidatedMultipartWithExtras`. `validate_early` fields
+    /// are still validated (and fail) as soon as they are received.
+    pub fn combine_errors(mut self, enabled: bool) -> Self {
+        self.combine_errors = enabled;
+        self
+    }
+    /// Recognise a `_method` field overriding the method of the request (see
+    /// `crate::method::MethodOverride`)
+    pub fn method_override(mut self, enabled: bool) -> Self {
+        self.method_override = enabled;
+        self
+    }
+    /// Reject forms that fill in the honeypot field, before reading any more of the form (see
+    /// `crate::honeypot::Honeypot`)
+    pub fn honeypot(mut self, honeypot: Honeypot) -> Self {
+        self.honeypot = Some(honeypot);
+        self
+    }
+    /// Capture the idempotency key of uploads from the `header` header (e.g. `Idempotency-Key`)
+    ///
+    /// The key is added to the request extensions as an `IdempotencyKey`, and passed to the
+    /// `MultipartLoadConfig` so files can be saved using `NamingStrategy::IdempotencyKey`.
+    pub fn idempotency_key(mut self, header: &str) -> Self {
+        self.idempotency_key = Some(header.to_string());
+        self
+    }
+    /// Sets a function that is called with the idempotency key before the body is read, if it
+    /// returns a response the upload is treated as a duplicate and that response is sent
+    /// instead (without reading the rest of the request)
+    ///
+    /// Clients often retry large uploads, this allows them to be answered (e.g. with the result
+    /// of the original upload) without receiving the file again.
+    ///
+    /// # Example
+    /// ```
+    /// # use actix_validated_forms::multipart::ValidatedMultipartFormConfig;
+    /// # use actix_web::HttpResponse;
+    /// # fn completed(_: &str) -> bool { false }
+    /// ValidatedMultipartFormConfig::default()
+    ///     .idempotency_key("idempotency-key")
+    ///     .on_duplicate(|_, key| {
+    ///         if completed(&key.0) {
+    ///             Some(HttpResponse::Conflict().finish())
+    ///         } else {
+    ///             None
+    ///         }
+    ///     });
+    /// ```
+    pub fn on_duplicate<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HttpRequest, &IdempotencyKey) -> Option<HttpResponse> + 'static,
+    {
+        self.on_duplicate = Some(Rc::new(f));
+        self
+    }
     pub fn error_handler<F>(mut self, f: F) -> Self
     where
         F: Fn(ValidatedFormError<MultipartErrorWrapper>, &HttpRequest) -> actix_web::Error
@@ -150,6 +850,12 @@ impl Default for ValidatedMultipartFormConfig {
     fn default() -> Self {
         ValidatedMultipartFormConfig {
             config: Default::default(),
+            combine_errors: false,
+            method_override: false,
+            honeypot: None,
+            idempotency_key: None,
+            on_duplicate: None,
+            request_config: None,
             error_handler: None,
         }
     }
@@ -159,15 +865,40 @@ impl Default for ValidatedMultipartFormConfig {
 pub enum MultipartErrorWrapper {
     Multipart(MultipartError),
     Deserialization(GetError),
+    /// The form was rejected while it was being loaded, by one of the `MultipartLoadConfig`
+    /// limits or checks (payload errors are still reported as `Multipart`)
+    Load(LoadError),
+    /// An error returned by a hand-written `TryFrom<Multiparts>` implementation
+    ///
+    /// Implement `From<YourError> for MultipartErrorWrapper` to use your own error type, it can
+    /// be downcast again in the `error_handler`
+    Custom(Box<dyn std::error::Error>),
 }
 
 impl std::error::Error for MultipartErrorWrapper {}
 
+impl From<GetError> for MultipartErrorWrapper {
+    fn from(e: GetError) -> Self {
+        MultipartErrorWrapper::Deserialization(e)
+    }
+}
+
+impl From<LoadError> for MultipartErrorWrapper {
+    fn from(e: LoadError) -> Self {
+        match e {
+            LoadError::Multipart(e) => MultipartErrorWrapper::Multipart(e),
+            e => MultipartErrorWrapper::Load(e),
+        }
+    }
+}
+
 impl Display for MultipartErrorWrapper {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match self {
             MultipartErrorWrapper::Multipart(e) => Display::fmt(&e, f),
             MultipartErrorWrapper::Deserialization(e) => Display::fmt(&e, f),
+            MultipartErrorWrapper::Load(e) => Display::fmt(&e, f),
+            MultipartErrorWrapper::Custom(e) => Display::fmt(&e, f),
         }
     }
 }
@@ -0,0 +1,48 @@
+/// Longest name in bytes that `sanitize_filename()` returns, the limit of most filesystems
+pub const MAX_FILENAME_LENGTH: usize = 255;
+
+/// Characters that Windows doesn't allow in a filename (other than the path separators)
+const RESERVED: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Makes an uploaded filename safe to use as the name of a file on disk, `None` if nothing of it
+/// is left
+///
+/// Any directory components are stripped (with either `/` or `\` separators), as are control
+/// characters, the characters Windows reserves, leading dots (so the file isn't hidden, and `..`
+/// is never returned) and leading or trailing whitespace. A name longer than
+/// `MAX_FILENAME_LENGTH` bytes is shortened, keeping its extension.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::sanitize_filename;
+/// assert_eq!(sanitize_filename("../../etc/passwd"), Some("passwd".into()));
+/// assert_eq!(sanitize_filename("C:\\Users\\a\\.bashrc"), Some("bashrc".into()));
+/// assert_eq!(sanitize_filename("report\u{0}?.pdf"), Some("report.pdf".into()));
+/// assert_eq!(sanitize_filename(".."), None);
+/// ```
+pub fn sanitize_filename(filename: &str) -> Option<String> {
+    let name = filename.rsplit(|c| c == '/' || c == '\\').next().unwrap();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !RESERVED.contains(c))
+        .collect();
+    // Windows also ignores trailing dots
+    let name = name
+        .trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        return None;
+    }
+    if name.len() <= MAX_FILENAME_LENGTH {
+        return Some(name.to_owned());
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if name.len() - dot < MAX_FILENAME_LENGTH / 2 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let mut end = MAX_FILENAME_LENGTH - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!("{}{}", stem[..end].trim_end(), extension))
+}
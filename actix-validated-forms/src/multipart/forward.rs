@@ -0,0 +1,127 @@
+use super::stream::read_chunks;
+use super::{MultipartField, Multiparts};
+use actix_web::web::Bytes;
+use futures::future;
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use std::fs::File;
+use std::io;
+
+/// Re-serializes a loaded form into a multipart body, e.g. for proxying it to another service
+pub trait IntoPayload {
+    /// Convert the form into a `multipart/form-data` body stream using `boundary`
+    ///
+    /// Text (and any `MemoryFile`) is written from memory, other files are streamed from disk (on
+    /// a background thread pool). Each part is written with just its `Content-Disposition` and
+    /// `Content-Type` headers. A `MultipartFile` that was deduplicated no longer has its
+    /// contents, and a `StoredFile` isn't available locally, so these produce an error.
+    ///
+    /// # Example
+    /// ```
+    /// # use actix_validated_forms::multipart::{load_parts, IntoPayload, MultipartLoadConfig};
+    /// # use actix_web::{HttpResponse, Error};
+    /// async fn route(payload: actix_multipart::Multipart) -> Result<HttpResponse, Error> {
+    ///     let parts = load_parts(payload, MultipartLoadConfig::default()).await?;
+    ///     let boundary = "------------------------forwarded";
+    ///     let upstream = awc::Client::default()
+    ///         .post("http://upstream/upload")
+    ///         .content_type(format!("multipart/form-data; boundary={}", boundary))
+    ///         .send_stream(parts.into_payload(boundary))
+    ///         .await?;
+    ///     # unimplemented!() }
+    /// ```
+    fn into_payload(self, boundary: &str) -> LocalBoxStream<'static, Result<Bytes, io::Error>>;
+}
+
+/// Escapes a header parameter value the same way browsers do
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+pub(super) fn part_header(
+    boundary: &str,
+    name: &str,
+    filename: Option<&str>,
+    content_type: Option<&mime::Mime>,
+) -> Bytes {
+    let mut header = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+        boundary,
+        escape(name)
+    );
+    if let Some(filename) = filename {
+        header.push_str(&format!("; filename=\"{}\"", escape(filename)));
+    }
+    header.push_str("\r\n");
+    if let Some(content_type) = content_type {
+        header.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    header.push_str("\r\n");
+    Bytes::from(header)
+}
+
+fn error_stream(e: io::Error) -> LocalBoxStream<'static, Result<Bytes, io::Error>> {
+    stream::once(future::err(e)).boxed_local()
+}
+
+impl IntoPayload for Multiparts {
+    fn into_payload(self, boundary: &str) -> LocalBoxStream<'static, Result<Bytes, io::Error>> {
+        let mut sections = Vec::new();
+        for part in self {
+            let (header, body) = match part {
+                MultipartField::Text(t) => (
+                    part_header(boundary, &t.name, None, Some(&t.mime)),
+                    stream::once(future::ok(Bytes::from(t.text))).boxed_local(),
+                ),
+                MultipartField::Bytes(b) => (
+                    part_header(boundary, &b.name, None, Some(&b.mime)),
+                    stream::once(future::ok(b.bytes)).boxed_local(),
+                ),
+                MultipartField::Stream(t) => {
+                    (part_header(boundary, &t.name, None, None), t.into_stream())
+                }
+                MultipartField::File(f) => {
+                    let header =
+                        part_header(boundary, &f.name, f.filename.as_deref(), Some(&f.mime));
+                    let body = if f.deduplicated {
+                        error_stream(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "The contents of a deduplicated file were discarded",
+                        ))
+                    } else {
+                        read_chunks(f.file, 0, f.size)
+                    };
+                    (header, body)
+                }
+                MultipartField::Saved(f) => {
+                    let header =
+                        part_header(boundary, &f.name, f.filename.as_deref(), Some(&f.mime));
+                    let body = match File::open(&f.path) {
+                        Ok(file) => read_chunks(file, 0, f.size),
+                        Err(e) => error_stream(e),
+                    };
+                    (header, body)
+                }
+                MultipartField::Stored(f) => (
+                    part_header(boundary, &f.name, f.filename.as_deref(), Some(&f.mime)),
+                    error_stream(io::Error::new(
+                        io::ErrorKind::Other,
+                        "The contents of a stored file aren't available locally",
+                    )),
+                ),
+                MultipartField::Memory(f) => (
+                    part_header(boundary, &f.name, f.filename.as_deref(), Some(&f.mime)),
+                    stream::once(future::ok(f.data)).boxed_local(),
+                ),
+            };
+            sections.push(stream::once(future::ok(header)).boxed_local());
+            sections.push(body);
+            sections.push(stream::once(future::ok(Bytes::from_static(b"\r\n"))).boxed_local());
+        }
+        let end = Bytes::from(format!("--{}--\r\n", boundary));
+        sections.push(stream::once(future::ok(end)).boxed_local());
+        stream::iter(sections).flatten().boxed_local()
+    }
+}
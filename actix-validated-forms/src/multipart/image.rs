@@ -0,0 +1,95 @@
+//! Checking that file uploads are images, of an allowed format and size
+//!
+//! Only the header of each image is decoded, so checking a large upload is cheap. As with
+//! `constraints` combine these in your own function, then use it as
+//! `#[validate(custom = "...")]` on a `MultipartFile` or `SavedFile` field (or an `Option` / `Vec`
+//! of them). Files encrypted by `MultipartLoadConfig::encrypt_files()` can't be checked.
+//!
+//! # Example
+//! ```
+//! # #[macro_use] extern crate validator_derive;
+//! # fn main() {
+//! # use actix_validated_forms_derive::FromMultipart;
+//! use actix_validated_forms::multipart::constraints::{max_size, FileField};
+//! use actix_validated_forms::multipart::image::{assert_image, ImageFormat};
+//! use actix_validated_forms::multipart::MultipartFile;
+//! use validator::{Validate, ValidationError};
+//!
+//! fn avatar<F: FileField>(file: &F) -> Result<(), ValidationError> {
+//!     max_size(file, 1024 * 1024)?;
+//!     assert_image(file, 512, 512, &[ImageFormat::Png, ImageFormat::Jpeg])
+//! }
+//!
+//! #[derive(FromMultipart, Validate)]
+//! struct Profile {
+//!     #[validate(custom = "avatar")]
+//!     avatar: MultipartFile,
+//! }
+//! # }
+//! ```
+use super::constraints::FileField;
+pub use ::image::{ImageError, ImageFormat};
+use std::path::Path;
+use validator::ValidationError;
+
+/// The format and dimensions of an image, read from its header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Reads the format (from the content, not the filename) and dimensions of the image at `path`
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::image::image_info;
+/// # use actix_validated_forms::multipart::MultipartFile;
+/// fn is_landscape(file: &MultipartFile) -> bool {
+///     match image_info(file.file.path()) {
+///         Ok(info) => info.width > info.height,
+///         Err(_) => false,
+///     }
+/// }
+/// ```
+pub fn image_info<P: AsRef<Path>>(path: P) -> Result<ImageInfo, ImageError> {
+    let reader = ::image::io::Reader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    let (width, height) = reader.into_dimensions()?;
+    Ok(ImageInfo {
+        // Known, otherwise `into_dimensions()` would have failed
+        format: format.unwrap(),
+        width,
+        height,
+    })
+}
+
+/// Fails unless every file is an image, of one of `formats` (or any supported format if empty),
+/// and no wider than `max_width` or taller than `max_height`
+///
+/// The error is an `image` error for a file that isn't a readable image, `image_format` for an
+/// image of another format, and `image_dimensions` for one that is too large.
+pub fn assert_image<F: FileField>(
+    field: &F,
+    max_width: u32,
+    max_height: u32,
+    formats: &[ImageFormat],
+) -> Result<(), ValidationError> {
+    for file in field.files() {
+        let info = image_info(file.path).map_err(|_| ValidationError::new("image"))?;
+        if !formats.is_empty() && !formats.contains(&info.format) {
+            let mut error = ValidationError::new("image_format");
+            let formats: Vec<String> = formats.iter().map(|f| format!("{:?}", f)).collect();
+            error.add_param("formats".into(), &formats);
+            return Err(error);
+        }
+        if info.width > max_width || info.height > max_height {
+            let mut error = ValidationError::new("image_dimensions");
+            error.add_param("max_width".into(), &max_width);
+            error.add_param("max_height".into(), &max_height);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
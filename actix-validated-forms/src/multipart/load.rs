@@ -1,24 +1,113 @@
-use super::{MultipartField, MultipartFile, MultipartText, Multiparts};
+use super::archive::{inspect_field, ArchiveError, ArchiveLimits};
+use super::drain::{share_payload, PayloadDrain};
+use super::filename::sanitize_filename;
+use super::memory::{create_memory_file, create_spooled_file, SpooledFile};
+use super::mixed::flatten_mixed;
+use super::orphans::{create_temp_file, make_private};
+use super::pool::{BufferPool, WriteBuffer};
+use super::saved::create_saved_file;
+use super::scan::{ScanError, ScanHook};
+use super::shutdown::{InFlight, ShutdownSignal};
+use super::sniff::{is_sniffable, sniff_mime, SNIFF_LENGTH};
+use super::storage::create_stored_file;
+use super::stream::create_text_stream;
+use super::throttle::Throttle;
+use super::transfer::decode_transfer_encoding;
+use super::{
+    belongs_to, content_id, MultipartBytes, MultipartField, MultipartFile, MultipartStorage,
+    MultipartText, Multiparts, NamingStrategy,
+};
+pub use crate::error::ConfigError;
 use actix_multipart::MultipartError;
-use actix_web::error::{BlockingError, ParseError, PayloadError};
+use actix_web::error::{ParseError, PayloadError};
 use actix_web::http::header;
-use actix_web::http::header::DispositionType;
-use actix_web::web::{self, BytesMut};
-use futures::{StreamExt, TryFutureExt, TryStreamExt};
-use std::io::Write;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::{HeaderMap, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, ResponseError};
+use encoding_rs::Encoding;
+use err_derive::Error;
+use futures::future::{self, Either, LocalBoxFuture};
+use futures::stream::{self, LocalBoxStream};
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::time::Delay;
 
 // https://tools.ietf.org/html/rfc7578#section-1
 // `content-type` defaults to text/plain
 // files SHOULD use appropriate mime or application/octet-stream
 // `filename` SHOULD be included but is not a MUST
 
+/// The name of the field that sets the default charset of the text fields that follow it
+/// (RFC 7578, section 4.6)
+pub const CHARSET_FIELD: &str = "_charset_";
+
 /// Configuration options when loading a multipart form
 #[derive(Clone)]
 pub struct MultipartLoadConfig {
-    text_limit: usize,
-    file_limit: u64,
-    max_parts: usize,
+    pub(crate) text_limit: usize,
+    pub(crate) file_limit: u64,
+    max_file_size: Option<u64>,
+    pub(crate) max_parts: usize,
+    pub(crate) max_parts_per_name: Option<usize>,
+    max_files: Option<usize>,
+    max_text_fields: Option<usize>,
+    save_to: Option<(PathBuf, NamingStrategy)>,
+    storage: Option<Arc<dyn MultipartStorage>>,
+    temp_dir: Option<PathBuf>,
+    preserve_extension: bool,
+    sanitize_filenames: bool,
+    private_temp_files: bool,
+    stream_text: Vec<String>,
+    in_memory: Vec<String>,
+    allowed_mimes: Vec<mime::Mime>,
+    field_mimes: Vec<(String, Vec<mime::Mime>)>,
+    /// The acceptable mismatches, if `reject_spoofed_mimes()` is set
+    spoof_check: Option<Vec<(mime::Mime, mime::Mime)>>,
+    allowed_extensions: Vec<String>,
+    denied_extensions: Vec<String>,
+    archive_limits: Option<ArchiveLimits>,
+    scan_hook: Option<Arc<dyn ScanHook>>,
+    spool_threshold: Option<u64>,
+    utf8_policy: Utf8Policy,
+    charsets: Vec<(String, &'static Encoding)>,
+    empty_names: EmptyNamePolicy,
+    blank_files: bool,
+    expected_fields: Option<Vec<String>>,
+    unknown_parts: UnknownPartPolicy,
+    strict: bool,
+    skip_malformed: bool,
+    decode_transfer_encoding: bool,
+    nested_file_sets: bool,
+    related: bool,
+    deadline: Option<Instant>,
+    read_timeout: Option<Duration>,
+    drain_limit: u64,
+    content_length: Option<u64>,
+    idempotency_key: Option<String>,
+    shutdown: Option<ShutdownSignal>,
+    stages: Vec<Arc<dyn PartStage>>,
+    write_permits: Option<(usize, Arc<Semaphore>)>,
+    max_throughput: Option<u64>,
+    buffer_pool: Option<Arc<BufferPool>>,
+    write_buffer_size: usize,
+    digests: bool,
+    exists: Option<Arc<dyn Fn(String) -> LocalBoxFuture<'static, bool> + Send + Sync>>,
 }
 
 impl MultipartLoadConfig {
@@ -34,28 +123,1133 @@ impl MultipartLoadConfig {
         self
     }
 
+    /// Maximum bytes of each individual file upload - by default only `file_limit` applies
+    ///
+    /// The `file_limit` still caps the total of all the files in the form, so that a form may
+    /// contain several files of up to `max` bytes each
+    pub fn max_file_size(mut self, max: u64) -> Self {
+        self.max_file_size = Some(max);
+        self
+    }
+
     /// Maximum parts the form may contain - default 1000
     pub fn max_parts(mut self, max: usize) -> Self {
         self.max_parts = max;
         self
     }
+
+    /// Maximum parts that may share the same field name - by default only `max_parts` applies
+    ///
+    /// Indexed names (e.g. `photos[0]`, `photos[1]`) count towards their field (`photos`)
+    pub fn max_parts_per_name(mut self, max: usize) -> Self {
+        self.max_parts_per_name = Some(max);
+        self
+    }
+
+    /// Maximum file parts the form may contain - by default only `max_parts` applies
+    ///
+    /// Unlike `max_parts` text fields aren't counted, so that a form with many text fields can't
+    /// be used to upload as many files
+    pub fn max_files(mut self, max: usize) -> Self {
+        self.max_files = Some(max);
+        self
+    }
+
+    /// Maximum text fields the form may contain - by default only `max_parts` applies
+    ///
+    /// Many small text fields can stay within the `text_limit`, while still being costly to
+    /// collect and deserialize
+    pub fn max_text_fields(mut self, max: usize) -> Self {
+        self.max_text_fields = Some(max);
+        self
+    }
+
+    /// Create temporary files (for `MultipartFile` and `TextStream` parts) in the `dir`
+    /// directory, which must already exist - by default the system temporary directory
+    ///
+    /// Combined with `ValidatedMultipartFormConfig::request_config()` this can be chosen per
+    /// request, for example to keep each tenant's uploads separate:
+    /// ```
+    /// # use actix_validated_forms::multipart::ValidatedMultipartFormConfig;
+    /// ValidatedMultipartFormConfig::default().request_config(|req, config| {
+    ///     match req.headers().get("x-tenant-id").and_then(|h| h.to_str().ok()) {
+    ///         Some(tenant) => config.temp_dir(format!("/var/uploads/{}", tenant)),
+    ///         None => config,
+    ///     }
+    /// });
+    /// ```
+    /// The tenant ID must of course be validated (or come from authentication) before being used
+    /// in a path.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// End the names of temporary files with the extension of the uploaded `filename` (e.g.
+    /// `.mp4`) - default false
+    ///
+    /// For tools that detect the format of a file by its extension, so that they can be given the
+    /// path of the temporary file directly. Extensions that aren't alphanumeric, or are longer
+    /// than 16 characters, are left off.
+    pub fn preserve_extension(mut self, preserve: bool) -> Self {
+        self.preserve_extension = preserve;
+        self
+    }
+
+    /// Replace the `filename` of every upload with `sanitize_filename()` as soon as its headers
+    /// are read - default false
+    ///
+    /// The extension checks, naming strategies and loaded files then only ever see the sanitized
+    /// name. A filename with nothing safe left in it becomes empty, so the part is still a file.
+    pub fn sanitize_filenames(mut self, sanitize: bool) -> Self {
+        self.sanitize_filenames = sanitize;
+        self
+    }
+
+    /// Ensure temporary files can only be read and written by the owner (mode `0600`), so that
+    /// other local users can't read uploads in flight - default false
+    ///
+    /// Temporary files are normally created with these permissions already, this also guards
+    /// against a umask or directory ACL that would widen them. Whether it was confirmed is
+    /// recorded as `MultipartFile::private` (it can't be on platforms other than Unix). Unnamed
+    /// (`O_TMPFILE`) files aren't used, since a `MultipartFile` must have a path.
+    pub fn private_temp_files(mut self, private: bool) -> Self {
+        self.private_temp_files = private;
+        self
+    }
+
+    /// Write file uploads directly to their final location in the `dir` directory (instead of a
+    /// temporary file), named according to the `naming` strategy
+    ///
+    /// File parts will be loaded as a `SavedFile` rather than a `MultipartFile`
+    pub fn save_to<P: Into<PathBuf>>(mut self, dir: P, naming: NamingStrategy) -> Self {
+        self.save_to = Some((dir.into(), naming));
+        self
+    }
+
+    /// Stream file uploads into `storage` (such as an object store) instead of a temporary file
+    ///
+    /// File parts will be loaded as a `StoredFile` rather than a `MultipartFile`, this takes
+    /// precedence over `save_to()`
+    pub fn storage<S: MultipartStorage + 'static>(mut self, storage: S) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Names of text fields that should be spooled to disk instead of loaded into memory
+    ///
+    /// These fields will be loaded as a `TextStream` rather than a `MultipartText`, and count
+    /// towards the `file_limit` instead of the `text_limit`. Indexed names (e.g. `rows[0]`) are
+    /// streamed if their field (`rows`) is.
+    pub fn stream_text(mut self, field_names: &[&str]) -> Self {
+        self.stream_text = field_names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Names of file fields that should be kept in memory instead of written to a temporary file
+    ///
+    /// These fields will be loaded as a `MemoryFile` rather than a `MultipartFile` (or a
+    /// `SavedFile`), and still count towards the `file_limit`. Indexed names (e.g. `avatars[0]`)
+    /// are kept in memory if their field (`avatars`) is.
+    pub fn in_memory(mut self, field_names: &[&str]) -> Self {
+        self.in_memory = field_names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Content types that file uploads may have (e.g. `image/png`, or `image/*` for any image) -
+    /// by default any
+    ///
+    /// A file part with any other content type fails the load with `LoadError::DisallowedMime`,
+    /// before any of it is written. Text fields aren't affected. Remember the content type is
+    /// reported by the client, DO NOT trust this as being accurate
+    ///
+    /// # Panics
+    /// If any of `mimes` isn't a valid content type
+    pub fn allowed_mimes(mut self, mimes: &[&str]) -> Self {
+        self.allowed_mimes = mimes
+            .iter()
+            .map(|m| {
+                m.parse()
+                    .expect("allowed_mimes must be valid content types")
+            })
+            .collect();
+        self
+    }
+
+    /// Content types that the file uploads of one field may have, in place of the
+    /// `allowed_mimes()` - by default those of `allowed_mimes()`
+    ///
+    /// Checked in the same way, so the load fails before any of a disallowed upload is written.
+    /// Indexed names (e.g. `photos[0]`) use the content types of their field (`photos`). This is
+    /// set by the `#[multipart(allowed_mimes = "...")]` attribute of `#[derive(FromMultipart)]`.
+    ///
+    /// # Panics
+    /// If any of `mimes` isn't a valid content type
+    pub fn field_mimes(mut self, field_name: &str, mimes: &[&str]) -> Self {
+        let mimes = mimes
+            .iter()
+            .map(|m| m.parse().expect("field_mimes must be valid content types"))
+            .collect();
+        self.field_mimes.push((field_name.to_string(), mimes));
+        self
+    }
+
+    /// Check the content of file uploads against their content type, failing the load with
+    /// `LoadError::SpoofedMime` if they don't match - default false
+    ///
+    /// The start of each file is compared with `sniff_mime()` before any of it is written. A file
+    /// that isn't recognised is treated as `application/octet-stream`, so it is only rejected if
+    /// its content type is one that would have been recognised (e.g. a PHP script uploaded as
+    /// `image/jpeg`). `acceptable` lists pairs of declared and sniffed types to allow anyway, for
+    /// example `("application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    /// "application/zip")` for Word documents, or `("application/octet-stream", "*/*")` to
+    /// allow files sent without a specific type. Either may have a `*` subtype.
+    ///
+    /// # Panics
+    /// If any of `acceptable` isn't a valid content type
+    pub fn reject_spoofed_mimes(mut self, acceptable: &[(&str, &str)]) -> Self {
+        let parse = |m: &str| {
+            m.parse()
+                .expect("reject_spoofed_mimes must be given valid content types")
+        };
+        self.spoof_check = Some(
+            acceptable
+                .iter()
+                .map(|(declared, sniffed)| (parse(declared), parse(sniffed)))
+                .collect(),
+        );
+        self
+    }
+
+    /// Extensions that the filenames of uploads must have (e.g. `jpg`) - by default any
+    ///
+    /// Extensions are compared case insensitively. A file part with any other extension, or
+    /// none, fails the load with `LoadError::DisallowedExtension` before any of it is written.
+    pub fn allowed_extensions(mut self, extensions: &[&str]) -> Self {
+        self.allowed_extensions = extensions.iter().map(|e| normalize_extension(e)).collect();
+        self
+    }
+
+    /// Extensions that the filenames of uploads must not have (e.g. `exe`, `php`, `svg`) - by
+    /// default none
+    ///
+    /// Extensions are compared case insensitively. A file part with one of these extensions
+    /// fails the load with `LoadError::DisallowedExtension` before any of it is written.
+    pub fn denied_extensions(mut self, extensions: &[&str]) -> Self {
+        self.denied_extensions = extensions.iter().map(|e| normalize_extension(e)).collect();
+        self
+    }
+
+    /// Inspect file uploads that are zip, tar or gzip archives once they have been loaded, failing
+    /// the load with `LoadError::Archive` if they look like a decompression bomb - by default
+    /// archives aren't inspected
+    ///
+    /// Each file is checked by `inspect_archive()`, reading only the archive's own records (on
+    /// the blocking thread pool) rather than decompressing it. Temporary, saved and in memory
+    /// files are inspected, but not those sent to the `storage()` or encrypted.
+    pub fn inspect_archives(mut self, limits: ArchiveLimits) -> Self {
+        self.archive_limits = Some(limits);
+        self
+    }
+
+    /// Scan each file upload loaded as a `MultipartFile` with `hook` (e.g. an antivirus), failing
+    /// the load with `LoadError::Scan` if a scan fails - by default files aren't scanned
+    ///
+    /// Files are scanned one at a time, as soon as each has been written, so the extractor doesn't
+    /// resolve until every file in the form has passed.
+    pub fn scan_hook<S: ScanHook + 'static>(mut self, hook: S) -> Self {
+        self.scan_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Keep file uploads in memory until they exceed `threshold` bytes (e.g. 256 KiB), only then
+    /// writing them to a temporary file
+    ///
+    /// File parts up to the threshold will be loaded as a `MemoryFile` rather than a
+    /// `MultipartFile`, so use `SpooledFile` as the type of file fields to accept either. Has no
+    /// effect on fields loaded as a `SavedFile`, or on `in_memory()` fields which are kept in
+    /// memory whatever their size. `validate()` checks that the threshold is below the
+    /// `file_limit` (and `max_file_size`), otherwise no file would ever be written to disk.
+    pub fn spool_threshold(mut self, threshold: u64) -> Self {
+        self.spool_threshold = Some(threshold);
+        self
+    }
+
+    /// How text parts that aren't valid UTF-8 are handled - default `Utf8Policy::Strict`
+    pub fn utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Decode a text field using `encoding`, regardless of the charset declared by the part
+    ///
+    /// Otherwise text is decoded using the `charset` parameter of the part's Content-Type, or the
+    /// value of a preceding `_charset_` field, or as UTF-8 (see `utf8_policy()`) if there is
+    /// neither. Malformed sequences in any encoding other than UTF-8 are replaced with
+    /// `U+FFFD REPLACEMENT CHARACTER`. Indexed names (e.g. `names[0]`) use the encoding of their
+    /// field (`names`).
+    pub fn field_charset(mut self, field_name: &str, encoding: &'static Encoding) -> Self {
+        self.charsets.push((field_name.to_string(), encoding));
+        self
+    }
+
+    /// Reject any deviation from RFC 7578 with a `LoadError::Nonconforming` - default false
+    ///
+    /// In strict mode parts must have a `form-data` Content-Disposition with a non-empty name
+    /// (the `empty_names` policy is ignored), and must not use the deprecated
+    /// Content-Transfer-Encoding header. File parts (those with a filename) must declare their
+    /// Content-Type, and no other headers are allowed (apart from Content-ID, if `related()` is
+    /// enabled). Malformed framing, such as stray CRLFs around a boundary, fails the load instead
+    /// of ending the form at the last readable part.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Skip parts with unusable headers instead of failing the whole form - default false
+    ///
+    /// Parts without a `form-data` Content-Disposition or a name are read and discarded (still
+    /// counting towards the limits), and if the body becomes unreadable the parts received so
+    /// far are kept. Each is recorded as a `SkippedPart`, see `load_parts_with_skipped()` and
+    /// `SkippedParts`. The form still fails to bind if a required field was among those
+    /// skipped. Has no effect in `strict()` mode.
+    pub fn skip_malformed(mut self, skip: bool) -> Self {
+        self.skip_malformed = skip;
+        self
+    }
+
+    /// Decode parts sent with a `base64` or `quoted-printable` Content-Transfer-Encoding, as
+    /// some older clients do - default false, delivering the encoded bytes as they were sent
+    ///
+    /// Parts are decoded before any of the `stage()`s, and the size limits apply to the decoded
+    /// body. A body that isn't validly encoded fails the load with
+    /// `PayloadError::EncodingCorrupted`. Can't be combined with `strict()` mode, which rejects
+    /// the header.
+    pub fn decode_transfer_encoding(mut self, decode: bool) -> Self {
+        self.decode_transfer_encoding = decode;
+        self
+    }
+
+    /// Accept several files for one field sent as a nested `multipart/mixed` part, as RFC 2388
+    /// allowed - default false, `actix_multipart` can't read past such a part so the form ends
+    /// there (as with any malformed framing, see `strict()`)
+    ///
+    /// Each file of the set is loaded as a part of its own, with the name of the field. Applies
+    /// to the `ValidatedMultipartForm` and `RawMultipart` extractors, when calling `load_parts()`
+    /// create the `Multipart` using `flatten_mixed()` instead.
+    pub fn nested_file_sets(mut self, nested: bool) -> Self {
+        self.nested_file_sets = nested;
+        self
+    }
+
+    /// Accept the parts of a `multipart/related` payload (RFC 2387), such as SOAP with
+    /// attachments - default false
+    ///
+    /// Parts that have a `Content-ID` header but not a `form-data` Content-Disposition are named
+    /// after their Content-ID (without the angle brackets), so `<image@example.com>` is loaded
+    /// as the field `image@example.com`. They are otherwise loaded like any other part (so are
+    /// text if they are `text/plain` without a filename).
+    pub fn related(mut self, related: bool) -> Self {
+        self.related = related;
+        self
+    }
+
+    /// Creates the `Multipart` for the payload of a request, flattening nested file sets if
+    /// `nested_file_sets()` is enabled, along with the `PayloadDrain` for `drain()`
+    pub(crate) fn multipart<S>(
+        &self,
+        headers: &HeaderMap,
+        payload: S,
+    ) -> (actix_multipart::Multipart, PayloadDrain)
+    where
+        S: Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static,
+    {
+        let (payload, drain) = share_payload(payload);
+        let multipart = if self.nested_file_sets {
+            flatten_mixed(headers, payload)
+        } else {
+            actix_multipart::Multipart::new(headers, payload)
+        };
+        (multipart, drain)
+    }
+
+    /// Discards the rest of the payload after the form failed to load, up to the
+    /// `drain_on_error()` limit
+    pub(crate) fn drain(&self, drain: PayloadDrain) -> impl Future<Output = ()> {
+        drain.drain(self.drain_limit, self.deadline)
+    }
+
+    /// Reject the form with `413 Payload Too Large` before reading any of it, if the
+    /// Content-Length of `req` is more than the `text_limit` and `file_limit` allow
+    ///
+    /// Some allowance is made for the boundary and headers of each part (up to `max_parts`).
+    /// The extractors do this automatically.
+    pub fn content_length(mut self, req: &HttpRequest) -> Self {
+        self.content_length = crate::payload::content_length(req).map(|l| l as u64);
+        self
+    }
+
+    /// Whether a part may have the header `name` in `strict()` mode (RFC 7578, section 4.8)
+    fn allows_header(&self, name: &header::HeaderName) -> bool {
+        name == header::CONTENT_DISPOSITION
+            || name == header::CONTENT_TYPE
+            || (self.related && name == "content-id")
+    }
+
+    /// The most bytes a multipart body can contain without exceeding the limits
+    fn max_body_size(&self) -> u64 {
+        (self.text_limit as u64)
+            .saturating_add(self.file_limit)
+            .saturating_add(self.max_parts as u64 * PART_OVERHEAD)
+    }
+
+    /// After the form fails to load, read and discard up to `max` bytes of the rest of the
+    /// request body before returning the error - default 0
+    ///
+    /// Otherwise the unread body is left in the connection, so it can't be kept alive and some
+    /// clients see the connection being reset rather than the error response. If more than `max`
+    /// bytes remain (or the `deadline()` passes) the rest is left unread. Applies to the
+    /// `ValidatedMultipartForm` and `RawMultipart` extractors.
+    pub fn drain_on_error(mut self, max: u64) -> Self {
+        self.drain_limit = max;
+        self
+    }
+
+    /// Abort loading with a `LoadError::Timeout` once `deadline` has passed
+    ///
+    /// If set more than once the earliest deadline applies. Parts that were partially loaded are
+    /// cleaned up the same as for any other error. The `ValidatedMultipartForm` extractor also
+    /// applies the `ExtractionDeadline` request extension.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(match self.deadline {
+            Some(existing) => existing.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
+    /// Abort loading with a `LoadError::ReadTimeout` if no data is received for `timeout` - default
+    /// unlimited
+    ///
+    /// Unlike the `deadline()` this allows a large upload to take as long as it needs, while still
+    /// cutting off a client that stalls (or trickles the body a few bytes at a time) so that it
+    /// can't hold on to a connection and a partially written temporary file indefinitely. The
+    /// timer restarts each time a chunk of the body arrives.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// The idempotency key of the request, used to name saved files by
+    /// `NamingStrategy::IdempotencyKey`
+    ///
+    /// Set by the `ValidatedMultipartForm` extractor from the header configured with
+    /// `ValidatedMultipartFormConfig::idempotency_key()`.
+    pub fn idempotency_key(mut self, key: &str) -> Self {
+        self.idempotency_key = Some(key.to_string());
+        self
+    }
+
+    /// Stop loading forms when the server shuts down, giving those in flight a grace period to
+    /// finish (see `ShutdownSignal`)
+    pub fn shutdown(mut self, signal: ShutdownSignal) -> Self {
+        self.shutdown = Some(signal);
+        self
+    }
+
+    /// Pass the body of each part through `stage`, after any stages that were already added (see
+    /// `PartStage`)
+    pub fn stage<S: PartStage + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Arc::new(stage));
+        self
+    }
+
+    /// What to do with parts that have an empty name - default `EmptyNamePolicy::Reject`
+    pub fn empty_names(mut self, policy: EmptyNamePolicy) -> Self {
+        self.empty_names = policy;
+        self
+    }
+
+    /// Keep the empty file parts (zero bytes, with an empty or no filename) that browsers send
+    /// for a file input that was left blank - default true
+    ///
+    /// When disabled such parts are dropped from the form, so an `Option<T>` field of any file
+    /// type is `None` (and a `Vec<T>` doesn't include them), whichever way files are stored. They
+    /// still count towards `max_files()`.
+    pub fn blank_files(mut self, keep: bool) -> Self {
+        self.blank_files = keep;
+        self
+    }
+
+    /// The names of the fields the form is expected to contain, for `unknown_parts()`
+    ///
+    /// A part is expected if it is named exactly the same as one of them, or is an item of it
+    /// such as `field[0]`. This is set automatically by `#[derive(FromMultipart)]`.
+    pub fn expected_fields(mut self, field_names: &[&str]) -> Self {
+        self.expected_fields = Some(field_names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    /// Adds to the `expected_fields()`, if they have been set
+    pub(crate) fn expect_field(mut self, field_name: &str) -> Self {
+        if let Some(fields) = &mut self.expected_fields {
+            fields.push(field_name.to_string());
+        }
+        self
+    }
+
+    /// What to do with parts that don't belong to any of the `expected_fields()` - default
+    /// `UnknownPartPolicy::Accept`
+    ///
+    /// Rejecting or skipping them means they aren't written to disk (or kept in memory) only to
+    /// be dropped when the form is converted.
+    pub fn unknown_parts(mut self, policy: UnknownPartPolicy) -> Self {
+        self.unknown_parts = policy;
+        self
+    }
+
+    /// Maximum file parts (including `stream_text` fields) being written to disk at once - by
+    /// default unlimited
+    ///
+    /// The limit is shared by every request loaded using this config (and its clones), so create
+    /// the config once outside of the `HttpServer::new` closure to share it between workers.
+    /// While waiting the rest of the request isn't read, applying backpressure to the client, but
+    /// the `deadline()` and `read_timeout()` still apply.
+    pub fn max_concurrent_writes(mut self, max: usize) -> Self {
+        self.write_permits = Some((max, Arc::new(Semaphore::new(max))));
+        self
+    }
+
+    /// Maximum rate (in bytes per second) that the body of a request is read at - by default
+    /// unlimited
+    ///
+    /// Applies to the bodies of all the parts of a request together, so that a single client
+    /// uploading quickly can't saturate the disk for everyone else. The client sees the upload
+    /// slow down, as the rest of the request isn't read while waiting. Each request gets its own
+    /// allowance, use `max_concurrent_writes()` to also limit how many are written at once.
+    ///
+    /// # Panics
+    /// If `bytes_per_sec` is zero, as nothing could ever be read
+    pub fn max_throughput(mut self, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "max_throughput must be at least 1");
+        self.max_throughput = Some(bytes_per_sec);
+        self
+    }
+
+    /// Bytes of a file part to collect before writing them to disk - default 256 KiB
+    ///
+    /// Each write is a hop to the blocking thread pool, so the (typically much smaller) chunks
+    /// received from the network are coalesced rather than written one at a time. Applies to
+    /// temporary, saved and `stream_text` files, set to 0 to write each chunk as it arrives.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.write_buffer_size = size;
+        self
+    }
+
+    /// Keep up to `max` buffers (for writing files and collecting text fields) for reuse once a
+    /// part has been loaded, rather than allocating new ones for each part - by default none
+    ///
+    /// The pool is shared by every request loaded using this config (and its clones), so create
+    /// the config once outside of the `HttpServer::new` closure to share it between workers. Each
+    /// buffer is at least the `write_buffer_size()`, so the pool can hold on to up to `max` times
+    /// that (more if a text field grew one larger).
+    pub fn buffer_pool(mut self, max: usize) -> Self {
+        self.buffer_pool = Some(Arc::new(BufferPool::new(max)));
+        self
+    }
+
+    /// A buffer from the `buffer_pool()` if there is one, otherwise a new one
+    pub(crate) fn write_buffer(&self) -> WriteBuffer {
+        match &self.buffer_pool {
+            Some(pool) => pool.take(self.write_buffer_size),
+            None => WriteBuffer::new(self.write_buffer_size),
+        }
+    }
+
+    /// Compute the SHA-256 and MD5 digests of file uploads while they are written to disk -
+    /// default false
+    ///
+    /// The hex encoded digests are set as `MultipartFile::sha256` and `MultipartFile::md5`, so
+    /// that the file needn't be read again to check its integrity.
+    pub fn digests(mut self, enabled: bool) -> Self {
+        self.digests = enabled;
+        self
+    }
+
+    /// Deduplicate file uploads using the SHA-256 digest of their contents
+    ///
+    /// Once a file has been received the (hex encoded) digest is passed to `exists`. If this
+    /// resolves to `true` the contents are discarded, leaving the temporary file empty, and the
+    /// `MultipartFile` is marked as `deduplicated` so that the application can skip persisting it
+    /// again. A file no larger than the `write_buffer_size()` is held in memory until then, so a
+    /// duplicate is never written to disk, a larger file is written as usual and then emptied.
+    /// Deduplicated files still count towards the `file_limit`.
+    ///
+    /// # Example
+    /// ```
+    /// # use actix_validated_forms::multipart::MultipartLoadConfig;
+    /// # use futures::FutureExt;
+    /// # async fn lookup(_: &str) -> bool { false }
+    /// MultipartLoadConfig::default()
+    ///     .deduplicate(|sha256| async move { lookup(&sha256).await }.boxed_local());
+    /// ```
+    pub fn deduplicate<F>(mut self, exists: F) -> Self
+    where
+        F: Fn(String) -> LocalBoxFuture<'static, bool> + Send + Sync + 'static,
+    {
+        self.exists = Some(Arc::new(exists));
+        self
+    }
 }
 
+impl MultipartLoadConfig {
+    /// Checks that the settings are consistent with each other, and that the directories they
+    /// refer to exist
+    ///
+    /// Call this at startup, so that a misconfiguration is reported straight away rather than as
+    /// confusing rejections of uploads.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let zero = [
+            ("max_parts", self.max_parts == 0),
+            ("max_parts_per_name", self.max_parts_per_name == Some(0)),
+            (
+                "max_concurrent_writes",
+                matches!(self.write_permits, Some((0, _))),
+            ),
+        ];
+        if let Some((setting, _)) = zero.iter().find(|(_, zero)| *zero) {
+            return Err(ConfigError::Invalid(format!(
+                "{} must be at least 1",
+                setting
+            )));
+        }
+        if let Some(max) = self.max_file_size {
+            if max > self.file_limit {
+                return Err(ConfigError::Inconsistent(format!(
+                    "max_file_size ({}) is greater than file_limit ({})",
+                    max, self.file_limit
+                )));
+            }
+        }
+        if let Some(threshold) = self.spool_threshold {
+            // A file is rejected before it would ever be written to disk
+            let (limit, setting) = match self.max_file_size {
+                Some(max) if max < self.file_limit => (max, "max_file_size"),
+                _ => (self.file_limit, "file_limit"),
+            };
+            if threshold >= limit {
+                return Err(ConfigError::Inconsistent(format!(
+                    "spool_threshold ({}) is not less than {} ({}), so files are never spooled",
+                    threshold, setting, limit
+                )));
+            }
+        }
+        if let Some(max) = self.max_parts_per_name {
+            if max > self.max_parts {
+                return Err(ConfigError::Inconsistent(format!(
+                    "max_parts_per_name ({}) is greater than max_parts ({})",
+                    max, self.max_parts
+                )));
+            }
+        }
+        if !self.stream_text.is_empty() && self.file_limit == 0 {
+            return Err(ConfigError::Inconsistent(
+                "stream_text fields count towards the file_limit, which is zero".to_string(),
+            ));
+        }
+        if self.strict && self.decode_transfer_encoding {
+            return Err(ConfigError::Inconsistent(
+                "strict mode rejects the parts that decode_transfer_encoding would decode"
+                    .to_string(),
+            ));
+        }
+        if self.empty_names == EmptyNamePolicy::Rename(String::new()) {
+            return Err(ConfigError::Inconsistent(
+                "empty_names renames parts to an empty name".to_string(),
+            ));
+        }
+        if let Some(dir) = &self.temp_dir {
+            check_directory("temp_dir", dir)?;
+        }
+        if let Some((dir, _)) = &self.save_to {
+            check_directory("save_to", dir)?;
+        }
+        Ok(())
+    }
+
+    /// A description of the effective limits and settings, to log at startup
+    ///
+    /// # Example
+    /// ```
+    /// # use actix_validated_forms::multipart::MultipartLoadConfig;
+    /// let config = MultipartLoadConfig::default().max_parts(10);
+    /// assert_eq!(
+    ///     config.summarize(),
+    ///     "text_limit: 1048576 bytes, file_limit: 536870912 bytes, max_file_size: unlimited, \
+    ///      max_parts: 10, max_parts_per_name: unlimited, max_files: unlimited, \
+    ///      max_text_fields: unlimited, max_concurrent_writes: unlimited, \
+    ///      max_throughput: unlimited, temp_dir: system default, save_to: none, strict: false, skip_malformed: false"
+    /// );
+    /// ```
+    pub fn summarize(&self) -> String {
+        let unlimited = |max: Option<usize>| match max {
+            Some(max) => max.to_string(),
+            None => "unlimited".to_string(),
+        };
+        format!(
+            "text_limit: {} bytes, file_limit: {} bytes, max_file_size: {}, max_parts: {}, \
+             max_parts_per_name: {}, max_files: {}, max_text_fields: {}, \
+             max_concurrent_writes: {}, max_throughput: {}, temp_dir: {}, save_to: {}, \
+             strict: {}, skip_malformed: {}",
+            self.text_limit,
+            self.file_limit,
+            match self.max_file_size {
+                Some(max) => format!("{} bytes", max),
+                None => "unlimited".to_string(),
+            },
+            self.max_parts,
+            unlimited(self.max_parts_per_name),
+            unlimited(self.max_files),
+            unlimited(self.max_text_fields),
+            unlimited(self.write_permits.as_ref().map(|(max, _)| *max)),
+            match self.max_throughput {
+                Some(max) => format!("{} bytes/s", max),
+                None => "unlimited".to_string(),
+            },
+            match &self.temp_dir {
+                Some(dir) => dir.display().to_string(),
+                None => "system default".to_string(),
+            },
+            match &self.save_to {
+                Some((dir, _)) => dir.display().to_string(),
+                None => "none".to_string(),
+            },
+            self.strict,
+            self.skip_malformed,
+        )
+    }
+
+    /// Whether a part named `name` belongs to one of the `expected_fields()`
+    fn expects(&self, name: &str) -> bool {
+        match &self.expected_fields {
+            Some(fields) => fields.iter().any(|f| belongs_to(name, f)),
+            None => true,
+        }
+    }
+
+    /// Whether a file upload for the part `name` with the content type `mime` is allowed by
+    /// `field_mimes()`, or otherwise `allowed_mimes()`
+    fn allows_mime(&self, name: &str, mime: &mime::Mime) -> bool {
+        let field_name = name.split('[').next().unwrap();
+        let allowed_mimes = self
+            .field_mimes
+            .iter()
+            .find(|(name, _)| name == field_name)
+            .map_or(&self.allowed_mimes, |(_, mimes)| mimes);
+        allowed_mimes.is_empty()
+            || allowed_mimes
+                .iter()
+                .any(|allowed| mime_matches(allowed, mime))
+    }
+
+    /// Whether a file with the content type `declared` may contain content sniffed as `sniffed`,
+    /// according to `reject_spoofed_mimes()`
+    fn allows_content(&self, declared: &mime::Mime, sniffed: Option<&mime::Mime>) -> bool {
+        let acceptable = match &self.spoof_check {
+            Some(acceptable) => acceptable,
+            None => return true,
+        };
+        let sniffed = sniffed.unwrap_or(&mime::APPLICATION_OCTET_STREAM);
+        let matches = if sniffed == &mime::APPLICATION_OCTET_STREAM {
+            !is_sniffable(declared)
+        } else {
+            declared.essence_str() == sniffed.essence_str()
+        };
+        matches
+            || acceptable
+                .iter()
+                .any(|(d, s)| mime_matches(d, declared) && mime_matches(s, sniffed))
+    }
+
+    /// Whether an upload named `filename` is allowed by `allowed_extensions()` and
+    /// `denied_extensions()`
+    fn allows_filename(&self, filename: &str) -> bool {
+        // Windows ignores trailing dots and spaces, so `shell.php.` would be saved as `shell.php`
+        let extension = Path::new(filename.trim_end_matches(|c| c == '.' || c == ' '))
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(normalize_extension);
+        match extension {
+            Some(extension) => {
+                !self.denied_extensions.contains(&extension)
+                    && (self.allowed_extensions.is_empty()
+                        || self.allowed_extensions.contains(&extension))
+            }
+            None => self.allowed_extensions.is_empty(),
+        }
+    }
+
+    /// Creates a temporary file in the `temp_dir`, for a part uploaded with `filename`, and
+    /// whether it was made private (see `private_temp_files()`)
+    pub(crate) fn temp_file(
+        &self,
+        filename: Option<&str>,
+    ) -> Result<(NamedTempFile, bool), MultipartError> {
+        let extension = filename
+            .filter(|_| self.preserve_extension)
+            .and_then(|f| Path::new(f).extension())
+            .and_then(OsStr::to_str)
+            .filter(|e| !e.is_empty() && e.len() <= 16)
+            .filter(|e| e.chars().all(|c| c.is_ascii_alphanumeric()));
+        let suffix = match extension {
+            Some(extension) => format!(".{}", extension),
+            None => String::new(),
+        };
+        let io_error = |e| MultipartError::Payload(PayloadError::Io(e));
+        let ntf = create_temp_file(self.temp_dir.as_deref(), &suffix).map_err(io_error)?;
+        let private = if self.private_temp_files {
+            make_private(ntf.as_file()).map_err(io_error)?
+        } else {
+            false
+        };
+        Ok((ntf, private))
+    }
+}
+
+/// Whether `mime` matches `pattern`, which may have a `*` type or subtype
+fn mime_matches(pattern: &mime::Mime, mime: &mime::Mime) -> bool {
+    (pattern.type_() == mime::STAR || pattern.type_() == mime.type_())
+        && (pattern.subtype() == mime::STAR || pattern.subtype() == mime.subtype())
+}
+
+fn normalize_extension(extension: &str) -> String {
+    extension.trim_start_matches('.').to_lowercase()
+}
+
+/// The bytes allowed for the boundary and headers of each part by
+/// `MultipartLoadConfig::content_length()`
+const PART_OVERHEAD: u64 = 1024;
+
 impl Default for MultipartLoadConfig {
     fn default() -> Self {
         // Defaults are 1MB of text and 512MB of files
         MultipartLoadConfig {
             text_limit: 1 * 1024 * 1024,
             file_limit: 512 * 1024 * 1024,
+            max_file_size: None,
             max_parts: 1000,
+            max_parts_per_name: None,
+            max_files: None,
+            max_text_fields: None,
+            save_to: None,
+            storage: None,
+            temp_dir: None,
+            preserve_extension: false,
+            sanitize_filenames: false,
+            private_temp_files: false,
+            stream_text: Vec::new(),
+            in_memory: Vec::new(),
+            allowed_mimes: Vec::new(),
+            field_mimes: Vec::new(),
+            spoof_check: None,
+            allowed_extensions: Vec::new(),
+            denied_extensions: Vec::new(),
+            archive_limits: None,
+            scan_hook: None,
+            spool_threshold: None,
+            utf8_policy: Utf8Policy::Strict,
+            charsets: Vec::new(),
+            empty_names: EmptyNamePolicy::Reject,
+            blank_files: true,
+            expected_fields: None,
+            unknown_parts: UnknownPartPolicy::Accept,
+            strict: false,
+            skip_malformed: false,
+            decode_transfer_encoding: false,
+            nested_file_sets: false,
+            related: false,
+            deadline: None,
+            read_timeout: None,
+            drain_limit: 0,
+            content_length: None,
+            idempotency_key: None,
+            shutdown: None,
+            stages: Vec::new(),
+            write_permits: None,
+            max_throughput: None,
+            buffer_pool: None,
+            write_buffer_size: 262_144,
+            digests: false,
+            exists: None,
+        }
+    }
+}
+
+fn check_directory(setting: &'static str, dir: &Path) -> Result<(), ConfigError> {
+    if dir.is_dir() {
+        Ok(())
+    } else {
+        Err(ConfigError::NotADirectory(setting, dir.to_owned()))
+    }
+}
+
+/// Decides how invalid UTF-8 in a text part is handled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Utf8Policy {
+    /// Fail loading the form with a parse error
+    Strict,
+    /// Replace any invalid sequences with `U+FFFD REPLACEMENT CHARACTER`
+    Lossy,
+    /// Decode the entire part as ISO-8859-1 (Latin-1) instead
+    Latin1Fallback,
+    /// Keep the part undecoded, as a `MultipartBytes`
+    Bytes,
+}
+
+/// Decides what happens to a part whose Content-Disposition `name` is an empty string
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmptyNamePolicy {
+    /// Fail loading the form with a parse error (the same as if the name were missing)
+    Reject,
+    /// Read and discard the part
+    Skip,
+    /// Load the part under the given name instead
+    Rename(String),
+}
+
+/// Decides what happens to a part that doesn't belong to any of the
+/// `MultipartLoadConfig::expected_fields()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownPartPolicy {
+    /// Load the part as usual
+    Accept,
+    /// Fail loading the form with `LoadError::UnexpectedPart`
+    Reject,
+    /// Read and discard the part (it still counts towards the limits)
+    Skip,
+}
+
+/// A deviation from RFC 7578, rejected by `MultipartLoadConfig::strict()` (or skipped by
+/// `MultipartLoadConfig::skip_malformed()`)
+#[derive(Clone, Copy, Debug, PartialEq, Error)]
+pub enum Nonconformance {
+    #[error(display = "Part is missing a Content-Disposition header")]
+    MissingDisposition,
+    #[error(display = "Part has a Content-Disposition other than form-data")]
+    NotFormData,
+    #[error(display = "Part is missing a Content-Disposition name")]
+    MissingName,
+    #[error(display = "Part has an empty Content-Disposition name")]
+    EmptyName,
+    #[error(display = "Part uses the deprecated Content-Transfer-Encoding header")]
+    TransferEncoding,
+    #[error(display = "File part is missing a Content-Type header")]
+    MissingContentType,
+    #[error(display = "Part has a header other than Content-Disposition and Content-Type")]
+    ForbiddenHeader,
+    #[error(display = "Multipart body is malformed")]
+    Malformed,
+}
+
+impl Nonconformance {
+    /// A stable code identifying the deviation, e.g. `missing_name`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Nonconformance::MissingDisposition => "missing_disposition",
+            Nonconformance::NotFormData => "not_form_data",
+            Nonconformance::MissingName => "missing_name",
+            Nonconformance::EmptyName => "empty_name",
+            Nonconformance::TransferEncoding => "transfer_encoding",
+            Nonconformance::MissingContentType => "missing_content_type",
+            Nonconformance::ForbiddenHeader => "forbidden_header",
+            Nonconformance::Malformed => "malformed",
+        }
+    }
+}
+
+/// A part that was discarded by `MultipartLoadConfig::skip_malformed()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedPart {
+    /// The name of the part, if it had one
+    pub name: Option<String>,
+    pub reason: Nonconformance,
+}
+
+/// The parts skipped while extracting a `ValidatedMultipartForm`, added to the request
+/// extensions when there were any
+///
+/// # Example
+/// ```
+/// # use actix_web::{HttpRequest, HttpResponse};
+/// # use actix_validated_forms::multipart::SkippedParts;
+/// async fn route(req: HttpRequest) -> HttpResponse {
+///     if let Some(skipped) = req.extensions().get::<SkippedParts>() {
+///         eprintln!("Skipped {} malformed parts", skipped.0.len());
+///     }
+///     # unimplemented!()
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SkippedParts(pub Vec<SkippedPart>);
+
+/// One of the size limits of `MultipartLoadConfig`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitKind {
+    /// The `MultipartLoadConfig::text_limit()`, of all the text fields together
+    Text,
+    /// The `MultipartLoadConfig::file_limit()`, of all the files together
+    File,
+    /// The `MultipartLoadConfig::max_file_size()`, of each file
+    FileSize,
+}
+
+/// A part that was larger than one of the size limits allowed
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultipartLimitError {
+    /// The name of the part
+    pub field: String,
+    /// The limit that was exceeded, in bytes
+    pub limit: u64,
+    pub kind: LimitKind,
+}
+
+impl std::error::Error for MultipartLimitError {}
+
+impl Display for MultipartLimitError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            LimitKind::Text => "text limit",
+            LimitKind::File => "file limit",
+            LimitKind::FileSize => "max file size",
+        };
+        write!(
+            f,
+            "Field '{}' exceeds the {} of {} bytes",
+            self.field, kind, self.limit
+        )
+    }
+}
+
+/// An error encountered while loading a multipart form
+#[derive(Debug, Error)]
+pub enum LoadError {
+    /// The multipart payload couldn't be read, or `MultipartLoadConfig::max_parts()` was
+    /// exceeded
+    #[error(display = "{}", _0)]
+    Multipart(MultipartError),
+    /// A part was larger than one of the size limits allowed
+    #[error(display = "{}", _0)]
+    Limit(MultipartLimitError),
+    /// More parts shared a field name than allowed by `MultipartLoadConfig::max_parts_per_name()`
+    #[error(display = "Too many parts for field '{}'", _0)]
+    TooManyParts(String),
+    /// The form contained more files than allowed by `MultipartLoadConfig::max_files()`
+    #[error(display = "Too many files")]
+    TooManyFiles,
+    /// The form contained more text fields than allowed by
+    /// `MultipartLoadConfig::max_text_fields()`
+    #[error(display = "Too many text fields")]
+    TooManyTextFields,
+    /// A part didn't belong to any of the `MultipartLoadConfig::expected_fields()` (only when
+    /// `MultipartLoadConfig::unknown_parts()` is `UnknownPartPolicy::Reject`)
+    #[error(display = "Unexpected part '{}'", _0)]
+    UnexpectedPart(String),
+    /// A file was uploaded with a content type not allowed by
+    /// `MultipartLoadConfig::allowed_mimes()`
+    #[error(display = "Content type '{}' is not allowed for field '{}'", _1, _0)]
+    DisallowedMime(String, mime::Mime),
+    /// The content of a file doesn't match its content type, and the mismatch isn't one of those
+    /// allowed by `MultipartLoadConfig::reject_spoofed_mimes()`
+    #[error(
+        display = "Content of the file for field '{}' doesn't match its content type '{}'",
+        _0,
+        _1
+    )]
+    SpoofedMime(String, mime::Mime),
+    /// A file is an archive that exceeds the `MultipartLoadConfig::inspect_archives()` limits
+    #[error(display = "Archive uploaded for field '{}' was rejected: {}", _0, _1)]
+    Archive(String, ArchiveError),
+    /// A file failed the `MultipartLoadConfig::scan_hook()`, for the field and filename given
+    #[error(
+        display = "File {:?} uploaded for field '{}' was rejected: {}",
+        _1,
+        _0,
+        _2
+    )]
+    Scan(String, Option<String>, ScanError),
+    /// A file was uploaded with a filename extension not allowed by
+    /// `MultipartLoadConfig::allowed_extensions()` or `MultipartLoadConfig::denied_extensions()`
+    #[error(display = "File '{}' does not have an allowed extension", _0)]
+    DisallowedExtension(String),
+    /// The form doesn't conform to RFC 7578 (only when `MultipartLoadConfig::strict()` is set)
+    #[error(display = "{}", _0)]
+    Nonconforming(Nonconformance),
+    /// The `MultipartLoadConfig::deadline()` passed before the form was loaded
+    #[error(display = "Multipart form wasn't loaded before the deadline")]
+    Timeout,
+    /// No data was received for the `MultipartLoadConfig::read_timeout()`
+    #[error(display = "Timed out waiting for the multipart form to be sent")]
+    ReadTimeout,
+    /// The Content-Length of the request is more than the limits allow (see
+    /// `MultipartLoadConfig::content_length()`)
+    #[error(
+        display = "Content-Length of {} bytes is more than the allowed {} bytes",
+        _0,
+        _1
+    )]
+    ContentLength(u64, u64),
+    /// The server is shutting down (see `MultipartLoadConfig::shutdown()`)
+    #[error(display = "Server is shutting down")]
+    ShuttingDown,
+}
+
+impl From<MultipartError> for LoadError {
+    fn from(e: MultipartError) -> Self {
+        LoadError::Multipart(e)
+    }
+}
+
+impl LoadError {
+    /// The closest `MultipartError`, for `load_parts()`
+    fn into_multipart_error(self) -> MultipartError {
+        let kind = match self {
+            LoadError::Multipart(e) => return e,
+            LoadError::Limit(_) | LoadError::ContentLength(..) => {
+                return MultipartError::Payload(PayloadError::Overflow)
+            }
+            LoadError::Timeout | LoadError::ReadTimeout => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::Other,
+        };
+        let e = std::io::Error::new(kind, self.to_string());
+        MultipartError::Payload(PayloadError::Io(e))
+    }
+}
+
+impl ResponseError for LoadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoadError::Multipart(e) => e.status_code(),
+            LoadError::Timeout | LoadError::ReadTimeout => StatusCode::REQUEST_TIMEOUT,
+            LoadError::ShuttingDown | LoadError::Scan(_, _, ScanError::Failed(_)) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            LoadError::Limit(_) | LoadError::ContentLength(..) => StatusCode::PAYLOAD_TOO_LARGE,
+            LoadError::DisallowedMime(..) | LoadError::SpoofedMime(..) => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            _ => StatusCode::BAD_REQUEST,
         }
     }
 }
 
 /// Use to load a multipart form from an Actix Multipart request
 ///
-/// This is an asynchronous operation, blocking IO such as writing an uploaded file
-/// to disk will be done on a background thread pool (using `actix_web::web::block`)
+/// This is an asynchronous operation, uploaded files are written to disk using asynchronous
+/// file IO (`tokio::fs`, or io-uring with the `io-uring` feature)
+///
+/// Forms rejected by the limits and checks of the config (see `LoadError`) are reported as the
+/// closest `MultipartError` (size limits as `PayloadError::Overflow`), use
+/// `load_parts_with_skipped()` to get the `LoadError` itself.
 ///
 /// # Example
 /// ```
@@ -66,65 +1260,1105 @@ impl Default for MultipartLoadConfig {
 ///     # unimplemented!() }
 /// ```
 pub async fn load_parts(
-    mut payload: actix_multipart::Multipart,
+    payload: actix_multipart::Multipart,
     config: MultipartLoadConfig,
 ) -> Result<Multiparts, MultipartError> {
-    let mut parts = Multiparts::new();
-    let mut text_budget = config.text_limit;
-    let mut file_budget = config.file_limit;
+    load_parts_with_skipped(payload, config)
+        .await
+        .map(|(parts, _)| parts)
+        .map_err(LoadError::into_multipart_error)
+}
 
-    while let Ok(Some(field)) = payload.try_next().await {
-        if parts.len() >= config.max_parts {
-            return Err(MultipartError::Payload(PayloadError::Overflow));
+/// The same as `load_parts()`, but also returns the parts that were discarded by
+/// `MultipartLoadConfig::skip_malformed()`
+pub async fn load_parts_with_skipped(
+    payload: actix_multipart::Multipart,
+    config: MultipartLoadConfig,
+) -> Result<(Multiparts, Vec<SkippedPart>), LoadError> {
+    let mut parts = Multiparts::new();
+    let mut loader = Loader::new(payload, config);
+    match loader.load_all(&mut parts).await {
+        Ok(_) => Ok((parts, loader.skipped)),
+        Err(e) => {
+            remove_saved_files(&parts);
+            Err(e)
         }
-        let cd = match field.content_disposition() {
-            Some(cd) => cd,
-            None => return Err(MultipartError::Parse(ParseError::Header)),
+    }
+}
+
+/// Saved (and stored) files aren't cleaned up automatically, so don't leave them behind when a
+/// load fails
+pub(crate) fn remove_saved_files(parts: &Multiparts) {
+    SavedFiles::of(parts).remove()
+}
+
+/// The saved (and stored) files of some parts, so that they can still be removed once the parts
+/// have been consumed, e.g. by a `TryFrom<Multiparts>` that fails
+pub(crate) struct SavedFiles {
+    paths: Vec<PathBuf>,
+    stored: Vec<Discard>,
+}
+
+type Discard = Box<dyn FnOnce() -> LocalBoxFuture<'static, ()> + Send>;
+
+impl SavedFiles {
+    pub(crate) fn of(parts: &Multiparts) -> Self {
+        let mut saved = SavedFiles {
+            paths: Vec::new(),
+            stored: Vec::new(),
         };
-        match cd.disposition {
-            DispositionType::FormData => {}
-            _ => return Err(MultipartError::Parse(ParseError::Header)),
+        for part in parts {
+            match part {
+                MultipartField::Saved(f) if !f.existing => saved.paths.push(f.path.clone()),
+                MultipartField::Stored(f) => saved.stored.push(Box::new(f.discard_later())),
+                _ => {}
+            }
         }
-        let name = match cd.get_name() {
-            Some(name) => name.to_owned(),
-            None => return Err(MultipartError::Parse(ParseError::Header)),
-        };
+        saved
+    }
 
-        // We need to default to TEXT_PLAIN however actix content_type() defaults to APPLICATION_OCTET_STREAM
-        let content_type = if field.headers().get(&header::CONTENT_TYPE).is_none() {
-            mime::TEXT_PLAIN
+    pub(crate) fn remove(self) {
+        for path in self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+        if self.stored.is_empty() {
+            return;
+        }
+        let discard_all = |stored: Vec<Discard>| future::join_all(stored.into_iter().map(|d| d()));
+        if actix_rt::Arbiter::is_running() {
+            actix_rt::spawn(discard_all(self.stored).map(|_| ()));
         } else {
-            field.content_type().clone()
-        };
+            // Outside of a running actix system (e.g. an expired session reaped by another thread)
+            // the storage is given a system of its own to discard the files on
+            let stored = self.stored;
+            std::thread::spawn(move || {
+                actix_rt::System::new("discard-stored-files").block_on(discard_all(stored));
+            });
+        }
+    }
+}
+
+/// The body of a part, as a stream of chunks
+pub(crate) trait PartBody: Stream<Item = Result<Bytes, MultipartError>> + Unpin {}
+
+impl<S> PartBody for S where S: Stream<Item = Result<Bytes, MultipartError>> + Unpin {}
+
+/// The body of a part, as it passes through the `PartStage`s
+pub type PartStream = LocalBoxStream<'static, Result<Bytes, MultipartError>>;
+
+/// The information from the headers of a part, used to decide how to load it
+#[derive(Clone, Debug)]
+pub struct PartInfo {
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The `filename` value in the `Content-Disposition` header, preferring the extended
+    /// `filename*` value (RFC 5987) if there is one
+    pub filename: Option<String>,
+    /// The plain `filename` value in the `Content-Disposition` header, when `filename` was taken
+    /// from the `filename*` value instead
+    pub fallback_filename: Option<String>,
+    /// The Content-Type of the part (`text/plain` if it didn't have one)
+    pub content_type: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+}
+
+impl PartInfo {
+    /// The `Content-ID` header of the part (without the angle brackets)
+    pub fn content_id(&self) -> Option<&str> {
+        content_id(&self.headers)
+    }
+
+    /// Whether the part is a text field (rather than a file upload)
+    pub fn is_text(&self) -> bool {
+        self.content_type.type_() == mime::TEXT
+            && self.content_type.subtype() == mime::PLAIN
+            && self.filename.is_none()
+    }
+}
+
+/// The `filename*` value of a Content-Disposition (already percent-decoded), decoded using its
+/// charset - `None` if there isn't one, or its charset isn't known
+fn extended_filename(cd: &header::ContentDisposition) -> Option<String> {
+    let ext = cd.get_filename_ext()?;
+    let encoding = Encoding::for_label(ext.charset.to_string().as_bytes())?;
+    Some(
+        encoding
+            .decode_without_bom_handling(&ext.value)
+            .0
+            .into_owned(),
+    )
+}
+
+/// How a part is loaded, decided once its headers have been parsed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartKind {
+    /// Into memory, as a `MultipartText`
+    Text,
+    /// Spooled to a temporary file, as a `TextStream` (see `MultipartLoadConfig::stream_text()`)
+    TextStream,
+    /// To a temporary file, as a `MultipartFile`
+    File,
+    /// To its final location, as a `SavedFile` (see `MultipartLoadConfig::save_to()`)
+    Saved,
+    /// Into memory, as a `MemoryFile` (see `MultipartLoadConfig::in_memory()`)
+    Memory,
+    /// Into a `MultipartStorage`, as a `StoredFile` (see `MultipartLoadConfig::storage()`)
+    Stored,
+}
+
+impl PartKind {
+    /// Whether the part will be written to disk
+    pub fn writes_to_disk(&self) -> bool {
+        match self {
+            PartKind::Text | PartKind::Memory | PartKind::Stored => false,
+            _ => true,
+        }
+    }
+}
+
+/// A custom stage of the loading pipeline, transforming the body of each part
+///
+/// A multipart form is loaded one part at a time: the headers of the part are parsed, it is
+/// classified (as a `PartKind`), its body is passed through each of the stages added with
+/// `MultipartLoadConfig::stage()` (in order), and finally it is stored (decoded into a `String`,
+/// or written to disk). The size limits apply to the body output by the last stage.
+///
+/// Closures with the same signature as `process()` are also stages.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{MultipartLoadConfig, PartInfo, PartKind, PartStream};
+/// # use futures::StreamExt;
+/// # fn encrypt(bytes: actix_web::web::Bytes) -> actix_web::web::Bytes { bytes }
+/// // Encrypt file uploads before they touch the disk
+/// MultipartLoadConfig::default().stage(|_: &PartInfo, kind: PartKind, body: PartStream| {
+///     match kind {
+///         PartKind::File => body.map(|chunk| chunk.map(encrypt)).boxed_local(),
+///         _ => body,
+///     }
+/// });
+/// ```
+pub trait PartStage: Send + Sync {
+    fn process(&self, info: &PartInfo, kind: PartKind, body: PartStream) -> PartStream;
+}
+
+impl<F> PartStage for F
+where
+    F: Fn(&PartInfo, PartKind, PartStream) -> PartStream + Send + Sync,
+{
+    fn process(&self, info: &PartInfo, kind: PartKind, body: PartStream) -> PartStream {
+        self(info, kind, body)
+    }
+}
+
+/// Loads parts from a multipart payload, keeping track of the remaining limits between parts
+pub(crate) struct Loader {
+    payload: actix_multipart::Multipart,
+    config: MultipartLoadConfig,
+    pub(crate) text_budget: usize,
+    pub(crate) file_budget: u64,
+    name_counts: HashMap<String, usize>,
+    file_count: usize,
+    text_count: usize,
+    saved_count: usize,
+    pub(crate) skipped: Vec<SkippedPart>,
+    /// Set if the server was already shutting down when loading began
+    rejected: bool,
+    /// The number of parts read from the payload so far
+    part_count: usize,
+    /// The charset given by the `_charset_` field, once it has been loaded
+    default_charset: Option<&'static Encoding>,
+    _in_flight: Option<InFlight>,
+    /// Shared by all the parts, if `max_throughput()` is set
+    throttle: Option<Throttle>,
+}
+
+impl Loader {
+    pub(crate) fn new(payload: actix_multipart::Multipart, config: MultipartLoadConfig) -> Self {
+        Loader {
+            text_budget: config.text_limit,
+            file_budget: config.file_limit,
+            name_counts: HashMap::new(),
+            file_count: 0,
+            text_count: 0,
+            saved_count: 0,
+            skipped: Vec::new(),
+            part_count: 0,
+            default_charset: None,
+            rejected: config
+                .shutdown
+                .as_ref()
+                .map_or(false, ShutdownSignal::is_shutting_down),
+            _in_flight: config.shutdown.as_ref().map(ShutdownSignal::track),
+            throttle: config.max_throughput.map(Throttle::new),
+            payload,
+            config,
+        }
+    }
+
+    /// Loads all the remaining parts into `parts`
+    pub(crate) async fn load_all(&mut self, parts: &mut Multiparts) -> Result<(), LoadError> {
+        while let Some((field, info)) = self.next_field().await? {
+            parts.extend(self.load_field(field, info).await?);
+        }
+        Ok(())
+    }
+
+    /// Reads the headers of the next part
+    ///
+    /// Every part read counts towards `max_parts()`, including those that are then skipped.
+    pub(crate) async fn next_field(
+        &mut self,
+    ) -> Result<Option<(actix_multipart::Field, PartInfo)>, LoadError> {
+        if self.rejected {
+            return Err(LoadError::ShuttingDown);
+        }
+        let max_body_size = self.config.max_body_size();
+        if let Some(length) = self.config.content_length.filter(|l| *l > max_body_size) {
+            return Err(LoadError::ContentLength(length, max_body_size));
+        }
+        loop {
+            let deadline = self.config.deadline;
+            let read_timeout = self.config.read_timeout;
+            let payload = &mut self.payload;
+            let read = async move {
+                let next = async move {
+                    match read_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, payload.try_next())
+                            .await
+                            .map_err(|_| LoadError::ReadTimeout),
+                        None => Ok(payload.try_next().await),
+                    }
+                };
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline.into(), next)
+                        .await
+                        .unwrap_or(Err(LoadError::Timeout)),
+                    None => next.await,
+                }
+            };
+            let next = match &self.config.shutdown {
+                Some(signal) => {
+                    match future::select(read.boxed_local(), signal.grace_expired()).await {
+                        Either::Left((next, _)) => next?,
+                        Either::Right(_) => return Err(signal.abort()),
+                    }
+                }
+                None => read.await?,
+            };
+            let field = match next {
+                Ok(Some(field)) => field,
+                Err(_) if self.config.strict => {
+                    return Err(LoadError::Nonconforming(Nonconformance::Malformed))
+                }
+                Err(_) if self.config.skip_malformed => {
+                    self.skipped.push(SkippedPart {
+                        name: None,
+                        reason: Nonconformance::Malformed,
+                    });
+                    return Ok(None);
+                }
+                _ => return Ok(None),
+            };
+            let index = self.part_count;
+            self.part_count += 1;
+            if self.part_count > self.config.max_parts {
+                return Err(MultipartError::Payload(PayloadError::Overflow).into());
+            }
+            let cd = match self
+                .related_disposition(&field)
+                .or_else(|| field.content_disposition())
+            {
+                Some(cd) => cd,
+                None => {
+                    self.reject(field, None, Nonconformance::MissingDisposition)
+                        .await?;
+                    continue;
+                }
+            };
+            let name = cd.get_name().map(|n| n.to_owned());
+            match cd.disposition {
+                DispositionType::FormData => {}
+                _ => {
+                    self.reject(field, name, Nonconformance::NotFormData)
+                        .await?;
+                    continue;
+                }
+            }
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    self.reject(field, None, Nonconformance::MissingName)
+                        .await?;
+                    continue;
+                }
+            };
+            if self.config.strict {
+                if name.is_empty() {
+                    return Err(LoadError::Nonconforming(Nonconformance::EmptyName));
+                }
+                if field.headers().contains_key("content-transfer-encoding") {
+                    return Err(LoadError::Nonconforming(Nonconformance::TransferEncoding));
+                }
+                if field
+                    .headers()
+                    .keys()
+                    .any(|h| !self.config.allows_header(h))
+                {
+                    return Err(LoadError::Nonconforming(Nonconformance::ForbiddenHeader));
+                }
+                let is_file = cd.get_filename().is_some() || cd.get_filename_ext().is_some();
+                if is_file && !field.headers().contains_key(header::CONTENT_TYPE) {
+                    return Err(LoadError::Nonconforming(Nonconformance::MissingContentType));
+                }
+            }
+
+            // We need to default to TEXT_PLAIN however actix content_type() defaults to APPLICATION_OCTET_STREAM
+            let content_type = if field.headers().get(&header::CONTENT_TYPE).is_none() {
+                mime::TEXT_PLAIN
+            } else {
+                field.content_type().clone()
+            };
 
-        let item = if content_type == mime::TEXT_PLAIN && cd.get_filename().is_none() {
-            let (r, size) = create_text(field, name, text_budget).await?;
-            text_budget = text_budget - size;
-            MultipartField::Text(r)
-        } else {
             let filename = cd.get_filename().map(|f| f.to_owned());
-            let r = create_file(field, name, filename, file_budget, content_type).await?;
-            file_budget = file_budget - r.size;
-            MultipartField::File(r)
+            let (mut filename, mut fallback_filename) = match extended_filename(&cd) {
+                Some(extended) => (Some(extended), filename),
+                None => (filename, None),
+            };
+            if self.config.sanitize_filenames {
+                let sanitize = |f: String| sanitize_filename(&f).unwrap_or_default();
+                filename = filename.map(sanitize);
+                fallback_filename = fallback_filename.map(sanitize);
+            }
+            let mut info = PartInfo {
+                name,
+                filename,
+                fallback_filename,
+                content_type,
+                headers: field.headers().clone(),
+                index,
+            };
+            if info.name.is_empty() {
+                match &self.config.empty_names {
+                    EmptyNamePolicy::Reject => {
+                        return Err(MultipartError::Parse(ParseError::Header).into())
+                    }
+                    EmptyNamePolicy::Skip => {
+                        self.skip_field(field, info.is_text()).await?;
+                        continue;
+                    }
+                    EmptyNamePolicy::Rename(name) => info.name = name.clone(),
+                }
+            }
+            if !self.config.expects(&info.name) {
+                match self.config.unknown_parts {
+                    UnknownPartPolicy::Accept => {}
+                    UnknownPartPolicy::Reject => return Err(LoadError::UnexpectedPart(info.name)),
+                    UnknownPartPolicy::Skip => {
+                        self.skip_field(field, info.is_text()).await?;
+                        continue;
+                    }
+                }
+            }
+            if !info.is_text() && !self.config.allows_mime(&info.name, &info.content_type) {
+                return Err(LoadError::DisallowedMime(info.name, info.content_type));
+            }
+            if let Some(filename) = &info.filename {
+                if !self.config.allows_filename(filename) {
+                    return Err(LoadError::DisallowedExtension(filename.clone()));
+                }
+            }
+            let (count, max) = if info.is_text() {
+                (&mut self.text_count, self.config.max_text_fields)
+            } else {
+                (&mut self.file_count, self.config.max_files)
+            };
+            if max.map_or(false, |max| *count >= max) {
+                return Err(if info.is_text() {
+                    LoadError::TooManyTextFields
+                } else {
+                    LoadError::TooManyFiles
+                });
+            }
+            *count += 1;
+            if let Some(max) = self.config.max_parts_per_name {
+                let field_name = info.name.split('[').next().unwrap();
+                let count = self.name_counts.entry(field_name.to_owned()).or_insert(0);
+                if *count >= max {
+                    return Err(LoadError::TooManyParts(field_name.to_owned()));
+                }
+                *count = *count + 1;
+            }
+            return Ok(Some((field, info)));
+        }
+    }
+
+    /// A `form-data` Content-Disposition naming the part after its Content-ID, if it is a part of
+    /// a `multipart/related` payload (see `MultipartLoadConfig::related()`)
+    fn related_disposition(&self, field: &actix_multipart::Field) -> Option<ContentDisposition> {
+        if !self.config.related {
+            return None;
+        }
+        let cd = field.content_disposition();
+        if cd.as_ref().map(|cd| &cd.disposition) == Some(&DispositionType::FormData) {
+            return None;
+        }
+        let id = content_id(field.headers())?;
+        let mut parameters = vec![DispositionParam::Name(id.to_owned())];
+        parameters.extend(
+            cd.into_iter()
+                .flat_map(|cd| cd.parameters)
+                .filter(|p| !p.is_name()),
+        );
+        Some(ContentDisposition {
+            disposition: DispositionType::FormData,
+            parameters,
+        })
+    }
+
+    /// Handles a part with unusable headers, either skipping it (if `skip_malformed`) or
+    /// returning an error (precise only in strict mode)
+    async fn reject(
+        &mut self,
+        field: actix_multipart::Field,
+        name: Option<String>,
+        nonconformance: Nonconformance,
+    ) -> Result<(), LoadError> {
+        if self.config.strict {
+            return Err(LoadError::Nonconforming(nonconformance));
+        }
+        if !self.config.skip_malformed {
+            return Err(MultipartError::Parse(ParseError::Header).into());
+        }
+        self.skip_field(field, false).await?;
+        self.skipped.push(SkippedPart {
+            name,
+            reason: nonconformance,
+        });
+        Ok(())
+    }
+
+    /// Reads and discards the body of a part (it still counts towards the limits)
+    async fn skip_field(
+        &mut self,
+        field: actix_multipart::Field,
+        is_text: bool,
+    ) -> Result<(), LoadError> {
+        let mut field = self.with_deadline(field);
+        let result = self.skip_body(&mut field, is_text).await;
+        self.check_deadline(result)
+    }
+
+    async fn skip_body<S: PartBody>(
+        &mut self,
+        field: &mut S,
+        is_text: bool,
+    ) -> Result<(), MultipartError> {
+        while let Some(chunk) = field.next().await {
+            let length = chunk?.len();
+            if is_text {
+                if self.text_budget < length {
+                    return Err(MultipartError::Payload(PayloadError::Overflow));
+                }
+                self.text_budget = self.text_budget - length;
+            } else {
+                if self.file_budget < length as u64 {
+                    return Err(MultipartError::Payload(PayloadError::Overflow));
+                }
+                self.file_budget = self.file_budget - length as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// The most bytes the next file upload may contain
+    pub(crate) fn file_max_size(&self) -> u64 {
+        match self.config.max_file_size {
+            Some(max) => max.min(self.file_budget),
+            None => self.file_budget,
+        }
+    }
+
+    /// Reads the body of a part, passing it through the classify, process and store stages
+    ///
+    /// Resolves to `None` if the part was a blank file that `blank_files(false)` drops.
+    pub(crate) async fn load_field<S: PartBody + 'static>(
+        &mut self,
+        field: S,
+        info: PartInfo,
+    ) -> Result<Option<MultipartField>, LoadError> {
+        let kind = self.classify(&info);
+        let body = self.process(&info, kind, field);
+        self.load_body(kind, body, info).await
+    }
+
+    /// Reads the body of a part that has already been passed through `process()` (as a part of
+    /// `kind`), through the store stage
+    pub(crate) async fn load_body(
+        &mut self,
+        kind: PartKind,
+        mut body: PartStream,
+        info: PartInfo,
+    ) -> Result<Option<MultipartField>, LoadError> {
+        if !self.config.blank_files && is_blank_file(&info) {
+            match self.check_deadline(first_chunk(&mut body).await)? {
+                Some(first) => body = Box::pin(stream::once(future::ok(first)).chain(body)),
+                None => return Ok(None),
+            }
+        }
+        if self.config.spoof_check.is_some() && !info.is_text() {
+            let prefix = self.check_deadline(read_prefix(&mut body, SNIFF_LENGTH).await)?;
+            let sniffed = sniff_mime(&prefix);
+            if !self
+                .config
+                .allows_content(&info.content_type, sniffed.as_ref())
+            {
+                return Err(LoadError::SpoofedMime(info.name, info.content_type));
+            }
+            body = Box::pin(stream::once(future::ok(prefix)).chain(body));
+        }
+        let result = self.store(kind, body, info).await;
+        let field = self.check_deadline(result)?;
+        if let Some(limits) = &self.config.archive_limits {
+            if let Err(e) = inspect_field(&field, limits).await {
+                let name = field.name().to_owned();
+                remove_saved_files(&vec![field]);
+                return Err(LoadError::Archive(name, e));
+            }
+        }
+        if let (Some(hook), MultipartField::File(file)) = (&self.config.scan_hook, &field) {
+            if let Err(e) = hook.scan(file).await {
+                return Err(LoadError::Scan(file.name.clone(), file.filename.clone(), e));
+            }
+        }
+        Ok(Some(field))
+    }
+
+    /// Passes the body of a part through the classify and process stages, for the handler of
+    /// `process_parts()` to read, counting the bytes read against the size limit of its kind
+    ///
+    /// The count should be passed to `charge()` once the handler is done with the part.
+    pub(crate) fn open_field<S: PartBody + 'static>(
+        &self,
+        field: S,
+        info: &PartInfo,
+    ) -> (PartKind, PartStream, Rc<Cell<u64>>) {
+        let kind = self.classify(info);
+        let max_size = match kind {
+            PartKind::Text => self.text_budget as u64,
+            PartKind::TextStream => self.file_budget,
+            _ => self.file_max_size(),
+        };
+        let size = Rc::new(Cell::new(0u64));
+        let counter = size.clone();
+        let body = self
+            .process(info, kind, field)
+            .and_then(move |bytes| {
+                let total = counter.get() + bytes.len() as u64;
+                if total > max_size {
+                    return future::err(MultipartError::Payload(PayloadError::Overflow));
+                }
+                counter.set(total);
+                future::ok(bytes)
+            })
+            .boxed_local();
+        (kind, body, size)
+    }
+
+    /// Counts the bytes read from a part opened with `open_field()` towards the limits
+    pub(crate) fn charge(&mut self, kind: PartKind, size: u64) {
+        match kind {
+            PartKind::Text => self.text_budget = self.text_budget - size as usize,
+            _ => self.file_budget = self.file_budget - size,
+        }
+    }
+
+    /// Interrupts the body with an error if the deadline (or the shutdown grace period) passes
+    /// while it is being read, or the read timeout passes between chunks
+    fn with_deadline<S: PartBody>(&self, body: S) -> DeadlineBody<S> {
+        DeadlineBody {
+            body,
+            delay: self
+                .config
+                .deadline
+                .map(|d| tokio::time::delay_until(d.into())),
+            idle: self
+                .config
+                .read_timeout
+                .map(|t| (t, tokio::time::delay_for(t))),
+            shutdown: self.config.shutdown.as_ref().map(|s| s.grace_expired()),
+        }
+    }
+
+    /// Waits for `future` while nothing is being read, failing with a timed out error (see
+    /// `check_deadline()`) if the deadline or the read timeout passes first
+    async fn before_deadline<F: Future>(&self, future: F) -> Result<F::Output, MultipartError> {
+        let idle = self.config.read_timeout.map(|t| Instant::now() + t);
+        let until = match (self.config.deadline, idle) {
+            (Some(deadline), Some(idle)) => Some(deadline.min(idle)),
+            (deadline, idle) => deadline.or(idle),
+        };
+        match until {
+            Some(until) => tokio::time::timeout_at(until.into(), future)
+                .await
+                .map_err(|_| {
+                    MultipartError::Payload(PayloadError::Io(std::io::ErrorKind::TimedOut.into()))
+                }),
+            None => Ok(future.await),
+        }
+    }
+
+    /// Replaces the error with a `LoadError::Timeout` if it was caused by the deadline passing
+    /// (`LoadError::ReadTimeout` for the read timeout, or `LoadError::ShuttingDown` for the end of
+    /// the shutdown grace period)
+    pub(crate) fn check_deadline<T, E: Into<LoadError>>(
+        &self,
+        result: Result<T, E>,
+    ) -> Result<T, LoadError> {
+        result.map_err(|e| {
+            let now = Instant::now();
+            let shutdown = self.config.shutdown.as_ref();
+            match (self.config.deadline, shutdown.and_then(|s| s.abort_at())) {
+                (Some(deadline), _) if now >= deadline => LoadError::Timeout,
+                (_, Some(abort_at)) if now >= abort_at => shutdown.unwrap().abort(),
+                _ => match e.into() {
+                    LoadError::Multipart(MultipartError::Payload(PayloadError::Io(e)))
+                        if e.kind() == std::io::ErrorKind::TimedOut
+                            && self.config.read_timeout.is_some() =>
+                    {
+                        LoadError::ReadTimeout
+                    }
+                    e => e,
+                },
+            }
+        })
+    }
+
+    /// Decides how a part will be loaded
+    pub(crate) fn classify(&self, info: &PartInfo) -> PartKind {
+        let field_name = info.name.split('[').next().unwrap();
+        match (info.is_text(), &self.config.save_to) {
+            (true, _) if self.config.stream_text.iter().any(|n| n == field_name) => {
+                PartKind::TextStream
+            }
+            (true, _) => PartKind::Text,
+            (false, _) if self.config.in_memory.iter().any(|n| n == field_name) => PartKind::Memory,
+            (false, _) if self.config.storage.is_some() => PartKind::Stored,
+            (false, Some(_)) => PartKind::Saved,
+            (false, None) => PartKind::File,
+        }
+    }
+
+    /// Applies the deadline, the throughput limit, any Content-Transfer-Encoding and then each of
+    /// the configured `PartStage`s to the body
+    pub(crate) fn process<S: PartBody + 'static>(
+        &self,
+        info: &PartInfo,
+        kind: PartKind,
+        body: S,
+    ) -> PartStream {
+        // Fused, as the first chunks are sometimes read ahead (e.g. to check for a blank file) and
+        // polling an `actix_multipart::Field` again once it has ended panics
+        let mut body: PartStream = Box::pin(self.with_deadline(body.fuse()));
+        if let Some(throttle) = &self.throttle {
+            body = throttle.apply(body);
+        }
+        if self.config.decode_transfer_encoding {
+            body = decode_transfer_encoding(info, body);
+        }
+        let body = self
+            .config
+            .stages
+            .iter()
+            .fold(body, |body, stage| stage.process(info, kind, body));
+        Box::pin(body.fuse())
+    }
+
+    /// The size limit that applies to the next part of `kind`, and its value
+    pub(crate) fn limit_of(&self, kind: PartKind) -> (LimitKind, u64) {
+        match (kind, self.config.max_file_size) {
+            (PartKind::Text, _) => (LimitKind::Text, self.config.text_limit as u64),
+            (PartKind::TextStream, _) => (LimitKind::File, self.config.file_limit),
+            (_, Some(max)) if max < self.file_budget => (LimitKind::FileSize, max),
+            _ => (LimitKind::File, self.config.file_limit),
+        }
+    }
+
+    /// Stores the body of a part, naming the part and limit if it is too large
+    async fn store(
+        &mut self,
+        kind: PartKind,
+        body: PartStream,
+        info: PartInfo,
+    ) -> Result<MultipartField, LoadError> {
+        let field = info.name.clone();
+        let (limit_kind, limit) = self.limit_of(kind);
+        self.store_part(kind, body, info)
+            .await
+            .map_err(|e| match e {
+                MultipartError::Payload(PayloadError::Overflow) => {
+                    LoadError::Limit(MultipartLimitError {
+                        field,
+                        limit,
+                        kind: limit_kind,
+                    })
+                }
+                e => e.into(),
+            })
+    }
+
+    /// Stores the body of a part, according to its kind
+    async fn store_part(
+        &mut self,
+        kind: PartKind,
+        body: PartStream,
+        info: PartInfo,
+    ) -> Result<MultipartField, MultipartError> {
+        let write_permits = self
+            .config
+            .write_permits
+            .as_ref()
+            .map(|(_, semaphore)| semaphore.clone())
+            .filter(|_| kind.writes_to_disk());
+        let _permit = match &write_permits {
+            Some(semaphore) => Some(self.before_deadline(semaphore.acquire()).await?),
+            None => None,
+        };
+        let max_size = self.file_max_size();
+        let item = match kind {
+            PartKind::TextStream => {
+                let r = create_text_stream(body, info, self.file_budget, &self.config).await?;
+                self.file_budget = self.file_budget - r.size;
+                MultipartField::Stream(r)
+            }
+            PartKind::Text => {
+                let field_name = info.name.split('[').next().unwrap();
+                let charset = self
+                    .config
+                    .charsets
+                    .iter()
+                    .find(|(name, _)| name == field_name)
+                    .map(|(_, encoding)| *encoding);
+                let r = create_text(
+                    body,
+                    info,
+                    self.config.write_buffer(),
+                    self.text_budget,
+                    charset,
+                    self.default_charset,
+                    self.config.utf8_policy,
+                )
+                .await?;
+                match &r {
+                    MultipartField::Text(t) => {
+                        self.text_budget = self.text_budget - t.size as usize;
+                        if t.name == CHARSET_FIELD {
+                            self.default_charset = Encoding::for_label(t.text.trim().as_bytes());
+                        }
+                    }
+                    MultipartField::Bytes(b) => {
+                        self.text_budget = self.text_budget - b.size as usize
+                    }
+                    _ => unreachable!(),
+                }
+                r
+            }
+            PartKind::Saved => {
+                let (dir, naming) = self.config.save_to.as_ref().unwrap();
+                let key = self
+                    .config
+                    .idempotency_key
+                    .as_ref()
+                    .map(|key| format!("{}-{}", key, self.saved_count));
+                self.saved_count += 1;
+                let buffer = self.config.write_buffer();
+                let r = create_saved_file(body, info, max_size, buffer, dir, naming, key).await?;
+                self.file_budget = self.file_budget - r.size;
+                MultipartField::Saved(r)
+            }
+            PartKind::Stored => {
+                let storage = self.config.storage.clone().unwrap();
+                let r = create_stored_file(body, info, max_size, storage).await?;
+                self.file_budget = self.file_budget - r.size;
+                MultipartField::Stored(r)
+            }
+            PartKind::Memory => {
+                let r = create_memory_file(body, info, max_size).await?;
+                self.file_budget = self.file_budget - r.size;
+                MultipartField::Memory(r)
+            }
+            PartKind::File => {
+                // Counted as it is received, as a deduplicated file is left empty
+                let received = Rc::new(Cell::new(0u64));
+                let counter = received.clone();
+                let mut body =
+                    body.inspect_ok(move |bytes| counter.set(counter.get() + bytes.len() as u64));
+                let item = match self.config.spool_threshold {
+                    Some(threshold) => {
+                        match create_spooled_file(body, info, max_size, threshold, &self.config)
+                            .await?
+                        {
+                            SpooledFile::Memory(f) => MultipartField::Memory(f),
+                            SpooledFile::File(f) => MultipartField::File(f),
+                        }
+                    }
+                    None => {
+                        // A part that fails before any of it arrives never creates a temporary file
+                        let first = first_chunk(&mut body).await?;
+                        let body = stream::iter(first.map(Ok)).chain(body);
+                        MultipartField::File(create_file(body, info, max_size, &self.config).await?)
+                    }
+                };
+                self.file_budget = self.file_budget - received.get();
+                item
+            }
         };
-        parts.push(item);
+        Ok(item)
     }
-    Ok(parts)
 }
 
-async fn create_file(
-    mut field: actix_multipart::Field,
-    name: String,
-    filename: Option<String>,
+struct DeadlineBody<S> {
+    body: S,
+    delay: Option<Delay>,
+    /// The read timeout, and when it will next pass unless another chunk arrives
+    idle: Option<(Duration, Delay)>,
+    shutdown: Option<LocalBoxFuture<'static, ()>>,
+}
+
+impl<S: PartBody> Stream for DeadlineBody<S> {
+    type Item = Result<Bytes, MultipartError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(delay) = self.delay.as_mut() {
+            // A delay that is already due may not fire until the timer next turns
+            let due = delay.deadline() <= tokio::time::Instant::now();
+            if due || Pin::new(delay).poll(cx).is_ready() {
+                let e = std::io::Error::new(std::io::ErrorKind::TimedOut, "Deadline passed");
+                return Poll::Ready(Some(Err(MultipartError::Payload(PayloadError::Io(e)))));
+            }
+        }
+        if let Some(shutdown) = self.shutdown.as_mut() {
+            if shutdown.as_mut().poll(cx).is_ready() {
+                let e = std::io::Error::new(std::io::ErrorKind::Other, "Server is shutting down");
+                return Poll::Ready(Some(Err(MultipartError::Payload(PayloadError::Io(e)))));
+            }
+        }
+        let next = Pin::new(&mut self.body).poll_next(cx);
+        if let Some((timeout, idle)) = self.idle.as_mut() {
+            match next {
+                Poll::Ready(_) => idle.reset((Instant::now() + *timeout).into()),
+                Poll::Pending => {
+                    if Pin::new(idle).poll(cx).is_ready() {
+                        let e = std::io::Error::new(std::io::ErrorKind::TimedOut, "Read timed out");
+                        return Poll::Ready(Some(Err(MultipartError::Payload(PayloadError::Io(
+                            e,
+                        )))));
+                    }
+                }
+            }
+        }
+        next
+    }
+}
+
+/// Whether a part could be a file input that was left blank, if its body turns out to be empty
+fn is_blank_file(info: &PartInfo) -> bool {
+    !info.is_text() && info.filename.as_deref().map_or(true, str::is_empty)
+}
+
+/// Reads at least `length` bytes from the start of a body (fewer if the body is shorter)
+pub(crate) async fn read_prefix<S: PartBody>(
+    field: &mut S,
+    length: usize,
+) -> Result<Bytes, MultipartError> {
+    let mut prefix = Vec::new();
+    while prefix.len() < length {
+        match field.next().await {
+            Some(chunk) => prefix.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    Ok(Bytes::from(prefix))
+}
+
+/// Waits for the first (non-empty) chunk of a body, resolving to `None` if the body is empty
+async fn first_chunk<S: PartBody>(field: &mut S) -> Result<Option<Bytes>, MultipartError> {
+    while let Some(chunk) = field.next().await {
+        let bytes = chunk?;
+        if !bytes.is_empty() {
+            return Ok(Some(bytes));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) async fn create_file<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
     max_size: u64,
-    mime: mime::Mime,
+    config: &MultipartLoadConfig,
 ) -> Result<MultipartFile, MultipartError> {
+    let io_error = |e| MultipartError::Payload(PayloadError::Io(e));
+    let (ntf, private) = config.temp_file(info.filename.as_deref())?;
+    let mut hasher = Some(Sha256::new()).filter(|_| config.digests || config.exists.is_some());
+    let mut md5_hasher = Some(Md5::new()).filter(|_| config.digests);
+    let mut update = |bytes: &Bytes| {
+        if let Some(h) = hasher.as_mut() {
+            h.update(bytes)
+        }
+        if let Some(h) = md5_hasher.as_mut() {
+            h.update(bytes)
+        }
+    };
+
+    // When deduplicating, a file that fits in the write buffer is held back until its digest has
+    // been checked, so that a duplicate is never written at all
+    let mut buffer = config.write_buffer();
+    let mut held_back = config.exists.is_some();
+    if held_back {
+        while let Some(chunk) = field.next().await {
+            let bytes = chunk?;
+            if (buffer.len() + bytes.len()) as u64 > max_size {
+                return Err(MultipartError::Payload(PayloadError::Overflow));
+            }
+            update(&bytes);
+            buffer.extend_from_slice(&bytes);
+            if buffer.len() > buffer.size() {
+                held_back = false;
+                break;
+            }
+        }
+    }
+    let mut size = buffer.len() as u64;
+    let held = if held_back {
+        Some(buffer)
+    } else {
+        // The start of the file that was read while trying to hold it back
+        write_bytes(ntf.as_file(), &buffer).await?;
+        buffer.clear();
+        size += write_field(&mut field, ntf.as_file(), max_size - size, buffer, update).await?;
+        None
+    };
+
+    let sha256 = hasher.map(|h| format!("{:x}", h.finalize()));
+    let md5 = md5_hasher.map(|h| format!("{:x}", h.finalize()));
+    let mut deduplicated = false;
+    if let (Some(exists), Some(hash)) = (&config.exists, &sha256) {
+        deduplicated = exists(hash.clone()).await;
+    }
+    match held {
+        Some(_) if deduplicated => size = 0,
+        Some(held) => write_bytes(ntf.as_file(), &held).await?,
+        None if deduplicated => {
+            // Too large to hold back, so the contents that were written are discarded
+            async_file(ntf.as_file())?
+                .set_len(0)
+                .await
+                .map_err(io_error)?;
+            size = 0;
+        }
+        None => {}
+    }
+    Ok(MultipartFile {
+        file: ntf,
+        size,
+        name: info.name,
+        filename: info.filename,
+        fallback_filename: info.fallback_filename,
+        mime: info.content_type,
+        headers: info.headers,
+        index: info.index,
+        sha256,
+        md5,
+        deduplicated,
+        private,
+    })
+}
+
+/// Writes all of `bytes` to `file`, waiting for the write to complete
+async fn write_bytes(file: &std::fs::File, bytes: &[u8]) -> Result<(), MultipartError> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let io_error = |e| MultipartError::Payload(PayloadError::Io(e));
+    let mut writer = FileWriter::new(file)?;
+    writer.write_all(bytes).await.map_err(io_error)?;
+    writer.flush().await.map_err(io_error)
+}
+
+/// Writes files using `tokio::fs`, or io-uring with the `io-uring` feature (on Linux, when it is
+/// supported by the kernel)
+enum FileWriter {
+    Tokio(tokio::fs::File),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(super::uring::UringFile),
+}
+
+impl FileWriter {
+    fn new(file: &std::fs::File) -> Result<Self, MultipartError> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            let uring = super::uring::UringFile::new(file)
+                .map_err(|e| MultipartError::Payload(PayloadError::Io(e)))?;
+            if let Some(uring) = uring {
+                return Ok(FileWriter::Uring(uring));
+            }
+        }
+        async_file(file).map(FileWriter::Tokio)
+    }
+
+    async fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            FileWriter::Tokio(file) => file.write_all(bytes).await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            FileWriter::Uring(file) => file.write_all(bytes).await,
+        }
+    }
+
+    /// Waits for the last write to complete
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            FileWriter::Tokio(file) => file.flush().await,
+            // Each write is complete once `write_all()` returns
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            FileWriter::Uring(_) => Ok(()),
+        }
+    }
+}
+
+/// Opens another handle to `file` for asynchronous IO
+fn async_file(file: &std::fs::File) -> Result<tokio::fs::File, MultipartError> {
+    file.try_clone()
+        .map(tokio::fs::File::from_std)
+        .map_err(|e| MultipartError::Payload(PayloadError::Io(e)))
+}
+
+/// Streams the body of a field into `file` (using asynchronous writes, see `FileWriter`),
+/// `inspect` is called with each chunk before it is written
+///
+/// Chunks are collected in `buffer` until there are at least `buffer.size()` bytes to write, a
+/// chunk that is already that large (with nothing buffered) is written as it is.
+///
+/// Returns the number of bytes written
+pub(crate) async fn write_field<S, F>(
+    field: &mut S,
+    file: &std::fs::File,
+    max_size: u64,
+    mut buffer: WriteBuffer,
+    mut inspect: F,
+) -> Result<u64, MultipartError>
+where
+    S: PartBody,
+    F: FnMut(&Bytes),
+{
+    let io_error = |e| MultipartError::Payload(PayloadError::Io(e));
+    let mut writer = FileWriter::new(file)?;
     let mut written = 0;
     let mut budget = max_size;
-    let mut ntf = match NamedTempFile::new() {
-        Ok(file) => file,
-        Err(e) => return Err(MultipartError::Payload(PayloadError::Io(e))),
-    };
 
     while let Some(chunk) = field.next().await {
         let bytes = chunk?;
@@ -132,38 +2366,39 @@ async fn create_file(
         if budget < length {
             return Err(MultipartError::Payload(PayloadError::Overflow));
         }
-        ntf = web::block(move || {
-            ntf.as_file()
-                .write_all(bytes.as_ref())
-                .map(|_| ntf)
-                .map_err(|e| MultipartError::Payload(PayloadError::Io(e)))
-        })
-        .map_err(|e: BlockingError<MultipartError>| match e {
-            BlockingError::Error(e) => e,
-            BlockingError::Canceled => MultipartError::Incomplete,
-        })
-        .await?;
-
+        inspect(&bytes);
         written = written + length;
         budget = budget - length;
+
+        if buffer.is_empty() && bytes.len() >= buffer.size() {
+            writer.write_all(&bytes).await.map_err(io_error)?;
+            continue;
+        }
+        buffer.extend_from_slice(&bytes);
+        if buffer.len() >= buffer.size() {
+            writer.write_all(&buffer).await.map_err(io_error)?;
+            buffer.clear();
+        }
     }
-    Ok(MultipartFile {
-        file: ntf,
-        size: written,
-        name,
-        filename,
-        mime,
-    })
+    if !buffer.is_empty() {
+        writer.write_all(&buffer).await.map_err(io_error)?;
+    }
+    // Waits for the last write to complete
+    writer.flush().await.map_err(io_error)?;
+    Ok(written)
 }
 
-async fn create_text(
-    mut field: actix_multipart::Field,
-    name: String,
+async fn create_text<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
+    mut acc: WriteBuffer,
     max_length: usize,
-) -> Result<(MultipartText, usize), MultipartError> {
+    charset: Option<&'static Encoding>,
+    default_charset: Option<&'static Encoding>,
+    utf8_policy: Utf8Policy,
+) -> Result<MultipartField, MultipartError> {
     let mut written = 0;
     let mut budget = max_length;
-    let mut acc = BytesMut::new();
 
     while let Some(chunk) = field.next().await {
         let bytes = chunk?;
@@ -171,12 +2406,51 @@ async fn create_text(
         if budget < length {
             return Err(MultipartError::Payload(PayloadError::Overflow));
         }
-        acc.extend(bytes);
+        acc.extend_from_slice(&bytes);
         written = written + length;
         budget = budget - length;
     }
-    //TODO: Currently only supports UTF-8, consider looking at the charset header and _charset_ field
-    let text = String::from_utf8(acc.to_vec())
-        .map_err(|a| MultipartError::Parse(ParseError::Utf8(a.utf8_error())))?;
-    Ok((MultipartText { name, text }, written))
+    let declared = info
+        .content_type
+        .get_param(mime::CHARSET)
+        .and_then(|c| Encoding::for_label(c.as_str().as_bytes()));
+    let text = match charset.or(declared).or(default_charset) {
+        Some(encoding) if encoding != encoding_rs::UTF_8 => encoding
+            .decode_without_bom_handling(&acc[..])
+            .0
+            .into_owned(),
+        _ if utf8_policy == Utf8Policy::Bytes && std::str::from_utf8(&acc).is_err() => {
+            return Ok(MultipartField::Bytes(MultipartBytes {
+                name: info.name,
+                bytes: Bytes::copy_from_slice(&acc),
+                mime: info.content_type,
+                headers: info.headers,
+                size: written as u64,
+                index: info.index,
+            }));
+        }
+        _ => decode_utf8(acc.to_vec(), utf8_policy)?,
+    };
+    Ok(MultipartField::Text(MultipartText {
+        name: info.name,
+        text,
+        mime: info.content_type,
+        headers: info.headers,
+        size: written as u64,
+        index: info.index,
+    }))
+}
+
+fn decode_utf8(bytes: Vec<u8>, utf8_policy: Utf8Policy) -> Result<String, MultipartError> {
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => match utf8_policy {
+            // With `Bytes` invalid text is kept as a `MultipartBytes` before reaching here
+            Utf8Policy::Strict | Utf8Policy::Bytes => {
+                return Err(MultipartError::Parse(ParseError::Utf8(e.utf8_error())))
+            }
+            Utf8Policy::Lossy => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            Utf8Policy::Latin1Fallback => e.as_bytes().iter().map(|&b| b as char).collect(),
+        },
+    })
 }
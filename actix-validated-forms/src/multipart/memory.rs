@@ -0,0 +1,185 @@
+use super::load::{create_file, PartBody, PartInfo};
+use super::{MultipartFile, MultipartLoadConfig};
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::http::HeaderMap;
+use actix_web::web::{Bytes, BytesMut};
+use futures::future;
+use futures::stream::{self, StreamExt};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+/// Structure used to represent a File upload that was kept in memory
+///
+/// Loaded instead of a `MultipartFile` for the fields named in
+/// `MultipartLoadConfig::in_memory()`, avoiding the cost of a temporary file for small uploads
+/// such as avatars. These still count towards the `file_limit`.
+#[derive(Debug, Clone)]
+pub struct MemoryFile {
+    /// The contents of the file
+    pub data: Bytes,
+    /// The size in bytes of the file
+    pub size: u64,
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The `filename` value in the `Content-Disposition` header
+    pub filename: Option<String>,
+    /// The Content-Type specified as reported in the uploaded form
+    /// DO NOT trust this as being accurate
+    pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+}
+
+impl MemoryFile {
+    /// Get the extension portion of the `filename` value in the `Content-Disposition` header
+    pub fn get_extension(&self) -> Option<&str> {
+        self.filename
+            .as_ref()
+            .and_then(|f| Path::new(f.as_str()).extension().and_then(OsStr::to_str))
+    }
+}
+
+/// A file upload that was either kept in memory or spilled to a temporary file, depending on its
+/// size (see `MultipartLoadConfig::spool_threshold()`)
+///
+/// With spooling enabled use this as the type of file fields, since a small upload is loaded as a
+/// `MemoryFile` rather than a `MultipartFile`.
+#[derive(Debug)]
+pub enum SpooledFile {
+    Memory(MemoryFile),
+    File(MultipartFile),
+}
+
+impl SpooledFile {
+    /// The name of the field in the multipart form
+    pub fn name(&self) -> &str {
+        match self {
+            SpooledFile::Memory(f) => &f.name,
+            SpooledFile::File(f) => &f.name,
+        }
+    }
+
+    /// The size in bytes of the file
+    pub fn size(&self) -> u64 {
+        match self {
+            SpooledFile::Memory(f) => f.size,
+            SpooledFile::File(f) => f.size,
+        }
+    }
+
+    /// The `filename` value in the `Content-Disposition` header
+    pub fn filename(&self) -> Option<&str> {
+        match self {
+            SpooledFile::Memory(f) => f.filename.as_deref(),
+            SpooledFile::File(f) => f.filename.as_deref(),
+        }
+    }
+
+    /// The Content-Type specified as reported in the uploaded form
+    /// DO NOT trust this as being accurate
+    pub fn mime(&self) -> &mime::Mime {
+        match self {
+            SpooledFile::Memory(f) => &f.mime,
+            SpooledFile::File(f) => &f.mime,
+        }
+    }
+
+    /// Get the extension portion of the `filename` value in the `Content-Disposition` header
+    pub fn get_extension(&self) -> Option<&str> {
+        match self {
+            SpooledFile::Memory(f) => f.get_extension(),
+            SpooledFile::File(f) => f.get_extension(),
+        }
+    }
+
+    /// Read the contents of the file, from wherever it is stored
+    pub fn reader(&self) -> io::Result<SpooledReader> {
+        Ok(match self {
+            SpooledFile::Memory(f) => SpooledReader::Memory(Cursor::new(f.data.clone())),
+            SpooledFile::File(f) => SpooledReader::File(f.file.reopen()?),
+        })
+    }
+}
+
+/// Reads the contents of a `SpooledFile`, see `SpooledFile::reader()`
+pub enum SpooledReader {
+    Memory(Cursor<Bytes>),
+    File(File),
+}
+
+impl Read for SpooledReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SpooledReader::Memory(r) => r.read(buf),
+            SpooledReader::File(r) => r.read(buf),
+        }
+    }
+}
+
+pub(crate) async fn create_memory_file<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
+    max_size: u64,
+) -> Result<MemoryFile, MultipartError> {
+    let mut budget = max_size;
+    let mut acc = BytesMut::new();
+
+    while let Some(chunk) = field.next().await {
+        let bytes = chunk?;
+        let length = bytes.len() as u64;
+        if budget < length {
+            return Err(MultipartError::Payload(PayloadError::Overflow));
+        }
+        acc.extend(bytes);
+        budget = budget - length;
+    }
+    Ok(MemoryFile {
+        size: acc.len() as u64,
+        data: acc.freeze(),
+        name: info.name,
+        filename: info.filename,
+        mime: info.content_type,
+        headers: info.headers,
+        index: info.index,
+    })
+}
+
+/// Buffers the body of a file part in memory until it exceeds `threshold` bytes, at which point
+/// the buffered bytes and the rest of the body are written to a temporary file instead
+pub(crate) async fn create_spooled_file<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
+    max_size: u64,
+    threshold: u64,
+    config: &MultipartLoadConfig,
+) -> Result<SpooledFile, MultipartError> {
+    let mut acc = BytesMut::new();
+
+    while let Some(chunk) = field.next().await {
+        let bytes = chunk?;
+        if (acc.len() + bytes.len()) as u64 > max_size {
+            return Err(MultipartError::Payload(PayloadError::Overflow));
+        }
+        acc.extend(bytes);
+        if acc.len() as u64 > threshold {
+            let spooled = stream::once(future::ok(acc.freeze())).chain(field);
+            let file = create_file(spooled, info, max_size, config).await?;
+            return Ok(SpooledFile::File(file));
+        }
+    }
+    Ok(SpooledFile::Memory(MemoryFile {
+        size: acc.len() as u64,
+        data: acc.freeze(),
+        name: info.name,
+        filename: info.filename,
+        mime: info.content_type,
+        headers: info.headers,
+        index: info.index,
+    }))
+}
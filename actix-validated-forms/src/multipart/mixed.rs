@@ -0,0 +1,367 @@
+use actix_multipart::Multipart;
+use actix_web::error::PayloadError;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::{HeaderMap, HeaderValue};
+use actix_web::web::{Bytes, BytesMut};
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The most bytes the headers of a part (or the rest of a delimiter line) may take up
+const MAX_HEADERS: usize = 16 * 1024;
+
+/// Parses a multipart payload, flattening any nested `multipart/mixed` file sets
+///
+/// RFC 2388 (the predecessor of RFC 7578) sends several files for one field as a single part
+/// with a nested `multipart/mixed` body, which `actix_multipart` rejects with
+/// `MultipartError::Nested`. Each file of such a set is instead rewritten as a part of its own,
+/// named after the field, so that it is loaded as a `MultipartFile` like any other. Only one
+/// level of nesting is flattened.
+///
+/// The extractors do this when `MultipartLoadConfig::nested_file_sets()` is enabled, when
+/// calling `load_parts()` use it in place of the `actix_multipart::Multipart` extractor.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{flatten_mixed, load_parts, MultipartLoadConfig};
+/// # use actix_web::{web, Error, HttpRequest, HttpResponse};
+/// async fn route(req: HttpRequest, payload: web::Payload) -> Result<HttpResponse, Error> {
+///     let multipart = flatten_mixed(req.headers(), payload);
+///     let mut form = load_parts(multipart, MultipartLoadConfig::default()).await?;
+///     # unimplemented!() }
+/// ```
+pub fn flatten_mixed<S>(headers: &HeaderMap, payload: S) -> Multipart
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static,
+{
+    match boundary_of(headers.get(header::CONTENT_TYPE), "form-data") {
+        Some(boundary) => Multipart::new(headers, FlattenMixed::new(payload, boundary)),
+        // Leave it to actix_multipart to report the missing boundary
+        None => Multipart::new(headers, payload),
+    }
+}
+
+/// The boundary of a multipart Content-Type, if its subtype is `subtype`
+fn boundary_of(content_type: Option<&HeaderValue>, subtype: &str) -> Option<Vec<u8>> {
+    let mime: mime::Mime = content_type?.to_str().ok()?.parse().ok()?;
+    if mime.type_() != mime::MULTIPART || mime.subtype() != subtype {
+        return None;
+    }
+    Some(mime.get_param(mime::BOUNDARY)?.as_str().as_bytes().to_vec())
+}
+
+/// A nested `multipart/mixed` part being flattened
+struct FileSet {
+    /// The name of the field the files belong to
+    name: String,
+    boundary: Vec<u8>,
+}
+
+enum State {
+    /// Before the first delimiter of the form or a file set, or after the end of a file set
+    ///
+    /// The bytes are only kept for the preamble of the form. The `prefix` is given to a
+    /// delimiter found at the very start (before any bytes have been consumed).
+    Preamble {
+        keep: bool,
+        prefix: Option<&'static [u8]>,
+    },
+    /// After the dashes and boundary of a delimiter, `prefix` is the CRLF (if any) that preceded
+    /// it
+    Delimiter {
+        prefix: &'static [u8],
+    },
+    /// After a complete delimiter line
+    Headers {
+        prefix: &'static [u8],
+    },
+    Body,
+    /// After the close delimiter of the form
+    Done,
+}
+
+struct FlattenMixed<S> {
+    payload: S,
+    buf: BytesMut,
+    boundary: Vec<u8>,
+    file_set: Option<FileSet>,
+    state: State,
+    eof: bool,
+}
+
+impl<S> FlattenMixed<S> {
+    fn new(payload: S, boundary: Vec<u8>) -> Self {
+        FlattenMixed {
+            payload,
+            buf: BytesMut::new(),
+            boundary,
+            file_set: None,
+            state: State::Preamble {
+                keep: true,
+                prefix: Some(b""),
+            },
+            eof: false,
+        }
+    }
+
+    /// The boundary of the file set if one is being read, otherwise of the form
+    fn current_boundary(&self) -> &[u8] {
+        match &self.file_set {
+            Some(set) => &set.boundary,
+            None => &self.boundary,
+        }
+    }
+
+    /// A delimiter of the form, that starts a part
+    fn delimiter(&self, prefix: &[u8]) -> Vec<u8> {
+        concat(&[prefix, b"--", &self.boundary, b"\r\n"])
+    }
+
+    /// Processes as much of the buffer as possible, returning the output
+    fn advance(&mut self) -> Result<Vec<u8>, PayloadError> {
+        let mut out = Vec::new();
+        while self.step(&mut out)? {}
+        Ok(out)
+    }
+
+    /// Takes a single step through the buffer, returning false if more input is needed
+    fn step(&mut self, out: &mut Vec<u8>) -> Result<bool, PayloadError> {
+        let dash_boundary = concat(&[b"--", self.current_boundary()]);
+        let needle = concat(&[b"\r\n", &dash_boundary]);
+        match self.state {
+            State::Preamble { keep, prefix } => {
+                if let Some(prefix) = prefix {
+                    if self.buf.len() < dash_boundary.len() && !self.eof {
+                        return Ok(false);
+                    }
+                    if self.buf.starts_with(&dash_boundary) {
+                        let _ = self.buf.split_to(dash_boundary.len());
+                        self.state = State::Delimiter { prefix };
+                        return Ok(true);
+                    }
+                }
+                let (end, found) = match find(&self.buf, &needle) {
+                    Some(pos) => (pos, true),
+                    None if self.eof => (self.buf.len(), false),
+                    None => (self.buf.len().saturating_sub(needle.len() - 1), false),
+                };
+                if end == 0 && !found {
+                    return Ok(false);
+                }
+                let preamble = self.buf.split_to(end);
+                if keep {
+                    out.extend_from_slice(&preamble);
+                }
+                if found {
+                    let _ = self.buf.split_to(needle.len());
+                    self.state = State::Delimiter { prefix: b"\r\n" };
+                } else {
+                    self.state = State::Preamble { keep, prefix: None };
+                }
+                Ok(true)
+            }
+            State::Delimiter { prefix } => {
+                if self.buf.starts_with(b"--") {
+                    let padding = self.buf[2..]
+                        .iter()
+                        .take_while(|b| **b == b' ' || **b == b'\t')
+                        .count();
+                    if 2 + padding == self.buf.len() && !self.eof {
+                        return Ok(false);
+                    }
+                    let _ = self.buf.split_to(2 + padding);
+                    match self.file_set.take() {
+                        // The end of a file set, its epilogue is discarded
+                        Some(_) => {
+                            self.state = State::Preamble {
+                                keep: false,
+                                prefix: None,
+                            }
+                        }
+                        None => {
+                            out.extend_from_slice(&concat(&[prefix, b"--", &self.boundary, b"--"]));
+                            self.state = State::Done;
+                        }
+                    }
+                    return Ok(true);
+                }
+                match find(&self.buf, b"\r\n") {
+                    Some(pos) => {
+                        let _ = self.buf.split_to(pos + 2);
+                        self.state = State::Headers { prefix };
+                        Ok(true)
+                    }
+                    None if self.buf.len() > MAX_HEADERS => Err(PayloadError::Overflow),
+                    None => Ok(false),
+                }
+            }
+            State::Headers { prefix } => {
+                let (lines, length) = if self.buf.starts_with(b"\r\n") {
+                    (0, 2)
+                } else {
+                    match find(&self.buf, b"\r\n\r\n") {
+                        Some(pos) => (pos + 2, pos + 4),
+                        None if self.buf.len() > MAX_HEADERS => return Err(PayloadError::Overflow),
+                        None => return Ok(false),
+                    }
+                };
+                let block = self.buf.split_to(length);
+                let lines = &block[..lines];
+                if let Some(set) = &self.file_set {
+                    out.extend_from_slice(&self.delimiter(prefix));
+                    out.extend_from_slice(&rewrite_headers(lines, &set.name));
+                    out.extend_from_slice(b"\r\n");
+                    self.state = State::Body;
+                } else if let Some(set) = file_set(lines) {
+                    // The part itself is dropped, its first file takes its delimiter
+                    self.file_set = Some(set);
+                    self.state = State::Preamble {
+                        keep: false,
+                        prefix: Some(prefix),
+                    };
+                } else {
+                    out.extend_from_slice(&self.delimiter(prefix));
+                    out.extend_from_slice(&block);
+                    self.state = State::Body;
+                }
+                Ok(true)
+            }
+            State::Body => {
+                let (end, found) = match find(&self.buf, &needle) {
+                    Some(pos) => (pos, true),
+                    None if self.eof => (self.buf.len(), false),
+                    None => (self.buf.len().saturating_sub(needle.len() - 1), false),
+                };
+                if end == 0 && !found {
+                    return Ok(false);
+                }
+                out.extend_from_slice(&self.buf.split_to(end));
+                if found {
+                    let _ = self.buf.split_to(needle.len());
+                    self.state = State::Delimiter { prefix: b"\r\n" };
+                }
+                Ok(true)
+            }
+            State::Done => {
+                if self.buf.is_empty() {
+                    return Ok(false);
+                }
+                out.extend_from_slice(&self.buf.split());
+                Ok(true)
+            }
+        }
+    }
+
+    /// Whatever was consumed but not yet output, when the payload ends part way through a
+    /// delimiter or headers (so that `actix_multipart` reports the form as incomplete)
+    fn unfinished(&self) -> Vec<u8> {
+        match self.state {
+            State::Delimiter { prefix } => concat(&[prefix, b"--", self.current_boundary()]),
+            State::Headers { prefix } => self.delimiter(prefix),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<S> Stream for FlattenMixed<S>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+{
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let mut out = match this.advance() {
+                Ok(out) => out,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+            if out.is_empty() && this.eof {
+                out = this.unfinished();
+                out.extend_from_slice(&this.buf.split());
+                this.state = State::Done;
+                if out.is_empty() {
+                    return Poll::Ready(None);
+                }
+            }
+            if !out.is_empty() {
+                return Poll::Ready(Some(Ok(Bytes::from(out))));
+            }
+            match Pin::new(&mut this.payload).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.buf.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => this.eof = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn concat(parts: &[&[u8]]) -> Vec<u8> {
+    parts.concat()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Splits a block of CRLF terminated header lines into (name, line) pairs
+fn header_lines(lines: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    lines
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let name = line.split(|b| *b == b':').next().unwrap();
+            (trim(name), line)
+        })
+}
+
+/// The value of the header named `name`
+fn header_value(lines: &[u8], name: &str) -> Option<HeaderValue> {
+    let (_, line) = header_lines(lines).find(|(n, _)| n.eq_ignore_ascii_case(name.as_bytes()))?;
+    let colon = line.iter().position(|b| *b == b':')?;
+    HeaderValue::from_bytes(trim(&line[colon + 1..])).ok()
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace());
+    match (start, end) {
+        (Some(start), Some(end)) => &bytes[start..=end],
+        _ => &[],
+    }
+}
+
+/// The file set, if the headers are those of a named `multipart/mixed` part
+fn file_set(lines: &[u8]) -> Option<FileSet> {
+    let content_type = header_value(lines, "content-type");
+    let boundary = boundary_of(content_type.as_ref(), "mixed")?;
+    let cd = ContentDisposition::from_raw(&header_value(lines, "content-disposition")?).ok()?;
+    Some(FileSet {
+        name: cd.get_name()?.to_owned(),
+        boundary,
+    })
+}
+
+/// Replaces the Content-Disposition of a file in a set (e.g. `file; filename="a.txt"`) with a
+/// `form-data` one named after the field of the set
+fn rewrite_headers(lines: &[u8], name: &str) -> Vec<u8> {
+    let mut parameters = vec![DispositionParam::Name(name.to_owned())];
+    if let Some(value) = header_value(lines, "content-disposition") {
+        if let Ok(cd) = ContentDisposition::from_raw(&value) {
+            parameters.extend(cd.parameters.into_iter().filter(|p| !p.is_name()));
+        }
+    }
+    let cd = ContentDisposition {
+        disposition: DispositionType::FormData,
+        parameters,
+    };
+    let mut out = format!("Content-Disposition: {}\r\n", cd).into_bytes();
+    for (header, line) in header_lines(lines) {
+        if !header.eq_ignore_ascii_case(b"content-disposition") {
+            out.extend_from_slice(line);
+            out.push(b'\n');
+        }
+    }
+    out
+}
@@ -1,14 +1,76 @@
+mod archive;
+pub mod constraints;
+mod deferred;
+mod describe;
+mod download;
+mod drain;
+#[cfg(feature = "encryption")]
+mod encryption;
 mod extractor;
+mod filename;
+mod forward;
+#[cfg(feature = "images")]
+pub mod image;
 mod load;
+mod memory;
+mod mixed;
+mod orphans;
+mod pool;
+#[cfg(feature = "relay")]
+mod relay;
+mod repeated;
+mod saved;
+mod scan;
+mod session;
+mod shutdown;
+mod slice;
+mod sniff;
+mod storage;
+mod stream;
 #[cfg(test)]
 mod test;
+#[cfg(feature = "testing")]
+mod testing;
+mod throttle;
+mod transfer;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+mod visit;
 
+pub use archive::*;
+pub use deferred::*;
+pub use describe::*;
+pub use download::*;
+#[cfg(feature = "encryption")]
+pub use encryption::*;
 pub use extractor::*;
+pub use filename::*;
+pub use forward::*;
 pub use load::*;
+pub use memory::*;
+pub use mixed::*;
+pub use orphans::*;
+#[cfg(feature = "relay")]
+pub use relay::*;
+pub use repeated::*;
+pub use saved::*;
+pub use scan::*;
+pub use session::*;
+pub use shutdown::*;
+pub use slice::*;
+pub use sniff::*;
+pub use storage::*;
+pub use stream::*;
+#[cfg(feature = "testing")]
+pub use testing::*;
+pub use transfer::*;
+pub use visit::*;
 
-use actix_web::http::StatusCode;
+use actix_web::http::{HeaderMap, StatusCode};
+use actix_web::web::Bytes;
 use actix_web::ResponseError;
 use err_derive::Error;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::path::Path;
 use std::str::FromStr;
@@ -27,10 +89,58 @@ use tempfile::NamedTempFile;
 /// ```
 pub type Multiparts = Vec<MultipartField>;
 
+/// The parts of a form grouped by the field they could belong to, so that taking each field of a
+/// large form doesn't search through all of the parts again
+///
+/// Parts are grouped by their name up to any `[index]`, so `tags`, `tags[0]` and `tags[1][name]`
+/// are all in the group for `tags`. Used by `#[derive(FromMultipart)]`.
+///
+/// # Example
+/// ```compile_fail
+/// # use actix_validated_forms::multipart::{IndexedParts, MultipartType};
+/// let mut parts = IndexedParts::new(parts);
+/// let int_val: i64 = MultipartType::get(parts.field("int_field"), "int_field")?;
+/// let str_val: String = MultipartType::get(parts.field("str_field"), "str_field")?;
+/// ```
+pub struct IndexedParts(HashMap<String, Multiparts>);
+
+impl IndexedParts {
+    pub fn new(parts: Multiparts) -> Self {
+        let mut groups = HashMap::<String, Multiparts>::new();
+        for part in parts {
+            let field_name = part.name().split('[').next().unwrap();
+            match groups.get_mut(field_name) {
+                Some(group) => group.push(part),
+                None => {
+                    groups.insert(field_name.to_owned(), vec![part]);
+                }
+            }
+        }
+        IndexedParts(groups)
+    }
+
+    /// The parts that could belong to `field_name`, in the order they were sent
+    pub fn field(&mut self, field_name: &str) -> &mut Multiparts {
+        self.0.entry(field_name.to_owned()).or_insert_with(Vec::new)
+    }
+
+    /// The parts that haven't been taken from any of the fields, in the order they were sent
+    pub fn into_remaining(self) -> Multiparts {
+        let mut parts: Multiparts = self.0.into_iter().flat_map(|(_, group)| group).collect();
+        parts.sort_by_key(MultipartField::index);
+        parts
+    }
+}
+
 /// Structure used to represent a File upload in a mulipart form
 ///
 /// A body part is treated as a file upload if the Content-Type header is set to anything
 /// other than `text/plain` or a `filename` is specified in the content disposition header.
+///
+/// The temporary file isn't created until the first bytes of the part are received (or the part
+/// ends empty), so a part that fails before then costs nothing. An empty part (such as a file
+/// input that was left empty) is loaded as an empty file, use
+/// `MultipartLoadConfig::blank_files()` to drop those instead.
 #[derive(Debug)]
 pub struct MultipartFile {
     /// The file data itself stored as a temporary file on disk
@@ -39,11 +149,31 @@ pub struct MultipartFile {
     pub size: u64,
     /// The name of the field in the multipart form
     pub name: String,
-    /// The `filename` value in the `Content-Disposition` header
+    /// The `filename` value in the `Content-Disposition` header, preferring the extended
+    /// `filename*` value (RFC 5987) if there is one
     pub filename: Option<String>,
+    /// The plain `filename` value in the `Content-Disposition` header, when `filename` was taken
+    /// from the `filename*` value instead
+    pub fallback_filename: Option<String>,
     /// The Content-Type specified as reported in the uploaded form
     /// DO NOT trust this as being accurate
     pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+    /// The hex encoded SHA-256 digest of the file, if `MultipartLoadConfig::digests()` or
+    /// deduplication was enabled
+    pub sha256: Option<String>,
+    /// The hex encoded MD5 digest of the file, if `MultipartLoadConfig::digests()` was enabled
+    pub md5: Option<String>,
+    /// Set if `MultipartLoadConfig::deduplicate()` found the file already exists, in which case
+    /// its contents were discarded (the temporary `file` is empty and `size` is 0)
+    pub deduplicated: bool,
+    /// Set if the temporary file was confirmed to be readable by its owner only (see
+    /// `MultipartLoadConfig::private_temp_files()`)
+    pub private: bool,
 }
 
 impl MultipartFile {
@@ -53,25 +183,154 @@ impl MultipartFile {
             .as_ref()
             .and_then(|f| Path::new(f.as_str()).extension().and_then(OsStr::to_str))
     }
+
+    /// The `filename` value in the `Content-Disposition` header, made safe to use as the name of a
+    /// file on disk (see `sanitize_filename()`)
+    pub fn sanitized_filename(&self) -> Option<String> {
+        self.filename.as_deref().and_then(sanitize_filename)
+    }
 }
 
 /// Structure used to represent a Text field in a mulipart form
 ///
-/// A body part is treated as text if the Content-Type header is equal to `text/plain`
-/// (or otherwise unspecified - since `text/plain` is the default), and no `filename` is
-/// specified in the content disposition header.
-#[derive(Debug)]
+/// A body part is treated as text if the Content-Type header is `text/plain` (with any
+/// parameters, or otherwise unspecified - since `text/plain` is the default), and no `filename`
+/// is specified in the content disposition header.
+#[derive(Debug, Clone)]
 pub struct MultipartText {
     /// The name of the field in the multipart form
     pub name: String,
     /// The text body of the field / part
     pub text: String,
+    /// The Content-Type of the part, including any parameters (such as `charset`)
+    pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The size in bytes of the body of the part
+    pub size: u64,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+}
+
+impl MultipartText {
+    /// Create a plain text field (with no part headers, and an `index` of 0)
+    pub fn new(name: String, text: String) -> Self {
+        MultipartText {
+            size: text.len() as u64,
+            name,
+            text,
+            mime: mime::TEXT_PLAIN,
+            headers: HeaderMap::new(),
+            index: 0,
+        }
+    }
+}
+
+/// Structure used to represent a Text field that isn't valid UTF-8, kept as raw bytes when
+/// loading with `Utf8Policy::Bytes`
+///
+/// Getting a `MultipartBytes` also takes the Text fields of the same name (as their UTF-8
+/// bytes), so that a field can be read whether or not it was valid.
+#[derive(Debug, Clone)]
+pub struct MultipartBytes {
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The undecoded body of the field / part
+    pub bytes: Bytes,
+    /// The Content-Type of the part, including any parameters (such as `charset`)
+    pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The size in bytes of the body of the part
+    pub size: u64,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+}
+
+impl From<MultipartText> for MultipartBytes {
+    fn from(text: MultipartText) -> Self {
+        MultipartBytes {
+            name: text.name,
+            bytes: Bytes::from(text.text),
+            mime: text.mime,
+            headers: text.headers,
+            size: text.size,
+            index: text.index,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum MultipartField {
     File(MultipartFile),
     Text(MultipartText),
+    Bytes(MultipartBytes),
+    Saved(SavedFile),
+    Stream(TextStream),
+    Memory(MemoryFile),
+    Stored(StoredFile),
+}
+
+impl MultipartField {
+    /// The name of the field in the multipart form
+    pub fn name(&self) -> &str {
+        match self {
+            MultipartField::File(x) => &x.name,
+            MultipartField::Text(x) => &x.name,
+            MultipartField::Bytes(x) => &x.name,
+            MultipartField::Saved(x) => &x.name,
+            MultipartField::Stream(x) => &x.name,
+            MultipartField::Memory(x) => &x.name,
+            MultipartField::Stored(x) => &x.name,
+        }
+    }
+
+    /// The `Content-ID` header of the part (without the angle brackets), that parts of a
+    /// `multipart/related` payload are referenced by
+    pub fn content_id(&self) -> Option<&str> {
+        content_id(self.headers())
+    }
+
+    /// The position of the part in the multipart form (starting from 0), for relating parts
+    /// of different fields by the order they were sent in
+    pub fn index(&self) -> usize {
+        match self {
+            MultipartField::File(x) => x.index,
+            MultipartField::Text(x) => x.index,
+            MultipartField::Bytes(x) => x.index,
+            MultipartField::Saved(x) => x.index,
+            MultipartField::Stream(x) => x.index,
+            MultipartField::Memory(x) => x.index,
+            MultipartField::Stored(x) => x.index,
+        }
+    }
+
+    /// All the headers of the part, for reading any custom headers sent by the client
+    pub fn headers(&self) -> &HeaderMap {
+        match self {
+            MultipartField::File(x) => &x.headers,
+            MultipartField::Text(x) => &x.headers,
+            MultipartField::Bytes(x) => &x.headers,
+            MultipartField::Saved(x) => &x.headers,
+            MultipartField::Stream(x) => &x.headers,
+            MultipartField::Memory(x) => &x.headers,
+            MultipartField::Stored(x) => &x.headers,
+        }
+    }
+
+    pub(crate) fn name_mut(&mut self) -> &mut String {
+        match self {
+            MultipartField::File(x) => &mut x.name,
+            MultipartField::Text(x) => &mut x.name,
+            MultipartField::Bytes(x) => &mut x.name,
+            MultipartField::Saved(x) => &mut x.name,
+            MultipartField::Stream(x) => &mut x.name,
+            MultipartField::Memory(x) => &mut x.name,
+            MultipartField::Stored(x) => &mut x.name,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -79,11 +338,62 @@ pub enum GetError {
     /// If this field is optional try using Option<T>::get() instead
     #[error(display = "Field '{}' not found", _0)]
     NotFound(String),
+    /// The name of the type the value couldn't be converted into, followed by the reason if it is
+    /// known (e.g. invalid UTF-8)
     #[error(display = "Field '{}' couldn't be converted into {}", _0, _1)]
     TypeError(String, String),
     /// If this field is actually an array of uploaded items try using Vec<T>::get() instead
     #[error(display = "Duplicate values found for field '{}'", _0)]
     DuplicateField(String),
+    /// A user-facing message for the field, defined using the `missing_msg`, `duplicate_msg` or
+    /// `type_msg` attributes of `#[derive(FromMultipart)]`
+    #[error(display = "{}", _1)]
+    Message(String, String),
+}
+
+impl GetError {
+    /// The name of the field that caused the error
+    pub fn field(&self) -> &str {
+        match self {
+            GetError::NotFound(n)
+            | GetError::TypeError(n, _)
+            | GetError::DuplicateField(n)
+            | GetError::Message(n, _) => n,
+        }
+    }
+
+    /// Replaces the error with a custom `Message` (if one is given for this kind of error)
+    pub fn with_messages(
+        self,
+        missing: Option<&str>,
+        duplicate: Option<&str>,
+        type_error: Option<&str>,
+    ) -> Self {
+        let message = match self {
+            GetError::NotFound(_) => missing,
+            GetError::DuplicateField(_) => duplicate,
+            GetError::TypeError(..) => type_error,
+            GetError::Message(..) => None,
+        };
+        match message {
+            Some(m) => GetError::Message(self.field().into(), m.into()),
+            None => self,
+        }
+    }
+}
+
+impl From<GetError> for validator::ValidationError {
+    fn from(e: GetError) -> Self {
+        let code = match e {
+            GetError::NotFound(_) => "required",
+            GetError::TypeError(..) => "invalid_type",
+            GetError::DuplicateField(_) => "duplicate",
+            GetError::Message(..) => "invalid",
+        };
+        let mut error = validator::ValidationError::new(code);
+        error.message = Some(e.to_string().into());
+        error
+    }
 }
 
 impl ResponseError for GetError {
@@ -99,7 +409,8 @@ where
 {
     /// Attempt to retrieve a named field/part from the Multipart form
     ///
-    /// Implementations are provided for any type that implements `FromStr`
+    /// Implementations are provided for any type that implements `FromStr`, and for the parts
+    /// themselves (e.g. `MultipartText` to also access the metadata of a text field)
     /// # Example
     /// ```no_run
     /// # use actix_validated_forms::multipart::MultipartType;
@@ -126,25 +437,107 @@ where
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError>;
 }
 
+fn exactly_one<T>(mut matches: Vec<T>, field_name: &str) -> Result<T, GetError> {
+    match matches.len() {
+        0 => Err(GetError::NotFound(field_name.into())),
+        1 => Ok(matches.pop().unwrap()),
+        _ => Err(GetError::DuplicateField(field_name.into())),
+    }
+}
+
+fn at_most_one<T>(mut matches: Vec<T>, field_name: &str) -> Result<Option<T>, GetError> {
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matches.pop().unwrap())),
+        _ => Err(GetError::DuplicateField(field_name.into())),
+    }
+}
+
+/// Splits a part name of the form `field_name[index]rest` into the index and the rest
+pub(crate) fn split_index<'a>(part_name: &'a str, field_name: &str) -> Option<(usize, &'a str)> {
+    let rest = part_name.strip_prefix(field_name)?.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let index = rest[..end].parse().ok()?;
+    Some((index, &rest[end + 1..]))
+}
+
+/// The value of a `Content-ID` header (RFC 2392), without the angle brackets
+pub(crate) fn content_id(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get("content-id")?.to_str().ok()?.trim();
+    let id = value.trim_start_matches('<').trim_end_matches('>').trim();
+    Some(id).filter(|id| !id.is_empty())
+}
+
+/// Whether a part belongs to a field, either by being named `field_name` exactly, or
+/// `field_name[index]...` as an item of a `Vec` or `Group`
+pub(crate) fn belongs_to(part_name: &str, field_name: &str) -> bool {
+    part_name == field_name || split_index(part_name, field_name).is_some()
+}
+
+/// Checks whether a part belongs to a field, either by being named `field_name` exactly, or
+/// `field_name[index]` in which case the index is returned
+fn match_name(part_name: &str, field_name: &str) -> Option<Option<usize>> {
+    if part_name == field_name {
+        return Some(None);
+    }
+    match split_index(part_name, field_name) {
+        Some((index, "")) => Some(Some(index)),
+        _ => None,
+    }
+}
+
+/// Orders matching parts by their index (if they had one)
+fn sort_indexed<T>(
+    mut matches: Vec<(Option<usize>, T)>,
+    field_name: &str,
+) -> Result<Vec<T>, GetError> {
+    matches.sort_by_key(|(index, _)| *index);
+    let duplicate_index = matches
+        .windows(2)
+        .any(|w| w[0].0.is_some() && w[0].0 == w[1].0);
+    if duplicate_index {
+        return Err(GetError::DuplicateField(field_name.into()));
+    }
+    Ok(matches.into_iter().map(|(_, x)| x).collect())
+}
+
+/// Removes the parts belonging to a field (of a type selected by `select`) from the form,
+/// converting each using `unwrap`
+fn take_parts<T, S, U>(
+    form: &mut Multiparts,
+    field_name: &str,
+    select: S,
+    unwrap: U,
+) -> Result<Vec<T>, GetError>
+where
+    S: Fn(&MultipartField) -> bool,
+    U: Fn(MultipartField) -> T,
+{
+    let indexes: Vec<(usize, Option<usize>)> = form
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| select(item))
+        .filter_map(|(idx, item)| match_name(item.name(), field_name).map(|i| (idx, i)))
+        .collect();
+    // Removed from the end so the positions stay valid, but kept in the order they were sent
+    let mut matches: Vec<_> = indexes
+        .iter()
+        .rev()
+        .map(|(idx, index)| (*index, unwrap(form.remove(*idx))))
+        .collect();
+    matches.reverse();
+    sort_indexed(matches, field_name)
+}
+
 impl<T: FromStr> MultipartType for T {
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
-        let mut matches = Vec::<T>::get(form, field_name)?;
-        match matches.len() {
-            0 => Err(GetError::NotFound(field_name.into())),
-            1 => Ok(matches.pop().unwrap()),
-            _ => Err(GetError::DuplicateField(field_name.into())),
-        }
+        exactly_one(Vec::<T>::get(form, field_name)?, field_name)
     }
 }
 
 impl<T: FromStr> MultipartTypeSpecial for Option<T> {
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
-        let mut matches = Vec::<T>::get(form, field_name)?;
-        match matches.len() {
-            0 => Ok(None),
-            1 => Ok(Some(matches.pop().unwrap())),
-            _ => Err(GetError::DuplicateField(field_name.into())),
-        }
+        at_most_one(Vec::<T>::get(form, field_name)?, field_name)
     }
 }
 
@@ -153,66 +546,262 @@ impl<T: FromStr> MultipartTypeSpecial for Vec<T> {
         let mut matches = Vec::new();
         for i in form {
             match i {
-                MultipartField::File(_) => {}
                 MultipartField::Text(x) => {
-                    if x.name == field_name {
+                    if let Some(index) = match_name(&x.name, field_name) {
+                        // The parse error isn't required to implement Display, so only the
+                        // type is reported
                         let y: T = x.text.parse().map_err(|_| {
                             GetError::TypeError(
                                 field_name.into(),
                                 std::any::type_name::<T>().into(),
                             )
                         })?;
-                        matches.push(y);
+                        matches.push((index, y));
                     }
                 }
+                MultipartField::Bytes(x) if match_name(&x.name, field_name).is_some() => {
+                    return Err(GetError::TypeError(
+                        field_name.into(),
+                        format!("{} (invalid UTF-8)", std::any::type_name::<T>()),
+                    ));
+                }
+                _ => {}
             }
         }
-        Ok(matches)
+        sort_indexed(matches, field_name)
+    }
+}
+
+impl MultipartType for MultipartText {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<MultipartText>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<MultipartText> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<MultipartText>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<MultipartText> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Text(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Text(x) => x,
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for MultipartBytes {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<MultipartBytes>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<MultipartBytes> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<MultipartBytes>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<MultipartBytes> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Text(_) | MultipartField::Bytes(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Text(x) => x.into(),
+                MultipartField::Bytes(x) => x,
+                _ => unreachable!(),
+            },
+        )
     }
 }
 
 impl MultipartType for MultipartFile {
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
-        let mut matches = Vec::<MultipartFile>::get(form, field_name)?;
-        match matches.len() {
-            0 => Err(GetError::NotFound(field_name.into())),
-            1 => Ok(matches.pop().unwrap()),
-            _ => Err(GetError::DuplicateField(field_name.into())),
-        }
+        exactly_one(Vec::<MultipartFile>::get(form, field_name)?, field_name)
     }
 }
 
 impl MultipartTypeSpecial for Option<MultipartFile> {
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
-        let mut matches = Vec::<MultipartFile>::get(form, field_name)?;
-        match matches.len() {
-            0 => Ok(None),
-            1 => Ok(Some(matches.pop().unwrap())),
-            _ => Err(GetError::DuplicateField(field_name.into())),
-        }
+        at_most_one(Vec::<MultipartFile>::get(form, field_name)?, field_name)
     }
 }
 
 impl MultipartTypeSpecial for Vec<MultipartFile> {
     fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
-        let mut indexes = Vec::new();
-        for (idx, item) in form.iter().enumerate() {
-            match item {
-                MultipartField::Text(_) => {}
-                MultipartField::File(x) => {
-                    if x.name == field_name {
-                        indexes.push(idx)
-                    }
-                }
-            }
-        }
-        Ok(indexes
-            .iter()
-            .rev()
-            .map(|idx| match form.remove(*idx) {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::File(_) => true,
+                _ => false,
+            },
+            |item| match item {
                 MultipartField::File(x) => x,
-                MultipartField::Text(_) => panic!(),
-            })
-            .collect())
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for SavedFile {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<SavedFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<SavedFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<SavedFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<SavedFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Saved(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Saved(x) => x,
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for TextStream {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<TextStream>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<TextStream> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<TextStream>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<TextStream> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Stream(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Stream(x) => x,
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for MemoryFile {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<MemoryFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<MemoryFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<MemoryFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<MemoryFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Memory(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Memory(x) => x,
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for StoredFile {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<StoredFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<StoredFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<StoredFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<StoredFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Stored(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Stored(x) => x,
+                _ => unreachable!(),
+            },
+        )
+    }
+}
+
+impl MultipartType for SpooledFile {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        exactly_one(Vec::<SpooledFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Option<SpooledFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        at_most_one(Vec::<SpooledFile>::get(form, field_name)?, field_name)
+    }
+}
+
+impl MultipartTypeSpecial for Vec<SpooledFile> {
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        take_parts(
+            form,
+            field_name,
+            |item| match item {
+                MultipartField::Memory(_) | MultipartField::File(_) => true,
+                _ => false,
+            },
+            |item| match item {
+                MultipartField::Memory(x) => SpooledFile::Memory(x),
+                MultipartField::File(x) => SpooledFile::File(x),
+                _ => unreachable!(),
+            },
+        )
     }
 }
@@ -0,0 +1,113 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+const PREFIX: &str = "avf-";
+
+thread_local! {
+    static PROCESS_PREFIX: String = format!("{}{}-{}-", PREFIX, hostname(), std::process::id());
+}
+
+/// Creates a temporary file in `dir` (or the system temporary directory), named with the prefix
+/// `avf-{host}-{pid}-` so that it can be found by `remove_orphaned_temp_files()`, and ending
+/// with `suffix`
+pub(crate) fn create_temp_file(dir: Option<&Path>, suffix: &str) -> io::Result<NamedTempFile> {
+    let prefix = PROCESS_PREFIX.with(|p| p.clone());
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(suffix);
+    match dir {
+        Some(dir) => builder.tempfile_in(dir),
+        None => builder.tempfile(),
+    }
+}
+
+/// Restricts a temporary file to being read and written by its owner, returning whether this
+/// could be confirmed (it can't be on platforms other than Unix)
+#[cfg(unix)]
+pub(crate) fn make_private(file: &fs::File) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file.metadata()?.permissions().mode() & 0o077 == 0)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn make_private(_file: &fs::File) -> io::Result<bool> {
+    Ok(false)
+}
+
+fn hostname() -> String {
+    let name = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .unwrap_or_default();
+    let name: String = name
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+        .collect();
+    if name.is_empty() {
+        "localhost".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Removes temporary files (and `save_to()` staging files) left in `dir` by processes on this
+/// host that are no longer running, returning how many were removed
+///
+/// Temporary files are normally deleted when they are dropped, but are left behind if the
+/// process crashes or is killed. Call this at startup for each directory used by
+/// `MultipartLoadConfig::temp_dir()` (or `std::env::temp_dir()` by default) and
+/// `MultipartLoadConfig::save_to()`. Files created by other hosts sharing the directory are
+/// left alone, each replica should clean up after itself. Whether a process is running can only
+/// be checked on Linux, elsewhere no files are removed.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::remove_orphaned_temp_files;
+/// let removed = remove_orphaned_temp_files(std::env::temp_dir()).unwrap();
+/// eprintln!("Removed {} orphaned uploads", removed);
+/// ```
+pub fn remove_orphaned_temp_files<P: AsRef<Path>>(dir: P) -> io::Result<usize> {
+    let host = hostname();
+    let current = std::process::id();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let owner = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(PREFIX))
+            .and_then(|name| {
+                // {host}-{pid}-{random}, the host may itself contain a '-'
+                let mut parts = name.rsplitn(3, '-');
+                let _random = parts.next()?;
+                let pid = parts.next()?.parse::<u32>().ok()?;
+                Some((parts.next()?.to_owned(), pid))
+            });
+        let orphaned = match owner {
+            Some((owner_host, pid)) => owner_host == host && pid != current && !is_alive(pid),
+            None => false,
+        };
+        if orphaned && entry.file_type()?.is_file() {
+            match fs::remove_file(entry.path()) {
+                Ok(_) => removed += 1,
+                // Another replica may have removed it first
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(removed)
+}
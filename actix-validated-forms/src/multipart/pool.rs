@@ -0,0 +1,81 @@
+use actix_web::web::BytesMut;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// Buffers kept between requests for reuse (see `MultipartLoadConfig::buffer_pool()`)
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    max: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max: usize) -> Self {
+        BufferPool {
+            buffers: Mutex::new(Vec::with_capacity(max)),
+            max,
+        }
+    }
+
+    /// Takes a buffer from the pool, or allocates a new one if it is empty
+    pub(crate) fn take(self: &Arc<Self>, size: usize) -> WriteBuffer {
+        let bytes = self.buffers.lock().ok().and_then(|mut b| b.pop());
+        let mut bytes = bytes.unwrap_or_else(|| BytesMut::with_capacity(size));
+        bytes.reserve(size);
+        WriteBuffer {
+            bytes,
+            size,
+            pool: Some(self.clone()),
+        }
+    }
+}
+
+/// A buffer of the `write_buffer_size`, that returns to its pool (if any) once it is dropped
+pub(crate) struct WriteBuffer {
+    bytes: BytesMut,
+    size: usize,
+    pool: Option<Arc<BufferPool>>,
+}
+
+impl WriteBuffer {
+    /// Allocates a buffer that isn't part of a pool
+    pub(crate) fn new(size: usize) -> Self {
+        WriteBuffer {
+            bytes: BytesMut::new(),
+            size,
+            pool: None,
+        }
+    }
+
+    /// The number of bytes to collect before writing them
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Deref for WriteBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.bytes
+    }
+}
+
+impl DerefMut for WriteBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.bytes
+    }
+}
+
+impl Drop for WriteBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            if let Ok(mut buffers) = pool.buffers.lock() {
+                if buffers.len() < pool.max {
+                    let mut bytes = std::mem::replace(&mut self.bytes, BytesMut::new());
+                    bytes.clear();
+                    buffers.push(bytes);
+                }
+            }
+        }
+    }
+}
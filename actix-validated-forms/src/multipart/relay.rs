@@ -0,0 +1,144 @@
+use super::{MultipartField, MultipartFile, Multiparts};
+use actix_web::web::Bytes;
+use futures::Stream;
+use reqwest::multipart::{Form, Part};
+use std::fs::File;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tempfile::NamedTempFile;
+use tokio::io::AsyncRead;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Streams a file from disk, keeping the temporary file alive until it has been read
+struct FileStream {
+    file: tokio::fs::File,
+    remaining: u64,
+    _temp: Option<NamedTempFile>,
+}
+
+impl FileStream {
+    fn new(file: File, size: u64, temp: Option<NamedTempFile>) -> Self {
+        FileStream {
+            file: tokio::fs::File::from_std(file),
+            remaining: size,
+            _temp: temp,
+        }
+    }
+
+    fn into_part(self, size: u64) -> Part {
+        Part::stream_with_length(reqwest::Body::wrap_stream(self), size)
+    }
+}
+
+impl Stream for FileStream {
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let mut buf = vec![0; self.remaining.min(CHUNK_SIZE) as usize];
+        match Pin::new(&mut self.file).poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(0)) => Poll::Ready(None),
+            Poll::Ready(Ok(n)) => {
+                self.remaining -= n as u64;
+                buf.truncate(n);
+                Poll::Ready(Some(Ok(Bytes::from(buf))))
+            }
+        }
+    }
+}
+
+fn with_metadata(part: Part, filename: Option<String>, mime: &mime::Mime) -> io::Result<Part> {
+    let part = match filename {
+        Some(filename) => part.file_name(filename),
+        None => part,
+    };
+    part.mime_str(mime.as_ref())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+impl MultipartFile {
+    /// Convert into a `reqwest` multipart part, for relaying the upload to another service
+    ///
+    /// The file is streamed from disk (the temporary file is deleted once the part is dropped).
+    /// A file that was deduplicated no longer has its contents, so produces an error.
+    pub fn into_reqwest_part(self) -> io::Result<Part> {
+        if self.deduplicated {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The contents of a deduplicated file were discarded",
+            ));
+        }
+        let file = self.file.reopen()?;
+        let part = FileStream::new(file, self.size, Some(self.file)).into_part(self.size);
+        with_metadata(part, self.filename, &self.mime)
+    }
+}
+
+/// Re-serializes a loaded form into a `reqwest` multipart form, e.g. for relaying it to a third
+/// party API without buffering the uploaded files into memory
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{load_parts, IntoReqwestForm, MultipartLoadConfig};
+/// # use actix_web::{HttpResponse, Error};
+/// async fn route(payload: actix_multipart::Multipart) -> Result<HttpResponse, Error> {
+///     let parts = load_parts(payload, MultipartLoadConfig::default()).await?;
+///     let response = reqwest::Client::new()
+///         .post("http://upstream/upload")
+///         .multipart(parts.into_reqwest_form()?)
+///         .send()
+///         .await;
+///     # unimplemented!() }
+/// ```
+pub trait IntoReqwestForm {
+    fn into_reqwest_form(self) -> io::Result<Form>;
+}
+
+impl IntoReqwestForm for Multiparts {
+    fn into_reqwest_form(self) -> io::Result<Form> {
+        let mut form = Form::new();
+        for part in self {
+            let (name, part) = match part {
+                MultipartField::Text(t) => {
+                    let part = with_metadata(Part::text(t.text), None, &t.mime)?;
+                    (t.name, part)
+                }
+                MultipartField::Bytes(b) => {
+                    let part = with_metadata(Part::bytes(b.bytes.to_vec()), None, &b.mime)?;
+                    (b.name, part)
+                }
+                MultipartField::Stream(t) => {
+                    let file = t.file.reopen()?;
+                    (
+                        t.name,
+                        FileStream::new(file, t.size, Some(t.file)).into_part(t.size),
+                    )
+                }
+                MultipartField::File(f) => (f.name.clone(), f.into_reqwest_part()?),
+                MultipartField::Saved(f) => {
+                    let file = File::open(&f.path)?;
+                    let part = FileStream::new(file, f.size, None).into_part(f.size);
+                    (f.name, with_metadata(part, f.filename, &f.mime)?)
+                }
+                MultipartField::Stored(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "The contents of a stored file aren't available locally",
+                    ))
+                }
+                MultipartField::Memory(f) => {
+                    let part = Part::stream_with_length(f.data, f.size);
+                    (f.name, with_metadata(part, f.filename, &f.mime)?)
+                }
+            };
+            form = form.part(name, part);
+        }
+        Ok(form)
+    }
+}
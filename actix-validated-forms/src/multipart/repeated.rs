@@ -0,0 +1,98 @@
+use super::{split_index, GetError, MultipartType, Multiparts};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops;
+
+/// A repeating group of fields, named using the `field_name[index][sub_field]` convention
+///
+/// Each index is collected into its own `Multiparts` (with the parts renamed to just `sub_field`)
+/// and converted into a `T`, in order of their index. This allows pairing files with their
+/// metadata.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// # use actix_validated_forms_derive::FromMultipart;
+/// # use actix_validated_forms::multipart::{MultipartFile, Repeated};
+/// // Binds `photos[0][file]`, `photos[0][caption]`, `photos[1][file]`, ...
+/// #[derive(FromMultipart)]
+/// struct Photo {
+///     file: MultipartFile,
+///     caption: String,
+/// }
+///
+/// #[derive(FromMultipart)]
+/// struct Album {
+///     title: String,
+///     photos: Repeated<Photo>,
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Repeated<T>(pub Vec<T>);
+
+impl<T> Repeated<T> {
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for Repeated<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> ops::DerefMut for Repeated<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+/// Splits the rest of a part name `[sub_field]...` into `sub_field...`
+fn sub_field_name(rest: &str) -> Option<String> {
+    let rest = rest.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    Some(format!("{}{}", &rest[..end], &rest[end + 1..]))
+}
+
+fn qualify_error(e: GetError, field_name: &str, index: usize) -> GetError {
+    let qualify = |sub_field: String| format!("{}[{}][{}]", field_name, index, sub_field);
+    match e {
+        GetError::NotFound(n) => GetError::NotFound(qualify(n)),
+        GetError::TypeError(n, t) => GetError::TypeError(qualify(n), t),
+        GetError::DuplicateField(n) => GetError::DuplicateField(qualify(n)),
+        GetError::Message(n, m) => GetError::Message(qualify(n), m),
+    }
+}
+
+impl<T> MultipartType for Repeated<T>
+where
+    T: TryFrom<Multiparts, Error = GetError>,
+{
+    fn get(form: &mut Multiparts, field_name: &str) -> Result<Self, GetError> {
+        let mut groups = BTreeMap::<usize, Multiparts>::new();
+        let mut idx = 0;
+        while idx < form.len() {
+            let sub_field = split_index(form[idx].name(), field_name)
+                .and_then(|(index, rest)| sub_field_name(rest).map(|s| (index, s)));
+            match sub_field {
+                Some((index, sub_field)) => {
+                    let mut item = form.remove(idx);
+                    *item.name_mut() = sub_field;
+                    groups.entry(index).or_insert_with(Vec::new).push(item);
+                }
+                None => idx = idx + 1,
+            }
+        }
+        let items = groups
+            .into_iter()
+            .map(|(index, group)| {
+                T::try_from(group).map_err(|e| qualify_error(e, field_name, index))
+            })
+            .collect::<Result<Vec<T>, GetError>>()?;
+        Ok(Repeated(items))
+    }
+}
@@ -0,0 +1,165 @@
+use super::load::{write_field, PartBody, PartInfo};
+use super::orphans::create_temp_file;
+use super::pool::WriteBuffer;
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Structure used to represent a File upload that was written directly to its final location
+///
+/// Loaded instead of a `MultipartFile` when `MultipartLoadConfig::save_to()` is configured.
+/// Unlike a `MultipartFile` the file is NOT deleted when this is dropped.
+#[derive(Debug)]
+pub struct SavedFile {
+    /// The location the file was saved to
+    pub path: PathBuf,
+    /// The size in bytes of the file
+    pub size: u64,
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The `filename` value in the `Content-Disposition` header
+    pub filename: Option<String>,
+    /// The Content-Type specified as reported in the uploaded form
+    /// DO NOT trust this as being accurate
+    pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+    /// Whether the file had already been saved by an earlier upload (see
+    /// `NamingStrategy::ContentHash`), in which case it isn't removed if the form fails to load
+    pub existing: bool,
+}
+
+impl SavedFile {
+    /// Get the extension portion of the `filename` value in the `Content-Disposition` header
+    pub fn get_extension(&self) -> Option<&str> {
+        self.filename
+            .as_ref()
+            .and_then(|f| Path::new(f.as_str()).extension().and_then(OsStr::to_str))
+    }
+}
+
+/// Decides the file name a `SavedFile` is written to within the destination directory
+#[derive(Clone)]
+pub enum NamingStrategy {
+    /// A random (v4) UUID
+    Uuid,
+    /// The hex encoded SHA-256 digest of the file contents
+    ///
+    /// The upload is streamed into a temporary file in the destination directory and then renamed
+    /// once the digest is known. An identical upload reuses the file that was already saved
+    /// (rather than replacing it), and is loaded with `SavedFile::existing` set.
+    ContentHash,
+    /// A custom function receiving the field name and the `filename` from the
+    /// `Content-Disposition` header
+    ///
+    /// The returned name must be a single path component, an existing file will not be replaced.
+    Callback(Arc<dyn Fn(&str, Option<&str>) -> String + Send + Sync>),
+    /// The idempotency key of the request (see `MultipartLoadConfig::idempotency_key()`)
+    /// followed by the number of the file within the form, e.g. `{key}-0`
+    ///
+    /// A retried upload fails if the earlier attempt's files were saved, rather than replacing
+    /// them or leaving a second copy. Requests without a key fall back to a random UUID.
+    IdempotencyKey,
+}
+
+fn io_error(e: io::Error) -> MultipartError {
+    MultipartError::Payload(PayloadError::Io(e))
+}
+
+fn invalid_file_name() -> MultipartError {
+    io_error(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Saved file name must be a single path component",
+    ))
+}
+
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => true,
+        _ => false,
+    }
+}
+
+pub(crate) async fn create_saved_file<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
+    max_size: u64,
+    buffer: WriteBuffer,
+    dir: &Path,
+    naming: &NamingStrategy,
+    key: Option<String>,
+) -> Result<SavedFile, MultipartError> {
+    let mut existing = false;
+    let (path, size) = match (naming, key) {
+        (NamingStrategy::ContentHash, _) => {
+            let ntf = create_temp_file(Some(dir), "").map_err(io_error)?;
+            let mut hasher = Sha256::new();
+            let size = write_field(&mut field, ntf.as_file(), max_size, buffer, |bytes| {
+                hasher.update(bytes)
+            })
+            .await?;
+            let path = dir.join(format!("{:x}", hasher.finalize()));
+            match ntf.persist_noclobber(&path) {
+                Ok(_) => {}
+                // The same contents, saved by an earlier upload
+                Err(e) if e.error.kind() == io::ErrorKind::AlreadyExists => existing = true,
+                Err(e) => return Err(io_error(e.error)),
+            }
+            (path, size)
+        }
+        (NamingStrategy::IdempotencyKey, Some(key)) => {
+            if !is_plain_file_name(&key) {
+                return Err(invalid_file_name());
+            }
+            let ntf = create_temp_file(Some(dir), "").map_err(io_error)?;
+            let size = write_field(&mut field, ntf.as_file(), max_size, buffer, |_| {}).await?;
+            let path = dir.join(key);
+            ntf.persist_noclobber(&path)
+                .map_err(|e| io_error(e.error))?;
+            (path, size)
+        }
+        _ => {
+            let file_name = match naming {
+                NamingStrategy::Callback(f) => f(&info.name, info.filename.as_deref()),
+                _ => Uuid::new_v4().to_string(),
+            };
+            if !is_plain_file_name(&file_name) {
+                return Err(invalid_file_name());
+            }
+            let path = dir.join(file_name);
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(io_error)?;
+            match write_field(&mut field, &file, max_size, buffer, |_| {}).await {
+                Ok(size) => (path, size),
+                Err(e) => {
+                    let _ = std::fs::remove_file(&path);
+                    return Err(e);
+                }
+            }
+        }
+    };
+    Ok(SavedFile {
+        path,
+        size,
+        name: info.name,
+        filename: info.filename,
+        mime: info.content_type,
+        headers: info.headers,
+        index: info.index,
+        existing,
+    })
+}
@@ -0,0 +1,48 @@
+use super::MultipartFile;
+use err_derive::Error;
+use futures::future::LocalBoxFuture;
+
+/// A malware scanner, such as ClamAV or a cloud scanning API, that checks each file upload before
+/// the form is accepted
+///
+/// Configured using `MultipartLoadConfig::scan_hook()`, each `MultipartFile` is scanned once it
+/// has been completely written to its temporary file, and the form fails to load with
+/// `LoadError::Scan` if any scan fails.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{MultipartFile, ScanError, ScanHook};
+/// # use futures::future::{FutureExt, LocalBoxFuture};
+/// # use std::path::Path;
+/// # async fn clamd_scan(_: &Path) -> std::io::Result<Option<String>> { Ok(None) }
+/// struct Clamd;
+///
+/// impl ScanHook for Clamd {
+///     fn scan(&self, file: &MultipartFile) -> LocalBoxFuture<'static, Result<(), ScanError>> {
+///         let path = file.file.path().to_owned();
+///         async move {
+///             match clamd_scan(&path).await {
+///                 Ok(None) => Ok(()),
+///                 Ok(Some(signature)) => Err(ScanError::Infected(signature)),
+///                 Err(e) => Err(ScanError::Failed(e.to_string())),
+///             }
+///         }
+///         .boxed_local()
+///     }
+/// }
+/// ```
+pub trait ScanHook: Send + Sync {
+    /// Scans an uploaded file, resolving to an error if it must be rejected
+    fn scan(&self, file: &MultipartFile) -> LocalBoxFuture<'static, Result<(), ScanError>>;
+}
+
+/// Why a file was rejected by a `ScanHook`
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum ScanError {
+    /// The file contains malware, named by the signature that matched it
+    #[error(display = "File is infected with {}", _0)]
+    Infected(String),
+    /// The file couldn't be scanned, e.g. because the scanner is unavailable
+    #[error(display = "File couldn't be scanned: {}", _0)]
+    Failed(String),
+}
@@ -0,0 +1,136 @@
+use super::load::remove_saved_files;
+use super::Multiparts;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+struct Session {
+    parts: Multiparts,
+    expires: Instant,
+}
+
+/// Keeps loaded multipart forms (including their temporary files) between requests
+///
+/// Allows a form to be submitted over several requests, for example a multi-step wizard that
+/// uploads files in its first step but only processes them in its last. Each stored form is
+/// identified by a random token that can be given back to the client.
+///
+/// Sessions expire after the configured time to live, at which point their files (including any
+/// saved or stored ones) are deleted. Expired sessions are removed whenever the store is accessed,
+/// by calling `remove_expired()`, or periodically in the background once `reap_every()` is called.
+///
+/// # Usage
+/// Create a single instance (outside of the `HttpServer::new` closure, so that it is shared
+/// between workers) and add it to your app as `web::Data`
+/// ```
+/// # use actix_validated_forms::multipart::UploadSessions;
+/// # use actix_web::{web, App};
+/// # use std::time::Duration;
+/// let sessions = web::Data::new(UploadSessions::new(Duration::from_secs(30 * 60)));
+/// let app = App::new().app_data(sessions.clone());
+/// ```
+#[derive(Clone)]
+pub struct UploadSessions {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    ttl: Duration,
+}
+
+impl UploadSessions {
+    /// Create a store where sessions expire `ttl` after they were last stored to
+    pub fn new(ttl: Duration) -> Self {
+        UploadSessions {
+            sessions: Default::default(),
+            ttl,
+        }
+    }
+
+    /// Store the parts of a form in a new session, returning its token
+    pub fn store(&self, parts: Multiparts) -> String {
+        let token = Uuid::new_v4().to_string();
+        let mut sessions = self.lock();
+        sessions.insert(
+            token.clone(),
+            Session {
+                parts,
+                expires: Instant::now() + self.ttl,
+            },
+        );
+        token
+    }
+
+    /// Add more parts to an existing session (refreshing its expiry)
+    ///
+    /// If the session doesn't exist (or has expired) the parts are returned back
+    pub fn extend(&self, token: &str, parts: Multiparts) -> Result<(), Multiparts> {
+        let mut sessions = self.lock();
+        match sessions.get_mut(token) {
+            Some(session) => {
+                session.parts.extend(parts);
+                session.expires = Instant::now() + self.ttl;
+                Ok(())
+            }
+            None => Err(parts),
+        }
+    }
+
+    /// Remove a session, returning its parts
+    pub fn take(&self, token: &str) -> Option<Multiparts> {
+        self.lock().remove(token).map(|s| s.parts)
+    }
+
+    /// Remove a session, deleting its temporary (and saved or stored) files
+    pub fn discard(&self, token: &str) -> bool {
+        match self.take(token) {
+            Some(parts) => {
+                remove_saved_files(&parts);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove all the expired sessions, returning how many there were
+    pub fn remove_expired(&self) -> usize {
+        let expired: Vec<Session> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let now = Instant::now();
+            let tokens: Vec<String> = sessions
+                .iter()
+                .filter(|(_, s)| s.expires <= now)
+                .map(|(token, _)| token.clone())
+                .collect();
+            tokens.iter().filter_map(|t| sessions.remove(t)).collect()
+        };
+        // Files are deleted here, after the lock has been released
+        for session in &expired {
+            remove_saved_files(&session.parts);
+        }
+        expired.len()
+    }
+
+    /// Remove the expired sessions every `period` in the background, so that their files are
+    /// deleted even if the store isn't accessed again
+    ///
+    /// Must be called within a running actix system, e.g. in `main` before starting the
+    /// `HttpServer`. The task stops once every clone of the store has been dropped.
+    pub fn reap_every(&self, period: Duration) {
+        let sessions = Arc::downgrade(&self.sessions);
+        let ttl = self.ttl;
+        actix_rt::spawn(async move {
+            let mut interval = actix_rt::time::interval(period);
+            loop {
+                interval.tick().await;
+                match sessions.upgrade() {
+                    Some(sessions) => UploadSessions { sessions, ttl }.remove_expired(),
+                    None => break,
+                };
+            }
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.remove_expired();
+        self.sessions.lock().unwrap()
+    }
+}
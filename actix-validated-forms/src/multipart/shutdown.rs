@@ -0,0 +1,122 @@
+use super::load::LoadError;
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Coordinates multipart uploads with a graceful shutdown of the server
+///
+/// Set using `MultipartLoadConfig::shutdown()`, then call `shutdown()` when the server begins
+/// shutting down (e.g. when handling `SIGTERM`, before calling `Server::stop(true)`). From then on
+/// forms that start loading fail immediately with `LoadError::ShuttingDown`, while uploads that
+/// are already in flight may continue for the `grace` period. Any still loading after that are
+/// aborted (with the same error) and counted in `aborted()`. A grace period of zero aborts them
+/// straight away.
+///
+/// Create the signal once, outside of the `HttpServer::new` closure, so that it is shared by all
+/// the workers.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{MultipartLoadConfig, ShutdownSignal};
+/// # use std::time::Duration;
+/// let signal = ShutdownSignal::new(Duration::from_secs(30));
+/// let config = MultipartLoadConfig::default().shutdown(signal.clone());
+/// // ... and when the server is stopping
+/// signal.shutdown();
+/// eprintln!("Waiting for {} uploads", signal.in_flight());
+/// ```
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    grace: Duration,
+    sender: watch::Sender<Option<Instant>>,
+    receiver: watch::Receiver<Option<Instant>>,
+    in_flight: AtomicUsize,
+    aborted: AtomicUsize,
+}
+
+impl ShutdownSignal {
+    /// Allow uploads that are in flight `grace` to finish once shutdown has begun
+    pub fn new(grace: Duration) -> Self {
+        let (sender, receiver) = watch::channel(None);
+        ShutdownSignal {
+            inner: Arc::new(Inner {
+                grace,
+                sender,
+                receiver,
+                in_flight: AtomicUsize::new(0),
+                aborted: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Begin shutting down, calling this again has no effect
+    pub fn shutdown(&self) {
+        if !self.is_shutting_down() {
+            let abort_at = Instant::now() + self.inner.grace;
+            let _ = self.inner.sender.broadcast(Some(abort_at));
+        }
+    }
+
+    /// Whether `shutdown()` has been called
+    pub fn is_shutting_down(&self) -> bool {
+        self.abort_at().is_some()
+    }
+
+    /// The number of forms currently being loaded
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// The number of forms that were still loading at the end of the grace period, and so were
+    /// aborted
+    pub fn aborted(&self) -> usize {
+        self.inner.aborted.load(Ordering::SeqCst)
+    }
+
+    /// When in flight uploads will be aborted, once shutdown has begun
+    pub(crate) fn abort_at(&self) -> Option<Instant> {
+        *self.inner.receiver.borrow()
+    }
+
+    /// Resolves at the end of the grace period (never, if shutdown doesn't begin)
+    pub(crate) fn grace_expired(&self) -> LocalBoxFuture<'static, ()> {
+        let mut receiver = self.inner.receiver.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Some(Some(abort_at)) => break tokio::time::delay_until(abort_at.into()).await,
+                    Some(None) => continue,
+                    None => future::pending().await,
+                }
+            }
+        }
+        .boxed_local()
+    }
+
+    /// Counts an upload that was aborted, returning the error to fail it with
+    pub(crate) fn abort(&self) -> LoadError {
+        self.inner.aborted.fetch_add(1, Ordering::SeqCst);
+        LoadError::ShuttingDown
+    }
+
+    /// Counts the form as in flight until the returned guard is dropped
+    pub(crate) fn track(&self) -> InFlight {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.clone())
+    }
+}
+
+/// A form being loaded, see `ShutdownSignal::in_flight()`
+pub(crate) struct InFlight(ShutdownSignal);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        (self.0).inner.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
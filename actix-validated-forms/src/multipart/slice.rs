@@ -0,0 +1,106 @@
+use super::MultipartFile;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A readable view of a range of bytes within a file (or any other `Read + Seek`)
+///
+/// Reads and seeks are relative to the start of the range, and stop at its end.
+pub struct FileSlice<R> {
+    inner: R,
+    offset: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<R: Read + Seek> FileSlice<R> {
+    /// A view of the `len` bytes of `inner` starting at `offset`
+    pub fn new(mut inner: R, offset: u64, len: u64) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(offset))?;
+        Ok(FileSlice {
+            inner,
+            offset,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// The length of the range
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for FileSlice<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let max = remaining.min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for FileSlice<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => offset_by(self.len, p),
+            SeekFrom::Current(p) => offset_by(self.pos, p),
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to before the start of the slice",
+            )
+        })?;
+        self.inner.seek(SeekFrom::Start(self.offset + target))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+fn offset_by(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
+}
+
+impl MultipartFile {
+    /// A view of `len` bytes of the file starting at `offset`, without copying them
+    ///
+    /// The view has its own handle to the temporary file, so multiple views can be read
+    /// independently. Fails if the range extends beyond the end of the file.
+    ///
+    /// # Example
+    /// ```
+    /// # use actix_validated_forms::multipart::MultipartFile;
+    /// # use std::io::Read;
+    /// fn thumbnail(file: &MultipartFile, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    ///     let mut thumbnail = Vec::new();
+    ///     file.slice(offset, len)?.read_to_end(&mut thumbnail)?;
+    ///     Ok(thumbnail)
+    /// }
+    /// ```
+    pub fn slice(&self, offset: u64, len: u64) -> io::Result<FileSlice<File>> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Slice extends beyond the end of the file",
+                ))
+            }
+        }
+        FileSlice::new(self.file.reopen()?, offset, len)
+    }
+}
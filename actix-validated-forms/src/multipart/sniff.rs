@@ -0,0 +1,73 @@
+use mime::Mime;
+
+/// Bytes of a file needed to recognise any of the types `sniff_mime()` knows
+pub const SNIFF_LENGTH: usize = 32;
+
+/// The content types recognised by their leading bytes, and a check for each
+const SIGNATURES: &[(&str, fn(&[u8]) -> bool)] = &[
+    ("image/png", |b| b.starts_with(b"\x89PNG\r\n\x1a\n")),
+    ("image/jpeg", |b| b.starts_with(b"\xff\xd8\xff")),
+    ("image/gif", |b| {
+        b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a")
+    }),
+    ("image/webp", |b| {
+        b.starts_with(b"RIFF") && b.get(8..12) == Some(&b"WEBP"[..])
+    }),
+    ("image/bmp", |b| b.starts_with(b"BM")),
+    ("image/tiff", |b| {
+        b.starts_with(b"II*\0") || b.starts_with(b"MM\0*")
+    }),
+    ("image/x-icon", |b| b.starts_with(b"\0\0\x01\0")),
+    ("application/pdf", |b| b.starts_with(b"%PDF-")),
+    ("application/zip", |b| b.starts_with(b"PK\x03\x04")),
+    ("application/gzip", |b| b.starts_with(b"\x1f\x8b")),
+    ("video/mp4", |b| b.get(4..8) == Some(&b"ftyp"[..])),
+    ("audio/mpeg", |b| b.starts_with(b"ID3")),
+    ("application/x-msdownload", |b| b.starts_with(b"MZ")),
+    ("application/x-executable", |b| b.starts_with(b"\x7fELF")),
+    ("application/x-sh", |b| b.starts_with(b"#!")),
+    ("application/x-httpd-php", |b| starts_with_tag(b, b"<?php")),
+    ("image/svg+xml", |b| starts_with_tag(b, b"<svg")),
+    ("text/html", |b| {
+        starts_with_tag(b, b"<!doctype html")
+            || starts_with_tag(b, b"<html")
+            || starts_with_tag(b, b"<script")
+    }),
+];
+
+/// Whether `bytes` (after any whitespace or UTF-8 BOM) starts with `tag`, ignoring case
+fn starts_with_tag(bytes: &[u8], tag: &[u8]) -> bool {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    match start.and_then(|start| bytes.get(start..start + tag.len())) {
+        Some(prefix) => prefix.eq_ignore_ascii_case(tag),
+        None => false,
+    }
+}
+
+/// Guesses the content type of a file from its first (up to `SNIFF_LENGTH`) bytes, `None` if
+/// it isn't one of the recognised types
+///
+/// Only a small number of common image, document, archive and executable formats are recognised
+/// (plus PHP, HTML and SVG, which are dangerous to serve as an image). Text formats without a
+/// signature, such as CSV, can't be recognised at all.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::sniff_mime;
+/// assert_eq!(sniff_mime(b"%PDF-1.7\n"), Some(mime::APPLICATION_PDF));
+/// assert_eq!(sniff_mime(b"name,email\n"), None);
+/// ```
+pub fn sniff_mime(prefix: &[u8]) -> Option<Mime> {
+    SIGNATURES
+        .iter()
+        .find(|(_, matches)| matches(prefix))
+        .map(|(mime, _)| mime.parse().unwrap())
+}
+
+/// Whether `sniff_mime()` would be able to recognise a file of type `mime`
+pub(crate) fn is_sniffable(mime: &Mime) -> bool {
+    SIGNATURES
+        .iter()
+        .any(|(sniffable, _)| *sniffable == mime.essence_str())
+}
@@ -0,0 +1,153 @@
+use super::load::{PartInfo, PartStream};
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::http::HeaderMap;
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::{StreamExt, TryStreamExt};
+use std::cell::Cell;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A destination that file uploads are streamed into, such as an object store, instead of a
+/// temporary file on local disk
+///
+/// Configured using `MultipartLoadConfig::storage()`, without one file uploads are written to a
+/// `NamedTempFile` and loaded as a `MultipartFile`.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{MultipartStorage, PartInfo, PartStream};
+/// # use actix_multipart::MultipartError;
+/// # use futures::future::{FutureExt, LocalBoxFuture};
+/// # use futures::TryStreamExt;
+/// # async fn put_object(_: &str, _: Vec<u8>) {}
+/// struct Bucket;
+///
+/// impl MultipartStorage for Bucket {
+///     fn store(
+///         &self,
+///         _info: &PartInfo,
+///         body: PartStream,
+///     ) -> LocalBoxFuture<'static, Result<String, MultipartError>> {
+///         async move {
+///             let key = "uploads/example".to_string();
+///             let data = body
+///                 .try_fold(Vec::new(), |mut acc, chunk| async move {
+///                     acc.extend_from_slice(&chunk);
+///                     Ok(acc)
+///                 })
+///                 .await?;
+///             put_object(&key, data).await;
+///             Ok(key)
+///         }
+///         .boxed_local()
+///     }
+/// }
+/// ```
+pub trait MultipartStorage: Send + Sync {
+    /// Streams the body of a file part into storage, resolving to the location it was stored at
+    ///
+    /// The `file_limit` is enforced on `body`, which fails with an overflow error if exceeded.
+    fn store(
+        &self,
+        info: &PartInfo,
+        body: PartStream,
+    ) -> LocalBoxFuture<'static, Result<String, MultipartError>>;
+
+    /// Removes a stored file, if the form it belongs to fails to load - by default does nothing
+    fn discard(&self, _location: &str) -> LocalBoxFuture<'static, ()> {
+        future::ready(()).boxed_local()
+    }
+}
+
+/// Structure used to represent a File upload that was streamed into a `MultipartStorage`
+///
+/// Loaded instead of a `MultipartFile` when `MultipartLoadConfig::storage()` is configured.
+pub struct StoredFile {
+    /// The location returned by `MultipartStorage::store()`
+    pub location: String,
+    /// The size in bytes of the file
+    pub size: u64,
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The `filename` value in the `Content-Disposition` header
+    pub filename: Option<String>,
+    /// The Content-Type specified as reported in the uploaded form
+    /// DO NOT trust this as being accurate
+    pub mime: mime::Mime,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+    storage: Arc<dyn MultipartStorage>,
+}
+
+impl StoredFile {
+    /// Get the extension portion of the `filename` value in the `Content-Disposition` header
+    pub fn get_extension(&self) -> Option<&str> {
+        self.filename
+            .as_ref()
+            .and_then(|f| Path::new(f.as_str()).extension().and_then(OsStr::to_str))
+    }
+
+    /// Removes the file from the storage it was streamed into
+    pub fn discard(&self) -> LocalBoxFuture<'static, ()> {
+        self.storage.discard(&self.location)
+    }
+
+    /// Removes the file later, even once the `StoredFile` itself has been consumed
+    pub(crate) fn discard_later(&self) -> impl FnOnce() -> LocalBoxFuture<'static, ()> {
+        let storage = self.storage.clone();
+        let location = self.location.clone();
+        move || storage.discard(&location)
+    }
+}
+
+impl fmt::Debug for StoredFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoredFile")
+            .field("location", &self.location)
+            .field("size", &self.size)
+            .field("name", &self.name)
+            .field("filename", &self.filename)
+            .field("mime", &self.mime)
+            .field("headers", &self.headers)
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+pub(crate) async fn create_stored_file(
+    body: PartStream,
+    info: PartInfo,
+    max_size: u64,
+    storage: Arc<dyn MultipartStorage>,
+) -> Result<StoredFile, MultipartError> {
+    let size = Rc::new(Cell::new(0u64));
+    let counter = size.clone();
+    let body = body
+        .and_then(move |bytes| {
+            let total = counter.get() + bytes.len() as u64;
+            if total > max_size {
+                return future::err(MultipartError::Payload(PayloadError::Overflow));
+            }
+            counter.set(total);
+            future::ok(bytes)
+        })
+        .boxed_local();
+    let location = storage.store(&info, body).await?;
+    Ok(StoredFile {
+        location,
+        size: size.get(),
+        name: info.name,
+        filename: info.filename,
+        mime: info.content_type,
+        headers: info.headers,
+        index: info.index,
+        storage,
+    })
+}
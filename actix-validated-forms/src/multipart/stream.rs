@@ -0,0 +1,100 @@
+use super::load::{write_field, PartBody, PartInfo};
+use super::MultipartLoadConfig;
+use actix_multipart::MultipartError;
+use actix_web::error::BlockingError;
+use actix_web::http::HeaderMap;
+use actix_web::web::{self, Bytes};
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use std::io::{self, Read, Seek, SeekFrom};
+use tempfile::NamedTempFile;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Structure used to represent a Text field in a multipart form that is too large to buffer
+///
+/// Text fields named in `MultipartLoadConfig::stream_text()` are spooled to a temporary file
+/// (counting towards the file limit rather than the text limit) and loaded as a `TextStream`.
+/// The content can then be consumed incrementally using `into_stream()`.
+#[derive(Debug)]
+pub struct TextStream {
+    /// The name of the field in the multipart form
+    pub name: String,
+    /// The size in bytes of the field / part
+    pub size: u64,
+    /// All the headers of the part
+    pub headers: HeaderMap,
+    /// The position of the part in the multipart form (starting from 0), counting any parts
+    /// that were skipped
+    pub index: usize,
+    pub(super) file: NamedTempFile,
+}
+
+impl TextStream {
+    /// Read the body of the field / part as a stream of chunks
+    ///
+    /// Reads are done on a background thread pool (using `actix_web::web::block`)
+    pub fn into_stream(self) -> LocalBoxStream<'static, Result<Bytes, io::Error>> {
+        read_chunks(self.file, 0, self.size)
+    }
+}
+
+/// Reads `length` bytes from `start` of a file as a stream of chunks, on a background thread
+pub(crate) fn read_chunks<R>(
+    file: R,
+    start: u64,
+    length: u64,
+) -> LocalBoxStream<'static, Result<Bytes, io::Error>>
+where
+    R: Read + Seek + Send + 'static,
+{
+    stream::unfold(
+        (Some(file), length, true),
+        move |(file, remaining, first)| async move {
+            let mut file = match file {
+                Some(file) if remaining > 0 => file,
+                _ => return None,
+            };
+            let res = web::block(move || {
+                if first {
+                    file.seek(SeekFrom::Start(start))?;
+                }
+                let mut buf = vec![0; std::cmp::min(CHUNK_SIZE as u64, remaining) as usize];
+                let read = file.read(&mut buf)?;
+                buf.truncate(read);
+                Ok::<_, io::Error>((file, buf))
+            })
+            .await;
+            match res {
+                Ok((_, buf)) if buf.is_empty() => None,
+                Ok((file, buf)) => {
+                    let remaining = remaining - buf.len() as u64;
+                    Some((Ok(Bytes::from(buf)), (Some(file), remaining, false)))
+                }
+                Err(BlockingError::Error(e)) => Some((Err(e), (None, 0, false))),
+                Err(BlockingError::Canceled) => Some((
+                    Err(io::Error::new(io::ErrorKind::Other, "Read was cancelled")),
+                    (None, 0, false),
+                )),
+            }
+        },
+    )
+    .boxed_local()
+}
+
+pub(crate) async fn create_text_stream<S: PartBody>(
+    mut field: S,
+    info: PartInfo,
+    max_size: u64,
+    config: &MultipartLoadConfig,
+) -> Result<TextStream, MultipartError> {
+    let (ntf, _) = config.temp_file(None)?;
+    let buffer = config.write_buffer();
+    let size = write_field(&mut field, ntf.as_file(), max_size, buffer, |_| {}).await?;
+    Ok(TextStream {
+        name: info.name,
+        size,
+        headers: info.headers,
+        index: info.index,
+        file: ntf,
+    })
+}
@@ -1,10 +1,12 @@
 use super::*;
-use actix_multipart::Multipart;
+use actix_multipart::{Multipart, MultipartError};
 use actix_multipart_rfc7578::client::multipart;
 use actix_web::{test, web, App, Error, HttpResponse};
 use awc::Client;
+use futures::{FutureExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
+use std::sync::Arc;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Response {
@@ -59,15 +61,247 @@ async fn file_size_limit_route(payload: Multipart) -> Result<HttpResponse, Error
     Ok(HttpResponse::Ok().into())
 }
 
+async fn detailed_file_size_limit_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    load_detailed(payload, MultipartLoadConfig::default().file_limit(2)).await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+async fn post_oversized_file(srv: &test::TestServer) -> (StatusCode, actix_web::web::Bytes) {
+    let mut form = multipart::Form::default();
+    let temp = NamedTempFile::new().unwrap();
+    temp.as_file()
+        .write("More than two bytes!!!".as_bytes())
+        .unwrap();
+    form.add_file("file", temp.path()).unwrap();
+
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type(form.content_type())
+        .send_body(multipart::Body::from(form))
+        .await
+        .unwrap();
+    (response.status(), response.body().await.unwrap())
+}
+
 #[actix_rt::test]
 async fn file_size_limit_test() {
     let srv = test::start(|| App::new().route("/", web::post().to(file_size_limit_route)));
+    let (status, body) = post_oversized_file(&srv).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!("A payload reached size limit.", body);
+
+    let srv = test::start(|| App::new().route("/", web::post().to(detailed_file_size_limit_route)));
+    let (status, body) = post_oversized_file(&srv).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!("Field 'file' exceeds the file limit of 2 bytes", body);
+}
+
+#[actix_rt::test]
+async fn saved_file_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().to_owned();
+    let srv = test::start(move || {
+        let dest = dest.clone();
+        App::new().route(
+            "/",
+            web::post().to(move |payload: Multipart| {
+                let config = MultipartLoadConfig::default().save_to(
+                    dest.clone(),
+                    NamingStrategy::Callback(Arc::new(|name, _| format!("{}.upload", name))),
+                );
+                async move {
+                    let mut parts = load_parts(payload, config).await?;
+                    let f: SavedFile = MultipartType::get(&mut parts, "file")?;
+                    Ok::<_, Error>(HttpResponse::Ok().body(f.path.to_string_lossy().to_string()))
+                }
+            }),
+        )
+    });
 
     let mut form = multipart::Form::default();
     let temp = NamedTempFile::new().unwrap();
-    temp.as_file()
-        .write("More than two bytes!!!".as_bytes())
+    temp.as_file().write("File contents".as_bytes()).unwrap();
+    form.add_file("file", temp.path()).unwrap();
+
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type(form.content_type())
+        .send_body(multipart::Body::from(form))
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    let expected = dir.path().join("file.upload");
+    assert_eq!(
+        response.body().await.unwrap(),
+        expected.to_string_lossy().as_bytes()
+    );
+    assert_eq!(std::fs::read(expected).unwrap(), b"File contents");
+}
+
+async fn text_stream_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    let config = MultipartLoadConfig::default()
+        .text_limit(4)
+        .stream_text(&["csv"]);
+    let mut parts = load_parts(payload, config).await?;
+    let csv: TextStream = MultipartType::get(&mut parts, "csv")?;
+    let mut body = Vec::new();
+    let mut chunks = csv.into_stream();
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[actix_rt::test]
+async fn text_stream_test() {
+    let srv = test::start(|| App::new().route("/", web::post().to(text_stream_route)));
+
+    let mut form = multipart::Form::default();
+    form.add_text("csv", "a,b,c\n1,2,3\n");
+
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type(form.content_type())
+        .send_body(multipart::Body::from(form))
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!("a,b,c\n1,2,3\n", response.body().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn indexed_text_stream_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"rows[1]\"\r\n\
+                 \r\n\
+                 4,5,6\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"rows[0]\"\r\n\
+                 \r\n\
+                 1,2,3\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default()
+        .text_limit(4)
+        .stream_text(&["rows"]);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let rows: Vec<TextStream> = MultipartTypeSpecial::get(&mut parts, "rows").unwrap();
+    let mut contents = Vec::new();
+    for row in rows {
+        let mut data = Vec::new();
+        let mut chunks = row.into_stream();
+        while let Some(chunk) = chunks.next().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        contents.push(String::from_utf8(data).unwrap());
+    }
+    assert_eq!(contents, ["1,2,3", "4,5,6"]);
+}
+
+async fn deferred_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    let mut form = load_parts_deferred(payload, MultipartLoadConfig::default(), 4).await?;
+    let string: String = MultipartType::get(&mut form.parts, "string")?;
+    assert_eq!(string, "Hello World");
+    let pending = form.pending().unwrap();
+    assert_eq!(pending.name, "file");
+    assert!(pending.prefix.len() >= 4);
+
+    let mut rest = form.materialize().await?;
+    let mut data = String::new();
+    let f: MultipartFile = MultipartType::get(&mut rest, "file")?;
+    f.file.reopen().unwrap().read_to_string(&mut data).unwrap();
+    let int: i32 = MultipartType::get(&mut rest, "int")?;
+    Ok(HttpResponse::Ok().body(format!("{} {}", data, int)))
+}
+
+#[actix_rt::test]
+async fn deferred_test() {
+    let srv = test::start(|| App::new().route("/", web::post().to(deferred_route)));
+
+    let mut form = multipart::Form::default();
+    form.add_text("string", "Hello World");
+    let temp = NamedTempFile::new().unwrap();
+    temp.as_file().write("File contents".as_bytes()).unwrap();
+    form.add_file("file", temp.path()).unwrap();
+    form.add_text("int", "69");
+
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type(form.content_type())
+        .send_body(multipart::Body::from(form))
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!("File contents 69", response.body().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn deferred_extractor_test() {
+    use actix_web::FromRequest;
+    let body = "--BOUNDARY\r\n\
+                Content-Disposition: form-data; name=\"title\"\r\n\
+                \r\n\
+                Hello\r\n\
+                --BOUNDARY\r\n\
+                Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                Content-Type: text/plain\r\n\
+                \r\n\
+                small\r\n\
+                --BOUNDARY\r\n\
+                Content-Disposition: form-data; name=\"int\"\r\n\
+                \r\n\
+                69\r\n\
+                --BOUNDARY--\r\n";
+    // Stages apply to the prefix, and only once to each chunk of the file
+    let config =
+        MultipartLoadConfig::default().stage(|_: &PartInfo, kind: PartKind, body: PartStream| {
+            if kind == PartKind::File {
+                body.map(|chunk| chunk.map(|c| c.to_ascii_uppercase().into()))
+                    .boxed_local()
+            } else {
+                body
+            }
+        });
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .app_data(config)
+        .set_payload(body)
+        .to_http_parts();
+    let mut form = DeferredParts::from_request(&req, &mut payload)
+        .await
         .unwrap();
+    let title: String = MultipartType::get(&mut form.parts, "title").unwrap();
+    assert_eq!(title, "Hello");
+    // The file is shorter than the prefix, so its body has already ended
+    assert_eq!(&form.pending().unwrap().prefix[..], b"SMALL");
+
+    let mut rest = form.materialize().await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut rest, "file").unwrap();
+    assert_eq!(std::fs::read(file.file.path()).unwrap(), b"SMALL");
+    let int: i32 = MultipartType::get(&mut rest, "int").unwrap();
+    assert_eq!(int, 69);
+}
+
+async fn deduplicate_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    // SHA-256 of "File contents"
+    let known = "69423babe8e61aab549f347bcc8b9d77b7dcaca198fb0597bde0b5f97f968e38";
+    let config = MultipartLoadConfig::default()
+        .deduplicate(move |sha256| futures::future::ready(sha256 == known).boxed_local());
+    let mut parts = load_parts(payload, config).await?;
+    let f: MultipartFile = MultipartType::get(&mut parts, "file")?;
+    let stored = f.file.as_file().metadata().unwrap().len();
+    Ok(HttpResponse::Ok().body(format!("{} {} {}", f.deduplicated, f.size, stored)))
+}
+
+#[actix_rt::test]
+async fn deduplicate_test() {
+    let srv = test::start(|| App::new().route("/", web::post().to(deduplicate_route)));
+
+    let mut form = multipart::Form::default();
+    let temp = NamedTempFile::new().unwrap();
+    temp.as_file().write("File contents".as_bytes()).unwrap();
     form.add_file("file", temp.path()).unwrap();
 
     let mut response = Client::default()
@@ -77,9 +311,3005 @@ async fn file_size_limit_test() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.status().is_success());
+    assert_eq!("true 0 0", response.body().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn deduplicate_buffer_test() {
+    use sha2::Digest;
+    let body = |files: &[&[u8]]| {
+        let mut body = Vec::new();
+        for contents in files {
+            body.extend_from_slice(
+                b"--BOUNDARY\r\n\
+                  Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                  Content-Type: application/octet-stream\r\n\
+                  \r\n",
+            );
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--BOUNDARY--\r\n");
+        body
+    };
+    let known = [b'a'; 100];
+    let other = [b'b'; 100];
+    let config = |buffer_size| {
+        let hash = format!("{:x}", sha2::Sha256::digest(&known));
+        MultipartLoadConfig::default()
+            .write_buffer_size(buffer_size)
+            .file_limit(150)
+            .deduplicate(move |sha256| futures::future::ready(sha256 == hash).boxed_local())
+    };
+    // Either held back in the buffer, or too large to be and so written and then emptied
+    for buffer_size in vec![1000, 10] {
+        let mut parts = load_raw(body(&[&known]), config(buffer_size))
+            .await
+            .unwrap();
+        let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+        assert!(file.deduplicated);
+        assert_eq!(file.size, 0);
+        assert_eq!(file.file.as_file().metadata().unwrap().len(), 0);
+
+        let mut parts = load_raw(body(&[&other]), config(buffer_size))
+            .await
+            .unwrap();
+        let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+        assert!(!file.deduplicated);
+        assert_eq!(file.size, 100);
+        assert_eq!(std::fs::read(file.file.path()).unwrap(), &other[..]);
+    }
+
+    // Deduplicated files still count towards the file limit
+    assert!(load_raw(body(&[&known, &known]), config(1000))
+        .await
+        .is_err());
+}
+
+#[actix_rt::test]
+async fn digests_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.sha256, None);
+    assert_eq!(file.md5, None);
+
+    let config = MultipartLoadConfig::default().digests(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(
+        file.sha256.as_deref(),
+        Some("d1b2a59fbea7e20077af9f91b27e95e865061b270be03ff539ab3b73587882e8")
+    );
+    assert_eq!(
+        file.md5.as_deref(),
+        Some("98bf7d8c15784f0a3d63204441e1e2aa")
+    );
+}
+
+#[test]
+fn upload_sessions_test() {
+    let text = |name: &str| {
+        MultipartField::Text(MultipartText::new(name.to_string(), "value".to_string()))
+    };
+    let sessions = UploadSessions::new(std::time::Duration::from_secs(60));
+    let token = sessions.store(vec![text("first")]);
+    assert!(sessions.extend(&token, vec![text("second")]).is_ok());
+    assert!(sessions.extend("unknown", vec![text("third")]).is_err());
+    let parts = sessions.take(&token).unwrap();
+    assert_eq!(parts.len(), 2);
+    assert!(sessions.take(&token).is_none());
+
+    let expired = UploadSessions::new(std::time::Duration::from_secs(0));
+    let token = expired.store(vec![text("first")]);
+    assert!(expired.take(&token).is_none());
+}
+
+#[test]
+fn upload_sessions_remove_files_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let saved = |name: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, "contents").unwrap();
+        MultipartField::Saved(SavedFile {
+            path,
+            size: 8,
+            name: "file".into(),
+            filename: None,
+            mime: mime::APPLICATION_OCTET_STREAM,
+            headers: actix_web::http::HeaderMap::new(),
+            index: 0,
+            existing: false,
+        })
+    };
+
+    let sessions = UploadSessions::new(std::time::Duration::from_secs(60));
+    let token = sessions.store(vec![saved("discarded")]);
+    assert!(sessions.discard(&token));
+    assert!(!dir.path().join("discarded").exists());
+    assert!(!sessions.discard(&token));
+
+    let expired = UploadSessions::new(std::time::Duration::from_secs(0));
+    expired.store(vec![saved("expired")]);
+    expired.remove_expired();
+    assert!(!dir.path().join("expired").exists());
+}
+
+#[actix_rt::test]
+async fn upload_sessions_reaper_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("expired");
+    std::fs::write(&path, "contents").unwrap();
+    let sessions = UploadSessions::new(std::time::Duration::from_millis(10));
+    sessions.store(vec![MultipartField::Saved(SavedFile {
+        path: path.clone(),
+        size: 8,
+        name: "file".into(),
+        filename: None,
+        mime: mime::APPLICATION_OCTET_STREAM,
+        headers: actix_web::http::HeaderMap::new(),
+        index: 0,
+        existing: false,
+    })]);
+    sessions.reap_every(std::time::Duration::from_millis(20));
+    assert!(path.exists());
+    actix_rt::time::delay_for(std::time::Duration::from_millis(100)).await;
+    assert!(!path.exists());
+}
+
+#[actix_rt::test]
+async fn file_response_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("upload");
+    std::fs::write(&path, "File contents").unwrap();
+    let srv = test::start(move || {
+        let path = path.clone();
+        App::new().route(
+            "/",
+            web::get().to(move || {
+                let file = SavedFile {
+                    path: path.clone(),
+                    size: 13,
+                    name: "file".into(),
+                    filename: Some("../secret\".txt".into()),
+                    mime: mime::TEXT_PLAIN,
+                    headers: actix_web::http::HeaderMap::new(),
+                    index: 0,
+                    existing: false,
+                };
+                async move { FileResponse::from_saved(&file) }
+            }),
+        )
+    });
+    let etag = "\"69423babe8e61aab549f347bcc8b9d77b7dcaca198fb0597bde0b5f97f968e38\"";
+
+    let mut response = Client::default().get(srv.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("etag").unwrap(), etag);
+    assert_eq!(
+        response.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"secret.txt\""
+    );
+    assert_eq!(response.body().await.unwrap(), "File contents");
+
+    let mut response = Client::default()
+        .get(srv.url("/"))
+        .header("range", "bytes=5-7")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(
+        response.headers().get("content-range").unwrap(),
+        "bytes 5-7/13"
+    );
+    assert_eq!(response.body().await.unwrap(), "con");
+
+    let response = Client::default()
+        .get(srv.url("/"))
+        .header("if-none-match", etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 304);
+}
+
+async fn text_metadata_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    let mut parts = load_parts(payload, MultipartLoadConfig::default()).await?;
+    let note = parts[0].headers().get("x-note").unwrap().to_str().unwrap();
+    let note = note.to_string();
+    match parts.pop() {
+        Some(MultipartField::Text(t)) => {
+            Ok(HttpResponse::Ok().body(format!("{} {} {} {}", t.text, t.mime, t.size, note)))
+        }
+        _ => Ok(HttpResponse::BadRequest().into()),
+    }
+}
+
+#[actix_rt::test]
+async fn text_metadata_test() {
+    let srv = test::start(|| App::new().route("/", web::post().to(text_metadata_route)));
+
+    let body = "--BOUNDARY\r\n\
+                Content-Disposition: form-data; name=\"comment\"\r\n\
+                Content-Type: text/plain; charset=utf-8\r\n\
+                X-Note: hello\r\n\
+                \r\n\
+                Some *text*\r\n\
+                --BOUNDARY--\r\n";
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type("multipart/form-data; boundary=BOUNDARY")
+        .send_body(body)
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.body().await.unwrap(),
+        "Some *text* text/plain; charset=utf-8 11 hello"
+    );
+}
+
+/// Loads a hand written body, with `BOUNDARY` as the boundary
+fn raw_multipart(body: Vec<u8>) -> Multipart {
+    let mut headers = actix_web::http::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"),
+    );
+    let payload = futures::stream::once(futures::future::ok::<_, actix_web::error::PayloadError>(
+        actix_web::web::Bytes::from(body),
+    ));
+    Multipart::new(&headers, payload)
+}
+
+/// Loads the parts like `load_parts()`, but returning the `LoadError` itself
+async fn load_detailed(
+    payload: Multipart,
+    config: MultipartLoadConfig,
+) -> Result<Multiparts, LoadError> {
+    load_parts_with_skipped(payload, config)
+        .await
+        .map(|(parts, _)| parts)
+}
+
+async fn load_raw(body: Vec<u8>, config: MultipartLoadConfig) -> Result<Multiparts, LoadError> {
+    load_detailed(raw_multipart(body), config).await
+}
+
+#[actix_rt::test]
+async fn nested_file_sets_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"photos\"\r\n\
+                 Content-Type: multipart/mixed; boundary=NESTED\r\n\
+                 \r\n\
+                 --NESTED\r\n\
+                 Content-Disposition: file; filename=\"a.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 first\r\n\
+                 --NESTED\r\n\
+                 Content-Disposition: file; filename=\"b.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 second\r\n\
+                 --NESTED--\r\n\
+                 --BOUNDARY--\r\n";
+    let mut headers = actix_web::http::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"),
+    );
+    // Split the body so that delimiters and headers span chunks
+    let chunks: Vec<Result<_, actix_web::error::PayloadError>> = body
+        .chunks(7)
+        .map(|c| Ok(actix_web::web::Bytes::copy_from_slice(c)))
+        .collect();
+    let payload = futures::stream::iter(chunks);
+    let mut parts = load_parts(
+        flatten_mixed(&headers, payload),
+        MultipartLoadConfig::default(),
+    )
+    .await
+    .unwrap();
+    let title: String = MultipartType::get(&mut parts, "title").unwrap();
+    assert_eq!(title, "Holiday");
+    let photos: Vec<MultipartFile> = MultipartTypeSpecial::get(&mut parts, "photos").unwrap();
+    assert_eq!(photos.len(), 2);
+    assert_eq!(photos[0].filename.as_deref(), Some("a.png"));
+    assert_eq!(photos[1].filename.as_deref(), Some("b.png"));
+    assert_eq!(photos[1].mime, mime::IMAGE_PNG);
+    let mut data = String::new();
+    photos[1]
+        .file
+        .reopen()
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "second");
+
+    // Otherwise the form ends before the nested part
+    let parts = load_raw(body.to_vec(), MultipartLoadConfig::default()).await;
+    assert_eq!(parts.unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn part_order_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"caption\"\r\n\
+                 \r\n\
+                 First\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"photo\"; filename=\"a.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 first\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"caption\"\r\n\
+                 \r\n\
+                 Second\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"photo\"; filename=\"b.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 second\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let indexes: Vec<usize> = parts.iter().map(|p| p.index()).collect();
+    assert_eq!(indexes, [0, 1, 2, 3]);
+    let captions: Vec<MultipartText> = MultipartTypeSpecial::get(&mut parts, "caption").unwrap();
+    let photos: Vec<MultipartFile> = MultipartTypeSpecial::get(&mut parts, "photo").unwrap();
+    let pairs: Vec<_> = captions
+        .iter()
+        .zip(photos.iter())
+        .map(|(c, p)| {
+            (
+                c.text.as_str(),
+                p.filename.as_deref().unwrap(),
+                c.index,
+                p.index,
+            )
+        })
+        .collect();
+    assert_eq!(pairs, [("First", "a.png", 0, 1), ("Second", "b.png", 2, 3)]);
+}
+
+#[actix_rt::test]
+async fn indexed_parts_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"tags[1]\"\r\n\
+                 \r\n\
+                 second\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Hello\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"tags[0]\"\r\n\
+                 \r\n\
+                 first\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"extra\"\r\n\
+                 \r\n\
+                 1\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"other\"\r\n\
+                 \r\n\
+                 2\r\n\
+                 --BOUNDARY--\r\n";
+    let parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let mut parts = IndexedParts::new(parts);
+    assert_eq!(parts.field("tags").len(), 2);
+    assert!(parts.field("missing").is_empty());
+    let tags: Vec<String> = MultipartTypeSpecial::get(parts.field("tags"), "tags").unwrap();
+    assert_eq!(tags, ["first", "second"]);
+    let title: String = MultipartType::get(parts.field("title"), "title").unwrap();
+    assert_eq!(title, "Hello");
+    // Text is read without being taken, and the parts come back in the order they were sent
+    let remaining = parts.into_remaining();
+    let names: Vec<&str> = remaining.iter().map(|p| p.name()).collect();
+    assert_eq!(names, ["tags[1]", "title", "tags[0]", "extra", "other"]);
+}
+
+#[actix_rt::test]
+async fn process_parts_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Hello\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 File contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"skipped\"\r\n\
+                 \r\n\
+                 Not read\r\n\
+                 --BOUNDARY--\r\n";
+    let process = |config: MultipartLoadConfig| {
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let handler_seen = seen.clone();
+        process_parts(raw_multipart(body.to_vec()), config, move |info, body| {
+            let seen = handler_seen.clone();
+            async move {
+                if info.name == "skipped" {
+                    return Ok(());
+                }
+                let data = futures::TryStreamExt::try_fold(body, Vec::new(), |mut acc, chunk| {
+                    acc.extend_from_slice(&chunk);
+                    futures::future::ok(acc)
+                })
+                .await?;
+                let data = String::from_utf8(data).unwrap();
+                seen.borrow_mut().push(format!("{}={}", info.name, data));
+                Ok::<_, LoadError>(())
+            }
+        })
+        .map(move |result| result.map(|_| seen.borrow().join(",")))
+    };
+
+    let seen = process(MultipartLoadConfig::default()).await.unwrap();
+    assert_eq!(seen, "title=Hello,file=File contents");
+
+    match process(MultipartLoadConfig::default().max_file_size(4)).await {
+        Err(LoadError::Multipart(actix_multipart::MultipartError::Payload(
+            actix_web::error::PayloadError::Overflow,
+        ))) => {}
+        _ => panic!("expected the file to exceed the limit"),
+    }
+}
+
+#[actix_rt::test]
+async fn related_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Type: application/xop+xml\r\n\
+                 Content-ID: <root@example.com>\r\n\
+                 \r\n\
+                 <Envelope/>\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Type: image/png\r\n\
+                 Content-ID: <image@example.com>\r\n\
+                 Content-Disposition: attachment; filename=\"a.png\"\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"note\"\r\n\
+                 Content-ID: <note@example.com>\r\n\
+                 \r\n\
+                 hello\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().related(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let ids: Vec<_> = parts.iter().map(|p| p.content_id().unwrap()).collect();
+    assert_eq!(
+        ids,
+        ["root@example.com", "image@example.com", "note@example.com"]
+    );
+    let root: MultipartFile = MultipartType::get(&mut parts, "root@example.com").unwrap();
+    assert_eq!(root.mime.essence_str(), "application/xop+xml");
+    let image: MultipartFile = MultipartType::get(&mut parts, "image@example.com").unwrap();
+    assert_eq!(image.filename.as_deref(), Some("a.png"));
+    let note: String = MultipartType::get(&mut parts, "note").unwrap();
+    assert_eq!(note, "hello");
+
+    assert!(load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .is_err());
+}
+
+#[actix_rt::test]
+async fn strict_test() {
+    let load = |headers: &str, strict: bool| {
+        let body = format!("--BOUNDARY\r\n{}\r\n\r\nvalue\r\n--BOUNDARY--\r\n", headers);
+        load_raw(
+            body.into_bytes(),
+            MultipartLoadConfig::default().strict(strict),
+        )
+    };
+    let code = |r: Result<Multiparts, LoadError>| match r {
+        Err(LoadError::Nonconforming(n)) => n.code(),
+        _ => panic!("expected a nonconformance"),
+    };
+
+    let conforming = "Content-Disposition: form-data; name=\"field\"";
+    assert_eq!(load(conforming, true).await.unwrap().len(), 1);
+
+    let missing_name = "Content-Disposition: form-data";
+    assert!(matches!(
+        load(missing_name, false).await,
+        Err(LoadError::Multipart(_))
+    ));
+    assert_eq!(code(load(missing_name, true).await), "missing_name");
+
+    let attachment = "Content-Disposition: attachment; name=\"field\"";
+    assert_eq!(code(load(attachment, true).await), "not_form_data");
+
+    let empty_name = "Content-Disposition: form-data; name=\"\"";
+    assert_eq!(code(load(empty_name, true).await), "empty_name");
+
+    let encoded = "Content-Disposition: form-data; name=\"field\"\r\n\
+                   Content-Transfer-Encoding: quoted-printable";
+    assert_eq!(load(encoded, false).await.unwrap().len(), 1);
+    assert_eq!(code(load(encoded, true).await), "transfer_encoding");
+
+    let forbidden = "Content-Disposition: form-data; name=\"field\"\r\n\
+                     X-Custom: value";
+    assert_eq!(load(forbidden, false).await.unwrap().len(), 1);
+    assert_eq!(code(load(forbidden, true).await), "forbidden_header");
+
+    let untyped = "Content-Disposition: form-data; name=\"field\"; filename=\"a.txt\"";
+    assert_eq!(load(untyped, false).await.unwrap().len(), 1);
+    assert_eq!(code(load(untyped, true).await), "missing_content_type");
+
+    let typed = "Content-Disposition: form-data; name=\"field\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain";
+    assert_eq!(load(typed, true).await.unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn skip_malformed_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"first\"\r\n\
+                 \r\n\
+                 1\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: attachment; name=\"bad\"\r\n\
+                 \r\n\
+                 2\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data\r\n\
+                 \r\n\
+                 3\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"last\"\r\n\
+                 \r\n\
+                 4\r\n\
+                 --BOUNDARY--\r\n";
+    let load = |config: MultipartLoadConfig| {
+        let mut headers = actix_web::http::HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::CONTENT_TYPE,
+            actix_web::http::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"),
+        );
+        let payload =
+            futures::stream::once(futures::future::ok::<_, actix_web::error::PayloadError>(
+                actix_web::web::Bytes::from_static(body),
+            ));
+        load_parts_with_skipped(Multipart::new(&headers, payload), config)
+    };
+
+    assert!(load(MultipartLoadConfig::default()).await.is_err());
+
+    let (mut parts, skipped) = load(MultipartLoadConfig::default().skip_malformed(true))
+        .await
+        .unwrap();
+    assert_eq!(parts.len(), 2);
+    let first: String = MultipartType::get(&mut parts, "first").unwrap();
+    let last: String = MultipartType::get(&mut parts, "last").unwrap();
+    assert_eq!((first.as_str(), last.as_str()), ("1", "4"));
     assert_eq!(
-        "A payload reached size limit.",
-        response.body().await.unwrap()
+        skipped,
+        vec![
+            SkippedPart {
+                name: Some("bad".into()),
+                reason: Nonconformance::NotFormData,
+            },
+            SkippedPart {
+                name: None,
+                reason: Nonconformance::MissingName,
+            },
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn deadline_test() {
+    use actix_web::FromRequest;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default();
+    assert!(load_raw(body.to_vec(), config.clone()).await.is_ok());
+
+    let passed = std::time::Instant::now();
+    let future = std::time::Instant::now() + std::time::Duration::from_secs(60);
+    let config = config.deadline(future).deadline(passed);
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::Timeout) => {}
+        _ => panic!("expected a timeout"),
+    }
+
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .set_payload(&body[..])
+        .to_http_parts();
+    req.extensions_mut().insert(ExtractionDeadline(passed));
+    let err = ValidatedMultipartForm::<Username>::from_request(&req, &mut payload)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), LoadError::Timeout.to_string());
+}
+
+/// Sends the start of a form and then nothing more
+fn stalled(start: &'static [u8]) -> Multipart {
+    let mut headers = actix_web::http::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"),
+    );
+    let payload = futures::stream::once(futures::future::ok::<_, actix_web::error::PayloadError>(
+        actix_web::web::Bytes::from_static(start),
+    ))
+    .chain(futures::stream::pending());
+    Multipart::new(&headers, payload)
+}
+
+#[actix_rt::test]
+async fn read_timeout_test() {
+    let config = MultipartLoadConfig::default().read_timeout(std::time::Duration::from_millis(50));
+    let in_body = b"--BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                    Content-Type: application/octet-stream\r\n\
+                    \r\n\
+                    the first few bytes";
+    let in_headers = b"--BOUNDARY\r\n\
+                       Content-Disposition: form-data; name=\"username\"\r\n\
+                       \r\n\
+                       jacob\r\n\
+                       --BOUNDARY\r\n\
+                       Content-Disp";
+    for start in &[&in_body[..], &in_headers[..]] {
+        match load_detailed(stalled(*start), config.clone()).await {
+            Err(e @ LoadError::ReadTimeout) => {
+                assert_eq!(e.status_code(), StatusCode::REQUEST_TIMEOUT)
+            }
+            _ => panic!("expected a read timeout"),
+        }
+    }
+    // Reported as the closest MultipartError by load_parts()
+    match load_parts(stalled(&in_body[..]), config).await {
+        Err(MultipartError::Payload(actix_web::error::PayloadError::Io(e))) => {
+            assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+            assert_eq!(e.to_string(), LoadError::ReadTimeout.to_string());
+        }
+        _ => panic!("expected a timed out payload"),
+    }
+}
+
+#[actix_rt::test]
+async fn content_length_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"text\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default()
+        .text_limit(10)
+        .file_limit(0)
+        .max_parts(1);
+    let request = |length: usize| {
+        test::TestRequest::default()
+            .header("content-length", length.to_string())
+            .to_http_request()
+    };
+    let loaded = load_raw(
+        body.to_vec(),
+        config.clone().content_length(&request(body.len())),
     );
+    assert!(loaded.await.is_ok());
+
+    // Rejected before the body is read, even though it is actually within the limits
+    match load_raw(body.to_vec(), config.content_length(&request(2000))).await {
+        Err(e @ LoadError::ContentLength(2000, 1034)) => {
+            assert_eq!(e.status_code(), StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        _ => panic!("expected the content length to be rejected"),
+    }
+}
+
+#[actix_rt::test]
+async fn honeypot_test() {
+    use actix_web::FromRequest;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"website\"\r\n\
+                 \r\n\
+                 http://spam.example\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let dir = tempfile::tempdir().unwrap();
+    let honeypot =
+        crate::honeypot::Honeypot::new("website").response(|_| HttpResponse::Ok().body("thanks"));
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .app_data(
+            ValidatedMultipartFormConfig::default()
+                .config(MultipartLoadConfig::default().temp_dir(dir.path()))
+                .honeypot(honeypot),
+        )
+        .set_payload(&body[..])
+        .to_http_parts();
+    let err = ValidatedMultipartForm::<Username>::from_request(&req, &mut payload)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.as_response_error().error_response().status(), 200);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"website\"\r\n\
+                 \r\n\
+                 \r\n\
+                 --BOUNDARY--\r\n";
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .app_data(
+            ValidatedMultipartFormConfig::default()
+                .honeypot(crate::honeypot::Honeypot::new("website")),
+        )
+        .set_payload(&body[..])
+        .to_http_parts();
+    let form = ValidatedMultipartForm::<Username>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(form.into_inner().0, "jacob");
+}
+
+#[derive(Debug)]
+struct SavedUpload {
+    file: SavedFile,
+}
+
+impl std::convert::TryFrom<Multiparts> for SavedUpload {
+    type Error = GetError;
+
+    fn try_from(mut parts: Multiparts) -> Result<Self, Self::Error> {
+        Ok(SavedUpload {
+            file: MultipartType::get(&mut parts, "file")?,
+        })
+    }
+}
+
+impl validator::Validate for SavedUpload {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        Ok(())
+    }
+}
+
+struct NamedUpload {
+    file: SavedFile,
+    name: String,
+}
+
+impl std::convert::TryFrom<Multiparts> for NamedUpload {
+    type Error = GetError;
+
+    fn try_from(mut parts: Multiparts) -> Result<Self, Self::Error> {
+        Ok(NamedUpload {
+            file: MultipartType::get(&mut parts, "file")?,
+            name: MultipartType::get(&mut parts, "name")?,
+        })
+    }
+}
+
+impl validator::Validate for NamedUpload {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        if self.name == "invalid" {
+            let mut errors = validator::ValidationErrors::new();
+            errors.add("name", validator::ValidationError::new("invalid"));
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn failed_form_removes_saved_files_test() {
+    use actix_web::FromRequest;
+    let dir = tempfile::tempdir().unwrap();
+    let config = ValidatedMultipartFormConfig::default()
+        .config(MultipartLoadConfig::default().save_to(dir.path(), NamingStrategy::Uuid));
+    let request = |name: &str| {
+        let mut body = b"--BOUNDARY\r\n\
+                         Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                         Content-Type: application/octet-stream\r\n\
+                         \r\n\
+                         contents\r\n"
+            .to_vec();
+        if !name.is_empty() {
+            body.extend_from_slice(
+                format!(
+                    "--BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"name\"\r\n\
+                     \r\n\
+                     {}\r\n",
+                    name
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend_from_slice(b"--BOUNDARY--\r\n");
+        test::TestRequest::post()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .app_data(config.clone())
+            .set_payload(body)
+            .to_http_parts()
+    };
+
+    // Fails to convert, after the file has been taken from the parts
+    let (req, mut payload) = request("");
+    let result = ValidatedMultipartForm::<NamedUpload>::from_request(&req, &mut payload).await;
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    // Fails to validate
+    let (req, mut payload) = request("invalid");
+    let result = ValidatedMultipartForm::<NamedUpload>::from_request(&req, &mut payload).await;
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    let (req, mut payload) = request("valid");
+    let form = ValidatedMultipartForm::<NamedUpload>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(form.name, "valid");
+    assert_eq!(std::fs::read(&form.file.path).unwrap(), b"contents");
+}
+
+#[actix_rt::test]
+async fn idempotency_key_test() {
+    use actix_web::FromRequest;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let dir = tempfile::tempdir().unwrap();
+    let config = ValidatedMultipartFormConfig::default()
+        .config(MultipartLoadConfig::default().save_to(dir.path(), NamingStrategy::IdempotencyKey))
+        .idempotency_key("idempotency-key")
+        .on_duplicate(|_, key| match key.0.as_str() {
+            "seen" => Some(HttpResponse::Conflict().finish()),
+            _ => None,
+        });
+    let request = |key: &str| {
+        test::TestRequest::post()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .header("idempotency-key", key)
+            .app_data(config.clone())
+            .set_payload(&body[..])
+            .to_http_parts()
+    };
+
+    let (req, mut payload) = request("\"abc-123\"");
+    let form = ValidatedMultipartForm::<SavedUpload>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(form.file.path, dir.path().join("abc-123-0"));
+    let key = req.extensions().get::<IdempotencyKey>().cloned();
+    assert_eq!(key, Some(IdempotencyKey("abc-123".into())));
+
+    // The retry fails, without replacing (or removing) the first upload
+    let (req, mut payload) = request("\"abc-123\"");
+    let result = ValidatedMultipartForm::<SavedUpload>::from_request(&req, &mut payload).await;
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    assert_eq!(std::fs::read(&form.file.path).unwrap(), b"contents");
+
+    let (req, mut payload) = request("seen");
+    let err = ValidatedMultipartForm::<SavedUpload>::from_request(&req, &mut payload)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.as_response_error().error_response().status(), 409);
+
+    let (req, mut payload) = request("../etc");
+    let err = ValidatedMultipartForm::<SavedUpload>::from_request(&req, &mut payload)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.as_response_error().error_response().status(), 400);
+}
+
+#[actix_rt::test]
+async fn content_hash_test() {
+    let body = |text: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n\
+             contents\r\n\
+             --BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"text\"\r\n\
+             \r\n\
+             {}\r\n\
+             --BOUNDARY--\r\n",
+            text
+        )
+        .into_bytes()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let config = MultipartLoadConfig::default()
+        .save_to(dir.path(), NamingStrategy::ContentHash)
+        .text_limit(10);
+    let mut parts = load_raw(body("short"), config.clone()).await.unwrap();
+    let first: SavedFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert!(!first.existing);
+
+    let mut parts = load_raw(body("short"), config.clone()).await.unwrap();
+    let second: SavedFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(second.path, first.path);
+    assert!(second.existing);
+
+    // An identical upload in a form that fails to load leaves the saved file alone
+    assert!(load_raw(body("too long to load"), config).await.is_err());
+    assert_eq!(std::fs::read(&first.path).unwrap(), b"contents");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[actix_rt::test]
+async fn shutdown_test() {
+    use std::time::Duration;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY--\r\n";
+    let signal = ShutdownSignal::new(Duration::from_secs(0));
+    let config = MultipartLoadConfig::default().shutdown(signal.clone());
+    assert!(load_raw(body.to_vec(), config.clone()).await.is_ok());
+
+    // An upload that is still being received when the server shuts down
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    tx.unbounded_send(Ok::<_, actix_web::error::PayloadError>(
+        actix_web::web::Bytes::from_static(
+            b"--BOUNDARY\r\n\
+              Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+              Content-Type: application/octet-stream\r\n\
+              \r\n\
+              partial",
+        ),
+    ))
+    .unwrap();
+    let mut headers = actix_web::http::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"),
+    );
+    let load = load_detailed(Multipart::new(&headers, rx), config.clone());
+    let stop = async {
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(signal.in_flight(), 1);
+        signal.shutdown();
+    };
+    let (result, _) = futures::join!(load, stop);
+    match result {
+        Err(LoadError::ShuttingDown) => {}
+        _ => panic!("expected the upload to be aborted"),
+    }
+    assert_eq!((signal.in_flight(), signal.aborted()), (0, 1));
+    drop(tx);
+
+    // New uploads are rejected straight away
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::ShuttingDown) => {}
+        _ => panic!("expected the upload to be rejected"),
+    }
+    assert_eq!(signal.aborted(), 1);
+}
+
+#[actix_rt::test]
+async fn stage_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"text\"\r\n\
+                 \r\n\
+                 plain\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 secret\r\n\
+                 --BOUNDARY--\r\n";
+    let xor = |chunk: actix_web::web::Bytes| -> actix_web::web::Bytes {
+        chunk.iter().map(|b| b ^ 0xFF).collect::<Vec<u8>>().into()
+    };
+    let config = MultipartLoadConfig::default()
+        .stage(move |_: &PartInfo, kind: PartKind, body: PartStream| {
+            if kind == PartKind::File {
+                body.map(move |chunk| chunk.map(xor)).boxed_local()
+            } else {
+                body
+            }
+        })
+        .stage(|info: &PartInfo, _: PartKind, body: PartStream| {
+            assert!(info.name == "text" || info.name == "file");
+            body
+        });
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let text: String = MultipartType::get(&mut parts, "text").unwrap();
+    assert_eq!(text, "plain");
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    let stored = std::fs::read(file.file.path()).unwrap();
+    assert_eq!(
+        stored,
+        b"secret".iter().map(|b| b ^ 0xFF).collect::<Vec<u8>>()
+    );
+    assert_eq!(file.size, 6);
+}
+
+#[cfg(feature = "encryption")]
+#[actix_rt::test]
+async fn encryption_test() {
+    use std::io::{Seek, SeekFrom};
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/csv\r\n\
+                 \r\n\
+                 name,ssn\r\n\
+                 --BOUNDARY--\r\n";
+    let key = EncryptionKey::new([7; 32]);
+    let config = MultipartLoadConfig::default().encrypt_files(key.clone());
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.size, NONCE_LENGTH + 8);
+    let stored = std::fs::read(file.file.path()).unwrap();
+    assert!(!stored.windows(3).any(|w| w == b"ssn"));
+
+    let mut plaintext = String::new();
+    let mut reader = file.decrypt(&key).unwrap();
+    reader.read_to_string(&mut plaintext).unwrap();
+    assert_eq!(plaintext, "name,ssn");
+    assert_eq!(reader.seek(SeekFrom::Start(5)).unwrap(), 5);
+    plaintext.clear();
+    reader.read_to_string(&mut plaintext).unwrap();
+    assert_eq!(plaintext, "ssn");
+    assert!(reader.seek(SeekFrom::Current(-10)).is_err());
+}
+
+#[actix_rt::test]
+async fn slice_test() {
+    use std::io::{Seek, SeekFrom};
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 headerTHUMBtrailer\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+
+    let mut thumbnail = String::new();
+    let mut slice = file.slice(6, 5).unwrap();
+    slice.read_to_string(&mut thumbnail).unwrap();
+    assert_eq!(thumbnail, "THUMB");
+    assert_eq!(slice.seek(SeekFrom::End(-2)).unwrap(), 3);
+    thumbnail.clear();
+    slice.read_to_string(&mut thumbnail).unwrap();
+    assert_eq!(thumbnail, "MB");
+    assert!(slice.seek(SeekFrom::Current(-6)).is_err());
+
+    assert!(file.slice(10, 8).is_ok());
+    assert!(file.slice(10, 9).is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[actix_rt::test]
+async fn orphaned_temp_files_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let dir = tempfile::tempdir().unwrap();
+    let config = MultipartLoadConfig::default().temp_dir(dir.path());
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    let name = file.file.path().file_name().unwrap().to_str().unwrap();
+    let prefix = format!("-{}-", std::process::id());
+    assert!(name.starts_with("avf-") && name.contains(&prefix));
+
+    // A process that has since exited
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    let orphan = name.replace(&prefix, &format!("-{}-", child.id()));
+    std::fs::write(dir.path().join(&orphan), b"left behind").unwrap();
+    std::fs::write(dir.path().join("avf-otherhost-1-abcdef"), b"not ours").unwrap();
+    std::fs::write(dir.path().join("unrelated"), b"not ours").unwrap();
+
+    assert_eq!(remove_orphaned_temp_files(dir.path()).unwrap(), 1);
+    assert!(!dir.path().join(orphan).exists());
+    assert!(file.file.path().exists());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+}
+
+#[test]
+fn config_validation_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = MultipartLoadConfig::default()
+        .temp_dir(dir.path())
+        .max_parts_per_name(10);
+    assert!(config.validate().is_ok());
+    assert!(config.summarize().contains("max_parts_per_name: 10"));
+
+    let config = config.max_parts(5);
+    match config.validate() {
+        Err(ConfigError::Inconsistent(message)) => assert_eq!(
+            message,
+            "max_parts_per_name (10) is greater than max_parts (5)"
+        ),
+        _ => panic!("expected an inconsistent config"),
+    }
+
+    let missing = dir.path().join("missing");
+    let config = MultipartLoadConfig::default().save_to(&missing, NamingStrategy::Uuid);
+    match config.validate() {
+        Err(ConfigError::NotADirectory("save_to", path)) => assert_eq!(path, missing),
+        _ => panic!("expected a missing directory"),
+    }
+    let form_config = ValidatedMultipartFormConfig::default().config(config);
+    assert!(form_config.validate().is_err());
+
+    match MultipartLoadConfig::default().max_parts(0).validate() {
+        Err(ConfigError::Invalid(message)) => assert_eq!(message, "max_parts must be at least 1"),
+        _ => panic!("expected an invalid config"),
+    }
+    let config = MultipartLoadConfig::default()
+        .file_limit(1024)
+        .max_file_size(2048);
+    match config.validate() {
+        Err(ConfigError::Inconsistent(message)) => assert_eq!(
+            message,
+            "max_file_size (2048) is greater than file_limit (1024)"
+        ),
+        _ => panic!("expected an inconsistent config"),
+    }
+
+    let config = MultipartLoadConfig::default()
+        .file_limit(1024)
+        .spool_threshold(512);
+    assert!(config.validate().is_ok());
+    match config.clone().spool_threshold(4096).validate() {
+        Err(ConfigError::Inconsistent(message)) => assert_eq!(
+            message,
+            "spool_threshold (4096) is not less than file_limit (1024), so files are never spooled"
+        ),
+        _ => panic!("expected an inconsistent config"),
+    }
+    match config.max_file_size(256).validate() {
+        Err(ConfigError::Inconsistent(message)) => assert_eq!(
+            message,
+            "spool_threshold (512) is not less than max_file_size (256), so files are never spooled"
+        ),
+        _ => panic!("expected an inconsistent config"),
+    }
+}
+
+#[actix_rt::test]
+async fn raw_multipart_test() {
+    use actix_web::FromRequest;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"tag\"\r\n\
+                 \r\n\
+                 rust\r\n\
+                 --BOUNDARY--\r\n";
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .set_payload(&body[..])
+        .to_http_parts();
+    let mut form = RawMultipart::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    let names: Vec<&str> = form.iter().map(|p| p.name()).collect();
+    assert_eq!(names, vec!["username", "tag"]);
+    let tag: String = MultipartType::get(&mut form, "tag").unwrap();
+    assert_eq!(tag, "rust");
+
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .app_data(MultipartLoadConfig::default().max_parts(1))
+        .set_payload(&body[..])
+        .to_http_parts();
+    let err = RawMultipart::from_request(&req, &mut payload)
+        .await
+        .err()
+        .unwrap();
+    assert!(err
+        .as_response_error()
+        .error_response()
+        .status()
+        .is_client_error());
+}
+
+#[actix_rt::test]
+async fn drain_on_error_test() {
+    use actix_web::FromRequest;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"tag\"\r\n\
+                 \r\n\
+                 rust\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"notes\"\r\n\
+                 \r\n\
+                 a longer part that is sent after the form has already failed\r\n\
+                 --BOUNDARY--\r\n";
+    let chunks: Vec<actix_web::web::Bytes> = body
+        .chunks(8)
+        .map(actix_web::web::Bytes::copy_from_slice)
+        .collect();
+    let total = chunks.len();
+    // Returns how many chunks of the body were read by a failed `RawMultipart`
+    let read = |config: MultipartLoadConfig| {
+        let read = Rc::new(Cell::new(0));
+        let counter = read.clone();
+        // Each chunk arrives separately, otherwise the multipart parser reads everything at once
+        let stream = futures::stream::iter(chunks.clone()).then(move |chunk| {
+            counter.set(counter.get() + 1);
+            async move {
+                let _ = tokio::task::yield_now().await;
+                Ok::<_, actix_web::error::PayloadError>(chunk)
+            }
+        });
+        let (req, _) = test::TestRequest::post()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .app_data(config.max_parts(1))
+            .to_http_parts();
+        let stream: actix_web::dev::PayloadStream = Box::pin(stream);
+        let mut payload = actix_web::dev::Payload::Stream(stream);
+        async move {
+            assert!(RawMultipart::from_request(&req, &mut payload)
+                .await
+                .is_err());
+            read.get()
+        }
+    };
+    assert!(read(MultipartLoadConfig::default()).await < total);
+    assert!(read(MultipartLoadConfig::default().drain_on_error(16)).await < total);
+    assert_eq!(
+        read(MultipartLoadConfig::default().drain_on_error(1024)).await,
+        total
+    );
+}
+
+#[actix_rt::test]
+async fn temp_dir_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let dir = tempfile::tempdir().unwrap();
+    let config = MultipartLoadConfig::default().temp_dir(dir.path());
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.file.path().parent(), Some(dir.path()));
+
+    let config = MultipartLoadConfig::default().temp_dir(dir.path().join("missing"));
+    assert!(load_raw(body.to_vec(), config).await.is_err());
+}
+
+#[actix_rt::test]
+async fn empty_file_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 \r\n\
+                 --BOUNDARY--\r\n";
+    let dir = tempfile::tempdir().unwrap();
+    let config = MultipartLoadConfig::default().temp_dir(dir.path());
+    let mut parts = load_raw(body.to_vec(), config.clone()).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.size, 0);
+    assert_eq!(std::fs::metadata(file.file.path()).unwrap().len(), 0);
+
+    // No temporary file is created for a part that fails before any of it arrives
+    drop(file);
+    let truncated = b"--BOUNDARY\r\n\
+                      Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                      Content-Type: application/octet-stream\r\n\
+                      \r\n";
+    assert!(load_raw(truncated.to_vec(), config).await.is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[actix_rt::test]
+async fn max_file_size_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"a\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"b\"; filename=\"b.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default()
+        .file_limit(16)
+        .max_file_size(8);
+    assert!(load_raw(body.to_vec(), config).await.is_ok());
+
+    let config = MultipartLoadConfig::default().max_file_size(7);
+    assert!(load_raw(body.to_vec(), config).await.is_err());
+
+    let config = MultipartLoadConfig::default()
+        .file_limit(15)
+        .max_file_size(8);
+    assert!(load_raw(body.to_vec(), config).await.is_err());
+}
+
+#[actix_rt::test]
+async fn limit_error_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"video\"; filename=\"a.mp4\"\r\n\
+                 Content-Type: video/mp4\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let limit = |config| async move {
+        match load_raw(body.to_vec(), config).await {
+            Err(LoadError::Limit(e)) => (e.field, e.kind, e.limit),
+            _ => panic!("expected a limit error"),
+        }
+    };
+    let config = MultipartLoadConfig::default().text_limit(4);
+    assert_eq!(limit(config).await, ("title".into(), LimitKind::Text, 4));
+    let config = MultipartLoadConfig::default().file_limit(4);
+    assert_eq!(limit(config).await, ("video".into(), LimitKind::File, 4));
+    let config = MultipartLoadConfig::default().max_file_size(4);
+    assert_eq!(
+        limit(config).await,
+        ("video".into(), LimitKind::FileSize, 4)
+    );
+}
+
+#[actix_rt::test]
+async fn max_files_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"a\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"text\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"b\"; filename=\"b.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().max_files(2);
+    assert_eq!(load_raw(body.to_vec(), config).await.unwrap().len(), 3);
+
+    let config = MultipartLoadConfig::default().max_files(1);
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::TooManyFiles) => {}
+        _ => panic!("expected too many files"),
+    }
+}
+
+#[actix_rt::test]
+async fn max_text_fields_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"a\"\r\n\
+                 \r\n\
+                 1\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"b\"\r\n\
+                 \r\n\
+                 2\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().max_text_fields(2);
+    assert_eq!(load_raw(body.to_vec(), config).await.unwrap().len(), 3);
+
+    let config = MultipartLoadConfig::default().max_text_fields(1);
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::TooManyTextFields) => {}
+        _ => panic!("expected too many text fields"),
+    }
+}
+
+#[actix_rt::test]
+async fn allowed_mimes_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"caption\"\r\n\
+                 \r\n\
+                 text\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"photo\"; filename=\"a.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().allowed_mimes(&["image/png", "image/jpeg"]);
+    assert_eq!(load_raw(body.to_vec(), config).await.unwrap().len(), 2);
+    let config = MultipartLoadConfig::default().allowed_mimes(&["image/*"]);
+    assert_eq!(load_raw(body.to_vec(), config).await.unwrap().len(), 2);
+
+    let config = MultipartLoadConfig::default().allowed_mimes(&["image/jpeg"]);
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::DisallowedMime(name, content_type)) => {
+            assert_eq!(name, "photo");
+            assert_eq!(content_type, mime::IMAGE_PNG);
+        }
+        _ => panic!("expected a disallowed mime"),
+    }
+
+    // The field's own content types replace the allowed_mimes
+    let config = MultipartLoadConfig::default()
+        .allowed_mimes(&["image/jpeg"])
+        .field_mimes("photo", &["image/png"]);
+    assert_eq!(load_raw(body.to_vec(), config).await.unwrap().len(), 2);
+    let config = MultipartLoadConfig::default().field_mimes("photo", &["application/pdf"]);
+    match load_raw(body.to_vec(), config).await {
+        Err(LoadError::DisallowedMime(name, _)) => assert_eq!(name, "photo"),
+        _ => panic!("expected a disallowed mime"),
+    }
+}
+
+#[actix_rt::test]
+async fn spoofed_mimes_test() {
+    let body = |content_type: &str, contents: &[u8]| {
+        let mut body = format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"photo\"; filename=\"a.jpg\"\r\n\
+             Content-Type: {}\r\n\
+             \r\n",
+            content_type
+        )
+        .into_bytes();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+        body
+    };
+    let jpeg = b"\xff\xd8\xff\xe0\0\x10JFIF\0";
+    let php = b"<?php system($_GET['cmd']); ?>";
+    let config = || MultipartLoadConfig::default().reject_spoofed_mimes(&[]);
+
+    // Without the check the content isn't looked at
+    let loaded = load_raw(body("image/jpeg", php), MultipartLoadConfig::default()).await;
+    assert!(loaded.is_ok());
+
+    let mut parts = load_raw(body("image/jpeg", jpeg), config()).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "photo").unwrap();
+    let mut data = Vec::new();
+    file.file.reopen().unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, &jpeg[..]);
+    // Unrecognised content is fine for a type that couldn't have been recognised
+    assert!(load_raw(body("text/csv", b"a,b\n1,2"), config())
+        .await
+        .is_ok());
+
+    for (content_type, contents) in &[
+        ("image/jpeg", &php[..]),
+        ("image/png", &jpeg[..]),
+        ("image/png", &b"not an image"[..]),
+        ("application/octet-stream", &php[..]),
+    ] {
+        match load_raw(body(content_type, contents), config()).await {
+            Err(e @ LoadError::SpoofedMime(..)) => {
+                assert_eq!(e.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            }
+            _ => panic!("expected {} to be rejected", content_type),
+        }
+    }
+
+    let config = MultipartLoadConfig::default().reject_spoofed_mimes(&[("image/*", "image/*")]);
+    assert!(load_raw(body("image/png", jpeg), config.clone())
+        .await
+        .is_ok());
+    assert!(load_raw(body("image/png", php), config).await.is_err());
+}
+
+#[actix_rt::test]
+async fn inspect_archives_test() {
+    use std::io::Cursor;
+    // A zip with a central directory declaring `entries` (without any of their data)
+    fn zip(entries: &[(&str, u32)]) -> Vec<u8> {
+        let mut zip = b"PK\x03\x04".to_vec();
+        zip.extend_from_slice(&[0; 26]);
+        let offset = zip.len() as u32;
+        for (name, size) in entries {
+            zip.extend_from_slice(b"PK\x01\x02");
+            zip.extend_from_slice(&[0; 20]);
+            zip.extend_from_slice(&size.to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&[0; 16]);
+            zip.extend_from_slice(name.as_bytes());
+        }
+        let directory_size = zip.len() as u32 - offset;
+        zip.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&directory_size.to_le_bytes());
+        zip.extend_from_slice(&offset.to_le_bytes());
+        zip.extend_from_slice(&[0; 2]);
+        zip
+    }
+    let inspect =
+        |bytes: &[u8], limits: ArchiveLimits| inspect_archive(Cursor::new(bytes), &limits);
+
+    let summary = inspect(
+        &zip(&[("a.txt", 10), ("b/c.zip", 20)]),
+        ArchiveLimits::default(),
+    );
+    assert_eq!(
+        summary.unwrap(),
+        Some(ArchiveSummary {
+            entries: 2,
+            total_size: 30,
+            nested: 1
+        })
+    );
+    assert!(inspect(b"Hello world", ArchiveLimits::default())
+        .unwrap()
+        .is_none());
+
+    let bomb = zip(&[("zeros", 4_000_000_000)]);
+    match inspect(&bomb, ArchiveLimits::default()) {
+        Err(ArchiveError::TooLarge(1_073_741_824)) => {}
+        r => panic!("expected the archive to be too large, got {:?}", r),
+    }
+    match inspect(&bomb, ArchiveLimits::default().max_total_size(u64::MAX)) {
+        Err(ArchiveError::Ratio(100)) => {}
+        r => panic!("expected the ratio to be too high, got {:?}", r),
+    }
+    let many = zip(&[("a", 1), ("b", 1), ("c", 1)]);
+    match inspect(&many, ArchiveLimits::default().max_entries(2)) {
+        Err(ArchiveError::TooManyEntries(2)) => {}
+        r => panic!("expected too many entries, got {:?}", r),
+    }
+    let nested = zip(&[("inner.ZIP", 1)]);
+    assert!(inspect(&nested, ArchiveLimits::default()).is_ok());
+    match inspect(&nested, ArchiveLimits::default().max_nested(0)) {
+        Err(ArchiveError::Nested(0)) => {}
+        r => panic!("expected a nested archive, got {:?}", r),
+    }
+    let truncated = &many[..many.len() - 10];
+    match inspect(truncated, ArchiveLimits::default()) {
+        Err(ArchiveError::Malformed) => {}
+        r => panic!("expected a malformed archive, got {:?}", r),
+    }
+
+    // A gzip file declares its uncompressed size in its last 4 bytes
+    let mut gzip = b"\x1f\x8b\x08\0\0\0\0\0\0\x03".to_vec();
+    gzip.extend_from_slice(&[0; 8]);
+    gzip.extend_from_slice(&1_000_000u32.to_le_bytes());
+    match inspect(&gzip, ArchiveLimits::default()) {
+        Err(ArchiveError::Ratio(100)) => {}
+        r => panic!("expected the ratio to be too high, got {:?}", r),
+    }
+
+    let mut tar = vec![0; 512];
+    tar[..5].copy_from_slice(b"a.txt");
+    tar[124..136].copy_from_slice(b"00000000012\0");
+    tar[257..262].copy_from_slice(b"ustar");
+    tar.extend_from_slice(&[b'x'; 10]);
+    tar.resize(512 * 4, 0);
+    let summary = inspect(&tar, ArchiveLimits::default()).unwrap().unwrap();
+    assert_eq!((summary.entries, summary.total_size), (1, 10));
+
+    // Uploads are inspected once they are loaded
+    let body = |contents: &[u8]| {
+        let mut body = b"--BOUNDARY\r\n\
+                         Content-Disposition: form-data; name=\"upload\"; filename=\"a.zip\"\r\n\
+                         Content-Type: application/zip\r\n\
+                         \r\n"
+            .to_vec();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+        body
+    };
+    let config = MultipartLoadConfig::default().inspect_archives(ArchiveLimits::default());
+    assert!(load_raw(body(&many), config.clone()).await.is_ok());
+    let in_memory = config.clone().in_memory(&["upload"]);
+    for config in vec![config, in_memory] {
+        match load_raw(body(&bomb), config).await {
+            Err(LoadError::Archive(name, ArchiveError::TooLarge(_))) => assert_eq!(name, "upload"),
+            _ => panic!("expected the archive to be rejected"),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn scan_hook_test() {
+    use futures::future::LocalBoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Rejects files containing "EICAR", and fails to scan files containing "OFFLINE"
+    struct Scanner(Arc<AtomicUsize>);
+
+    impl ScanHook for Scanner {
+        fn scan(&self, file: &MultipartFile) -> LocalBoxFuture<'static, Result<(), ScanError>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let contents = std::fs::read_to_string(file.file.path()).unwrap();
+            async move {
+                if contents.contains("EICAR") {
+                    Err(ScanError::Infected("Eicar-Signature".into()))
+                } else if contents.contains("OFFLINE") {
+                    Err(ScanError::Failed("connection refused".into()))
+                } else {
+                    Ok(())
+                }
+            }
+            .boxed_local()
+        }
+    }
+
+    let body = |contents: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"text\"\r\n\
+             \r\n\
+             EICAR\r\n\
+             --BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             {}\r\n\
+             --BOUNDARY--\r\n",
+            contents
+        )
+        .into_bytes()
+    };
+    let scans = Arc::new(AtomicUsize::new(0));
+    let config = MultipartLoadConfig::default().scan_hook(Scanner(scans.clone()));
+
+    // Text fields aren't scanned
+    assert_eq!(
+        load_raw(body("clean"), config.clone()).await.unwrap().len(),
+        2
+    );
+    assert_eq!(scans.load(Ordering::SeqCst), 1);
+
+    match load_raw(body("X5O EICAR"), config.clone()).await {
+        Err(e @ LoadError::Scan(..)) => {
+            assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+            match e {
+                LoadError::Scan(name, filename, ScanError::Infected(signature)) => {
+                    assert_eq!(name, "upload");
+                    assert_eq!(filename.as_deref(), Some("a.txt"));
+                    assert_eq!(signature, "Eicar-Signature");
+                }
+                _ => unreachable!(),
+            }
+        }
+        _ => panic!("expected the file to be rejected"),
+    }
+    match load_raw(body("OFFLINE"), config).await {
+        Err(e @ LoadError::Scan(_, _, ScanError::Failed(_))) => {
+            assert_eq!(e.status_code(), StatusCode::SERVICE_UNAVAILABLE)
+        }
+        _ => panic!("expected the scan to fail"),
+    }
+    assert_eq!(scans.load(Ordering::SeqCst), 3);
+}
+
+#[actix_rt::test]
+async fn sanitize_filenames_test() {
+    assert_eq!(sanitize_filename("a/b\\c.txt"), Some("c.txt".into()));
+    assert_eq!(sanitize_filename("  ...hidden. . "), Some("hidden".into()));
+    assert_eq!(sanitize_filename("<CON>|.txt"), Some("CON.txt".into()));
+    assert_eq!(sanitize_filename("dir/"), None);
+    let long = format!("{}.tar.gz", "é".repeat(200));
+    let short = sanitize_filename(&long).unwrap();
+    assert!(short.len() <= MAX_FILENAME_LENGTH);
+    assert!(short.starts_with("éé") && short.ends_with("é.gz"));
+
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"upload\"; filename=\"../../a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 Hello\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"dots\"; filename=\"..\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 World\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "upload").unwrap();
+    assert_eq!(file.filename.as_deref(), Some("../../a.txt"));
+    assert_eq!(file.sanitized_filename().as_deref(), Some("a.txt"));
+
+    let config = MultipartLoadConfig::default().sanitize_filenames(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "upload").unwrap();
+    assert_eq!(file.filename.as_deref(), Some("a.txt"));
+    let dots: MultipartFile = MultipartType::get(&mut parts, "dots").unwrap();
+    assert_eq!(dots.filename.as_deref(), Some(""));
+}
+
+#[actix_rt::test]
+async fn extensions_test() {
+    let body = |filename: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n\
+             contents\r\n\
+             --BOUNDARY--\r\n",
+            filename
+        )
+        .into_bytes()
+    };
+    let denied = || MultipartLoadConfig::default().denied_extensions(&["exe", ".php"]);
+    assert!(load_raw(body("a.txt"), denied()).await.is_ok());
+    assert!(load_raw(body("a"), denied()).await.is_ok());
+    for filename in &["a.exe", "a.PHP", "a.php. "] {
+        match load_raw(body(filename), denied()).await {
+            Err(LoadError::DisallowedExtension(f)) => assert_eq!(f, *filename),
+            _ => panic!("expected a disallowed extension"),
+        }
+    }
+
+    let allowed = || MultipartLoadConfig::default().allowed_extensions(&["jpg", "png"]);
+    assert!(load_raw(body("a.JPG"), allowed()).await.is_ok());
+    assert!(load_raw(body("a.gif"), allowed()).await.is_err());
+    assert!(load_raw(body("a"), allowed()).await.is_err());
+}
+
+#[actix_rt::test]
+async fn unknown_parts_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"photos[0]\"; filename=\"a.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"unknown\"; filename=\"b.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = |policy| {
+        MultipartLoadConfig::default()
+            .expected_fields(&["photos"])
+            .unknown_parts(policy)
+    };
+    let parts = load_raw(body.to_vec(), config(UnknownPartPolicy::Accept)).await;
+    assert_eq!(parts.unwrap().len(), 2);
+
+    let parts = load_raw(body.to_vec(), config(UnknownPartPolicy::Skip)).await;
+    let parts = parts.unwrap();
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].name(), "photos[0]");
+
+    match load_raw(body.to_vec(), config(UnknownPartPolicy::Reject)).await {
+        Err(LoadError::UnexpectedPart(name)) => assert_eq!(name, "unknown"),
+        _ => panic!("expected an unexpected part"),
+    }
+}
+
+#[actix_rt::test]
+async fn skipped_parts_count_test() {
+    let part = |name: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"{}\"; filename=\"\"\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n\
+             \r\n",
+            name
+        )
+    };
+    let body = |name: &str| format!("{}--BOUNDARY--\r\n", part(name).repeat(3)).into_bytes();
+    let configs = vec![
+        (
+            "unknown",
+            MultipartLoadConfig::default()
+                .expected_fields(&["photos"])
+                .unknown_parts(UnknownPartPolicy::Skip),
+        ),
+        (
+            "",
+            MultipartLoadConfig::default().empty_names(EmptyNamePolicy::Skip),
+        ),
+        ("blank", MultipartLoadConfig::default().blank_files(false)),
+    ];
+    for (name, config) in configs {
+        assert!(load_raw(body(name), config.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        match load_raw(body(name), config.max_parts(2)).await {
+            Err(LoadError::Multipart(actix_multipart::MultipartError::Payload(
+                actix_web::error::PayloadError::Overflow,
+            ))) => {}
+            _ => panic!("expected the skipped parts to count towards max_parts"),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn preserve_extension_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"video\"; filename=\"clip.MP4\"\r\n\
+                 Content-Type: video/mp4\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"other\"; filename=\"a.b c\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().preserve_extension(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let video: MultipartFile = MultipartType::get(&mut parts, "video").unwrap();
+    assert_eq!(video.file.path().extension().unwrap(), "MP4");
+    let other: MultipartFile = MultipartType::get(&mut parts, "other").unwrap();
+    assert_eq!(other.file.path().extension(), None);
+}
+
+#[actix_rt::test]
+async fn extended_filename_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"nihon.txt\"; \
+                 filename*=UTF-8''%E6%97%A5%E6%9C%AC.txt\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"latin\"; filename*=iso-8859-1''caf%E9.txt\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"plain\"; filename=\"plain.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.filename.as_deref(), Some("日本.txt"));
+    assert_eq!(file.fallback_filename.as_deref(), Some("nihon.txt"));
+    let latin: MultipartFile = MultipartType::get(&mut parts, "latin").unwrap();
+    assert_eq!(latin.filename.as_deref(), Some("café.txt"));
+    assert_eq!(latin.fallback_filename, None);
+    let plain: MultipartFile = MultipartType::get(&mut parts, "plain").unwrap();
+    assert_eq!(plain.filename.as_deref(), Some("plain.txt"));
+    assert_eq!(plain.fallback_filename, None);
+}
+
+#[cfg(unix)]
+#[actix_rt::test]
+async fn private_temp_files_test() {
+    use std::os::unix::fs::PermissionsExt;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert!(!file.private);
+
+    let config = MultipartLoadConfig::default().private_temp_files(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert!(file.private);
+    let mode = file.file.as_file().metadata().unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[actix_rt::test]
+async fn in_memory_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+                 Content-Type: image/png\r\n\
+                 \r\n\
+                 not a png\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().in_memory(&["avatar"]);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let avatar: MemoryFile = MultipartType::get(&mut parts, "avatar").unwrap();
+    assert_eq!(&avatar.data[..], b"not a png");
+    assert_eq!(avatar.size, 9);
+    assert_eq!(avatar.get_extension(), Some("png"));
+    let _: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+
+    let config = MultipartLoadConfig::default()
+        .in_memory(&["avatar"])
+        .file_limit(4);
+    assert!(load_raw(body.to_vec(), config).await.is_err());
+}
+
+#[actix_rt::test]
+async fn spool_threshold_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"small\"; filename=\"a.txt\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 tiny\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"large\"; filename=\"b.txt\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 larger than the threshold\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().spool_threshold(8);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let small: SpooledFile = MultipartType::get(&mut parts, "small").unwrap();
+    let large: SpooledFile = MultipartType::get(&mut parts, "large").unwrap();
+    assert!(matches!(small, SpooledFile::Memory(_)));
+    assert!(matches!(large, SpooledFile::File(_)));
+    let mut data = String::new();
+    large.reader().unwrap().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "larger than the threshold");
+    assert_eq!(large.size(), 25);
+}
+
+#[derive(Clone, Default)]
+struct MapStorage(Arc<std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>>);
+
+impl MultipartStorage for MapStorage {
+    fn store(
+        &self,
+        info: &PartInfo,
+        body: PartStream,
+    ) -> futures::future::LocalBoxFuture<'static, Result<String, actix_multipart::MultipartError>>
+    {
+        let map = self.0.clone();
+        let key = format!("uploads/{}", info.name);
+        async move {
+            let mut data = Vec::new();
+            let mut body = body;
+            while let Some(chunk) = body.next().await {
+                data.extend_from_slice(&chunk?);
+            }
+            map.lock().unwrap().insert(key.clone(), data);
+            Ok(key)
+        }
+        .boxed_local()
+    }
+
+    fn discard(&self, location: &str) -> futures::future::LocalBoxFuture<'static, ()> {
+        self.0.lock().unwrap().remove(location);
+        futures::future::ready(()).boxed_local()
+    }
+}
+
+#[actix_rt::test]
+async fn storage_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let storage = MapStorage::default();
+    let config = MultipartLoadConfig::default().storage(storage.clone());
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let file: StoredFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.location, "uploads/file");
+    assert_eq!(file.size, 8);
+    assert_eq!(storage.0.lock().unwrap()["uploads/file"], b"contents");
+
+    let config = MultipartLoadConfig::default()
+        .storage(storage)
+        .file_limit(4);
+    assert!(load_raw(body.to_vec(), config).await.is_err());
+}
+
+#[actix_rt::test]
+async fn discard_stored_outside_system_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let storage = MapStorage::default();
+    let config = MultipartLoadConfig::default().storage(storage.clone());
+    let parts = load_raw(body.to_vec(), config).await.unwrap();
+    let sessions = UploadSessions::new(std::time::Duration::from_secs(60));
+    let token = sessions.store(parts);
+    // A thread that isn't running an actix system
+    let discarded = std::thread::spawn(move || sessions.discard(&token));
+    assert!(discarded.join().unwrap());
+    for _ in 0..50 {
+        if storage.0.lock().unwrap().is_empty() {
+            return;
+        }
+        actix_rt::time::delay_for(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("stored file wasn't discarded");
+}
+
+#[actix_rt::test]
+async fn charset_test() {
+    let mut body = b"--BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"declared\"\r\n\
+                     Content-Type: text/plain; charset=windows-1252\r\n\
+                     \r\n\
+                     caf"
+    .to_vec();
+    body.push(0xE9);
+    body.extend_from_slice(
+        b"\r\n--BOUNDARY\r\n\
+          Content-Disposition: form-data; name=\"legacy\"\r\n\
+          Content-Type: text/plain; charset=utf-8\r\n\
+          \r\n",
+    );
+    // "Nihon" in Shift JIS
+    body.extend_from_slice(&[0x93, 0xFA, 0x96, 0x7B]);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+
+    let config = MultipartLoadConfig::default().field_charset("legacy", encoding_rs::SHIFT_JIS);
+    let mut parts = load_raw(body, config).await.unwrap();
+    let declared: String = MultipartType::get(&mut parts, "declared").unwrap();
+    assert_eq!(declared, "café");
+    let legacy: String = MultipartType::get(&mut parts, "legacy").unwrap();
+    assert_eq!(legacy, "日本");
+}
+
+#[actix_rt::test]
+async fn charset_field_test() {
+    let mut body = b"--BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"before\"\r\n\
+                     \r\n\
+                     caf\xC3\xA9\r\n\
+                     --BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"_charset_\"\r\n\
+                     \r\n\
+                     windows-1252\r\n\
+                     --BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"after\"\r\n\
+                     \r\n\
+                     caf"
+    .to_vec();
+    body.push(0xE9);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+
+    let mut parts = load_raw(body, MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let before: String = MultipartType::get(&mut parts, "before").unwrap();
+    assert_eq!(before, "café");
+    let after: String = MultipartType::get(&mut parts, "after").unwrap();
+    assert_eq!(after, "café");
+}
+
+#[actix_rt::test]
+async fn transfer_encoding_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"quoted\"\r\n\
+                 Content-Transfer-Encoding: quoted-printable\r\n\
+                 \r\n\
+                 caf=C3=A9 au =\r\n\
+                 lait\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 Content-Transfer-Encoding: BASE64\r\n\
+                 \r\n\
+                 RmlsZSBj\r\n\
+                 b250ZW50cw==\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().decode_transfer_encoding(true);
+    let mut parts = load_raw(body.to_vec(), config).await.unwrap();
+    let quoted: String = MultipartType::get(&mut parts, "quoted").unwrap();
+    assert_eq!(quoted, "café au lait");
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.size, 13);
+    assert_eq!(
+        std::fs::read_to_string(file.file.path()).unwrap(),
+        "File contents"
+    );
+
+    let mut parts = load_raw(body.to_vec(), MultipartLoadConfig::default())
+        .await
+        .unwrap();
+    let quoted: String = MultipartType::get(&mut parts, "quoted").unwrap();
+    assert_eq!(quoted, "caf=C3=A9 au =\r\nlait");
+
+    let corrupt = b"--BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"field\"\r\n\
+                    Content-Transfer-Encoding: base64\r\n\
+                    \r\n\
+                    not*base64\r\n\
+                    --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().decode_transfer_encoding(true);
+    match load_raw(corrupt.to_vec(), config).await {
+        Err(LoadError::Multipart(actix_multipart::MultipartError::Payload(
+            actix_web::error::PayloadError::EncodingCorrupted,
+        ))) => {}
+        _ => panic!("expected a corrupted encoding"),
+    }
+}
+
+#[actix_rt::test]
+async fn utf8_policy_test() {
+    let srv = test::start(|| {
+        App::new().route(
+            "/",
+            web::post().to(
+                |payload: Multipart, req: actix_web::HttpRequest| async move {
+                    let policy = match req.query_string() {
+                        "lossy" => Utf8Policy::Lossy,
+                        "latin1" => Utf8Policy::Latin1Fallback,
+                        _ => Utf8Policy::Strict,
+                    };
+                    let config = MultipartLoadConfig::default().utf8_policy(policy);
+                    let mut parts = load_parts(payload, config).await?;
+                    let text: String = MultipartType::get(&mut parts, "comment")?;
+                    Ok::<_, Error>(HttpResponse::Ok().body(text))
+                },
+            ),
+        )
+    });
+
+    let send = |query: &'static str| {
+        let mut body = b"--BOUNDARY\r\n\
+                         Content-Disposition: form-data; name=\"comment\"\r\n\
+                         \r\n\
+                         caf"
+        .to_vec();
+        body.push(0xE9);
+        body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+        Client::default()
+            .post(srv.url(&format!("/?{}", query)))
+            .content_type("multipart/form-data; boundary=BOUNDARY")
+            .send_body(body)
+    };
+
+    let response = send("strict").await.unwrap();
+    assert_eq!(response.status(), 400);
+    let mut response = send("lossy").await.unwrap();
+    assert_eq!(response.body().await.unwrap(), "caf\u{FFFD}");
+    let mut response = send("latin1").await.unwrap();
+    assert_eq!(response.body().await.unwrap(), "café");
+}
+
+#[actix_rt::test]
+async fn utf8_bytes_test() {
+    let mut body = b"--BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"valid\"\r\n\
+                     \r\n\
+                     cafe\r\n\
+                     --BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"invalid\"\r\n\
+                     \r\n\
+                     caf"
+    .to_vec();
+    body.push(0xE9);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+
+    let config = MultipartLoadConfig::default().utf8_policy(Utf8Policy::Bytes);
+    let mut parts = load_raw(body, config).await.unwrap();
+    let text: Result<String, GetError> = MultipartType::get(&mut parts, "invalid");
+    match text {
+        Err(GetError::TypeError(name, _)) => assert_eq!(name, "invalid"),
+        _ => panic!("expected a type error"),
+    }
+    let invalid: MultipartBytes = MultipartType::get(&mut parts, "invalid").unwrap();
+    assert_eq!(&invalid.bytes[..], b"caf\xE9");
+    assert_eq!(invalid.size, 4);
+    let valid: MultipartBytes = MultipartType::get(&mut parts, "valid").unwrap();
+    assert_eq!(&valid.bytes[..], b"cafe");
+}
+
+async fn max_parts_per_name_route(payload: Multipart) -> Result<HttpResponse, Error> {
+    load_parts(
+        payload,
+        MultipartLoadConfig::default().max_parts_per_name(2),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[actix_rt::test]
+async fn max_parts_per_name_test() {
+    let srv = test::start(|| App::new().route("/", web::post().to(max_parts_per_name_route)));
+
+    let send = |names: &[&str]| {
+        let mut form = multipart::Form::default();
+        for name in names {
+            form.add_text(name.to_string(), "value");
+        }
+        Client::default()
+            .post(srv.url("/"))
+            .content_type(form.content_type())
+            .send_body(multipart::Body::from(form))
+    };
+
+    let response = send(&["photos[0]", "photos[1]", "title"]).await.unwrap();
+    assert!(response.status().is_success());
+
+    let mut response = send(&["photos[0]", "photos[1]", "photos[2]"])
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().await.unwrap(),
+        "Too many parts for field 'photos'"
+    );
+}
+
+#[actix_rt::test]
+async fn empty_names_test() {
+    let srv = test::start(|| {
+        App::new().route(
+            "/",
+            web::post().to(
+                |payload: Multipart, req: actix_web::HttpRequest| async move {
+                    let policy = match req.query_string() {
+                        "skip" => EmptyNamePolicy::Skip,
+                        "rename" => EmptyNamePolicy::Rename("unnamed".into()),
+                        _ => EmptyNamePolicy::Reject,
+                    };
+                    let config = MultipartLoadConfig::default().empty_names(policy);
+                    let parts = load_parts(payload, config).await?;
+                    let names: Vec<&str> = parts.iter().map(|p| p.name()).collect();
+                    Ok::<_, Error>(HttpResponse::Ok().body(names.join(",")))
+                },
+            ),
+        )
+    });
+
+    let send = |query: &'static str| {
+        let body = "--BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"\"\r\n\
+                    \r\n\
+                    junk\r\n\
+                    --BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"title\"\r\n\
+                    \r\n\
+                    Hello\r\n\
+                    --BOUNDARY--\r\n";
+        Client::default()
+            .post(srv.url(&format!("/?{}", query)))
+            .content_type("multipart/form-data; boundary=BOUNDARY")
+            .send_body(body)
+    };
+
+    let response = send("reject").await.unwrap();
+    assert_eq!(response.status(), 400);
+    let mut response = send("skip").await.unwrap();
+    assert_eq!(response.body().await.unwrap(), "title");
+    let mut response = send("rename").await.unwrap();
+    assert_eq!(response.body().await.unwrap(), "unnamed,title");
+}
+
+#[actix_rt::test]
+async fn blank_files_test() {
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"avatar\"; filename=\"\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 \r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"cv\"; filename=\"\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 \r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"notes\"; filename=\"notes.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 \r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default().in_memory(&["avatar"]);
+    let mut parts = load_raw(body.to_vec(), config.clone()).await.unwrap();
+    let avatar: Option<MemoryFile> = MultipartTypeSpecial::get(&mut parts, "avatar").unwrap();
+    assert_eq!(avatar.unwrap().size, 0);
+    assert_eq!(parts.len(), 2);
+
+    let mut parts = load_raw(body.to_vec(), config.blank_files(false))
+        .await
+        .unwrap();
+    let avatar: Option<MemoryFile> = MultipartTypeSpecial::get(&mut parts, "avatar").unwrap();
+    assert!(avatar.is_none());
+    let cv: Option<MultipartFile> = MultipartTypeSpecial::get(&mut parts, "cv").unwrap();
+    assert!(cv.is_none());
+    // An empty file that was chosen (it has a filename) is kept
+    let notes: MultipartFile = MultipartType::get(&mut parts, "notes").unwrap();
+    assert_eq!(notes.size, 0);
+}
+
+#[test]
+fn raw_text_test() {
+    let mut parts = vec![
+        MultipartField::Text(MultipartText::new("a".into(), "1".into())),
+        MultipartField::Text(MultipartText::new("b[1]".into(), "3".into())),
+        MultipartField::Text(MultipartText::new("b[0]".into(), "2".into())),
+    ];
+    let a: MultipartText = MultipartType::get(&mut parts, "a").unwrap();
+    assert_eq!(a.text, "1");
+    let c: Option<MultipartText> = MultipartTypeSpecial::get(&mut parts, "c").unwrap();
+    assert!(c.is_none());
+    let b: Vec<MultipartText> = MultipartTypeSpecial::get(&mut parts, "b").unwrap();
+    assert_eq!(
+        b.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(),
+        ["2", "3"]
+    );
+    assert!(parts.is_empty());
+}
+
+#[derive(Debug)]
+struct NotAllowed(String);
+
+impl std::fmt::Display for NotAllowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "'{}' is not allowed", self.0)
+    }
+}
+
+impl std::error::Error for NotAllowed {}
+
+impl From<NotAllowed> for MultipartErrorWrapper {
+    fn from(e: NotAllowed) -> Self {
+        MultipartErrorWrapper::Custom(Box::new(e))
+    }
+}
+
+struct Username(String);
+
+impl std::convert::TryFrom<Multiparts> for Username {
+    type Error = NotAllowed;
+
+    fn try_from(mut parts: Multiparts) -> Result<Self, Self::Error> {
+        let name: String = MultipartType::get(&mut parts, "username").unwrap();
+        if name == "admin" {
+            return Err(NotAllowed(name));
+        }
+        Ok(Username(name))
+    }
+}
+
+impl validator::Validate for Username {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        Ok(())
+    }
+}
+
+/// Parses from a string, but with an error that doesn't implement `Display`
+#[derive(Debug, PartialEq)]
+struct Opaque(u8);
+
+impl std::str::FromStr for Opaque {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Opaque).map_err(|_| ())
+    }
+}
+
+#[test]
+fn from_str_without_display_test() {
+    let text = |name: &str, value: &str| {
+        MultipartField::Text(MultipartText::new(name.to_string(), value.to_string()))
+    };
+    let mut parts = vec![text("valid", "7"), text("invalid", "x")];
+    let valid: Opaque = MultipartType::get(&mut parts, "valid").unwrap();
+    assert_eq!(valid, Opaque(7));
+    let missing: Option<Opaque> = MultipartTypeSpecial::get(&mut parts, "missing").unwrap();
+    assert_eq!(missing, None);
+    match <Vec<Opaque> as MultipartTypeSpecial>::get(&mut parts, "invalid") {
+        Err(GetError::TypeError(name, ty)) => {
+            assert_eq!(name, "invalid");
+            assert!(ty.ends_with("Opaque"));
+        }
+        _ => panic!("expected a type error"),
+    }
+}
+
+#[actix_rt::test]
+async fn method_override_test() {
+    use actix_web::FromRequest;
+    let extract = |method: &'static str| {
+        let body = format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"username\"\r\n\
+             \r\n\
+             jacob\r\n\
+             --BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"_method\"\r\n\
+             \r\n\
+             {}\r\n\
+             --BOUNDARY--\r\n",
+            method
+        );
+        let (req, mut payload) = test::TestRequest::post()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .app_data(ValidatedMultipartFormConfig::default().method_override(true))
+            .set_payload(body)
+            .to_http_parts();
+        async move {
+            let form = ValidatedMultipartForm::<Username>::from_request(&req, &mut payload).await;
+            (form, crate::method::effective_method(&req))
+        }
+    };
+    let (form, method) = extract("patch").await;
+    assert_eq!(form.unwrap().into_inner().0, "jacob");
+    assert_eq!(method, actix_web::http::Method::PATCH);
+    let (form, _) = extract("GET").await;
+    let response = form.err().unwrap().as_response_error().error_response();
+    assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn custom_try_from_error_test() {
+    let srv = test::start(|| {
+        App::new()
+            .app_data(
+                ValidatedMultipartFormConfig::default().error_handler(|e, _| match e {
+                    crate::error::ValidatedFormError::Deserialization(
+                        MultipartErrorWrapper::Custom(e),
+                    ) if e.is::<NotAllowed>() => actix_web::error::ErrorForbidden(e.to_string()),
+                    e => actix_web::error::ErrorBadRequest(e.to_string()),
+                }),
+            )
+            .route(
+                "/",
+                web::post().to(|form: ValidatedMultipartForm<Username>| async move {
+                    HttpResponse::Ok().body(form.into_inner().0)
+                }),
+            )
+    });
+
+    let send = |username: &'static str| {
+        let mut form = multipart::Form::default();
+        form.add_text("username", username);
+        Client::default()
+            .post(srv.url("/"))
+            .content_type(form.content_type())
+            .send_body(multipart::Body::from(form))
+    };
+
+    let mut response = send("jacob").await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.body().await.unwrap(), "jacob");
+
+    let mut response = send("admin").await.unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.body().await.unwrap(), "'admin' is not allowed");
+}
+
+#[test]
+fn constraints_test() {
+    use constraints::{allowed_extensions, allowed_mime, max_size};
+    let file = |filename: &str, mime: mime::Mime| MultipartFile {
+        file: NamedTempFile::new().unwrap(),
+        size: 100,
+        name: "file".into(),
+        filename: Some(filename.into()),
+        fallback_filename: None,
+        mime,
+        headers: actix_web::http::HeaderMap::new(),
+        index: 0,
+        sha256: None,
+        md5: None,
+        deduplicated: false,
+        private: false,
+    };
+    let png = file("image.PNG", mime::IMAGE_PNG);
+    assert!(max_size(&png, 100).is_ok());
+    assert_eq!(max_size(&png, 99).unwrap_err().code, "max_size");
+    assert!(allowed_extensions(&png, &["png", "jpg"]).is_ok());
+    assert!(allowed_mime(&png, &[mime::IMAGE_PNG]).is_ok());
+
+    let files = vec![png, file("notes.txt", mime::TEXT_PLAIN)];
+    assert!(max_size(&files, 100).is_ok());
+    assert!(allowed_extensions(&files, &["png"]).is_err());
+    assert!(allowed_mime(&files, &[mime::IMAGE_PNG]).is_err());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn image_test() {
+    use super::image::{assert_image, image_info, ImageFormat, ImageInfo};
+    let file = |contents: &dyn Fn(&std::path::Path)| {
+        let file = NamedTempFile::new().unwrap();
+        contents(file.path());
+        MultipartFile {
+            file,
+            size: 100,
+            name: "file".into(),
+            filename: Some("image.png".into()),
+            fallback_filename: None,
+            mime: mime::IMAGE_PNG,
+            headers: actix_web::http::HeaderMap::new(),
+            index: 0,
+            sha256: None,
+            md5: None,
+            deduplicated: false,
+            private: false,
+        }
+    };
+    let png = file(&|path| {
+        ::image::RgbImage::new(40, 20)
+            .save_with_format(path, ImageFormat::Png)
+            .unwrap()
+    });
+    assert_eq!(
+        image_info(png.file.path()).unwrap(),
+        ImageInfo {
+            format: ImageFormat::Png,
+            width: 40,
+            height: 20
+        }
+    );
+    assert!(assert_image(&png, 40, 20, &[]).is_ok());
+    assert!(assert_image(&png, 100, 100, &[ImageFormat::Png, ImageFormat::Jpeg]).is_ok());
+    let code = |r: Result<(), validator::ValidationError>| r.unwrap_err().code;
+    assert_eq!(code(assert_image(&png, 39, 20, &[])), "image_dimensions");
+    assert_eq!(code(assert_image(&png, 40, 19, &[])), "image_dimensions");
+    assert_eq!(
+        code(assert_image(&png, 100, 100, &[ImageFormat::Jpeg])),
+        "image_format"
+    );
+
+    let text = file(&|path| std::fs::write(path, "not an image").unwrap());
+    assert_eq!(code(assert_image(&text, 100, 100, &[])), "image");
+    assert!(assert_image(&vec![png, text], 100, 100, &[]).is_err());
+}
+
+#[actix_rt::test]
+async fn into_payload_test() {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(b"File contents").unwrap();
+    let parts = vec![
+        MultipartField::Text(MultipartText::new("title".into(), "Say \"hi\"".into())),
+        MultipartField::File(MultipartFile {
+            file,
+            size: 13,
+            name: "file".into(),
+            filename: Some("notes.txt".into()),
+            fallback_filename: None,
+            mime: mime::TEXT_PLAIN,
+            headers: actix_web::http::HeaderMap::new(),
+            index: 0,
+            sha256: None,
+            md5: None,
+            deduplicated: false,
+            private: false,
+        }),
+    ];
+
+    let boundary = "forwarded-boundary";
+    let mut headers = actix_web::http::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::HeaderValue::from_str(&format!(
+            "multipart/form-data; boundary={}",
+            boundary
+        ))
+        .unwrap(),
+    );
+    let body = parts
+        .into_payload(boundary)
+        .map(|r| r.map_err(actix_web::error::PayloadError::Io));
+    let mut loaded = load_parts(
+        Multipart::new(&headers, body),
+        MultipartLoadConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let title: String = MultipartType::get(&mut loaded, "title").unwrap();
+    assert_eq!(title, "Say \"hi\"");
+    let f: MultipartFile = MultipartType::get(&mut loaded, "file").unwrap();
+    assert_eq!(f.filename.as_deref(), Some("notes.txt"));
+    let mut data = String::new();
+    f.file.reopen().unwrap().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "File contents");
+}
+
+struct Plan {
+    file_limit: u64,
+}
+
+#[derive(Debug)]
+struct Upload {
+    file: MultipartFile,
+}
+
+impl std::convert::TryFrom<Multiparts> for Upload {
+    type Error = GetError;
+
+    fn try_from(mut parts: Multiparts) -> Result<Self, Self::Error> {
+        Ok(Upload {
+            file: MultipartType::get(&mut parts, "file")?,
+        })
+    }
+}
+
+impl validator::Validate for Upload {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        Ok(())
+    }
+}
+
+static EARLY_VALIDATIONS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+struct EarlyCount;
+
+impl std::convert::TryFrom<Multiparts> for EarlyCount {
+    type Error = GetError;
+
+    fn try_from(_: Multiparts) -> Result<Self, Self::Error> {
+        Ok(EarlyCount)
+    }
+}
+
+impl validator::Validate for EarlyCount {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        Ok(())
+    }
+}
+
+impl EarlyValidate for EarlyCount {
+    fn validate_early(_: &mut Multiparts) -> Result<(), validator::ValidationErrors> {
+        EARLY_VALIDATIONS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn validate_early_once_test() {
+    use actix_web::FromRequest;
+    let text = |name: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"{}\"\r\n\
+             \r\n\
+             value\r\n",
+            name
+        )
+    };
+    let file = |name: &str| {
+        format!(
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"{}\"; filename=\"a.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n\
+             contents\r\n",
+            name
+        )
+    };
+    // Only files that follow new text fields cause them to be validated again
+    let body = [
+        text("a"),
+        file("b"),
+        file("c"),
+        text("d"),
+        file("e"),
+        file("f"),
+    ]
+    .concat()
+        + "--BOUNDARY--\r\n";
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .set_payload(body.clone())
+        .to_http_parts();
+    EarlyValidatedMultipartForm::<EarlyCount>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(
+        EARLY_VALIDATIONS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+
+    // Early validation is only done when opted into
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .set_payload(body)
+        .to_http_parts();
+    ValidatedMultipartForm::<EarlyCount>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(
+        EARLY_VALIDATIONS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+}
+
+#[actix_rt::test]
+async fn hand_written_form_test() {
+    use actix_web::FromRequest;
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let (req, mut payload) = test::TestRequest::post()
+        .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+        .set_payload(&body[..])
+        .to_http_parts();
+    let form = ValidatedMultipartForm::<Upload>::from_request(&req, &mut payload)
+        .await
+        .unwrap();
+    assert_eq!(form.file.filename.as_deref(), Some("a.txt"));
+    assert_eq!(std::fs::read(form.file.file.path()).unwrap(), b"contents");
+}
+
+#[actix_rt::test]
+async fn request_config_test() {
+    let srv = test::start(|| {
+        App::new()
+            .app_data(
+                ValidatedMultipartFormConfig::default()
+                    .config(MultipartLoadConfig::default().file_limit(2))
+                    .request_config(|req, config| match req.extensions().get::<Plan>() {
+                        Some(plan) => config.file_limit(plan.file_limit),
+                        None => config,
+                    }),
+            )
+            .wrap_fn(|req, srv| {
+                use actix_web::dev::Service;
+                use actix_web::HttpMessage;
+                if req.query_string() == "premium" {
+                    req.extensions_mut().insert(Plan { file_limit: 1024 });
+                }
+                srv.call(req)
+            })
+            .route(
+                "/",
+                web::post().to(|form: ValidatedMultipartForm<Upload>| async move {
+                    HttpResponse::Ok().body(form.file.size.to_string())
+                }),
+            )
+    });
+
+    let send = |query: &'static str| {
+        let mut form = multipart::Form::default();
+        let temp = NamedTempFile::new().unwrap();
+        temp.as_file().write("File contents".as_bytes()).unwrap();
+        form.add_file("file", temp.path()).unwrap();
+        Client::default()
+            .post(srv.url(&format!("/?{}", query)))
+            .content_type(form.content_type())
+            .send_body(multipart::Body::from(form))
+    };
+
+    let response = send("free").await.unwrap();
+    assert!(!response.status().is_success());
+    let mut response = send("premium").await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.body().await.unwrap(), "13");
+}
+
+#[actix_rt::test]
+async fn write_buffer_size_test() {
+    let chunks: &[&'static [u8]] = &[b"abc", b"defg", b"hi", b"jklmnopq", b"r"];
+    for buffer_size in &[0, 8, 64] {
+        let chunks: Vec<Result<_, actix_multipart::MultipartError>> = chunks
+            .iter()
+            .map(|c| Ok(actix_web::web::Bytes::from_static(c)))
+            .collect();
+        let mut field = futures::stream::iter(chunks);
+        let file = NamedTempFile::new().unwrap();
+        let buffer = super::pool::WriteBuffer::new(*buffer_size);
+        let written = super::load::write_field(&mut field, file.as_file(), 100, buffer, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(written, 18);
+        let mut data = String::new();
+        file.reopen().unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "abcdefghijklmnopqr");
+    }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[actix_rt::test]
+async fn uring_write_test() {
+    let mut file = NamedTempFile::new().unwrap();
+    let mut uring = match super::uring::UringFile::new(file.as_file()).unwrap() {
+        Some(uring) => uring,
+        // io-uring isn't supported by this kernel, so files are written using tokio::fs
+        None => return,
+    };
+    uring.write_all(b"abc").await.unwrap();
+    // The file position is shared with the other handles to the file, as it is for tokio::fs
+    file.as_file_mut().write_all(b"def").unwrap();
+    uring.write_all(&[b'x'; 100_000]).await.unwrap();
+    let mut data = Vec::new();
+    file.reopen().unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(&data[..6], b"abcdef");
+    assert_eq!(data.len(), 100_006);
+    assert!(data[6..].iter().all(|b| *b == b'x'));
+}
+
+#[actix_rt::test]
+async fn buffer_pool_test() {
+    use super::pool::BufferPool;
+    let pool = Arc::new(BufferPool::new(1));
+    let mut buffer = pool.take(64);
+    buffer.extend_from_slice(b"abc");
+    let reused = buffer.as_ptr();
+    drop(buffer);
+    // The buffer is returned empty, and a second one isn't kept
+    let first = pool.take(64);
+    let second = pool.take(64);
+    assert!(first.is_empty() && first.capacity() >= 64);
+    assert_eq!(first.as_ptr(), reused);
+    assert_ne!(second.as_ptr(), reused);
+
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"username\"\r\n\
+                 \r\n\
+                 jacob\r\n\
+                 --BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    let config = MultipartLoadConfig::default()
+        .write_buffer_size(4)
+        .buffer_pool(2);
+    for _ in 0..2 {
+        let mut parts = load_raw(body.to_vec(), config.clone()).await.unwrap();
+        let username: String = MultipartType::get(&mut parts, "username").unwrap();
+        assert_eq!(username, "jacob");
+        let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+        let mut data = String::new();
+        file.file
+            .reopen()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "contents");
+    }
+}
+
+#[actix_rt::test]
+async fn max_throughput_test() {
+    let mut body = b"--BOUNDARY\r\n\
+                     Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                     Content-Type: application/octet-stream\r\n\
+                     \r\n"
+        .to_vec();
+    body.extend_from_slice(&[b'a'; 400]);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+    let config = MultipartLoadConfig::default().max_throughput(2000);
+    let started = std::time::Instant::now();
+    let mut parts = load_raw(body, config).await.unwrap();
+    // 400 bytes at 2000 bytes/s
+    assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+    let file: MultipartFile = MultipartType::get(&mut parts, "file").unwrap();
+    assert_eq!(file.size, 400);
+}
+
+#[actix_rt::test]
+async fn write_permit_timeout_test() {
+    let config = MultipartLoadConfig::default().max_concurrent_writes(1);
+    let holding = b"--BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                    Content-Type: application/octet-stream\r\n\
+                    \r\n\
+                    the first few bytes";
+    let body = b"--BOUNDARY\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"b.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 contents\r\n\
+                 --BOUNDARY--\r\n";
+    // The first upload stalls while holding the only write permit
+    let first = load_parts(stalled(holding), config.clone()).boxed_local();
+    let timeout = std::time::Duration::from_millis(50);
+    let waiting = async {
+        let read_timeout = load_raw(body.to_vec(), config.clone().read_timeout(timeout)).await;
+        let deadline = std::time::Instant::now() + timeout;
+        let deadline = load_raw(body.to_vec(), config.clone().deadline(deadline)).await;
+        (read_timeout, deadline)
+    };
+    match futures::future::select(first, waiting.boxed_local()).await {
+        futures::future::Either::Right(((read_timeout, deadline), _)) => {
+            assert!(matches!(read_timeout, Err(LoadError::ReadTimeout)));
+            assert!(matches!(deadline, Err(LoadError::Timeout)));
+        }
+        _ => panic!("expected the first upload to still be loading"),
+    };
+}
+
+#[actix_rt::test]
+async fn max_concurrent_writes_test() {
+    let config = MultipartLoadConfig::default().max_concurrent_writes(1);
+    let srv = test::start(move || {
+        let config = config.clone();
+        App::new().route(
+            "/",
+            web::post().to(move |payload: Multipart| {
+                let config = config.clone();
+                async move {
+                    let mut parts = load_parts(payload, config).await?;
+                    let files: Vec<MultipartFile> = MultipartTypeSpecial::get(&mut parts, "file")?;
+                    Ok::<_, Error>(HttpResponse::Ok().body(files.len().to_string()))
+                }
+            }),
+        )
+    });
+
+    let mut form = multipart::Form::default();
+    let temp = NamedTempFile::new().unwrap();
+    temp.as_file().write("File contents".as_bytes()).unwrap();
+    form.add_file("file", temp.path()).unwrap();
+    form.add_file("file", temp.path()).unwrap();
+
+    let mut response = Client::default()
+        .post(srv.url("/"))
+        .content_type(form.content_type())
+        .send_body(multipart::Body::from(form))
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!(response.body().await.unwrap(), "2");
 }
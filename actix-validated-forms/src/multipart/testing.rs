@@ -0,0 +1,221 @@
+use super::forward::part_header;
+use super::{
+    load_parts_with_skipped, DescribeForm, EarlyValidate, EarlyValidatedMultipartForm, LoadError,
+    MultipartErrorWrapper, MultipartField, MultipartLoadConfig, Multiparts,
+    ValidatedMultipartFormConfig, ValidatedMultipartWithExtras,
+};
+use actix_multipart::Multipart;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{test, FromRequest};
+use std::convert::TryFrom;
+use std::io::Read;
+use validator::Validate;
+
+const BOUNDARY: &str = "actix-validated-forms-test-boundary";
+
+enum TestPart {
+    Text {
+        name: String,
+        text: String,
+    },
+    File {
+        name: String,
+        filename: String,
+        mime: mime::Mime,
+        contents: Bytes,
+    },
+}
+
+/// A multipart form to submit in tests, to check your form definitions (and their validation)
+/// against the requests your clients will send
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive;
+/// # fn main() {
+/// # use actix_validated_forms_derive::FromMultipart;
+/// # use actix_validated_forms::multipart::{MultipartFile, TestForm};
+/// # use validator::Validate;
+/// #[derive(FromMultipart, Validate)]
+/// struct Upload {
+///     title: String,
+///     image: MultipartFile,
+/// }
+///
+/// async fn upload_form() {
+///     let upload: Upload = TestForm::new()
+///         .text("title", "Holiday")
+///         .file("image", "beach.png", mime::IMAGE_PNG, &b"not really a png"[..])
+///         .assert_round_trip()
+///         .await;
+///     assert_eq!(upload.title, "Holiday");
+/// }
+/// # }
+/// ```
+#[derive(Default)]
+pub struct TestForm {
+    parts: Vec<TestPart>,
+}
+
+impl TestForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a text field
+    pub fn text(mut self, name: &str, text: &str) -> Self {
+        self.parts.push(TestPart::Text {
+            name: name.into(),
+            text: text.into(),
+        });
+        self
+    }
+
+    /// Add a file upload
+    pub fn file<B: Into<Bytes>>(
+        mut self,
+        name: &str,
+        filename: &str,
+        mime: mime::Mime,
+        contents: B,
+    ) -> Self {
+        self.parts.push(TestPart::File {
+            name: name.into(),
+            filename: filename.into(),
+            mime,
+            contents: contents.into(),
+        });
+        self
+    }
+
+    /// The `Content-Type` header to submit the form with
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", BOUNDARY)
+    }
+
+    /// The encoded body of the form
+    pub fn to_bytes(&self) -> Bytes {
+        let mut body = BytesMut::new();
+        for part in &self.parts {
+            match part {
+                TestPart::Text { name, text } => {
+                    body.extend_from_slice(&part_header(BOUNDARY, name, None, None));
+                    body.extend_from_slice(text.as_bytes());
+                }
+                TestPart::File {
+                    name,
+                    filename,
+                    mime,
+                    contents,
+                } => {
+                    body.extend_from_slice(&part_header(
+                        BOUNDARY,
+                        name,
+                        Some(filename.as_str()),
+                        Some(mime),
+                    ));
+                    body.extend_from_slice(contents);
+                }
+            }
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        body.freeze()
+    }
+
+    fn request(&self) -> test::TestRequest {
+        test::TestRequest::post()
+            .header(CONTENT_TYPE, self.content_type())
+            .set_payload(self.to_bytes())
+    }
+
+    /// Load the form using `load_parts_with_skipped()`
+    pub async fn load(&self, config: MultipartLoadConfig) -> Result<Multiparts, LoadError> {
+        let (req, mut payload) = self.request().to_http_parts();
+        load_parts_with_skipped(Multipart::new(req.headers(), payload.take()), config)
+            .await
+            .map(|(parts, _)| parts)
+    }
+
+    /// Extract the form using the `EarlyValidatedMultipartForm` extractor
+    pub async fn extract<T>(
+        &self,
+        config: ValidatedMultipartFormConfig,
+    ) -> Result<T, actix_web::Error>
+    where
+        T: TryFrom<Multiparts> + EarlyValidate + Validate + 'static,
+        T::Error: Into<MultipartErrorWrapper>,
+    {
+        let (req, mut payload) = self.request().app_data(config).to_http_parts();
+        EarlyValidatedMultipartForm::<T>::from_request(&req, &mut payload)
+            .await
+            .map(EarlyValidatedMultipartForm::into_inner)
+    }
+
+    /// Extract the form using the `ValidatedMultipartWithExtras` extractor
+    pub async fn extract_with_extras<T>(
+        &self,
+        config: ValidatedMultipartFormConfig,
+    ) -> Result<(T, Multiparts), actix_web::Error>
+    where
+        T: TryFrom<Multiparts> + EarlyValidate + DescribeForm + Validate + 'static,
+        T::Error: Into<MultipartErrorWrapper>,
+    {
+        let (req, mut payload) = self.request().app_data(config).to_http_parts();
+        ValidatedMultipartWithExtras::<T>::from_request(&req, &mut payload)
+            .await
+            .map(ValidatedMultipartWithExtras::into_inner)
+    }
+
+    /// Panics unless every field is received exactly as it was submitted, and the form is
+    /// successfully extracted into (and validated as) a `T`
+    pub async fn assert_round_trip<T>(&self) -> T
+    where
+        T: TryFrom<Multiparts> + EarlyValidate + Validate + 'static,
+        T::Error: Into<MultipartErrorWrapper>,
+    {
+        let loaded = self
+            .load(MultipartLoadConfig::default())
+            .await
+            .unwrap_or_else(|e| panic!("Failed to load form: {}", e));
+        assert_eq!(loaded.len(), self.parts.len(), "Number of parts differs");
+        for (submitted, received) in self.parts.iter().zip(loaded) {
+            assert_part(submitted, received);
+        }
+        self.extract(ValidatedMultipartFormConfig::default())
+            .await
+            .unwrap_or_else(|e| panic!("Failed to extract form: {}", e))
+    }
+}
+
+fn assert_part(submitted: &TestPart, received: MultipartField) {
+    match (submitted, received) {
+        (TestPart::Text { name, text }, MultipartField::Text(t)) => {
+            assert_eq!(&t.name, name);
+            assert_eq!(&t.text, text, "Text of field '{}' differs", name);
+        }
+        (
+            TestPart::File {
+                name,
+                filename,
+                mime,
+                contents,
+            },
+            MultipartField::File(f),
+        ) => {
+            assert_eq!(&f.name, name);
+            assert_eq!(f.filename.as_ref(), Some(filename));
+            assert_eq!(&f.mime, mime, "Content-Type of field '{}' differs", name);
+            let mut data = Vec::new();
+            f.file.reopen().unwrap().read_to_end(&mut data).unwrap();
+            assert_eq!(
+                &data[..],
+                &contents[..],
+                "Contents of field '{}' differ",
+                name
+            );
+        }
+        (_, received) => panic!("Field '{}' was received as the wrong type", received.name()),
+    }
+}
@@ -0,0 +1,49 @@
+use super::PartStream;
+use futures::StreamExt;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Limits the rate that the bodies of a request's parts are read at, the parts sharing the same
+/// allowance
+#[derive(Clone)]
+pub(crate) struct Throttle {
+    bytes_per_sec: u64,
+    /// When the bytes read so far will have been paid for
+    next: Rc<Cell<Instant>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Throttle {
+            bytes_per_sec,
+            next: Rc::new(Cell::new(Instant::now())),
+        }
+    }
+
+    /// Holds back each chunk of `body` until the rate allows it
+    pub(crate) fn apply(&self, body: PartStream) -> PartStream {
+        let throttle = self.clone();
+        body.then(move |chunk| {
+            let until = chunk
+                .as_ref()
+                .ok()
+                .map(|bytes| throttle.charge(bytes.len()));
+            async move {
+                if let Some(until) = until {
+                    tokio::time::delay_until(until.into()).await;
+                }
+                chunk
+            }
+        })
+        .boxed_local()
+    }
+
+    /// Takes `len` bytes from the allowance, returning when they will have been paid for
+    fn charge(&self, len: usize) -> Instant {
+        let start = self.next.get().max(Instant::now());
+        let until = start + Duration::from_secs_f64(len as f64 / self.bytes_per_sec as f64);
+        self.next.set(until);
+        until
+    }
+}
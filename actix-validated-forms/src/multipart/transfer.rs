@@ -0,0 +1,190 @@
+use super::{PartInfo, PartStream};
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::web::Bytes;
+use futures::stream::{self, StreamExt};
+
+/// The deprecated Content-Transfer-Encoding of a part (RFC 2045), that some older clients still
+/// use to send binary data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferEncoding {
+    Base64,
+    QuotedPrintable,
+}
+
+impl TransferEncoding {
+    /// The encoding declared by the part's headers, `None` for `7bit`, `8bit` and `binary` (which
+    /// need no decoding) or values that aren't recognised
+    pub fn of(info: &PartInfo) -> Option<Self> {
+        let value = info
+            .headers
+            .get("content-transfer-encoding")?
+            .to_str()
+            .ok()?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "base64" => Some(TransferEncoding::Base64),
+            "quoted-printable" => Some(TransferEncoding::QuotedPrintable),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the body of a part if it has a Content-Transfer-Encoding, otherwise returns it as is
+pub(crate) fn decode_transfer_encoding(info: &PartInfo, body: PartStream) -> PartStream {
+    let decoder = match TransferEncoding::of(info) {
+        Some(TransferEncoding::Base64) => Decoder::Base64(Base64Decoder::default()),
+        Some(TransferEncoding::QuotedPrintable) => Decoder::QuotedPrintable(Vec::new()),
+        None => return body,
+    };
+    stream::unfold(Some((body, decoder)), |state| async move {
+        let (mut body, mut decoder) = state?;
+        match body.next().await {
+            Some(Ok(chunk)) => Some((decoder.update(&chunk), Some((body, decoder)))),
+            Some(Err(e)) => Some((Err(e), None)),
+            None => match decoder.finish() {
+                Ok(rest) if rest.is_empty() => None,
+                result => Some((result, None)),
+            },
+        }
+    })
+    .boxed_local()
+}
+
+fn corrupted() -> MultipartError {
+    MultipartError::Payload(PayloadError::EncodingCorrupted)
+}
+
+enum Decoder {
+    Base64(Base64Decoder),
+    /// The bytes of an escape sequence that was split between chunks
+    QuotedPrintable(Vec<u8>),
+}
+
+impl Decoder {
+    fn update(&mut self, chunk: &[u8]) -> Result<Bytes, MultipartError> {
+        match self {
+            Decoder::Base64(decoder) => decoder.update(chunk),
+            Decoder::QuotedPrintable(pending) => {
+                let mut input = std::mem::replace(pending, Vec::new());
+                input.extend_from_slice(chunk);
+                let (decoded, rest) = decode_quoted_printable(&input)?;
+                *pending = rest.to_vec();
+                Ok(decoded)
+            }
+        }
+    }
+
+    /// Whatever remains once the body has ended
+    fn finish(&mut self) -> Result<Bytes, MultipartError> {
+        match self {
+            Decoder::Base64(decoder) => decoder.finish(),
+            // An `=` at the very end is a soft line break without the line break
+            Decoder::QuotedPrintable(pending) => match pending.get(1) {
+                Some(b) if !b.is_ascii_whitespace() => Err(corrupted()),
+                _ => Ok(Bytes::new()),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Base64Decoder {
+    /// The 6 bit values of an incomplete group of four characters
+    group: [u8; 4],
+    len: usize,
+    padding: usize,
+}
+
+impl Base64Decoder {
+    fn update(&mut self, chunk: &[u8]) -> Result<Bytes, MultipartError> {
+        let mut out = Vec::with_capacity(chunk.len() / 4 * 3 + 3);
+        for &c in chunk.iter().filter(|c| !c.is_ascii_whitespace()) {
+            if c == b'=' {
+                // Padding can only complete a group of two or three characters
+                if self.len < 2 || self.len + self.padding == 4 {
+                    return Err(corrupted());
+                }
+                self.padding += 1;
+                if self.len + self.padding == 4 {
+                    self.flush(&mut out);
+                }
+                continue;
+            }
+            if self.padding > 0 {
+                return Err(corrupted());
+            }
+            self.group[self.len] = base64_value(c).ok_or_else(corrupted)?;
+            self.len += 1;
+            if self.len == 4 {
+                self.flush(&mut out);
+                self.len = 0;
+            }
+        }
+        Ok(Bytes::from(out))
+    }
+
+    /// Decodes a trailing group that wasn't padded
+    fn finish(&mut self) -> Result<Bytes, MultipartError> {
+        let mut out = Vec::new();
+        match (self.len, self.padding) {
+            (_, p) if p > 0 => {
+                if self.len + p != 4 {
+                    return Err(corrupted());
+                }
+            }
+            (1, _) => return Err(corrupted()),
+            (0, _) => {}
+            _ => self.flush(&mut out),
+        }
+        Ok(Bytes::from(out))
+    }
+
+    fn flush(&self, out: &mut Vec<u8>) {
+        let group = &self.group[..self.len];
+        let bits = group
+            .iter()
+            .chain(std::iter::repeat(&0))
+            .take(4)
+            .fold(0u32, |bits, v| bits << 6 | *v as u32);
+        let bytes = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        out.extend_from_slice(&bytes[..self.len - 1]);
+    }
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes as much of `input` as possible, returning the decoded bytes and the start of an
+/// escape sequence that needs the next chunk to be decoded
+fn decode_quoted_printable(input: &[u8]) -> Result<(Bytes, &[u8]), MultipartError> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] != b'=' {
+            out.push(input[i]);
+            i += 1;
+            continue;
+        }
+        match &input[i + 1..] {
+            // Soft line breaks are removed
+            [b'\r', b'\n', ..] => i += 3,
+            [b'\n', ..] => i += 2,
+            [high, low, ..] => {
+                let high = (*high as char).to_digit(16).ok_or_else(corrupted)?;
+                let low = (*low as char).to_digit(16).ok_or_else(corrupted)?;
+                out.push((high << 4 | low) as u8);
+                i += 3;
+            }
+            _ => return Ok((Bytes::from(out), &input[i..])),
+        }
+    }
+    Ok((Bytes::from(out), &[]))
+}
@@ -0,0 +1,157 @@
+use actix_web::web::Bytes;
+use futures::channel::oneshot;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::{mpsc, Arc};
+use uring::{opcode, types, IoUring};
+
+/// The number of writes that may be in flight on each ring
+const RING_ENTRIES: u32 = 64;
+
+/// A write to submit to the ring, the file and buffer are owned by the request so that they
+/// outlive the write even if its future is dropped
+struct WriteRequest {
+    file: Arc<File>,
+    bytes: Bytes,
+    done: oneshot::Sender<io::Result<usize>>,
+}
+
+thread_local! {
+    // `None` if the kernel doesn't support io-uring (or writing at the file position with it)
+    static RING: Option<mpsc::Sender<WriteRequest>> = start().ok();
+}
+
+/// Starts a thread that submits writes to its own io-uring, and waits for them to complete
+///
+/// `tokio-uring` can't be used as it requires tokio 1.x (and its own runtime), whereas the
+/// writes are made from actix-web's tokio 0.2 runtime.
+fn start() -> io::Result<mpsc::Sender<WriteRequest>> {
+    let ring = IoUring::new(RING_ENTRIES)?;
+    if !ring.params().is_feature_rw_cur_pos() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "io-uring can't write at the file position",
+        ));
+    }
+    let (sender, requests) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("multipart-io-uring".into())
+        .spawn(move || run(ring, requests))?;
+    Ok(sender)
+}
+
+fn run(mut ring: IoUring, requests: mpsc::Receiver<WriteRequest>) {
+    let mut in_flight: HashMap<u64, WriteRequest> = HashMap::new();
+    let mut next_id = 0u64;
+    loop {
+        // Only block waiting for requests when there aren't any writes to wait for
+        if in_flight.is_empty() {
+            match requests.recv() {
+                Ok(request) => submit(&mut ring, &mut in_flight, &mut next_id, request),
+                // Every sender has gone, and all their writes are complete
+                Err(_) => return,
+            }
+        }
+        while in_flight.len() < RING_ENTRIES as usize {
+            match requests.try_recv() {
+                Ok(request) => submit(&mut ring, &mut in_flight, &mut next_id, request),
+                Err(_) => break,
+            }
+        }
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // The ring is unusable, fail the writes rather than leaving them waiting
+                for (_, request) in in_flight.drain() {
+                    let _ = request
+                        .done
+                        .send(Err(io::Error::new(e.kind(), e.to_string())));
+                }
+                return;
+            }
+        }
+        for cqe in ring.completion() {
+            if let Some(request) = in_flight.remove(&cqe.user_data()) {
+                let result = match cqe.result() {
+                    n if n < 0 => Err(io::Error::from_raw_os_error(-n)),
+                    n => Ok(n as usize),
+                };
+                let _ = request.done.send(result);
+            }
+        }
+    }
+}
+
+fn submit(
+    ring: &mut IoUring,
+    in_flight: &mut HashMap<u64, WriteRequest>,
+    next_id: &mut u64,
+    request: WriteRequest,
+) {
+    let length = request.bytes.len().min(u32::MAX as usize) as u32;
+    let entry = opcode::Write::new(
+        types::Fd(request.file.as_raw_fd()),
+        request.bytes.as_ptr(),
+        length,
+    )
+    // Write at (and advance) the file position, the same as `write(2)`
+    .offset(u64::MAX)
+    .build()
+    .user_data(*next_id);
+    // There is space as there are fewer than RING_ENTRIES writes in flight (and none queued)
+    // Safety: the file and buffer are kept alive in `in_flight` until the write completes
+    if unsafe { ring.submission().push(&entry) }.is_err() {
+        let e = io::Error::new(io::ErrorKind::Other, "io-uring submission queue is full");
+        let _ = request.done.send(Err(e));
+        return;
+    }
+    in_flight.insert(*next_id, request);
+    *next_id = next_id.wrapping_add(1);
+}
+
+/// A file written using io-uring, by a thread started for each of the threads (e.g. actix
+/// workers) that write files
+pub(crate) struct UringFile {
+    file: Arc<File>,
+    ring: mpsc::Sender<WriteRequest>,
+}
+
+impl UringFile {
+    /// Returns `None` if io-uring isn't supported, in which case `tokio::fs` should be used
+    pub(crate) fn new(file: &File) -> io::Result<Option<Self>> {
+        let ring = match RING.with(Clone::clone) {
+            Some(ring) => ring,
+            None => return Ok(None),
+        };
+        Ok(Some(UringFile {
+            file: Arc::new(file.try_clone()?),
+            ring,
+        }))
+    }
+
+    /// Writes all of `bytes` at the file position
+    ///
+    /// `bytes` is copied, as the ring may still be writing it if this future is dropped.
+    pub(crate) async fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut bytes = Bytes::copy_from_slice(bytes);
+        let stopped = || io::Error::new(io::ErrorKind::Other, "io-uring thread has stopped");
+        while !bytes.is_empty() {
+            let (done, written) = oneshot::channel();
+            let request = WriteRequest {
+                file: self.file.clone(),
+                bytes: bytes.clone(),
+                done,
+            };
+            self.ring.send(request).map_err(|_| stopped())?;
+            let written = written.await.map_err(|_| stopped())??;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            bytes = bytes.slice(written..);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use super::load::{LoadError, Loader, PartInfo, PartStream};
+use super::MultipartLoadConfig;
+use std::future::Future;
+
+/// Use to process a multipart form one part at a time, without loading it
+///
+/// `handler` is called with the headers of each part as it arrives, and its body as a stream of
+/// chunks, so that a large upload can be piped straight to its destination rather than first
+/// being written to a temporary file. The next part isn't read until the handler's future has
+/// resolved, and any of the body that the handler didn't read is discarded.
+///
+/// The headers of each part are checked as they are by `load_parts()` (including the count
+/// limits, and the `empty_names()` and `unknown_parts()` policies), and the body passes through
+/// the same `stage()`s. The bytes the handler reads count towards the size limits, a body that
+/// exceeds them ends with a `PayloadError::Overflow` error.
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::multipart::{process_parts, MultipartLoadConfig};
+/// # use actix_web::{HttpResponse, Error};
+/// # use futures::StreamExt;
+/// async fn route(payload: actix_multipart::Multipart) -> Result<HttpResponse, Error> {
+///     process_parts(payload, MultipartLoadConfig::default(), |info, mut body| async move {
+///         while let Some(chunk) = body.next().await {
+///             let chunk = chunk?;
+///             // Send the chunk on to wherever `info.name` is going
+///         }
+///         Ok::<_, Error>(())
+///     })
+///     .await?;
+///     # unimplemented!() }
+/// ```
+pub async fn process_parts<F, Fut, E>(
+    payload: actix_multipart::Multipart,
+    config: MultipartLoadConfig,
+    mut handler: F,
+) -> Result<(), E>
+where
+    F: FnMut(PartInfo, PartStream) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: From<LoadError>,
+{
+    let mut loader = Loader::new(payload, config);
+    while let Some((field, info)) = loader.next_field().await? {
+        let (kind, body, size) = loader.open_field(field, &info);
+        let result = handler(info, body).await;
+        loader.charge(kind, size.get());
+        result?;
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use crate::error::ValidatedFormError;
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorBadRequest, PayloadError};
+use actix_web::web::BytesMut;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{err, ok, Ready};
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use validator::Validate;
+
+/// Extractor for an application/x-ndjson HTTP request body, as a stream of validated items
+///
+/// Each non-blank line of the body is deserialized as JSON and validated separately, as it
+/// arrives, so that a large import doesn't have to be held in memory. An item that fails to
+/// deserialize or validate is yielded as an `NdjsonError::Item` with its line number, and the
+/// stream continues with the next line. Other errors end the stream.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Contact {
+///     #[validate(email)]
+///     email: String,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::ndjson::ValidatedNdjsonStream;
+/// # use futures::StreamExt;
+///
+/// async fn route(
+///     mut contacts: ValidatedNdjsonStream<Contact>,
+/// ) -> HttpResponse {
+///     while let Some(contact) = contacts.next().await {
+///         match contact {
+///             Ok(contact) => { /* Import the contact */ }
+///             Err(e) => { /* Report the line that was skipped */ }
+///         }
+///     }
+///     # unimplemented!(); }
+/// # }
+/// ```
+pub struct ValidatedNdjsonStream<T> {
+    payload: Payload,
+    buf: BytesMut,
+    line: usize,
+    max_line_length: usize,
+    eof: bool,
+    done: bool,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> ValidatedNdjsonStream<T> {
+    /// The number of lines read so far
+    pub fn lines(&self) -> usize {
+        self.line
+    }
+
+    fn next_line(&mut self) -> Option<(usize, BytesMut)> {
+        let end = match self.buf.iter().position(|b| *b == b'\n') {
+            Some(pos) => pos + 1,
+            None if self.eof && !self.buf.is_empty() => self.buf.len(),
+            None => return None,
+        };
+        self.line += 1;
+        Some((self.line, self.buf.split_to(end)))
+    }
+}
+
+impl<T> FromRequest for ValidatedNdjsonStream<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedNdjsonConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        if req.content_type() != "application/x-ndjson" {
+            return err(ErrorBadRequest("Content type error"));
+        }
+        ok(ValidatedNdjsonStream {
+            payload: payload.take(),
+            buf: BytesMut::new(),
+            line: 0,
+            max_line_length: config.max_line_length,
+            eof: false,
+            done: false,
+            _item: PhantomData,
+        })
+    }
+}
+
+impl<T> Stream for ValidatedNdjsonStream<T>
+where
+    T: Validate + DeserializeOwned,
+{
+    type Item = Result<T, NdjsonError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if let Some((line, bytes)) = this.next_line() {
+                if bytes.len() > this.max_line_length {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(NdjsonError::LineTooLong { line })));
+                }
+                if bytes.iter().all(|b| b.is_ascii_whitespace()) {
+                    continue;
+                }
+                let item = serde_json::from_slice::<T>(&bytes)
+                    .map_err(ValidatedFormError::Deserialization)
+                    .and_then(|c| match c.validate() {
+                        Ok(_) => Ok(c),
+                        Err(e) => Err(ValidatedFormError::Validation(e)),
+                    })
+                    .map_err(|error| NdjsonError::Item { line, error });
+                return Poll::Ready(Some(item));
+            }
+            if this.eof {
+                return Poll::Ready(None);
+            }
+            if this.buf.len() > this.max_line_length {
+                this.done = true;
+                let line = this.line + 1;
+                return Poll::Ready(Some(Err(NdjsonError::LineTooLong { line })));
+            }
+            match Pin::new(&mut this.payload).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.buf.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(e))) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(NdjsonError::Payload(e))));
+                }
+                Poll::Ready(None) => this.eof = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NdjsonError {
+    /// The item on this line failed to either deserialize or validate, the stream continues
+    Item {
+        line: usize,
+        error: ValidatedFormError<serde_json::Error>,
+    },
+    /// The line is longer than the `ValidatedNdjsonConfig::max_line_length()`, ending the
+    /// stream
+    LineTooLong { line: usize },
+    /// The payload failed to be read, ending the stream
+    Payload(PayloadError),
+}
+
+impl NdjsonError {
+    /// The line number (starting from 1) that the error occurred on, if any
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            NdjsonError::Item { line, .. } => Some(*line),
+            NdjsonError::LineTooLong { line } => Some(*line),
+            NdjsonError::Payload(_) => None,
+        }
+    }
+}
+
+impl std::error::Error for NdjsonError {}
+
+impl Display for NdjsonError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            NdjsonError::Item { line, error } => write!(f, "Line {}: {}", line, error),
+            NdjsonError::LineTooLong { line } => {
+                write!(f, "Line {}: line is longer than allowed", line)
+            }
+            NdjsonError::Payload(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedNdjsonStream extractor
+///
+/// # Usage
+/// Add a `ValidatedNdjsonConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::ndjson::ValidatedNdjsonConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(ValidatedNdjsonConfig::default().max_line_length(4096));
+/// ```
+#[derive(Clone)]
+pub struct ValidatedNdjsonConfig {
+    max_line_length: usize,
+}
+
+impl ValidatedNdjsonConfig {
+    /// Set the max length of a single line (including the newline), there is no limit on the
+    /// number of lines. By default max length is 64Kb
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+}
+
+impl Default for ValidatedNdjsonConfig {
+    fn default() -> Self {
+        ValidatedNdjsonConfig {
+            max_line_length: 65_536,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use futures::StreamExt;
+    use serde::Deserialize;
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Contact {
+        #[validate(length(min = 1, max = 5))]
+        name: String,
+    }
+
+    async fn route(mut contacts: ValidatedNdjsonStream<Contact>) -> impl Responder {
+        let mut results = Vec::new();
+        while let Some(contact) = contacts.next().await {
+            results.push(match contact {
+                Ok(contact) => contact.name,
+                Err(NdjsonError::Item {
+                    line,
+                    error: ValidatedFormError::Validation(_),
+                }) => format!("invalid {}", line),
+                Err(NdjsonError::Item { line, .. }) => format!("malformed {}", line),
+                Err(e) => e.to_string(),
+            });
+        }
+        HttpResponse::Ok().body(results.join(","))
+    }
+
+    fn request(body: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/x-ndjson")
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn test_stream() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedNdjsonConfig::default().max_line_length(32))
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let body =
+            "{\"name\": \"ann\"}\n\n{\"name\": \"\"}\r\n{\"nom\": \"bob\"}\n{\"name\": \"cat\"}";
+        let resp = test::read_response(&mut app, request(body).to_request()).await;
+        assert_eq!(resp, "ann,invalid 3,malformed 4,cat");
+
+        let body = "{\"name\": \"ann\"}\n{\"name\": \"a very very very long name\"}\n";
+        let resp = test::read_response(&mut app, request(body).to_request()).await;
+        assert_eq!(resp, "ann,Line 2: line is longer than allowed");
+
+        let req = test::TestRequest::post()
+            .header("content-type", "application/json")
+            .set_payload("{\"name\": \"ann\"}")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
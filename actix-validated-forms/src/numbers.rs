@@ -0,0 +1,103 @@
+/// The separators used to write a number, e.g. `1.234,56` in much of Europe
+///
+/// Set using `ValidatedFormConfig::number_format()` or `ValidatedQueryConfig::number_format()`,
+/// numeric fields are then accepted in this format (as well as the usual `1234.56`).
+///
+/// # Example
+/// ```
+/// # use actix_validated_forms::numbers::NumberFormat;
+/// let format = NumberFormat::comma_decimal();
+/// assert_eq!(format.normalize("-1.234.567,8").as_deref(), Some("-1234567.8"));
+/// assert_eq!(format.normalize("1 234"), Some("1234".to_string()));
+/// // Groups must be of three digits, so this isn't misread as 15
+/// assert_eq!(format.normalize("1.5"), None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumberFormat {
+    decimal: char,
+    grouping: &'static [char],
+}
+
+impl NumberFormat {
+    /// A format with the `decimal` separator, and any of the `grouping` characters separating
+    /// the thousands
+    pub fn new(decimal: char, grouping: &'static [char]) -> Self {
+        NumberFormat { decimal, grouping }
+    }
+
+    /// `1,234.56` (also accepts spaces between the thousands)
+    pub fn point_decimal() -> Self {
+        Self::new('.', &[',', ' ', '\u{a0}', '\u{202f}'])
+    }
+
+    /// `1.234,56` (also accepts spaces between the thousands)
+    pub fn comma_decimal() -> Self {
+        Self::new(',', &['.', ' ', '\u{a0}', '\u{202f}'])
+    }
+
+    /// `1'234.56`
+    pub fn apostrophe_grouping() -> Self {
+        Self::new('.', &['\'', '\u{2019}'])
+    }
+
+    /// Converts `value` into the format understood by `str::parse()`, or `None` if it isn't a
+    /// number written in this format
+    pub fn normalize(&self, value: &str) -> Option<String> {
+        let value = value.trim();
+        let (sign, digits) = match value.chars().next() {
+            Some(c @ '-') | Some(c @ '+') => (Some(c), &value[1..]),
+            _ => (None, value),
+        };
+        let mut parts = digits.splitn(2, self.decimal);
+        let integer = parts.next().unwrap_or_default();
+        let fraction = parts.next();
+
+        let groups: Vec<&str> = integer.split(|c| self.grouping.contains(&c)).collect();
+        let valid_groups = groups.iter().enumerate().all(|(i, g)| {
+            let all_digits = g.chars().all(|c| c.is_ascii_digit());
+            match i {
+                0 if groups.len() > 1 => all_digits && (1..=3).contains(&g.len()),
+                0 => all_digits && !g.is_empty(),
+                _ => all_digits && g.len() == 3,
+            }
+        });
+        let valid_fraction = fraction.map_or(true, |f| {
+            !f.is_empty() && f.chars().all(|c| c.is_ascii_digit())
+        });
+        if !valid_groups || !valid_fraction {
+            return None;
+        }
+
+        let mut normalized: String = sign.into_iter().collect();
+        normalized.extend(groups);
+        if let Some(fraction) = fraction {
+            normalized.push('.');
+            normalized.push_str(fraction);
+        }
+        Some(normalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let format = NumberFormat::point_decimal();
+        assert_eq!(format.normalize("1,234.5").as_deref(), Some("1234.5"));
+        assert_eq!(format.normalize("+12").as_deref(), Some("+12"));
+        assert_eq!(format.normalize("1,23"), None);
+        assert_eq!(format.normalize("1.2.3"), None);
+        assert_eq!(format.normalize(""), None);
+
+        let format = NumberFormat::comma_decimal();
+        assert_eq!(format.normalize("0,5").as_deref(), Some("0.5"));
+        assert_eq!(format.normalize("12\u{a0}345").as_deref(), Some("12345"));
+        assert_eq!(format.normalize(",5"), None);
+        assert_eq!(format.normalize("1,"), None);
+
+        let format = NumberFormat::apostrophe_grouping();
+        assert_eq!(format.normalize("1'000'000").as_deref(), Some("1000000"));
+    }
+}
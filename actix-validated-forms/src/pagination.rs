@@ -0,0 +1,354 @@
+use crate::error::ValidatedFormError;
+use actix_web::dev::Payload;
+use actix_web::error::QueryPayloadError;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{err, ok, Ready};
+use serde::de::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use validator::{ValidationError, ValidationErrors};
+
+/// The direction of a `SortField`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// A field to order the results by
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortField {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+impl SortField {
+    /// Parses a single field, prefixed with `-` for descending order, e.g. `-created`
+    fn parse(value: &str) -> Self {
+        match value.strip_prefix('-') {
+            Some(field) => SortField {
+                field: field.to_owned(),
+                order: SortOrder::Descending,
+            },
+            None => SortField {
+                field: value.trim_start_matches('+').to_owned(),
+                order: SortOrder::Ascending,
+            },
+        }
+    }
+}
+
+/// Validated extractor for the pagination and sorting parameters of a query string
+///
+/// Accepts `?page=2&limit=20&sort=-created,name` (the parameter names can be changed using the
+/// `ValidatedPaginationConfig`). Either a 1-based `page` or an `offset` may be used, if an
+/// `offset` is given then it takes precedence. Sort fields are comma separated (or repeated),
+/// and a `-` prefix sorts in descending order. Any other query parameters are ignored, so this can
+/// be used alongside a `ValidatedQuery`.
+///
+/// # Example
+/// ```
+/// # use actix_web::HttpResponse;
+/// # use actix_validated_forms::pagination::ValidatedPagination;
+/// async fn route(pagination: ValidatedPagination) -> HttpResponse {
+///     let (offset, limit) = (pagination.offset, pagination.limit);
+///     # unimplemented!()
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedPagination {
+    /// The 1-based page number
+    pub page: u64,
+    pub limit: u64,
+    /// The number of items to skip
+    pub offset: u64,
+    /// Only contains the fields that are `sortable`, in order of precedence
+    pub sort: Vec<SortField>,
+}
+
+impl FromRequest for ValidatedPagination {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedPaginationConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(ValidatedPaginationConfig::default());
+
+        serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+            .map_err(|e| ValidatedFormError::Deserialization(QueryPayloadError::Deserialize(e)))
+            .and_then(|params| config.paginate(&params))
+            .map(ok)
+            .unwrap_or_else(move |e| {
+                let e = if let Some(error_handler) = config.error_handler {
+                    (error_handler)(e, req)
+                } else {
+                    e.into()
+                };
+                err(e)
+            })
+    }
+}
+
+/// Configure the behaviour of the ValidatedPagination extractor
+///
+/// # Usage
+/// Add a `ValidatedPaginationConfig` to your actix app data
+/// ```
+/// # use actix_web::web::scope;
+/// # use actix_validated_forms::pagination::ValidatedPaginationConfig;
+/// scope("/").app_data(
+///     ValidatedPaginationConfig::default()
+///         .limit_param("per_page")
+///         .max_limit(50)
+///         .sortable(&["name", "created"])
+///         .default_sort("-created"),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedPaginationConfig {
+    page_param: String,
+    limit_param: String,
+    offset_param: String,
+    sort_param: String,
+    default_limit: u64,
+    max_limit: u64,
+    sortable: Vec<String>,
+    default_sort: Vec<SortField>,
+    error_handler: Option<
+        Arc<
+            dyn Fn(ValidatedFormError<QueryPayloadError>, &HttpRequest) -> actix_web::Error
+                + Send
+                + Sync,
+        >,
+    >,
+}
+
+impl ValidatedPaginationConfig {
+    /// Set the name of the page parameter. By default `page`
+    pub fn page_param(mut self, name: &str) -> Self {
+        self.page_param = name.into();
+        self
+    }
+
+    /// Set the name of the limit parameter. By default `limit`
+    pub fn limit_param(mut self, name: &str) -> Self {
+        self.limit_param = name.into();
+        self
+    }
+
+    /// Set the name of the offset parameter. By default `offset`
+    pub fn offset_param(mut self, name: &str) -> Self {
+        self.offset_param = name.into();
+        self
+    }
+
+    /// Set the name of the sort parameter. By default `sort`
+    pub fn sort_param(mut self, name: &str) -> Self {
+        self.sort_param = name.into();
+        self
+    }
+
+    /// Set the limit used when none is requested. By default 20
+    pub fn default_limit(mut self, limit: u64) -> Self {
+        self.default_limit = limit;
+        self
+    }
+
+    /// Set the largest limit that may be requested. By default 100
+    pub fn max_limit(mut self, limit: u64) -> Self {
+        self.max_limit = limit;
+        self
+    }
+
+    /// Set the fields that results may be sorted by, requesting any other field is rejected
+    ///
+    /// By default no fields are sortable. Sort fields often end up in a database query, so they
+    /// must always be checked against a whitelist.
+    pub fn sortable(mut self, fields: &[&str]) -> Self {
+        self.sortable = fields.iter().map(|f| (*f).to_owned()).collect();
+        self
+    }
+
+    /// Set the ordering used when none is requested, in the same format as the sort parameter
+    /// e.g. `-created,name`
+    pub fn default_sort(mut self, sort: &str) -> Self {
+        self.default_sort = parse_sort(sort).collect();
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a query that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<QueryPayloadError>, &HttpRequest) -> actix_web::Error
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+
+    fn paginate(
+        &self,
+        params: &[(String, String)],
+    ) -> Result<ValidatedPagination, ValidatedFormError<QueryPayloadError>> {
+        let find = |name: &str| {
+            params
+                .iter()
+                .rev()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let page = parse_param::<u64>(&self.page_param, find(&self.page_param))?;
+        let limit = parse_param::<u64>(&self.limit_param, find(&self.limit_param))?;
+        let offset = parse_param::<u64>(&self.offset_param, find(&self.offset_param))?;
+        let sort: Vec<SortField> = params
+            .iter()
+            .filter(|(k, _)| *k == self.sort_param)
+            .flat_map(|(_, v)| parse_sort(v))
+            .collect();
+
+        let mut errors = ValidationErrors::new();
+        let limit = limit.unwrap_or(self.default_limit);
+        if limit < 1 || limit > self.max_limit {
+            errors.add("limit", range_error(1, self.max_limit));
+        }
+        if page == Some(0) {
+            let mut error = ValidationError::new("range");
+            error.add_param("min".into(), &1);
+            errors.add("page", error);
+        }
+        let unsortable: Vec<&str> = sort
+            .iter()
+            .map(|s| s.field.as_str())
+            .filter(|f| !self.sortable.iter().any(|s| s == f))
+            .collect();
+        if !unsortable.is_empty() {
+            let mut error = ValidationError::new("sortable");
+            error.add_param("fields".into(), &unsortable);
+            error.add_param("sortable".into(), &self.sortable);
+            errors.add("sort", error);
+        }
+        if !errors.is_empty() {
+            return Err(ValidatedFormError::Validation(errors));
+        }
+
+        let offset = offset.unwrap_or_else(|| (page.unwrap_or(1) - 1).saturating_mul(limit));
+        Ok(ValidatedPagination {
+            page: offset / limit + 1,
+            limit,
+            offset,
+            sort: if sort.is_empty() {
+                self.default_sort.clone()
+            } else {
+                sort
+            },
+        })
+    }
+}
+
+impl Default for ValidatedPaginationConfig {
+    fn default() -> Self {
+        ValidatedPaginationConfig {
+            page_param: "page".into(),
+            limit_param: "limit".into(),
+            offset_param: "offset".into(),
+            sort_param: "sort".into(),
+            default_limit: 20,
+            max_limit: 100,
+            sortable: Vec::new(),
+            default_sort: Vec::new(),
+            error_handler: None,
+        }
+    }
+}
+
+fn parse_param<T: FromStr>(
+    name: &str,
+    value: Option<&str>,
+) -> Result<Option<T>, ValidatedFormError<QueryPayloadError>>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .map(|v| {
+            v.parse().map_err(|e| {
+                let e = serde::de::value::Error::custom(format!(
+                    "Invalid value {:?} for '{}': {}",
+                    v, name, e
+                ));
+                ValidatedFormError::Deserialization(QueryPayloadError::Deserialize(e))
+            })
+        })
+        .transpose()
+}
+
+fn parse_sort(value: &str) -> impl Iterator<Item = SortField> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(SortField::parse)
+}
+
+fn range_error(min: u64, max: u64) -> ValidationError {
+    let mut error = ValidationError::new("range");
+    error.add_param("min".into(), &min);
+    error.add_param("max".into(), &max);
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+
+    async fn route(pagination: ValidatedPagination) -> impl Responder {
+        HttpResponse::Ok().body(format!("{:?}", pagination))
+    }
+
+    #[test]
+    fn test_pagination() {
+        let config = ValidatedPaginationConfig::default()
+            .limit_param("per_page")
+            .max_limit(50)
+            .sortable(&["name", "created"])
+            .default_sort("-created");
+        let paginate = |query: &str| {
+            let params = serde_urlencoded::from_str::<Vec<(String, String)>>(query).unwrap();
+            config.paginate(&params)
+        };
+
+        let p = paginate("page=3&per_page=10").unwrap();
+        assert_eq!((p.page, p.limit, p.offset), (3, 10, 20));
+        assert_eq!(p.sort, vec![SortField::parse("-created")]);
+
+        let p = paginate("offset=45&limit=999&sort=name,-created").unwrap();
+        assert_eq!((p.page, p.limit, p.offset), (3, 20, 45));
+        assert_eq!(p.sort[0].order, SortOrder::Ascending);
+        assert_eq!(p.sort[1].order, SortOrder::Descending);
+
+        assert!(paginate("per_page=51").is_err());
+        assert!(paginate("page=0").is_err());
+        assert!(paginate("page=abc").is_err());
+        assert!(paginate("sort=password").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_extractor() {
+        let mut app = test::init_service(App::new().route("/", web::get().to(route))).await;
+        let req = test::TestRequest::with_uri("/?page=2&search=hello").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::with_uri("/?sort=name").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
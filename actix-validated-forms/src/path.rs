@@ -0,0 +1,399 @@
+use crate::error::ValidatedFormError;
+use actix_router::PathDeserializer;
+use actix_web::dev::Payload;
+use actix_web::error::PathError;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{err, ok, Ready};
+use serde::de::{DeserializeOwned, Error as _};
+use serde_urlencoded::de;
+use std::sync::Arc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for the parameters of the matched path
+///
+/// # Example
+/// First define a structure to represent the path parameters that implements
+/// `serde::Deserialize` and `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct PostPath {
+///     #[validate(range(min = 1))]
+///     id: u64,
+///     #[validate(length(min = 1, max = 64))]
+///     slug: String,
+/// }
+/// # use actix_web::{web, App, HttpResponse};
+/// # use actix_validated_forms::path::ValidatedPath;
+///
+/// async fn route(
+///     path: ValidatedPath<PostPath>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+///
+/// App::new().route("/users/{id}/posts/{slug}", web::get().to(route));
+/// # }
+/// ```
+/// Just like the `actix_web::web::Path` when the body of route is executed `path` can be
+/// dereferenced to a `PostPath`, however it has the additional guarantee to have been
+/// successfully validated.
+pub struct ValidatedPath<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedPath<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedPath<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedPath<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedPath<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedPathConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(ValidatedPathConfig::default());
+
+        let validated = T::deserialize(PathDeserializer::new(req.match_info()))
+            .map_err(|e| ValidatedFormError::Deserialization(PathError::Deserialize(e)))
+            .and_then(|c: T| {
+                c.validate()
+                    .map(|_| c)
+                    .map_err(|e| ValidatedFormError::Validation(e))
+            });
+        match validated {
+            Ok(val) => ok(ValidatedPath(val)),
+            Err(e) => err(if let Some(error_handler) = config.error_handler {
+                (error_handler)(e, req)
+            } else {
+                e.into()
+            }),
+        }
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedPath<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedPath<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Configure the behaviour of the ValidatedPath extractor
+///
+/// # Usage
+/// Add a `ValidatedPathConfig` to your actix app data
+/// ```
+/// # use actix_web::web::scope;
+/// # use actix_validated_forms::path::ValidatedPathConfig;
+/// scope("/").app_data(
+///     ValidatedPathConfig::default()
+///         .error_handler(|e, _| actix_web::error::ErrorNotFound(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedPathConfig {
+    error_handler: Option<
+        Arc<dyn Fn(ValidatedFormError<PathError>, &HttpRequest) -> actix_web::Error + Send + Sync>,
+    >,
+}
+
+impl ValidatedPathConfig {
+    /// Sets a custom error handler to convert the error (arising from path parameters that
+    /// failed to either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<PathError>, &HttpRequest) -> actix_web::Error
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedPathConfig {
+    fn default() -> Self {
+        ValidatedPathConfig {
+            error_handler: None,
+        }
+    }
+}
+
+/// Validated extractor for a structure combining the parameters of the matched path and the
+/// query string
+///
+/// # Example
+/// First define a structure to represent both sets of parameters that implements
+/// `serde::Deserialize` and `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct ListRepos {
+///     #[validate(length(min = 1, max = 39))]
+///     org: String,
+///     #[validate(range(min = 1, max = 100))]
+///     limit: u32,
+/// }
+/// # use actix_web::{web, App, HttpResponse};
+/// # use actix_validated_forms::path::ValidatedPathQuery;
+///
+/// async fn route(
+///     params: ValidatedPathQuery<ListRepos>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+///
+/// App::new().route("/orgs/{org}/repos", web::get().to(route));
+/// # }
+/// ```
+/// The path parameters take precedence, a query parameter with the same name as a path
+/// parameter is ignored. When the body of route is executed `params` can be dereferenced to a
+/// `ListRepos`, with the guarantee to have been successfully validated.
+pub struct ValidatedPathQuery<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedPathQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedPathQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedPathQuery<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedPathQuery<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedPathQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(ValidatedPathQueryConfig::default());
+
+        let validated = merge_path_query(req)
+            .and_then(|pairs| serde_urlencoded::to_string(pairs).map_err(de::Error::custom))
+            .and_then(|merged| serde_urlencoded::from_str::<T>(&merged))
+            .map_err(ValidatedFormError::Deserialization)
+            .and_then(|c: T| {
+                c.validate()
+                    .map(|_| c)
+                    .map_err(|e| ValidatedFormError::Validation(e))
+            });
+        match validated {
+            Ok(val) => ok(ValidatedPathQuery(val)),
+            Err(e) => err(if let Some(error_handler) = config.error_handler {
+                (error_handler)(e, req)
+            } else {
+                e.into()
+            }),
+        }
+    }
+}
+
+/// The path parameters followed by the query parameters not shadowed by them
+fn merge_path_query(req: &HttpRequest) -> Result<Vec<(String, String)>, de::Error> {
+    let mut pairs: Vec<(String, String)> = req
+        .match_info()
+        .iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+    let path_len = pairs.len();
+    for (k, v) in serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())? {
+        if !pairs[..path_len].iter().any(|(p, _)| *p == k) {
+            pairs.push((k, v));
+        }
+    }
+    Ok(pairs)
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedPathQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedPathQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Configure the behaviour of the ValidatedPathQuery extractor
+///
+/// # Usage
+/// Add a `ValidatedPathQueryConfig` to your actix app data
+/// ```
+/// # use actix_web::web::scope;
+/// # use actix_validated_forms::path::ValidatedPathQueryConfig;
+/// scope("/").app_data(
+///     ValidatedPathQueryConfig::default()
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedPathQueryConfig {
+    error_handler: Option<
+        Arc<dyn Fn(ValidatedFormError<de::Error>, &HttpRequest) -> actix_web::Error + Send + Sync>,
+    >,
+}
+
+impl ValidatedPathQueryConfig {
+    /// Sets a custom error handler to convert the error (arising from parameters that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<de::Error>, &HttpRequest) -> actix_web::Error
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedPathQueryConfig {
+    fn default() -> Self {
+        ValidatedPathQueryConfig {
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct ExamplePath {
+        #[validate(range(min = 1))]
+        id: u64,
+        #[validate(length(min = 1, max = 5))]
+        slug: String,
+    }
+
+    async fn route(path: ValidatedPath<ExamplePath>) -> impl Responder {
+        HttpResponse::Ok().json(&*path)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app =
+            test::init_service(App::new().route("/users/{id}/posts/{slug}", web::get().to(route)))
+                .await;
+        let req = test::TestRequest::with_uri("/users/7/posts/hello").to_request();
+        let resp: ExamplePath = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.id, 7);
+        assert_eq!(resp.slug, "hello");
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedPathConfig::default()
+                        .error_handler(|e, _| actix_web::error::ErrorNotFound(e.to_string())),
+                )
+                .route("/users/{id}/posts/{slug}", web::get().to(route)),
+        )
+        .await;
+        for uri in &["/users/0/posts/hello", "/users/abc/posts/hello"] {
+            let req = test::TestRequest::with_uri(uri).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct ListRepos {
+        #[validate(length(min = 1, max = 5))]
+        org: String,
+        #[validate(range(min = 1, max = 100))]
+        limit: u32,
+    }
+
+    async fn list_route(params: ValidatedPathQuery<ListRepos>) -> impl Responder {
+        HttpResponse::Ok().json(&*params)
+    }
+
+    #[actix_rt::test]
+    async fn test_path_query() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedPathQueryConfig::default()
+                        .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string())),
+                )
+                .route("/orgs/{org}/repos", web::get().to(list_route)),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/orgs/acme/repos?limit=20&org=other").to_request();
+        let resp: ListRepos = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.org, "acme");
+        assert_eq!(resp.limit, 20);
+
+        for uri in &[
+            "/orgs/acme/repos",
+            "/orgs/acme/repos?limit=0",
+            "/orgs/toolong/repos?limit=20",
+        ] {
+            let req = test::TestRequest::with_uri(uri).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
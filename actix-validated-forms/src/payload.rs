@@ -0,0 +1,39 @@
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::web::BytesMut;
+use actix_web::HttpRequest;
+use futures::StreamExt;
+
+pub(crate) enum BodyError {
+    Overflow,
+    Payload(PayloadError),
+}
+
+/// The value of the Content-Length header, if it is present and valid
+pub(crate) fn content_length(req: &HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|l| l.to_str().ok())
+        .and_then(|l| l.parse::<usize>().ok())
+}
+
+/// Reads the whole payload, failing as soon as it is known to be larger than `limit`
+pub(crate) async fn read_body(
+    mut payload: Payload,
+    length: Option<usize>,
+    limit: usize,
+) -> Result<BytesMut, BodyError> {
+    if length.map_or(false, |l| l > limit) {
+        return Err(BodyError::Overflow);
+    }
+    let mut body = BytesMut::with_capacity(length.unwrap_or(0));
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(BodyError::Payload)?;
+        if body.len() + chunk.len() > limit {
+            return Err(BodyError::Overflow);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
@@ -0,0 +1,529 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{err, ok, FutureExt, LocalBoxFuture, Ready};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for an application/x-www-form-urlencoded HTTP request body with nested
+/// field names
+///
+/// Unlike the `ValidatedForm` the body is deserialized using [serde_qs], so that fields named
+/// like `address[street]` are deserialized into a nested structure, and fields named like
+/// `items[0][qty]` into a `Vec`. The brackets may be percent encoded, as they are by browsers.
+///
+/// # Example
+/// First define a structure to represent the form that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Address {
+///     #[validate(length(min = 1, max = 100))]
+///     street: String,
+/// }
+///
+/// #[derive(Deserialize, Validate)]
+/// struct Order {
+///     #[validate]
+///     address: Address,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::qs::ValidatedQsForm;
+///
+/// async fn route(
+///     order: ValidatedQsForm<Order>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `order` can be dereferenced to an `Order`, with the
+/// guarantee to have been successfully validated.
+///
+/// [serde_qs]: https://github.com/samscott89/serde_qs
+pub struct ValidatedQsForm<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedQsForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedQsForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedQsForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedQsForm<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedQsFormConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let depth = config.depth;
+        let is_form = req.content_type() == "application/x-www-form-urlencoded";
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            if !is_form {
+                return Err(QsErrorWrapper::ContentType);
+            }
+            let body = read_body(payload, length, limit).await?;
+            serde_qs::Config::new(depth, false)
+                .deserialize_bytes::<T>(&body)
+                .map_err(QsErrorWrapper::Deserialize)
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedQsForm(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedQsForm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedQsForm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum QsErrorWrapper {
+    /// The Content-Type of the request isn't `application/x-www-form-urlencoded`
+    ContentType,
+    /// The body is larger than the `ValidatedQsFormConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    Deserialize(serde_qs::Error),
+}
+
+impl std::error::Error for QsErrorWrapper {}
+
+impl From<BodyError> for QsErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => QsErrorWrapper::Overflow,
+            BodyError::Payload(e) => QsErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for QsErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            QsErrorWrapper::ContentType => f.write_str("Content type error"),
+            QsErrorWrapper::Overflow => f.write_str("Form payload size is bigger than allowed"),
+            QsErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            QsErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedQsForm extractor
+///
+/// # Usage
+/// Add a `ValidatedQsFormConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::qs::ValidatedQsFormConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedQsFormConfig::default()
+///         .depth(3)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedQsFormConfig {
+    limit: usize,
+    depth: usize,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<QsErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedQsFormConfig {
+    /// Set the max size of payload. By default max size is 16Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Set the max depth of nested field names, any brackets beyond it are treated as part of
+    /// the name. By default max depth is 5
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a form that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<QsErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedQsFormConfig {
+    fn default() -> Self {
+        ValidatedQsFormConfig {
+            limit: 16384,
+            depth: 5,
+            error_handler: None,
+        }
+    }
+}
+
+/// Validated extractor for a Url Encoded HTTP Query String with nested parameter names
+///
+/// Like the `ValidatedQsForm` the query string is deserialized using [serde_qs], so that
+/// parameters named like `filter[status]` are deserialized into a nested structure, and
+/// parameters named like `filter[tags][]` into a `Vec`.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Filter {
+///     status: Option<String>,
+///     #[serde(default)]
+///     #[validate(length(max = 5))]
+///     tags: Vec<String>,
+/// }
+///
+/// #[derive(Deserialize, Validate)]
+/// struct Search {
+///     #[validate]
+///     filter: Filter,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::qs::ValidatedQsQuery;
+///
+/// async fn route(
+///     search: ValidatedQsQuery<Search>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `search` can be dereferenced to a `Search`, with the
+/// guarantee to have been successfully validated.
+///
+/// [serde_qs]: https://github.com/samscott89/serde_qs
+pub struct ValidatedQsQuery<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedQsQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedQsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedQsQuery<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedQsQuery<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ValidatedQsQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(ValidatedQsQueryConfig::default());
+
+        let validated = serde_qs::Config::new(config.depth, false)
+            .deserialize_str::<T>(req.query_string())
+            .map_err(ValidatedFormError::Deserialization)
+            .and_then(|c| {
+                c.validate()
+                    .map(|_| c)
+                    .map_err(ValidatedFormError::Validation)
+            });
+        match validated {
+            Ok(val) => ok(ValidatedQsQuery(val)),
+            Err(e) => err(if let Some(error_handler) = config.error_handler {
+                (error_handler)(e, req)
+            } else {
+                e.into()
+            }),
+        }
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Configure the behaviour of the ValidatedQsQuery extractor
+///
+/// # Usage
+/// Add a `ValidatedQsQueryConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::qs::ValidatedQsQueryConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedQsQueryConfig::default()
+///         .depth(2)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedQsQueryConfig {
+    depth: usize,
+    error_handler: Option<
+        Arc<
+            dyn Fn(ValidatedFormError<serde_qs::Error>, &HttpRequest) -> actix_web::Error
+                + Send
+                + Sync,
+        >,
+    >,
+}
+
+impl ValidatedQsQueryConfig {
+    /// Set the max depth of nested parameter names, any brackets beyond it are treated as part
+    /// of the name. By default max depth is 5
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a query string that
+    /// failed to either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<serde_qs::Error>, &HttpRequest) -> actix_web::Error
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedQsQueryConfig {
+    fn default() -> Self {
+        ValidatedQsQueryConfig {
+            depth: 5,
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::Deserialize;
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Item {
+        sku: String,
+        qty: u32,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Address {
+        #[validate(length(min = 1, max = 10))]
+        street: String,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Order {
+        #[validate]
+        address: Address,
+        items: Vec<Item>,
+    }
+
+    async fn route(order: ValidatedQsForm<Order>) -> impl Responder {
+        let items: Vec<String> = order
+            .items
+            .iter()
+            .map(|i| format!("{}x{}", i.qty, i.sku))
+            .collect();
+        HttpResponse::Ok().body(format!("{}: {}", order.address.street, items.join(",")))
+    }
+
+    fn request(body: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let body = "address[street]=Main+St&items[0][sku]=a1&items[0][qty]=2&items[1][sku]=b2\
+                    &items[1][qty]=1";
+        let resp = test::read_response(&mut app, request(body).to_request()).await;
+        assert_eq!(resp, "Main St: 2xa1,1xb2");
+
+        // As submitted by a browser
+        let body = "address%5Bstreet%5D=High+St&items%5B0%5D%5Bsku%5D=c3&items%5B0%5D%5Bqty%5D=5";
+        let resp = test::read_response(&mut app, request(body).to_request()).await;
+        assert_eq!(resp, "High St: 5xc3");
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedQsFormConfig::default().error_handler(|e, _| match e {
+                        ValidatedFormError::Validation(_) => {
+                            actix_web::error::ErrorUnprocessableEntity("invalid")
+                        }
+                        e => actix_web::error::ErrorBadRequest(e.to_string()),
+                    }),
+                )
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let body = "address[street]=Far+too+long+to+be+valid&items[0][sku]=a1&items[0][qty]=2";
+        let resp = test::call_service(&mut app, request(body).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = "address[street]=Main+St&items[0][sku]=a1&items[0][qty]=many";
+        let resp = test::call_service(&mut app, request(body).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/json")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Content type error");
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Filter {
+        status: Option<String>,
+        #[serde(default)]
+        #[validate(length(max = 2))]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    pub struct Search {
+        #[validate]
+        filter: Filter,
+    }
+
+    async fn search_route(search: ValidatedQsQuery<Search>) -> impl Responder {
+        HttpResponse::Ok().body(format!(
+            "{} {}",
+            search.filter.status.as_deref().unwrap_or("any"),
+            search.filter.tags.join(",")
+        ))
+    }
+
+    #[actix_rt::test]
+    async fn test_query() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedQsQueryConfig::default().error_handler(|e, _| match e {
+                        ValidatedFormError::Validation(_) => {
+                            actix_web::error::ErrorUnprocessableEntity("invalid")
+                        }
+                        e => actix_web::error::ErrorBadRequest(e.to_string()),
+                    }),
+                )
+                .route("/", web::get().to(search_route)),
+        )
+        .await;
+        let uri = "/?filter%5Bstatus%5D=open&filter%5Btags%5D%5B%5D=a&filter%5Btags%5D%5B%5D=b";
+        let req = test::TestRequest::with_uri(uri).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "open a,b");
+
+        let req = test::TestRequest::with_uri("/?filter%5Bstatus%5D=closed").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "closed ");
+
+        let uri = "/?filter%5Btags%5D%5B%5D=a&filter%5Btags%5D%5B%5D=b&filter%5Btags%5D%5B%5D=c";
+        let req = test::TestRequest::with_uri(uri).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::with_uri("/?filter=open").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::ValidatedFormError;
+use crate::numbers::NumberFormat;
 use actix_web::dev::Payload;
 use actix_web::error::QueryPayloadError;
 use actix_web::{FromRequest, HttpRequest};
@@ -69,30 +70,95 @@ where
 
     #[inline]
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        let config = req
-            .app_data::<Self::Config>()
-            .map(|c| c.clone())
-            .unwrap_or(ValidatedQueryConfig::default());
-
-        serde_urlencoded::from_str::<T>(req.query_string())
-            .map_err(move |e| {
-                ValidatedFormError::Deserialization(QueryPayloadError::Deserialize(e))
+        let config = query_config(req);
+        match extract::<T>(req, &config) {
+            Ok(val) => ok(ValidatedQuery(val)),
+            Err(e) => err(if let Some(error_handler) = config.error_handler {
+                (error_handler)(e, req)
+            } else {
+                e.into()
+            }),
+        }
+    }
+}
+
+impl<T> ValidatedQuery<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    /// Extracts the query of a WebSocket handshake request, rejecting the upgrade if it is
+    /// invalid
+    ///
+    /// The `ValidatedQuery` extractor never reads the payload, so it can be used in a WebSocket
+    /// route as usual. This is for when the query is needed elsewhere in the handshake, for
+    /// example while building the actor passed to `ws::start()`. Unlike the extractor, unless a
+    /// `ValidatedQueryConfig::error_handler()` is configured the error is a `400 Bad Request`,
+    /// so the client can tell that the upgrade was refused because of its parameters.
+    ///
+    /// # Example
+    /// ```
+    /// # #[macro_use] extern crate validator_derive; fn main() {
+    /// # use actix_web::{web, Error, HttpRequest, HttpResponse};
+    /// # use actix_validated_forms::query::ValidatedQuery;
+    /// # use serde::Deserialize;
+    /// # use validator::Validate;
+    /// #[derive(Deserialize, Validate)]
+    /// struct Subscription {
+    ///     #[validate(length(min = 1))]
+    ///     channel: String,
+    /// }
+    ///
+    /// async fn subscribe(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
+    ///     let subscription = ValidatedQuery::<Subscription>::from_handshake(&req)?;
+    ///     // ws::start(ChannelSocket::new(subscription.into_inner()), &req, stream)
+    ///     # unimplemented!()
+    /// }
+    /// # }
+    /// ```
+    pub fn from_handshake(req: &HttpRequest) -> Result<Self, actix_web::Error> {
+        let config = query_config(req);
+        extract::<T>(req, &config)
+            .map(ValidatedQuery)
+            .map_err(|e| match config.error_handler {
+                Some(error_handler) => (error_handler)(e, req),
+                None => actix_web::error::ErrorBadRequest(e),
             })
+    }
+}
+
+fn query_config(req: &HttpRequest) -> ValidatedQueryConfig {
+    req.app_data::<ValidatedQueryConfig>()
+        .map(|c| c.clone())
+        .unwrap_or(ValidatedQueryConfig::default())
+}
+
+/// Deserializes and validates the query string of the request (without touching the payload)
+fn extract<T>(
+    req: &HttpRequest,
+    config: &ValidatedQueryConfig,
+) -> Result<T, ValidatedFormError<QueryPayloadError>>
+where
+    T: Validate + DeserializeOwned,
+{
+    let validated = if config.combine_errors {
+        serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+            .map_err(ValidatedFormError::Deserialization)
+            .and_then(|pairs| crate::diagnostics::bind_combined::<T>(pairs, config.number_format))
+    } else {
+        let deserialized = if config.diagnostics || config.number_format.is_some() {
+            crate::diagnostics::from_str::<T>(req.query_string(), config.number_format)
+        } else {
+            serde_urlencoded::from_str::<T>(req.query_string())
+        };
+        deserialized
+            .map_err(ValidatedFormError::Deserialization)
             .and_then(|c: T| {
                 c.validate()
                     .map(|_| c)
                     .map_err(|e| ValidatedFormError::Validation(e))
             })
-            .map(|val| ok(ValidatedQuery(val)))
-            .unwrap_or_else(move |e| {
-                let e = if let Some(error_handler) = config.error_handler {
-                    (error_handler)(e, req)
-                } else {
-                    e.into()
-                };
-                err(e)
-            })
-    }
+    };
+    validated.map_err(|e| e.map_deserialization(QueryPayloadError::Deserialize))
 }
 
 impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedQuery<T> {
@@ -139,6 +205,9 @@ impl<T: Validate + fmt::Display> fmt::Display for ValidatedQuery<T> {
 /// ```
 #[derive(Clone)]
 pub struct ValidatedQueryConfig {
+    diagnostics: bool,
+    combine_errors: bool,
+    number_format: Option<NumberFormat>,
     error_handler: Option<
         Arc<
             dyn Fn(ValidatedFormError<QueryPayloadError>, &HttpRequest) -> actix_web::Error
@@ -149,6 +218,35 @@ pub struct ValidatedQueryConfig {
 }
 
 impl ValidatedQueryConfig {
+    /// Report which field failed to deserialize, its value and the expected type, e.g.
+    /// `Invalid value "abc" for field 'limit', expected i64: invalid digit found in string`
+    ///
+    /// This has some overhead so is disabled by default.
+    pub fn diagnostics(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled;
+        self
+    }
+
+    /// Report deserialization and validation errors together, instead of failing on the first
+    /// field that can't be deserialized
+    ///
+    /// Every field is bound if possible (fields that fail to deserialize are left out, which
+    /// still works if they are optional), then what was bound is validated. Fields that failed
+    /// to deserialize are reported alongside the validation errors as a
+    /// `ValidatedFormError::Validation`, with the code `invalid_type` (or `required` if missing).
+    pub fn combine_errors(mut self, enabled: bool) -> Self {
+        self.combine_errors = enabled;
+        self
+    }
+
+    /// Accept numeric fields written in `format` (e.g. `1.234,56`), as well as the usual format
+    ///
+    /// Enables `diagnostics()`, which is used to parse the numbers.
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = Some(format);
+        self
+    }
+
     /// Sets a custom error handler to convert the error (arising from a query that failed to
     /// either deserialize or validate) into a different type
     pub fn error_handler<F>(mut self, f: F) -> Self
@@ -166,6 +264,9 @@ impl ValidatedQueryConfig {
 impl Default for ValidatedQueryConfig {
     fn default() -> Self {
         ValidatedQueryConfig {
+            diagnostics: false,
+            combine_errors: false,
+            number_format: None,
             error_handler: None,
         }
     }
@@ -228,4 +329,51 @@ mod tests {
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
     }
+
+    #[actix_rt::test]
+    async fn test_diagnostics() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(ValidatedQueryConfig::default().diagnostics(true))
+                .route("/", web::get().to(route)),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/?limit=20&offset=abc").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(
+            resp,
+            "Query deserialize error: Invalid value \"abc\" for field 'offset', expected i64: \
+             invalid digit found in string"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_number_format() {
+        let config = ValidatedQueryConfig::default().number_format(NumberFormat::comma_decimal());
+        let mut app =
+            test::init_service(App::new().app_data(config).route("/", web::get().to(route))).await;
+        let req = test::TestRequest::with_uri("/?limit=20&offset=1.500").to_request();
+        let resp: ExampleQuery = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.offset, 1500);
+    }
+
+    #[actix_rt::test]
+    async fn test_from_handshake() {
+        let mut app = test::init_service(App::new().route(
+            "/",
+            web::get().to(|req: HttpRequest, payload: web::Payload| async move {
+                let query = ValidatedQuery::<ExampleQuery>::from_handshake(&req)?;
+                // The payload is still available to hand over to the WebSocket
+                drop(payload);
+                Ok::<_, actix_web::Error>(HttpResponse::Ok().json(&*query))
+            }),
+        ))
+        .await;
+        let req = test::TestRequest::with_uri("/?limit=20&offset=40").to_request();
+        let resp: ExampleQuery = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.limit, 20);
+        let req = test::TestRequest::with_uri("/?limit=9999&offset=40").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
 }
@@ -0,0 +1,359 @@
+use crate::body::{BodyErrorWrapper, BodyFormat};
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// A type built from both the query string and the body of a request
+///
+/// Implement this for the structure that you want to validate as a whole, so that validation
+/// can compare fields of the query string with fields of the body.
+pub trait FromQueryAndBody {
+    /// Deserialized from the query string
+    type Query: DeserializeOwned;
+    /// Deserialized from the body, in any of the accepted `BodyFormat`s
+    type Body: DeserializeOwned;
+
+    fn from_query_and_body(query: Self::Query, body: Self::Body) -> Self;
+}
+
+/// Validated extractor for a structure combining the query string and the body of a request
+///
+/// # Example
+/// First define structures to represent the query string and the body that implement
+/// `serde::Deserialize`, and a structure that combines them that implements `FromQueryAndBody`
+/// and `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::{Validate, ValidationError};
+/// # use actix_validated_forms::request::FromQueryAndBody;
+/// #[derive(Deserialize)]
+/// struct PublishQuery {
+///     #[serde(default)]
+///     dry_run: bool,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct PublishBody {
+///     notify: Vec<String>,
+/// }
+///
+/// #[derive(Validate)]
+/// #[validate(schema(function = "validate_publish"))]
+/// struct Publish {
+///     query: PublishQuery,
+///     body: PublishBody,
+/// }
+///
+/// fn validate_publish(publish: &Publish) -> Result<(), ValidationError> {
+///     if publish.query.dry_run && !publish.body.notify.is_empty() {
+///         return Err(ValidationError::new("notify_on_dry_run"));
+///     }
+///     Ok(())
+/// }
+///
+/// impl FromQueryAndBody for Publish {
+///     type Query = PublishQuery;
+///     type Body = PublishBody;
+///
+///     fn from_query_and_body(query: PublishQuery, body: PublishBody) -> Self {
+///         Publish { query, body }
+///     }
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::request::ValidatedRequest;
+///
+/// async fn route(
+///     publish: ValidatedRequest<Publish>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `publish` can be dereferenced to a `Publish`, with the
+/// guarantee to have been successfully validated.
+pub struct ValidatedRequest<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedRequest<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedRequest<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedRequest<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedRequest<T>
+where
+    T: Validate + FromQueryAndBody + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedRequestConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let query = serde_urlencoded::from_str::<T::Query>(req.query_string());
+        let format = BodyFormat::from_content_type(req.content_type())
+            .filter(|f| config.formats.contains(f));
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            // The query string is checked first, so that the body needn't be read
+            let query = query.map_err(RequestErrorWrapper::Query)?;
+            let format = format.ok_or(BodyErrorWrapper::ContentType)?;
+            let body = read_body(payload, length, limit)
+                .await
+                .map_err(BodyErrorWrapper::from)?;
+            let body = format.deserialize::<T::Body>(&body)?;
+            Ok::<_, RequestErrorWrapper>(T::from_query_and_body(query, body))
+        }
+        .map(move |res| {
+            let validated =
+                res.map_err(ValidatedFormError::Deserialization)
+                    .and_then(|c: T| match c.validate() {
+                        Ok(_) => Ok(ValidatedRequest(c)),
+                        Err(e) => Err(ValidatedFormError::Validation(e)),
+                    });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedRequest<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedRequest<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestErrorWrapper {
+    /// The query string failed to deserialize
+    Query(serde_urlencoded::de::Error),
+    /// The body failed to be read or deserialized
+    Body(BodyErrorWrapper),
+}
+
+impl std::error::Error for RequestErrorWrapper {}
+
+impl From<BodyErrorWrapper> for RequestErrorWrapper {
+    fn from(e: BodyErrorWrapper) -> Self {
+        RequestErrorWrapper::Body(e)
+    }
+}
+
+impl Display for RequestErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RequestErrorWrapper::Query(e) => Display::fmt(&e, f),
+            RequestErrorWrapper::Body(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedRequest extractor
+///
+/// # Usage
+/// Add a `ValidatedRequestConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::body::BodyFormat;
+/// # use actix_validated_forms::request::ValidatedRequestConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedRequestConfig::default()
+///         .formats(&[BodyFormat::Json])
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedRequestConfig {
+    limit: usize,
+    formats: Vec<BodyFormat>,
+    error_handler: Option<
+        Rc<dyn Fn(ValidatedFormError<RequestErrorWrapper>, &HttpRequest) -> actix_web::Error>,
+    >,
+}
+
+impl ValidatedRequestConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the formats that are accepted for the body, by default `BodyFormat::Form` and
+    /// `BodyFormat::Json`
+    ///
+    /// # Panics
+    /// If `formats` is empty, as no body could ever be accepted
+    pub fn formats(mut self, formats: &[BodyFormat]) -> Self {
+        assert!(
+            !formats.is_empty(),
+            "ValidatedRequestConfig must accept at least one format"
+        );
+        self.formats = formats.to_vec();
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a query string or body
+    /// that failed to deserialize, or a combination that failed to validate) into a different
+    /// type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<RequestErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedRequestConfig {
+    fn default() -> Self {
+        ValidatedRequestConfig {
+            limit: 262_144,
+            formats: vec![BodyFormat::Form, BodyFormat::Json],
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::Deserialize;
+    use validator::{Validate, ValidationError};
+
+    #[derive(Debug, Deserialize)]
+    pub struct PublishQuery {
+        #[serde(default)]
+        dry_run: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct PublishBody {
+        notify: Vec<String>,
+    }
+
+    #[derive(Debug, Validate)]
+    #[validate(schema(function = "validate_publish"))]
+    pub struct Publish {
+        query: PublishQuery,
+        body: PublishBody,
+    }
+
+    fn validate_publish(publish: &Publish) -> Result<(), ValidationError> {
+        if publish.query.dry_run && !publish.body.notify.is_empty() {
+            return Err(ValidationError::new("notify_on_dry_run"));
+        }
+        Ok(())
+    }
+
+    impl FromQueryAndBody for Publish {
+        type Query = PublishQuery;
+        type Body = PublishBody;
+
+        fn from_query_and_body(query: PublishQuery, body: PublishBody) -> Self {
+            Publish { query, body }
+        }
+    }
+
+    async fn route(publish: ValidatedRequest<Publish>) -> impl Responder {
+        HttpResponse::Ok().body(format!(
+            "{} {}",
+            publish.query.dry_run,
+            publish.body.notify.len()
+        ))
+    }
+
+    fn request(uri: &str, body: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(uri)
+            .header("content-type", "application/json")
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let req = request("/?dry_run=true", r#"{"notify": []}"#).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "true 0");
+
+        let req = request("/", r#"{"notify": ["ops"]}"#).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "false 1");
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(
+                    ValidatedRequestConfig::default().error_handler(|e, _| match e {
+                        ValidatedFormError::Validation(_) => {
+                            actix_web::error::ErrorUnprocessableEntity("invalid")
+                        }
+                        ValidatedFormError::Deserialization(RequestErrorWrapper::Query(_)) => {
+                            actix_web::error::ErrorBadRequest("query")
+                        }
+                        ValidatedFormError::Deserialization(RequestErrorWrapper::Body(_)) => {
+                            actix_web::error::ErrorBadRequest("body")
+                        }
+                    }),
+                )
+                .route("/", web::post().to(route)),
+        )
+        .await;
+        let req = request("/?dry_run=true", r#"{"notify": ["ops"]}"#).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = request("/?dry_run=maybe", r#"{"notify": []}"#).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "query");
+
+        let req = request("/", r#"{"notify": "ops"}"#).to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "body");
+    }
+}
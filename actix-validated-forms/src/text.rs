@@ -0,0 +1,317 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for a text/plain HTTP request body
+///
+/// The body is decoded using the charset of the Content-Type (UTF-8 by default), and then
+/// parsed using `FromStr`.
+///
+/// # Example
+/// First define a type to represent the body that implements `std::str::FromStr` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use validator::{Validate, ValidationErrors, ValidationError};
+/// struct Paste(String);
+///
+/// impl FromStr for Paste {
+///     type Err = std::convert::Infallible;
+///
+///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+///         Ok(Paste(s.to_owned()))
+///     }
+/// }
+///
+/// impl Validate for Paste {
+///     fn validate(&self) -> Result<(), ValidationErrors> {
+///         let mut errors = ValidationErrors::new();
+///         if self.0.trim().is_empty() {
+///             errors.add("paste", ValidationError::new("empty"));
+///             return Err(errors);
+///         }
+///         Ok(())
+///     }
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::text::ValidatedText;
+///
+/// async fn route(
+///     paste: ValidatedText<Paste>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// ```
+/// When the body of route is executed `paste` can be dereferenced to a `Paste`, with the
+/// guarantee to have been successfully validated.
+pub struct ValidatedText<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedText<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedText<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedText<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedText<T>
+where
+    T: Validate + FromStr + 'static,
+    T::Err: Display,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedTextConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let encoding = if req.content_type() == "text/plain" {
+            req.encoding().ok()
+        } else {
+            None
+        };
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            let encoding = encoding.ok_or(TextErrorWrapper::ContentType)?;
+            let body = read_body(payload, length, limit).await?;
+            let text = encoding
+                .decode_without_bom_handling_and_without_replacement(&body)
+                .ok_or(TextErrorWrapper::Encoding(encoding.name()))?;
+            T::from_str(&text).map_err(|e| TextErrorWrapper::Parse(e.to_string()))
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedText(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedText<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedText<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum TextErrorWrapper {
+    /// The Content-Type of the request isn't `text/plain`, or has an unknown charset
+    ContentType,
+    /// The body is larger than the `ValidatedTextConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    /// The body isn't valid in the named charset
+    Encoding(&'static str),
+    /// The error from `FromStr`
+    Parse(String),
+}
+
+impl std::error::Error for TextErrorWrapper {}
+
+impl From<BodyError> for TextErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => TextErrorWrapper::Overflow,
+            BodyError::Payload(e) => TextErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for TextErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TextErrorWrapper::ContentType => f.write_str("Content type error"),
+            TextErrorWrapper::Overflow => f.write_str("Text payload size is bigger than allowed"),
+            TextErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            TextErrorWrapper::Encoding(charset) => write!(f, "Text is not valid {}", charset),
+            TextErrorWrapper::Parse(e) => f.write_str(e),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedText extractor
+///
+/// # Usage
+/// Add a `ValidatedTextConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::text::ValidatedTextConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedTextConfig::default()
+///         .limit(65_536)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedTextConfig {
+    limit: usize,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<TextErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedTextConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a body that failed to
+    /// either parse or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<TextErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedTextConfig {
+    fn default() -> Self {
+        ValidatedTextConfig {
+            limit: 262_144,
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use validator::{ValidationError, ValidationErrors};
+
+    #[derive(Debug)]
+    pub struct Version(u32, u32);
+
+    impl FromStr for Version {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut parts = s.trim().splitn(2, '.').map(|p| p.parse::<u32>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(major)), Some(Ok(minor))) => Ok(Version(major, minor)),
+                _ => Err(format!("{} is not a version", s.trim())),
+            }
+        }
+    }
+
+    impl Validate for Version {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.0 == 0 {
+                errors.add("major", ValidationError::new("range"));
+                return Err(errors);
+            }
+            Ok(())
+        }
+    }
+
+    async fn route(version: ValidatedText<Version>) -> impl Responder {
+        let Version(major, minor) = version.into_inner();
+        HttpResponse::Ok().body(format!("{}.{}", major, minor))
+    }
+
+    fn request(content_type: &str, body: &'static [u8]) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", content_type)
+            .set_payload(body)
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let req = request("text/plain", b"1.2\n").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "1.2");
+
+        let req = request("text/plain; charset=utf-16le", b"1\x00.\x004\x00").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "1.4");
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app =
+            test::init_service(
+                App::new()
+                    .app_data(ValidatedTextConfig::default().limit(16).error_handler(
+                        |e, _| match e {
+                            ValidatedFormError::Validation(_) => {
+                                actix_web::error::ErrorUnprocessableEntity("invalid")
+                            }
+                            e => actix_web::error::ErrorBadRequest(e.to_string()),
+                        },
+                    ))
+                    .route("/", web::post().to(route)),
+            )
+            .await;
+        let req = request("text/plain", b"0.1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = request("text/plain", b"latest").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "latest is not a version");
+
+        let req = request("text/plain", b"1.\xff").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Text is not valid UTF-8");
+
+        let req = request("text/plain", b"1.00000000000000000000").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Text payload size is bigger than allowed");
+
+        let req = request("application/json", b"\"1.2\"").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Content type error");
+    }
+}
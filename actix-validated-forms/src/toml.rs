@@ -0,0 +1,292 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::Utf8Error;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for an application/toml HTTP request body
+///
+/// # Example
+/// First define a structure to represent the body that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Deployment {
+///     #[validate(length(min = 1, max = 64))]
+///     image: String,
+///     #[validate(range(min = 1, max = 100))]
+///     replicas: u32,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::toml::ValidatedToml;
+///
+/// async fn route(
+///     deployment: ValidatedToml<Deployment>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `deployment` can be dereferenced to a `Deployment`, with the
+/// guarantee to have been successfully validated.
+pub struct ValidatedToml<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedToml<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedToml<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedToml<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedToml<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedTomlConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let is_toml = req.content_type() == "application/toml";
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            if !is_toml {
+                return Err(TomlErrorWrapper::ContentType);
+            }
+            let body = read_body(payload, length, limit).await?;
+            let body = std::str::from_utf8(&body).map_err(TomlErrorWrapper::Utf8)?;
+            ::toml::from_str::<T>(body).map_err(TomlErrorWrapper::Deserialize)
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedToml(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedToml<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedToml<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum TomlErrorWrapper {
+    /// The Content-Type of the request isn't `application/toml`
+    ContentType,
+    /// The body is larger than the `ValidatedTomlConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    /// The body isn't valid UTF-8, as required by TOML
+    Utf8(Utf8Error),
+    /// The body failed to parse or deserialize, see `TomlErrorWrapper::line_col()`
+    Deserialize(::toml::de::Error),
+}
+
+impl TomlErrorWrapper {
+    /// The (one-based) line and column in the body at which the error occurred, if known
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            TomlErrorWrapper::Deserialize(e) => e.line_col().map(|(l, c)| (l + 1, c + 1)),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for TomlErrorWrapper {}
+
+impl From<BodyError> for TomlErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => TomlErrorWrapper::Overflow,
+            BodyError::Payload(e) => TomlErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for TomlErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TomlErrorWrapper::ContentType => f.write_str("Content type error"),
+            TomlErrorWrapper::Overflow => f.write_str("TOML payload size is bigger than allowed"),
+            TomlErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            TomlErrorWrapper::Utf8(e) => Display::fmt(&e, f),
+            TomlErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedToml extractor
+///
+/// # Usage
+/// Add a `ValidatedTomlConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::toml::ValidatedTomlConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedTomlConfig::default()
+///         .limit(4096)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedTomlConfig {
+    limit: usize,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<TomlErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedTomlConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a body that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<TomlErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedTomlConfig {
+    fn default() -> Self {
+        ValidatedTomlConfig {
+            limit: 262_144,
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct Deployment {
+        #[validate(length(min = 1, max = 5))]
+        image: String,
+        replicas: u32,
+    }
+
+    async fn route(deployment: ValidatedToml<Deployment>) -> impl Responder {
+        HttpResponse::Ok().json(&*deployment)
+    }
+
+    fn request(body: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/toml")
+            .set_payload(body.to_owned())
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let req = request("image = \"web\"\nreplicas = 3\n").to_request();
+        let resp: Deployment = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.image, "web");
+        assert_eq!(resp.replicas, 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app =
+            test::init_service(
+                App::new()
+                    .app_data(ValidatedTomlConfig::default().limit(64).error_handler(
+                        |e, _| match e {
+                            ValidatedFormError::Validation(_) => {
+                                actix_web::error::ErrorUnprocessableEntity("invalid")
+                            }
+                            ValidatedFormError::Deserialization(e) => match e.line_col() {
+                                Some((line, col)) => {
+                                    actix_web::error::ErrorBadRequest(format!("{}:{}", line, col))
+                                }
+                                None => actix_web::error::ErrorBadRequest(e.to_string()),
+                            },
+                        },
+                    ))
+                    .route("/", web::post().to(route)),
+            )
+            .await;
+        let req = request("image = \"too long\"\nreplicas = 3\n").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = request("image = \"web\"\nreplicas = \n").to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert!(std::str::from_utf8(&resp).unwrap().starts_with("2:"));
+
+        let req = request(&format!("image = \"{}\"\nreplicas = 3\n", "a".repeat(64)));
+        let resp = test::read_response(&mut app, req.to_request()).await;
+        assert_eq!(resp, "TOML payload size is bigger than allowed");
+
+        let req = test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/json")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Content type error");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::error::ValidatedFormError;
+use crate::payload::{content_length, read_body, BodyError};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use quick_xml::de::{DeError, Deserializer};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::{fmt, ops};
+use validator::Validate;
+
+/// Validated extractor for an application/xml (or text/xml) HTTP request body
+///
+/// # Example
+/// First define a structure to represent the body that implements `serde::Deserialize` and
+/// `validator::Validate` traits. Then use the extractor in your route
+///
+/// ```
+/// # #[macro_use] extern crate validator_derive; fn main() {
+/// # use serde::Deserialize;
+/// # use validator::Validate;
+/// #[derive(Deserialize, Validate)]
+/// struct Invoice {
+///     #[validate(length(min = 1, max = 32))]
+///     reference: String,
+///     #[validate(range(min = 0.01))]
+///     amount: f64,
+/// }
+/// # use actix_web::{HttpResponse};
+/// # use actix_validated_forms::xml::ValidatedXml;
+///
+/// async fn route(
+///     invoice: ValidatedXml<Invoice>,
+/// ) -> HttpResponse {
+///     # unimplemented!(); }
+/// # }
+/// ```
+/// When the body of route is executed `invoice` can be dereferenced to an `Invoice`, with the
+/// guarantee to have been successfully validated.
+pub struct ValidatedXml<T: Validate>(pub T);
+
+impl<T: Validate> ValidatedXml<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate> ops::Deref for ValidatedXml<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Validate> ops::DerefMut for ValidatedXml<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedXml<T>
+where
+    T: Validate + DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ValidatedXmlConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req2 = req.clone();
+        let config = req
+            .app_data::<Self::Config>()
+            .map(|c| c.clone())
+            .unwrap_or(Self::Config::default());
+        let limit = config.limit;
+        let is_xml = match req.content_type() {
+            "application/xml" | "text/xml" => true,
+            _ => false,
+        };
+        let length = content_length(req);
+        let payload = payload.take();
+
+        async move {
+            if !is_xml {
+                return Err(XmlErrorWrapper::ContentType);
+            }
+            let body = read_body(payload, length, limit).await?;
+            let mut deserializer = Deserializer::from_reader(&body[..]);
+            serde_path_to_error::deserialize::<_, T>(&mut deserializer)
+                .map_err(XmlErrorWrapper::Deserialize)
+        }
+        .map(move |res| {
+            let validated = res
+                .map_err(ValidatedFormError::Deserialization)
+                .and_then(|c| match c.validate() {
+                    Ok(_) => Ok(ValidatedXml(c)),
+                    Err(e) => Err(ValidatedFormError::Validation(e)),
+                });
+            validated.map_err(|e| {
+                if let Some(err) = config.error_handler {
+                    (*err)(e, &req2)
+                } else {
+                    e.into()
+                }
+            })
+        })
+        .boxed_local()
+    }
+}
+
+impl<T: Validate + fmt::Debug> fmt::Debug for ValidatedXml<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Validate + fmt::Display> fmt::Display for ValidatedXml<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+pub enum XmlErrorWrapper {
+    /// The Content-Type of the request isn't `application/xml` or `text/xml`
+    ContentType,
+    /// The body is larger than the `ValidatedXmlConfig::limit()`
+    Overflow,
+    Payload(PayloadError),
+    /// The body failed to deserialize, the error includes the path to the element
+    Deserialize(serde_path_to_error::Error<DeError>),
+}
+
+impl std::error::Error for XmlErrorWrapper {}
+
+impl From<BodyError> for XmlErrorWrapper {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::Overflow => XmlErrorWrapper::Overflow,
+            BodyError::Payload(e) => XmlErrorWrapper::Payload(e),
+        }
+    }
+}
+
+impl Display for XmlErrorWrapper {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            XmlErrorWrapper::ContentType => f.write_str("Content type error"),
+            XmlErrorWrapper::Overflow => f.write_str("XML payload size is bigger than allowed"),
+            XmlErrorWrapper::Payload(e) => Display::fmt(&e, f),
+            XmlErrorWrapper::Deserialize(e) => Display::fmt(&e, f),
+        }
+    }
+}
+
+/// Configure the behaviour of the ValidatedXml extractor
+///
+/// # Usage
+/// Add a `ValidatedXmlConfig` to your actix app data
+/// ```
+/// # use actix_validated_forms::xml::ValidatedXmlConfig;
+/// # use actix_web::web::scope;
+/// scope("/").app_data(
+///     ValidatedXmlConfig::default()
+///         .limit(4096)
+///         .error_handler(|e, _| actix_web::error::ErrorBadRequest(e.to_string()))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ValidatedXmlConfig {
+    limit: usize,
+    error_handler:
+        Option<Rc<dyn Fn(ValidatedFormError<XmlErrorWrapper>, &HttpRequest) -> actix_web::Error>>,
+}
+
+impl ValidatedXmlConfig {
+    /// Set the max size of payload. By default max size is 256Kb
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets a custom error handler to convert the error (arising from a body that failed to
+    /// either deserialize or validate) into a different type
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ValidatedFormError<XmlErrorWrapper>, &HttpRequest) -> actix_web::Error + 'static,
+    {
+        self.error_handler = Some(Rc::new(f));
+        self
+    }
+}
+
+impl Default for ValidatedXmlConfig {
+    fn default() -> Self {
+        ValidatedXmlConfig {
+            limit: 262_144,
+            error_handler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, Responder};
+    use serde::{Deserialize, Serialize};
+    use validator::Validate;
+
+    #[derive(Debug, Deserialize, Validate, Serialize)]
+    pub struct Reading {
+        #[validate(length(min = 1, max = 5))]
+        sensor: String,
+        value: i32,
+    }
+
+    async fn route(reading: ValidatedXml<Reading>) -> impl Responder {
+        HttpResponse::Ok().json(&*reading)
+    }
+
+    fn request(sensor: &str, value: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/xml; charset=utf-8")
+            .set_payload(format!(
+                "<Reading><sensor>{}</sensor><value>{}</value></Reading>",
+                sensor, value
+            ))
+    }
+
+    #[actix_rt::test]
+    async fn test_valid() {
+        let mut app = test::init_service(App::new().route("/", web::post().to(route))).await;
+        let resp: Reading =
+            test::read_response_json(&mut app, request("t1", "21").to_request()).await;
+        assert_eq!(resp.sensor, "t1");
+        assert_eq!(resp.value, 21);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid() {
+        let mut app =
+            test::init_service(
+                App::new()
+                    .app_data(ValidatedXmlConfig::default().limit(128).error_handler(
+                        |e, _| match e {
+                            ValidatedFormError::Validation(_) => {
+                                actix_web::error::ErrorUnprocessableEntity("invalid")
+                            }
+                            e => actix_web::error::ErrorBadRequest(e.to_string()),
+                        },
+                    ))
+                    .route("/", web::post().to(route)),
+            )
+            .await;
+        let resp = test::call_service(&mut app, request("too long", "21").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let resp = test::read_response(&mut app, request("t1", "warm").to_request()).await;
+        assert!(std::str::from_utf8(&resp).unwrap().starts_with("value: "));
+
+        let resp =
+            test::read_response(&mut app, request(&"a".repeat(100), "21").to_request()).await;
+        assert_eq!(resp, "XML payload size is bigger than allowed");
+
+        let req = test::TestRequest::post()
+            .uri("/")
+            .header("content-type", "application/json")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(resp, "Content type error");
+    }
+}
@@ -1,9 +1,19 @@
+#[cfg(test)]
+#[macro_use]
+extern crate validator_derive;
+
 #[cfg(test)]
 mod tests {
 
-    use actix_validated_forms::multipart::{MultipartField, MultipartText, Multiparts};
-    use actix_validated_forms::FromMultipart;
+    use actix_validated_forms::enums::FormEnum as _;
+    use actix_validated_forms::multipart::{
+        DescribeForm, EarlyValidate, FieldKind, MultipartBytes, MultipartField, MultipartFile,
+        MultipartLoadConfig, MultipartText, Multiparts, Repeated, TestForm, UnknownPartPolicy,
+        ValidatedMultipartFormConfig,
+    };
+    use actix_validated_forms::{DescribeForm, FormEnum, FromMultipart};
     use std::convert::TryFrom;
+    use validator::Validate;
 
     #[derive(FromMultipart, Debug)]
     struct Test {
@@ -21,34 +31,34 @@ mod tests {
     #[test]
     fn it_works() {
         let mut m = Multiparts::new();
-        m.push(MultipartField::Text(MultipartText {
-            name: "string".to_string(),
-            text: "Hello World".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "some_string".to_string(),
-            text: "Hello World".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "int".to_string(),
-            text: "69".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "float".to_string(),
-            text: "-1.25".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "int_array".to_string(),
-            text: "2".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "int_array".to_string(),
-            text: "4".to_string(),
-        }));
-        m.push(MultipartField::Text(MultipartText {
-            name: "int_array".to_string(),
-            text: "6".to_string(),
-        }));
+        m.push(MultipartField::Text(MultipartText::new(
+            "string".to_string(),
+            "Hello World".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "some_string".to_string(),
+            "Hello World".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "int".to_string(),
+            "69".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "float".to_string(),
+            "-1.25".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "int_array".to_string(),
+            "2".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "int_array".to_string(),
+            "4".to_string(),
+        )));
+        m.push(MultipartField::Text(MultipartText::new(
+            "int_array".to_string(),
+            "6".to_string(),
+        )));
         let result = match Test::try_from(m) {
             Ok(r) => r,
             Err(e) => panic!("{}", e),
@@ -61,4 +71,435 @@ mod tests {
         assert_eq!(result.float, -1.25);
         assert_eq!(result.int_array, vec![2, 4, 6]);
     }
+
+    #[derive(FromMultipart, Validate, Debug)]
+    #[allow(dead_code)]
+    struct EarlyTest {
+        #[multipart(validate_early)]
+        #[validate(length(max = 5))]
+        title: String,
+        #[validate(range(min = 1))]
+        count: i32,
+    }
+
+    #[test]
+    fn validate_early() {
+        let mut m = Multiparts::new();
+        assert!(EarlyTest::validate_early(&mut m).is_ok());
+        m.push(MultipartField::Text(MultipartText::new(
+            "count".to_string(),
+            "0".to_string(),
+        )));
+        assert!(EarlyTest::validate_early(&mut m).is_ok());
+        m.push(MultipartField::Text(MultipartText::new(
+            "title".to_string(),
+            "Too long".to_string(),
+        )));
+        let errors = EarlyTest::validate_early(&mut m).unwrap_err();
+        assert!(errors.field_errors().contains_key("title"));
+        assert!(!errors.field_errors().contains_key("count"));
+    }
+
+    #[derive(FromMultipart, Validate, Debug)]
+    struct EarlyCrossFieldTest {
+        #[multipart(validate_early)]
+        #[validate(length(min = 8), must_match = "confirm")]
+        password: String,
+        confirm: String,
+        #[multipart(validate_early)]
+        #[validate(custom = "Self::check_type")]
+        r#type: String,
+    }
+
+    impl EarlyCrossFieldTest {
+        fn check_type(value: &str) -> Result<(), validator::ValidationError> {
+            match value {
+                "admin" => Err(validator::ValidationError::new("reserved")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn validate_early_cross_field() {
+        // Only the validators that don't refer to `Self` or other fields are checked early
+        let mut m = vec![text("password", "short"), text("type", "admin")];
+        let errors = EarlyCrossFieldTest::validate_early(&mut m).unwrap_err();
+        assert_eq!(errors.field_errors().len(), 1);
+        assert_eq!(errors.field_errors()["password"][0].code, "length");
+
+        let m = vec![
+            text("password", "long enough"),
+            text("confirm", "different"),
+            text("type", "admin"),
+        ];
+        let form = EarlyCrossFieldTest::try_from(m).unwrap();
+        assert_eq!(form.r#type, "admin");
+        assert_eq!(form.confirm, "different");
+        let errors = form.validate().unwrap_err();
+        assert_eq!(errors.field_errors()["password"][0].code, "must_match");
+
+        let mut m = vec![text("password", "long enough")];
+        let errors = EarlyCrossFieldTest::bind_combined(&mut m)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(errors.field_errors()["type"][0].code, "required");
+    }
+
+    fn text(name: &str, text: &str) -> MultipartField {
+        MultipartField::Text(MultipartText::new(name.to_string(), text.to_string()))
+    }
+
+    #[derive(FromMultipart, Debug)]
+    #[allow(dead_code)]
+    struct MessagesTest {
+        #[multipart(
+            type_msg = "Age must be a number",
+            duplicate_msg = "Only one age please"
+        )]
+        age: Option<u32>,
+        #[multipart(missing_msg = "Please attach a photo")]
+        photo: MultipartFile,
+    }
+
+    #[test]
+    fn custom_messages() {
+        let err = MessagesTest::try_from(Multiparts::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Please attach a photo");
+        assert_eq!(err.field(), "photo");
+
+        let mut m = Multiparts::new();
+        m.push(text("age", "old"));
+        let err = MessagesTest::try_from(m).unwrap_err();
+        assert_eq!(err.to_string(), "Age must be a number");
+
+        let mut m = Multiparts::new();
+        m.push(text("age", "1"));
+        m.push(text("age", "2"));
+        let err = MessagesTest::try_from(m).unwrap_err();
+        assert_eq!(err.to_string(), "Only one age please");
+    }
+
+    #[test]
+    fn bind_combined() {
+        let mut m = Multiparts::new();
+        m.push(text("age", "old"));
+        let errors = match MessagesTest::bind_combined(&mut m) {
+            Some(Err(e)) => e,
+            _ => panic!("Expected binding errors"),
+        };
+        let errors = errors.field_errors();
+        assert_eq!(errors["age"][0].code, "invalid");
+        assert_eq!(errors["photo"][0].code, "invalid");
+        assert_eq!(
+            errors["photo"][0].message.as_deref(),
+            Some("Please attach a photo")
+        );
+
+        let mut m = Multiparts::new();
+        m.push(text("count", "1"));
+        let errors = EarlyTest::bind_combined(&mut m).unwrap().unwrap_err();
+        assert_eq!(errors.field_errors()["title"][0].code, "required");
+        assert!(!errors.field_errors().contains_key("count"));
+    }
+
+    #[derive(FromMultipart, DescribeForm, Debug)]
+    struct Tag {
+        label: String,
+        weight: Option<i32>,
+    }
+
+    #[derive(FromMultipart, Debug)]
+    struct IndexedTest {
+        ids: Vec<i32>,
+        tags: Repeated<Tag>,
+    }
+
+    #[test]
+    fn indexed_fields() {
+        let mut m = Multiparts::new();
+        m.push(text("ids[1]", "20"));
+        m.push(text("tags[1][label]", "second"));
+        m.push(text("ids[0]", "10"));
+        m.push(text("tags[0][label]", "first"));
+        m.push(text("tags[0][weight]", "5"));
+        let result = IndexedTest::try_from(m).unwrap();
+        assert_eq!(result.ids, vec![10, 20]);
+        assert_eq!(result.tags.len(), 2);
+        assert_eq!(result.tags[0].label, "first");
+        assert_eq!(result.tags[0].weight, Some(5));
+        assert_eq!(result.tags[1].label, "second");
+        assert_eq!(result.tags[1].weight, None);
+
+        let mut m = Multiparts::new();
+        m.push(text("tags[0][weight]", "5"));
+        let err = IndexedTest::try_from(m).unwrap_err();
+        assert_eq!(err.to_string(), "Field 'tags[0][label]' not found");
+    }
+
+    #[derive(FromMultipart, Debug)]
+    struct Note {
+        text: String,
+    }
+
+    // `Note` doesn't implement `DescribeForm`, which is only needed by `#[derive(DescribeForm)]`
+    #[derive(FromMultipart, Debug)]
+    struct UndescribedTest {
+        notes: Repeated<Note>,
+    }
+
+    #[test]
+    fn undescribed_fields() {
+        let mut m = Multiparts::new();
+        m.push(text("notes[0][text]", "hello"));
+        let result = UndescribedTest::try_from(m).unwrap();
+        assert_eq!(result.notes[0].text, "hello");
+    }
+
+    #[derive(FromMultipart, DescribeForm)]
+    #[allow(dead_code)]
+    struct DescribeTest {
+        title: String,
+        count: Option<i32>,
+        image: MultipartFile,
+        tags: Repeated<Tag>,
+    }
+
+    #[test]
+    fn describe_fields() {
+        let fields = DescribeTest::describe_fields();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].name, "title");
+        assert_eq!(fields[0].kind, FieldKind::Text);
+        assert!(fields[0].required);
+        assert_eq!(fields[1].value_type.as_deref(), Some("i32"));
+        assert!(!fields[1].required);
+        assert_eq!(fields[2].kind, FieldKind::File);
+        assert_eq!(fields[3].kind, FieldKind::Group);
+        assert!(fields[3].multiple);
+        assert_eq!(fields[3].fields[0].name, "label");
+        assert!(!fields[3].fields[1].required);
+    }
+
+    #[derive(FromMultipart, DescribeForm, Debug)]
+    struct BytesTest {
+        raw: MultipartBytes,
+        optional: Option<MultipartBytes>,
+        all: Vec<MultipartBytes>,
+    }
+
+    #[test]
+    fn bytes_fields() {
+        let fields = BytesTest::describe_fields();
+        assert_eq!(fields[0].kind, FieldKind::Text);
+        assert!(!fields[1].required);
+        assert!(fields[2].multiple);
+
+        let form = vec![text("raw", "abc"), text("all", "1"), text("all", "2")];
+        let result = BytesTest::try_from(form).unwrap();
+        assert_eq!(&result.raw.bytes[..], b"abc");
+        assert!(result.optional.is_none());
+        assert_eq!(result.all.len(), 2);
+    }
+
+    #[derive(FromMultipart, DescribeForm, Validate)]
+    struct RoundTripTest {
+        #[validate(length(max = 16))]
+        title: String,
+        sizes: Vec<u32>,
+        image: MultipartFile,
+    }
+
+    #[actix_rt::test]
+    async fn round_trip() {
+        let form = TestForm::new()
+            .text("title", "Holiday")
+            .text("sizes", "1")
+            .text("sizes", "2")
+            .file(
+                "image",
+                "beach.png",
+                mime::IMAGE_PNG,
+                &b"Not really a png"[..],
+            );
+        let result: RoundTripTest = form.assert_round_trip().await;
+        assert_eq!(result.title, "Holiday");
+        assert_eq!(result.sizes, vec![1, 2]);
+        assert_eq!(result.image.filename.as_deref(), Some("beach.png"));
+
+        // A file input that was left empty is still bound as a file
+        let form = TestForm::new().text("title", "Holiday").file(
+            "image",
+            "",
+            mime::APPLICATION_OCTET_STREAM,
+            &b""[..],
+        );
+        let result: RoundTripTest = form.assert_round_trip().await;
+        assert_eq!(result.image.size, 0);
+        assert!(result.sizes.is_empty());
+
+        let form = TestForm::new().text("title", "Far too long to be valid");
+        assert!(form
+            .extract::<RoundTripTest>(Default::default())
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn extras() {
+        let form = TestForm::new()
+            .text("title", "Holiday")
+            .text("sizes", "1")
+            .text("notes", "Leftover")
+            .file(
+                "image",
+                "beach.png",
+                mime::IMAGE_PNG,
+                &b"Not really a png"[..],
+            );
+        let (result, extras) = form
+            .extract_with_extras::<RoundTripTest>(Default::default())
+            .await
+            .unwrap();
+        assert_eq!(result.title, "Holiday");
+        assert_eq!(result.sizes, vec![1]);
+        assert_eq!(extras.len(), 1);
+        assert_eq!(extras[0].name(), "notes");
+    }
+
+    #[actix_rt::test]
+    async fn unknown_parts() {
+        let form = TestForm::new()
+            .text("title", "Holiday")
+            .text("sizes", "1")
+            .text("sizes[1]", "2")
+            .text("notes", "Leftover")
+            .file(
+                "image",
+                "beach.png",
+                mime::IMAGE_PNG,
+                &b"Not really a png"[..],
+            );
+        let config = |policy| {
+            ValidatedMultipartFormConfig::default()
+                .config(MultipartLoadConfig::default().unknown_parts(policy))
+        };
+        let result = form
+            .extract::<RoundTripTest>(config(UnknownPartPolicy::Skip))
+            .await
+            .unwrap();
+        assert_eq!(result.sizes, vec![1, 2]);
+        let (_, extras) = form
+            .extract_with_extras::<RoundTripTest>(config(UnknownPartPolicy::Skip))
+            .await
+            .unwrap();
+        assert!(extras.is_empty());
+        assert!(form
+            .extract::<RoundTripTest>(config(UnknownPartPolicy::Reject))
+            .await
+            .is_err());
+    }
+
+    #[derive(FromMultipart, Validate)]
+    struct CharsetTest {
+        #[multipart(charset = "windows-1252")]
+        legacy: String,
+        modern: String,
+    }
+
+    #[actix_rt::test]
+    async fn charset() {
+        // TestForm always submits UTF-8, so the legacy field is decoded into mojibake
+        let result: CharsetTest = TestForm::new()
+            .text("legacy", "café")
+            .text("modern", "café")
+            .extract(Default::default())
+            .await
+            .unwrap();
+        assert_eq!(result.legacy, "cafÃ©");
+        assert_eq!(result.modern, "café");
+    }
+
+    #[derive(FromMultipart, Validate)]
+    struct MimeTest {
+        #[multipart(allowed_mimes = "image/png, image/jpeg")]
+        photo: MultipartFile,
+        notes: Option<MultipartFile>,
+    }
+
+    #[actix_rt::test]
+    async fn allowed_mimes() {
+        let result: MimeTest = TestForm::new()
+            .file("photo", "a.jpg", mime::IMAGE_JPEG, "contents")
+            .file("notes", "notes.txt", mime::TEXT_PLAIN, "contents")
+            .extract(Default::default())
+            .await
+            .unwrap();
+        assert_eq!(result.photo.mime, mime::IMAGE_JPEG);
+        assert!(result.notes.is_some());
+
+        let err = TestForm::new()
+            .file("photo", "a.gif", mime::IMAGE_GIF, "contents")
+            .extract::<MimeTest>(Default::default())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Content type 'image/gif' is not allowed for field 'photo'"
+        );
+    }
+
+    #[derive(FormEnum, Debug, PartialEq)]
+    enum Colour {
+        Red,
+        #[form_enum(alias = "grey")]
+        Gray,
+        #[form_enum(rename = "navy", alias = "dark-blue")]
+        DarkBlue,
+    }
+
+    #[derive(FromMultipart, Debug)]
+    struct EnumTest {
+        colour: Colour,
+        others: Vec<Colour>,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct EnumQuery {
+        colour: Option<Colour>,
+    }
+
+    #[test]
+    fn form_enum() {
+        assert_eq!("RED".parse::<Colour>().unwrap(), Colour::Red);
+        assert_eq!(" Grey ".parse::<Colour>().unwrap(), Colour::Gray);
+        assert_eq!("Dark-Blue".parse::<Colour>().unwrap(), Colour::DarkBlue);
+        assert_eq!(Colour::DarkBlue.to_string(), "navy");
+        assert_eq!(Colour::VARIANTS, &["red", "gray", "navy"]);
+
+        let mut m = Multiparts::new();
+        m.push(text("colour", "navy"));
+        m.push(text("others", "gray"));
+        m.push(text("others", "red"));
+        let result = EnumTest::try_from(m).unwrap();
+        assert_eq!(result.colour, Colour::DarkBlue);
+        assert_eq!(result.others, vec![Colour::Gray, Colour::Red]);
+
+        let mut m = Multiparts::new();
+        m.push(text("colour", "purple"));
+        let err = EnumTest::try_from(m).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Field 'colour' couldn't be converted into derive_tests::tests::Colour"
+        );
+
+        let q: EnumQuery = serde_urlencoded::from_str("colour=Red").unwrap();
+        assert_eq!(q.colour, Some(Colour::Red));
+        let err = serde_urlencoded::from_str::<EnumQuery>("colour=blue").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value \"blue\", expected one of: red, gray, navy"
+        );
+    }
 }